@@ -0,0 +1,715 @@
+use super::{
+	disk::{DiskManager, Page, PageId},
+	objects::{
+		fulltext::{StemmerKind, TextIndexDef, TokenizerKind},
+		secondary_index::{IndexDef, IndexKind},
+		storage::StorageKind,
+	},
+	record::ValueType,
+	table::TableDef,
+};
+use crate::{
+	query::{self, BinaryOp, Expr, UnaryOp},
+	util::slice_to_array,
+	*,
+};
+
+/// Version of the catalog's encoding, written first so a newer one can be told apart
+const CATALOG_VERSION: u8 = 1;
+
+/// Column types without parameters, a string column being stored as the next byte followed by its maximum length
+const VALUE_TYPES: [ValueType; 4] = [
+	ValueType::U32,
+	ValueType::I32,
+	ValueType::Point,
+	ValueType::Rect,
+];
+const STORAGE_KINDS: [StorageKind; 2] = [StorageKind::Heap, StorageKind::Lsm];
+/// Compressions a table can choose, after 0 for the database's default
+const COMPRESSIONS: [Compression; 3] = [Compression::None, Compression::Fast, Compression::High];
+const INDEX_KINDS: [IndexKind; 2] = [IndexKind::Hash, IndexKind::Spatial];
+const UNARY_OPS: [UnaryOp; 2] = [UnaryOp::Neg, UnaryOp::Not];
+const BINARY_OPS: [BinaryOp; 15] = [
+	BinaryOp::Add,
+	BinaryOp::Sub,
+	BinaryOp::Mul,
+	BinaryOp::Div,
+	BinaryOp::Rem,
+	BinaryOp::Eq,
+	BinaryOp::NotEq,
+	BinaryOp::Lt,
+	BinaryOp::LtEq,
+	BinaryOp::Gt,
+	BinaryOp::GtEq,
+	BinaryOp::And,
+	BinaryOp::Or,
+	BinaryOp::Like,
+	BinaryOp::NotLike,
+];
+
+/// A table, and the page its storage is rooted at
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableEntry {
+	pub name: String,
+	pub def: TableDef,
+	pub root: PageId,
+}
+
+/// A secondary index, the table it indexes, and the pages it and its Bloom filter are rooted at
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexEntry {
+	pub name: String,
+	pub table: String,
+	pub def: IndexDef,
+	pub root: PageId,
+	pub bloom: Option<PageId>,
+}
+
+/// A full-text index, the table it indexes, and the page it is rooted at
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextIndexEntry {
+	pub name: String,
+	pub table: String,
+	pub def: TextIndexDef,
+	pub root: PageId,
+}
+
+/// Every table and index in a database
+///
+/// Stored in a chain of pages, the first recorded in the file header, that holds the length of the encoded catalog
+/// followed by the encoding itself:
+/// ```txt
+/// |len|version|n_tables|tables...|n_indexes|indexes...|n_text_indexes|text_indexes...|
+/// 0   4       5        9
+/// ```
+/// The whole catalog is rewritten whenever something is added, reusing the pages it already has.
+#[derive(Debug, Default)]
+pub struct Catalog {
+	pages: Vec<PageId>,
+	tables: Vec<TableEntry>,
+	indexes: Vec<IndexEntry>,
+	text_indexes: Vec<TextIndexEntry>,
+}
+impl Catalog {
+	/// Reads the catalog, which is empty if no table was ever created
+	pub fn load(disk: &mut DiskManager) -> Result<Catalog> {
+		let Some(mut id) = disk.catalog_root()? else {
+			return Ok(Catalog::default());
+		};
+		let mut pages = Vec::new();
+		let mut bytes = Vec::new();
+		loop {
+			let page = disk.read_page(id)?;
+			pages.push(id);
+			bytes.extend_from_slice(&page.data);
+			if page.next == page.id {
+				break;
+			}
+			id = page.next;
+		}
+
+		let mut reader = Reader::new(&bytes);
+		let len = reader.u32()? as usize;
+		let mut reader = Reader::new(reader.bytes(len)?);
+		if reader.u8()? != CATALOG_VERSION {
+			return Err(Error::Corruption("Unknown catalog version".to_string()));
+		}
+		let tables = (0..reader.u32()?)
+			.map(|_| reader.table())
+			.collect::<Result<_>>()?;
+		let indexes = (0..reader.u32()?)
+			.map(|_| reader.index())
+			.collect::<Result<_>>()?;
+		let text_indexes = (0..reader.u32()?)
+			.map(|_| reader.text_index())
+			.collect::<Result<_>>()?;
+		Ok(Catalog {
+			pages,
+			tables,
+			indexes,
+			text_indexes,
+		})
+	}
+
+	pub fn tables(&self) -> impl Iterator<Item = &TableEntry> {
+		self.tables.iter()
+	}
+
+	pub fn table(&self, name: &str) -> Option<&TableEntry> {
+		self.tables.iter().find(|table| table.name == name)
+	}
+
+	pub fn index(&self, name: &str) -> Option<&IndexEntry> {
+		self.indexes.iter().find(|index| index.name == name)
+	}
+
+	pub fn text_index(&self, name: &str) -> Option<&TextIndexEntry> {
+		self.text_indexes.iter().find(|index| index.name == name)
+	}
+
+	/// Whether an index or a full-text index has this name, as they share names
+	pub fn has_index_named(&self, name: &str) -> bool {
+		self.index(name).is_some() || self.text_index(name).is_some()
+	}
+
+	/// Every index of a table
+	pub fn indexes_of<'a>(&'a self, table: &'a str) -> impl Iterator<Item = &'a IndexEntry> {
+		self.indexes
+			.iter()
+			.filter(move |index| index.table == table)
+	}
+
+	/// Every full-text index of a table
+	pub fn text_indexes_of<'a>(
+		&'a self,
+		table: &'a str,
+	) -> impl Iterator<Item = &'a TextIndexEntry> {
+		self.text_indexes
+			.iter()
+			.filter(move |index| index.table == table)
+	}
+
+	/// Adds a table, writing out the catalog
+	pub fn add_table(&mut self, disk: &mut DiskManager, entry: TableEntry) -> Result<()> {
+		self.tables.push(entry);
+		let res = self.save(disk);
+		if res.is_err() {
+			self.tables.pop();
+		}
+		res
+	}
+
+	/// Adds an index, writing out the catalog
+	pub fn add_index(&mut self, disk: &mut DiskManager, entry: IndexEntry) -> Result<()> {
+		self.indexes.push(entry);
+		let res = self.save(disk);
+		if res.is_err() {
+			self.indexes.pop();
+		}
+		res
+	}
+
+	/// Adds a full-text index, writing out the catalog
+	pub fn add_text_index(&mut self, disk: &mut DiskManager, entry: TextIndexEntry) -> Result<()> {
+		self.text_indexes.push(entry);
+		let res = self.save(disk);
+		if res.is_err() {
+			self.text_indexes.pop();
+		}
+		res
+	}
+
+	fn save(&mut self, disk: &mut DiskManager) -> Result<()> {
+		let mut writer = Writer::default();
+		writer.u8(CATALOG_VERSION);
+		writer.u32(self.tables.len() as u32);
+		for table in self.tables.iter() {
+			writer.table(table);
+		}
+		writer.u32(self.indexes.len() as u32);
+		for index in self.indexes.iter() {
+			writer.index(index)?;
+		}
+		writer.u32(self.text_indexes.len() as u32);
+		for index in self.text_indexes.iter() {
+			writer.text_index(index);
+		}
+		let mut bytes = (writer.bytes.len() as u32).to_le_bytes().to_vec();
+		bytes.extend_from_slice(&writer.bytes);
+
+		let capacity = disk.empty_page(0).data.len();
+		let n_pages = bytes.len().div_ceil(capacity);
+		while self.pages.len() < n_pages {
+			let page = disk.allocate_page()?;
+			self.pages.push(page.id);
+		}
+		// entries are never removed, so the catalog never shrinks out of a page it has
+		let ids = &self.pages[..n_pages];
+		let pages: Vec<Page> = bytes
+			.chunks(capacity)
+			.enumerate()
+			.map(|(i, chunk)| {
+				let mut page = disk.empty_page(ids[i]);
+				page.data[..chunk.len()].copy_from_slice(chunk);
+				page.prev = ids[i.saturating_sub(1)];
+				page.next = *ids.get(i + 1).unwrap_or(&ids[i]);
+				page
+			})
+			.collect();
+		disk.flush_pages(&pages)?;
+		if disk.catalog_root()?.is_none() {
+			disk.set_catalog_root(self.pages[0])?;
+		}
+		Ok(())
+	}
+}
+
+/// Checks that an index predicate can be stored in the catalog, before anything is created for the index
+pub fn check_predicate(expr: &Expr) -> Result<()> {
+	Writer::default().expr(expr)
+}
+
+#[derive(Default)]
+struct Writer {
+	bytes: Vec<u8>,
+}
+impl Writer {
+	fn u8(&mut self, n: u8) {
+		self.bytes.push(n);
+	}
+
+	fn u32(&mut self, n: u32) {
+		self.bytes.extend_from_slice(&n.to_le_bytes());
+	}
+
+	fn str(&mut self, s: &str) {
+		self.u32(s.len() as u32);
+		self.bytes.extend_from_slice(s.as_bytes());
+	}
+
+	fn names(&mut self, names: &[String]) {
+		self.u32(names.len() as u32);
+		for name in names {
+			self.str(name);
+		}
+	}
+
+	fn table(&mut self, table: &TableEntry) {
+		self.str(&table.name);
+		self.u32(table.def.columns().len() as u32);
+		for (name, ty) in table.def.columns() {
+			self.str(name);
+			match ty {
+				ValueType::String(max_len) => {
+					self.u8(VALUE_TYPES.len() as u8);
+					self.bytes.extend_from_slice(&max_len.to_le_bytes());
+				}
+				ty => self.u8(position(&VALUE_TYPES, ty)),
+			}
+		}
+		self.u8(position(&STORAGE_KINDS, &table.def.storage_kind()));
+		match table.def.compression_override() {
+			Some(compression) => self.u8(1 + position(&COMPRESSIONS, &compression)),
+			None => self.u8(0),
+		}
+		self.u32(table.root);
+	}
+
+	fn index(&mut self, index: &IndexEntry) -> Result<()> {
+		self.str(&index.name);
+		self.str(&index.table);
+		self.u8(position(&INDEX_KINDS, &index.def.kind));
+		self.names(&index.def.key_columns);
+		self.names(&index.def.include_columns);
+		match &index.def.predicate {
+			Some(expr) => {
+				self.u8(1);
+				self.expr(expr)?;
+			}
+			None => self.u8(0),
+		}
+		match (&index.def.bloom_filter, index.bloom) {
+			(Some(bloom), Some(header)) => {
+				self.u8(1);
+				self.bytes
+					.extend_from_slice(&bloom.expected_keys.to_le_bytes());
+				self.bytes
+					.extend_from_slice(&bloom.false_positive_rate.to_le_bytes());
+				self.u32(header);
+			}
+			_ => self.u8(0),
+		}
+		self.u32(index.root);
+		Ok(())
+	}
+
+	fn text_index(&mut self, index: &TextIndexEntry) {
+		self.str(&index.name);
+		self.str(&index.table);
+		self.str(&index.def.column);
+		match index.def.tokenizer {
+			TokenizerKind::Whitespace => self.u8(0),
+			TokenizerKind::Words => self.u8(1),
+			TokenizerKind::NGrams(n) => {
+				self.u8(2);
+				self.u8(n);
+			}
+		}
+		self.u8(index.def.lowercase as u8);
+		self.names(&index.def.stop_words);
+		match index.def.stemmer {
+			None => self.u8(0),
+			Some(StemmerKind::Porter) => self.u8(1),
+		}
+		self.u32(index.root);
+	}
+
+	fn point(&mut self, p: &Point) {
+		self.bytes.extend_from_slice(&p.to_bytes());
+	}
+
+	/// Encodes the expressions a table's records can be evaluated against, the only ones a catalog needs to hold
+	fn expr(&mut self, expr: &Expr) -> Result<()> {
+		match expr {
+			Expr::Value(query::Value::Identifier(name)) => {
+				self.u8(0);
+				self.str(name);
+			}
+			Expr::Value(query::Value::Integer(n)) => {
+				self.u8(1);
+				self.bytes.extend_from_slice(&n.to_le_bytes());
+			}
+			Expr::Value(query::Value::Float(n)) => {
+				self.u8(2);
+				self.bytes.extend_from_slice(&n.to_le_bytes());
+			}
+			Expr::Value(query::Value::Bool(b)) => {
+				self.u8(3);
+				self.u8(*b as u8);
+			}
+			Expr::Value(query::Value::Null) => self.u8(4),
+			Expr::Value(query::Value::Point(p)) => {
+				self.u8(11);
+				self.point(p);
+			}
+			Expr::Value(query::Value::Rect(r)) => {
+				self.u8(12);
+				self.point(&r.min);
+				self.point(&r.max);
+			}
+			Expr::Value(query::Value::String(s)) => {
+				self.u8(13);
+				self.str(s);
+			}
+			Expr::Column { object, name } => {
+				self.u8(5);
+				self.str(object);
+				self.str(name);
+			}
+			Expr::Unary { op, expr } => {
+				self.u8(6);
+				self.u8(position(&UNARY_OPS, op));
+				self.expr(expr)?;
+			}
+			Expr::Binary { op, left, right } => {
+				self.u8(7);
+				self.u8(position(&BINARY_OPS, op));
+				self.expr(left)?;
+				self.expr(right)?;
+			}
+			Expr::In {
+				expr,
+				list,
+				negated,
+			} => {
+				self.u8(8);
+				self.u8(*negated as u8);
+				self.expr(expr)?;
+				self.u32(list.len() as u32);
+				for item in list {
+					self.expr(item)?;
+				}
+			}
+			Expr::Between {
+				expr,
+				low,
+				high,
+				negated,
+			} => {
+				self.u8(9);
+				self.u8(*negated as u8);
+				self.expr(expr)?;
+				self.expr(low)?;
+				self.expr(high)?;
+			}
+			Expr::IsNull { expr, negated } => {
+				self.u8(10);
+				self.u8(*negated as u8);
+				self.expr(expr)?;
+			}
+			_ => {
+				return Err(Error::InvalidArgument(
+					"Expression can't be stored in the catalog".to_string(),
+				));
+			}
+		}
+		Ok(())
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	cur: usize,
+}
+impl<'a> Reader<'a> {
+	fn new(bytes: &'a [u8]) -> Reader<'a> {
+		Reader { bytes, cur: 0 }
+	}
+
+	fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+		let Some(bytes) = self.bytes.get(self.cur..self.cur + len) else {
+			return Err(Error::Corruption("Catalog is truncated".to_string()));
+		};
+		self.cur += len;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn u32(&mut self) -> Result<u32> {
+		Ok(u32::from_le_bytes(slice_to_array(self.bytes(4)?)))
+	}
+
+	fn u64(&mut self) -> Result<u64> {
+		Ok(u64::from_le_bytes(slice_to_array(self.bytes(8)?)))
+	}
+
+	fn point(&mut self) -> Result<Point> {
+		Ok(Point::from_bytes(self.bytes(Point::SIZE)?))
+	}
+
+	fn str(&mut self) -> Result<String> {
+		let len = self.u32()? as usize;
+		String::from_utf8(self.bytes(len)?.to_vec())
+			.map_err(|_| Error::Corruption("Catalog holds a name that isn't UTF-8".to_string()))
+	}
+
+	fn names(&mut self) -> Result<Vec<String>> {
+		(0..self.u32()?).map(|_| self.str()).collect()
+	}
+
+	fn one_of<T: Copy>(&mut self, items: &[T], what: &str) -> Result<T> {
+		let i = self.u8()? as usize;
+		items
+			.get(i)
+			.copied()
+			.ok_or_else(|| Error::Corruption(format!("Unknown {what} in catalog")))
+	}
+
+	fn table(&mut self) -> Result<TableEntry> {
+		let name = self.str()?;
+		let mut def = TableDef::new();
+		for _ in 0..self.u32()? {
+			let column = self.str()?;
+			let ty = match self.u8()? as usize {
+				i if i == VALUE_TYPES.len() => {
+					ValueType::String(u16::from_le_bytes(slice_to_array(self.bytes(2)?)))
+				}
+				i => *VALUE_TYPES.get(i).ok_or_else(|| {
+					Error::Corruption("Unknown column type in catalog".to_string())
+				})?,
+			};
+			def = def.column(column, ty);
+		}
+		let mut def = def.storage(self.one_of(&STORAGE_KINDS, "storage kind")?);
+		match self.u8()? {
+			0 => {}
+			i => {
+				let compression = COMPRESSIONS.get(i as usize - 1).ok_or_else(|| {
+					Error::Corruption("Unknown compression in catalog".to_string())
+				})?;
+				def = def.compression(*compression);
+			}
+		}
+		Ok(TableEntry {
+			name,
+			def,
+			root: self.u32()?,
+		})
+	}
+
+	fn index(&mut self) -> Result<IndexEntry> {
+		let name = self.str()?;
+		let table = self.str()?;
+		let kind = self.one_of(&INDEX_KINDS, "index kind")?;
+		let mut def = IndexDef::new(self.names()?)
+			.kind(kind)
+			.include(self.names()?);
+		if self.u8()? != 0 {
+			def = def.filter(self.expr()?);
+		}
+		let mut bloom = None;
+		if self.u8()? != 0 {
+			def = def.bloom_filter(self.u64()?, f64::from_bits(self.u64()?));
+			bloom = Some(self.u32()?);
+		}
+		Ok(IndexEntry {
+			name,
+			table,
+			def,
+			root: self.u32()?,
+			bloom,
+		})
+	}
+
+	fn text_index(&mut self) -> Result<TextIndexEntry> {
+		let name = self.str()?;
+		let table = self.str()?;
+		let column = self.str()?;
+		let tokenizer = match self.u8()? {
+			0 => TokenizerKind::Whitespace,
+			1 => TokenizerKind::Words,
+			2 => TokenizerKind::NGrams(self.u8()?),
+			_ => {
+				return Err(Error::Corruption(
+					"Unknown tokenizer in catalog".to_string(),
+				));
+			}
+		};
+		let mut def = TextIndexDef::new(column)
+			.tokenizer(tokenizer)
+			.lowercase(self.u8()? != 0)
+			.stop_words(self.names()?);
+		def.stemmer = match self.u8()? {
+			0 => None,
+			1 => Some(StemmerKind::Porter),
+			_ => {
+				return Err(Error::Corruption("Unknown stemmer in catalog".to_string()));
+			}
+		};
+		Ok(TextIndexEntry {
+			name,
+			table,
+			def,
+			root: self.u32()?,
+		})
+	}
+
+	fn expr(&mut self) -> Result<Expr> {
+		Ok(match self.u8()? {
+			0 => Expr::ident(self.str()?),
+			1 => query::Value::Integer(self.u64()? as i64).into(),
+			2 => query::Value::Float(f64::from_bits(self.u64()?)).into(),
+			3 => query::Value::Bool(self.u8()? != 0).into(),
+			4 => query::Value::Null.into(),
+			5 => Expr::Column {
+				object: self.str()?,
+				name: self.str()?,
+			},
+			6 => Expr::unary(self.one_of(&UNARY_OPS, "operator")?, self.expr()?),
+			7 => Expr::binary(
+				self.one_of(&BINARY_OPS, "operator")?,
+				self.expr()?,
+				self.expr()?,
+			),
+			8 => {
+				let negated = self.u8()? != 0;
+				let expr = Box::new(self.expr()?);
+				let list = (0..self.u32()?)
+					.map(|_| self.expr())
+					.collect::<Result<_>>()?;
+				Expr::In {
+					expr,
+					list,
+					negated,
+				}
+			}
+			9 => Expr::Between {
+				negated: self.u8()? != 0,
+				expr: Box::new(self.expr()?),
+				low: Box::new(self.expr()?),
+				high: Box::new(self.expr()?),
+			},
+			10 => Expr::IsNull {
+				negated: self.u8()? != 0,
+				expr: Box::new(self.expr()?),
+			},
+			11 => query::Value::Point(self.point()?).into(),
+			12 => query::Value::Rect(Rect {
+				min: self.point()?,
+				max: self.point()?,
+			})
+			.into(),
+			13 => query::Value::String(self.str()?).into(),
+			_ => {
+				return Err(Error::Corruption(
+					"Unknown expression in catalog".to_string(),
+				));
+			}
+		})
+	}
+}
+
+/// Position of an item in one of the lists mapping enums to the bytes they are stored as
+fn position<T: PartialEq>(items: &[T], item: &T) -> u8 {
+	items.iter().position(|i| i == item).unwrap() as u8
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn persists() {
+		let mut disk = DiskManager::temp("catalog_persists");
+		assert!(Catalog::load(&mut disk).unwrap().table("Users").is_none());
+
+		let mut catalog = Catalog::load(&mut disk).unwrap();
+		let def = TableDef::new()
+			.column("id", ValueType::U32)
+			.column("area", ValueType::Rect)
+			.column("name", ValueType::String(40));
+		let predicate = Expr::In {
+			expr: Box::new(Expr::unary(UnaryOp::Neg, Expr::ident("id"))),
+			list: vec![
+				query::Value::String("none".to_string()).into(),
+				query::Value::Integer(-1).into(),
+				query::Value::Float(2.5).into(),
+				query::Value::Null.into(),
+				query::Value::Point(Point::new(1.0, -2.0)).into(),
+				query::Value::Rect(Rect::new(Point::new(0.0, 0.0), Point::new(3.0, 4.5))).into(),
+			],
+			negated: true,
+		};
+		let index = IndexDef::new(["id"])
+			.include(["area"])
+			.filter(predicate)
+			.bloom_filter(1_000, 0.05);
+
+		// enough tables to take more than one page
+		let n = 1000;
+		for i in 0..n {
+			let table = TableEntry {
+				name: format!("Table{i}"),
+				def: def.clone(),
+				root: i,
+			};
+			catalog.add_table(&mut disk, table).unwrap();
+		}
+		assert!(catalog.pages.len() > 1);
+		let entry = IndexEntry {
+			name: "ById".to_string(),
+			table: "Table7".to_string(),
+			def: index.clone(),
+			root: 12,
+			bloom: Some(13),
+		};
+		catalog.add_index(&mut disk, entry.clone()).unwrap();
+		let text_entry = TextIndexEntry {
+			name: "Bios".to_string(),
+			table: "Table7".to_string(),
+			def: TextIndexDef::new("name")
+				.tokenizer(TokenizerKind::NGrams(3))
+				.lowercase(false)
+				.stop_words(["the", "a"])
+				.stemmer(StemmerKind::Porter),
+			root: 14,
+		};
+		catalog
+			.add_text_index(&mut disk, text_entry.clone())
+			.unwrap();
+
+		let catalog = Catalog::load(&mut disk).unwrap();
+		assert_eq!(catalog.tables.len(), n as usize);
+		assert_eq!(catalog.table("Table999").unwrap().root, 999);
+		assert_eq!(catalog.table("Table3").unwrap().def, def);
+		assert_eq!(catalog.index("ById"), Some(&entry));
+		assert_eq!(catalog.indexes_of("Table7").count(), 1);
+		assert_eq!(catalog.indexes_of("Table6").count(), 0);
+		assert_eq!(catalog.text_index("Bios"), Some(&text_entry));
+		assert_eq!(catalog.text_indexes_of("Table7").count(), 1);
+		assert!(catalog.has_index_named("Bios") && catalog.has_index_named("ById"));
+	}
+}
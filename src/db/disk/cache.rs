@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::PageId;
+
+/// Least recently used cache of page bytes, kept in sync with the file by `DiskManager` writing through it
+pub struct PageCache {
+	capacity: usize,
+	/// Page bytes, and the tick they were last used at
+	pages: HashMap<PageId, (Box<[u8]>, u64)>,
+	/// Pages by the tick they were last used at, oldest first
+	lru: BTreeMap<u64, PageId>,
+	tick: u64,
+}
+impl PageCache {
+	/// Creates a cache holding at most `capacity` pages, caching nothing if it is 0
+	pub fn new(capacity: usize) -> PageCache {
+		PageCache {
+			capacity,
+			pages: HashMap::new(),
+			lru: BTreeMap::new(),
+			tick: 0,
+		}
+	}
+
+	#[cfg(test)]
+	fn len(&self) -> usize {
+		self.pages.len()
+	}
+
+	/// Gets a page's bytes, marking it as recently used
+	pub fn get(&mut self, id: PageId) -> Option<&[u8]> {
+		let tick = self.next_tick();
+		let (bytes, used) = self.pages.get_mut(&id)?;
+		self.lru.remove(used);
+		self.lru.insert(tick, id);
+		*used = tick;
+		Some(bytes)
+	}
+
+	/// Caches a page's bytes, replacing any older copy and evicting the least recently used page if full
+	pub fn put(&mut self, id: PageId, bytes: &[u8]) {
+		if self.capacity == 0 {
+			return;
+		}
+		let tick = self.next_tick();
+		if let Some((_, used)) = self.pages.remove(&id) {
+			self.lru.remove(&used);
+		} else if self.pages.len() >= self.capacity
+			&& let Some((_, oldest)) = self.lru.pop_first()
+		{
+			self.pages.remove(&oldest);
+		}
+		self.pages.insert(id, (bytes.into(), tick));
+		self.lru.insert(tick, id);
+	}
+
+	/// Drops every cached page, once the file changed underneath the cache
+	pub fn clear(&mut self) {
+		self.pages.clear();
+		self.lru.clear();
+	}
+
+	fn next_tick(&mut self) -> u64 {
+		self.tick += 1;
+		self.tick
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let mut cache = PageCache::new(2);
+		cache.put(1, &[1]);
+		cache.put(2, &[2]);
+		assert_eq!(cache.get(1), Some(&[1u8][..]));
+		cache.put(3, &[3]);
+		assert_eq!(cache.len(), 2);
+		assert_eq!(cache.get(2), None);
+
+		// updating a page doesn't evict anything
+		cache.put(1, &[4]);
+		assert_eq!(cache.get(1), Some(&[4u8][..]));
+		assert_eq!(cache.get(3), Some(&[3u8][..]));
+
+		let mut disabled = PageCache::new(0);
+		disabled.put(1, &[1]);
+		assert_eq!(disabled.len(), 0);
+	}
+}
@@ -0,0 +1,238 @@
+//!
+//! LZ4-style block compression of page bytes.
+//!
+//! The output is a series of sequences, each a run of literal bytes followed by a match copying earlier output:
+//! ```txt
+//! |token|literal_len...|literals|offset|match_len...|
+//! ```
+//! The token's high nibble is the literal length and its low nibble the match length minus `MIN_MATCH`, with a nibble
+//! of 15 continued by bytes that are added on until one is below 255. The last sequence stops after its literals.
+//!
+//! Both compression levels produce the same format, `High` just searches harder for long matches.
+//!
+use crate::Compression;
+
+/// Shortest match worth encoding, shorter ones cost more than the literals
+const MIN_MATCH: usize = 4;
+/// Furthest back a match can start, so offsets fit in a u16
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+const NO_POS: u32 = u32::MAX;
+
+/// Compresses bytes, the output is only smaller if the input is compressible
+pub fn compress(input: &[u8], level: Compression) -> Vec<u8> {
+	let (attempts, lazy) = match level {
+		Compression::None | Compression::Fast => (1, false),
+		Compression::High => (64, true),
+	};
+	let mut matcher = Matcher::new(input, attempts);
+	let mut out = Vec::with_capacity(input.len() / 2);
+
+	let mut anchor = 0;
+	let mut pos = 0;
+	while pos + MIN_MATCH <= input.len() {
+		let Some(mut m) = matcher.find(pos) else {
+			pos += 1;
+			continue;
+		};
+		// take a longer match starting at the next byte instead, if there is one
+		while lazy && pos + 1 + MIN_MATCH <= input.len() {
+			match matcher.find(pos + 1) {
+				Some(next) if next.len > m.len => {
+					pos += 1;
+					m = next;
+				}
+				_ => break,
+			}
+		}
+		write_sequence(&mut out, &input[anchor..pos], Some(m));
+		pos += m.len;
+		anchor = pos;
+	}
+	write_sequence(&mut out, &input[anchor..], None);
+	out
+}
+
+/// Decompresses bytes that decompress to exactly `len` bytes, `None` if they are malformed
+pub fn decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+	let mut out = Vec::with_capacity(len);
+	let mut i = 0;
+	loop {
+		let token = *input.get(i)?;
+		i += 1;
+
+		let lit_len = read_len(input, &mut i, (token >> 4) as usize)?;
+		let literals = input.get(i..(i + lit_len))?;
+		if out.len() + lit_len > len {
+			return None;
+		}
+		out.extend_from_slice(literals);
+		i += lit_len;
+		if i == input.len() {
+			break;
+		}
+
+		let offset = u16::from_le_bytes([*input.get(i)?, *input.get(i + 1)?]) as usize;
+		i += 2;
+		let match_len = read_len(input, &mut i, (token & 0xf) as usize)? + MIN_MATCH;
+		if offset == 0 || offset > out.len() || out.len() + match_len > len {
+			return None;
+		}
+		// byte by byte, since a match can overlap the bytes it produces
+		let start = out.len() - offset;
+		for j in 0..match_len {
+			out.push(out[start + j]);
+		}
+	}
+	(out.len() == len).then_some(out)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Match {
+	offset: usize,
+	len: usize,
+}
+
+/// Finds earlier occurences of the bytes at a position, through chains of positions with the same hash
+struct Matcher<'a> {
+	input: &'a [u8],
+	/// Latest position with each hash
+	head: Vec<u32>,
+	/// Previous position with the same hash as each position
+	prev: Vec<u32>,
+	/// How many positions of a chain to try
+	attempts: usize,
+	/// Positions before this have been added to the chains
+	inserted: usize,
+}
+impl<'a> Matcher<'a> {
+	fn new(input: &'a [u8], attempts: usize) -> Matcher<'a> {
+		Matcher {
+			input,
+			head: vec![NO_POS; 1 << HASH_BITS],
+			prev: vec![NO_POS; input.len()],
+			attempts,
+			inserted: 0,
+		}
+	}
+
+	fn hash(&self, pos: usize) -> usize {
+		let word = u32::from_le_bytes(self.input[pos..(pos + 4)].try_into().unwrap());
+		(word.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+	}
+
+	/// Longest match for the bytes at `pos`, positions must be searched in increasing order
+	fn find(&mut self, pos: usize) -> Option<Match> {
+		while self.inserted < pos {
+			let i = self.inserted;
+			if i + MIN_MATCH <= self.input.len() {
+				let h = self.hash(i);
+				self.prev[i] = self.head[h];
+				self.head[h] = i as u32;
+			}
+			self.inserted += 1;
+		}
+
+		let mut best: Option<Match> = None;
+		let mut candidate = self.head[self.hash(pos)];
+		for _ in 0..self.attempts {
+			if candidate == NO_POS || pos - candidate as usize > MAX_OFFSET {
+				break;
+			}
+			let c = candidate as usize;
+			let len = self.input[pos..]
+				.iter()
+				.zip(&self.input[c..])
+				.take_while(|(a, b)| a == b)
+				.count();
+			if len >= MIN_MATCH && best.is_none_or(|m| len > m.len) {
+				best = Some(Match {
+					offset: pos - c,
+					len,
+				});
+			}
+			candidate = self.prev[c];
+		}
+		best
+	}
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], m: Option<Match>) {
+	let lit_len = literals.len();
+	let match_len = m.map_or(0, |m| m.len - MIN_MATCH);
+	out.push(((lit_len.min(15) as u8) << 4) | match_len.min(15) as u8);
+	if lit_len >= 15 {
+		write_len(out, lit_len - 15);
+	}
+	out.extend_from_slice(literals);
+	if let Some(m) = m {
+		out.extend_from_slice(&(m.offset as u16).to_le_bytes());
+		if match_len >= 15 {
+			write_len(out, match_len - 15);
+		}
+	}
+}
+
+fn write_len(out: &mut Vec<u8>, mut len: usize) {
+	while len >= 255 {
+		out.push(255);
+		len -= 255;
+	}
+	out.push(len as u8);
+}
+
+/// Reads a length that starts as a token's nibble
+fn read_len(input: &[u8], i: &mut usize, nibble: usize) -> Option<usize> {
+	let mut len = nibble;
+	if nibble == 15 {
+		loop {
+			let b = *input.get(*i)?;
+			*i += 1;
+			len += b as usize;
+			if b < 255 {
+				break;
+			}
+		}
+	}
+	Some(len)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let log_line = b"2024-05-01T12:00:00Z INFO request served path=/api/users status=200\n";
+		let logs: Vec<u8> = log_line.iter().copied().cycle().take(8_000).collect();
+		// pseudo-random bytes, which can't be compressed
+		let mut state = 1u64;
+		let noise: Vec<u8> = (0..8_000)
+			.map(|_| {
+				state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+				(state >> 56) as u8
+			})
+			.collect();
+
+		for input in [&logs[..], &noise[..], &[0u8; 8_000][..], &[][..], b"abc"] {
+			for level in [Compression::Fast, Compression::High] {
+				let compressed = compress(input, level);
+				assert_eq!(decompress(&compressed, input.len()).as_deref(), Some(input));
+			}
+		}
+
+		let fast = compress(&logs, Compression::Fast).len();
+		let high = compress(&logs, Compression::High).len();
+		assert!(fast < logs.len() / 5);
+		assert!(high <= fast);
+	}
+
+	#[test]
+	fn malformed() {
+		let compressed = compress(&[7u8; 100], Compression::Fast);
+		assert_eq!(decompress(&compressed, 99), None);
+		assert_eq!(decompress(&compressed[..compressed.len() - 1], 100), None);
+		// match reaching back before the start of the output
+		assert_eq!(decompress(&[0x00, 0x05, 0x00], 4), None);
+	}
+}
@@ -0,0 +1,112 @@
+//!
+//! ChaCha20-Poly1305 authenticated encryption (RFC 8439), from the `chacha20poly1305` crate.
+//!
+//! Only available with the `encryption` feature, without it every key is rejected when a database is opened.
+//!
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, KeyInit};
+
+use crate::Result;
+
+pub type Key = [u8; 32];
+pub type Nonce = [u8; 12];
+pub type Tag = [u8; 16];
+
+/// A key set up to encrypt and decrypt with
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct Cipher {
+	key: Key,
+	aead: ChaCha20Poly1305,
+}
+
+/// A key set up to encrypt and decrypt with, which can't be made without the `encryption` feature
+#[cfg(not(feature = "encryption"))]
+#[derive(Clone)]
+pub struct Cipher(());
+
+#[cfg(feature = "encryption")]
+impl Cipher {
+	pub fn new(key: &Key) -> Result<Cipher> {
+		Ok(Cipher {
+			key: *key,
+			aead: ChaCha20Poly1305::new(key.into()),
+		})
+	}
+
+	pub fn key(&self) -> &Key {
+		&self.key
+	}
+
+	/// Encrypts data in place, returning the tag that authenticates it and the additional data
+	pub fn seal(&self, nonce: &Nonce, aad: &[u8], data: &mut [u8]) -> Tag {
+		self.aead
+			.encrypt_in_place_detached(nonce.into(), aad, data)
+			.expect("Page is too large to encrypt")
+			.into()
+	}
+
+	/// Checks the tag and decrypts data in place, returning `false` (leaving data encrypted) if it isn't authentic
+	pub fn open(&self, nonce: &Nonce, aad: &[u8], data: &mut [u8], tag: &Tag) -> bool {
+		self.aead
+			.decrypt_in_place_detached(nonce.into(), aad, data, tag.into())
+			.is_ok()
+	}
+}
+
+#[cfg(not(feature = "encryption"))]
+impl Cipher {
+	pub fn new(_key: &Key) -> Result<Cipher> {
+		Err(crate::Error::InvalidArgument(
+			"Encryption needs lildb to be built with the \"encryption\" feature".to_string(),
+		))
+	}
+
+	pub fn key(&self) -> &Key {
+		unreachable!("Ciphers can't be made without the encryption feature")
+	}
+
+	pub fn seal(&self, _nonce: &Nonce, _aad: &[u8], _data: &mut [u8]) -> Tag {
+		unreachable!("Ciphers can't be made without the encryption feature")
+	}
+
+	pub fn open(&self, _nonce: &Nonce, _aad: &[u8], _data: &mut [u8], _tag: &Tag) -> bool {
+		unreachable!("Ciphers can't be made without the encryption feature")
+	}
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+	use super::*;
+	use crate::util::slice_to_array;
+
+	fn hex(s: &str) -> Vec<u8> {
+		(0..s.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&s[i..(i + 2)], 16).unwrap())
+			.collect()
+	}
+
+	const SUNSCREEN: &[u8] =
+		b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the \
+		future, sunscreen would be it.";
+
+	/// Pages written by earlier versions have to stay readable, so the layout is pinned to the RFC 8439 vector
+	#[test]
+	fn aead() {
+		let cipher = Cipher::new(&std::array::from_fn(|i| 0x80 + i as u8)).unwrap();
+		let nonce: Nonce = slice_to_array(&hex("070000004041424344454647"));
+		let aad = hex("50515253c0c1c2c3c4c5c6c7");
+		let mut data = SUNSCREEN.to_vec();
+		let tag = cipher.seal(&nonce, &aad, &mut data);
+		assert_eq!(data[..16], hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+		assert_eq!(tag.to_vec(), hex("1ae10b594f09e26a7e902ecbd0600691"));
+
+		let mut tampered = data.clone();
+		tampered[3] ^= 1;
+		assert!(!cipher.open(&nonce, &aad, &mut tampered, &tag));
+		assert!(!cipher.open(&nonce, b"other", &mut data.clone(), &tag));
+		assert!(cipher.open(&nonce, &aad, &mut data, &tag));
+		assert_eq!(data, SUNSCREEN);
+	}
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use super::PageId;
+use crate::{
+	vfs::{LockKind, VfsFile},
+	*,
+};
+
+/// Identifies a file as a rollback journal, at the start of its header
+const MAGIC: [u8; 8] = *b"lildbjnl";
+/// Size of the journal header
+///
+/// Layout:
+/// ```txt
+/// |magic|slot_size|n_pages|catalog|checksum|
+/// 0     8         12      16      20       28
+/// ```
+///
+/// `n_pages` and `catalog` are the number of pages and the catalog root when the transaction began, and `checksum`
+/// is the FNV-1a hash of everything before it.
+const HEADER_SIZE: usize = 28;
+
+/// Rollback journal of a transaction, holding every page the transaction overwrote as it was before
+///
+/// Each saved page follows the header as its page ID, its slot as stored in the database file, and the FNV-1a hash of
+/// both. The journal is synced before any page it saves is overwritten, so a page is never lost, and an entry cut
+/// short by a crash is of a page that wasn't overwritten yet. The file is locked until the transaction ends, which
+/// tells a journal still being written from one a crash left behind.
+pub struct Journal {
+	file: Box<dyn VfsFile>,
+	slot_size: usize,
+	/// Pages in the database when the transaction began, pages allocated since are cut off instead of saved
+	n_pages: u32,
+	/// Pages saved so far
+	saved: HashSet<PageId>,
+	/// Length of the journal, where the next page is saved
+	len: u64,
+	/// Whether pages were saved since the last sync
+	unsynced: bool,
+}
+impl Journal {
+	/// Starts a journal in a file, replacing its contents, and syncs its header
+	pub fn create(
+		file: Box<dyn VfsFile>,
+		slot_size: usize,
+		n_pages: u32,
+		catalog_root: PageId,
+	) -> Result<Journal> {
+		file.lock(LockKind::Exclusive)?;
+		let mut header = [0u8; HEADER_SIZE];
+		header[0..8].copy_from_slice(&MAGIC);
+		header[8..12].copy_from_slice(&(slot_size as u32).to_le_bytes());
+		header[12..16].copy_from_slice(&n_pages.to_le_bytes());
+		header[16..20].copy_from_slice(&catalog_root.to_le_bytes());
+		let checksum = util::hash_bytes(&header[..20]);
+		header[20..28].copy_from_slice(&checksum.to_le_bytes());
+		let res = (|| {
+			file.set_len(0)?;
+			file.write_at(&header, 0)?;
+			file.sync()
+		})();
+		if let Err(e) = res {
+			file.unlock()?;
+			return Err(e.into());
+		}
+		Ok(Journal {
+			file,
+			slot_size,
+			n_pages,
+			saved: HashSet::new(),
+			len: HEADER_SIZE as u64,
+			unsynced: false,
+		})
+	}
+
+	/// Whether a page has to be saved before it's overwritten, which pages allocated since the journal began and the
+	/// first page, holding the file header, don't
+	pub fn needs(&self, id: PageId) -> bool {
+		id != 0 && id < self.n_pages && !self.saved.contains(&id)
+	}
+
+	/// Appends a page's slot, which `sync` has to make durable before the page is overwritten
+	pub fn save(&mut self, id: PageId, slot: &[u8]) -> Result<()> {
+		debug_assert_eq!(slot.len(), self.slot_size);
+		let mut entry = Vec::with_capacity(4 + slot.len() + 8);
+		entry.extend_from_slice(&id.to_le_bytes());
+		entry.extend_from_slice(slot);
+		let checksum = util::hash_bytes(&entry);
+		entry.extend_from_slice(&checksum.to_le_bytes());
+		self.file.write_at(&entry, self.len)?;
+		self.len += entry.len() as u64;
+		self.saved.insert(id);
+		self.unsynced = true;
+		Ok(())
+	}
+
+	/// Makes the pages saved so far durable
+	pub fn sync(&mut self) -> Result<()> {
+		if self.unsynced {
+			self.file.sync()?;
+			self.unsynced = false;
+		}
+		Ok(())
+	}
+
+	/// Reads back what the journal holds
+	pub fn read(&self) -> Result<Option<Saved>> {
+		read(&*self.file, self.slot_size)
+	}
+
+	/// Empties the journal, once its transaction is committed or rolled back, and unlocks it
+	pub fn finish(self) -> Result<()> {
+		let res = self.file.set_len(0).and_then(|()| self.file.sync());
+		self.file.unlock()?;
+		Ok(res?)
+	}
+}
+
+/// What a journal holds, to roll its transaction back
+pub struct Saved {
+	pub n_pages: u32,
+	pub catalog_root: PageId,
+	/// Pages and their slots as they were before the transaction
+	pub pages: Vec<(PageId, Vec<u8>)>,
+}
+
+/// Reads a journal a crashed connection may have left behind, `None` if it's empty, another connection is still
+/// writing it, or its header never made it to the file, in which case nothing it covers was overwritten
+pub fn recover(file: &dyn VfsFile, slot_size: usize) -> Result<Option<Saved>> {
+	if !file.try_lock(LockKind::Exclusive)? {
+		return Ok(None);
+	}
+	let res = read(file, slot_size);
+	file.unlock()?;
+	res
+}
+
+fn read(file: &dyn VfsFile, slot_size: usize) -> Result<Option<Saved>> {
+	let len = file.len()?;
+	if len < HEADER_SIZE as u64 {
+		return Ok(None);
+	}
+	let mut header = [0u8; HEADER_SIZE];
+	file.read_at(&mut header, 0)?;
+	let checksum = u64::from_le_bytes(util::slice_to_array(&header[20..28]));
+	if header[0..8] != MAGIC || util::hash_bytes(&header[..20]) != checksum {
+		return Ok(None);
+	}
+	if u32::from_le_bytes(util::slice_to_array(&header[8..12])) as usize != slot_size {
+		return Err(Error::Corruption(
+			"Rollback journal doesn't match the database's page size".to_string(),
+		));
+	}
+	let mut saved = Saved {
+		n_pages: u32::from_le_bytes(util::slice_to_array(&header[12..16])),
+		catalog_root: PageId::from_le_bytes(util::slice_to_array(&header[16..20])),
+		pages: Vec::new(),
+	};
+	let entry_len = (4 + slot_size + 8) as u64;
+	let mut offset = HEADER_SIZE as u64;
+	while offset + entry_len <= len {
+		let mut entry = vec![0u8; entry_len as usize];
+		file.read_at(&mut entry, offset)?;
+		let (body, checksum) = entry.split_at(4 + slot_size);
+		// an entry cut short by a crash is of a page that wasn't overwritten, and so is every one after it
+		if util::hash_bytes(body) != u64::from_le_bytes(util::slice_to_array(checksum)) {
+			break;
+		}
+		let id = PageId::from_le_bytes(util::slice_to_array(&body[..4]));
+		saved.pages.push((id, body[4..].to_vec()));
+		offset += entry_len;
+	}
+	Ok(Some(saved))
+}
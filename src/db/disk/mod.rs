@@ -1,88 +1,1700 @@
+mod cache;
+mod compress;
+mod crypt;
+mod journal;
 mod page;
 
-use std::fs::File;
-#[cfg(unix)]
-use std::os::unix::fs::FileExt;
-#[cfg(windows)]
-use std::os::windows::fs::FileExt;
+use std::{
+	thread,
+	time::{Duration, Instant},
+};
 
-use crate::*;
-use page::Page;
-pub use page::PageId;
+use crate::{
+	vfs::{LockKind, Mmap, ReadOp, VfsFile, WriteOp},
+	*,
+};
+use cache::PageCache;
+use journal::Journal;
+pub use page::{
+	Page, PageId, RecordId,
+	bloom_block::{BloomBlockPageView, block_bits},
+	fixed_len::FixedLenPageView,
+	hash_bucket::HashBucketPageView,
+	hash_directory::{HashDirectoryPageView, HashSlotsPageView},
+	memtable_log::MemtableLogPageView,
+	postings::PostingsPageView,
+	rtree_node::{RTreeEntry, RTreeNodePageView, node_capacity},
+	sorted_run::SortedRunPageView,
+};
+
+/// Identifies a file as a database, at the start of the file header
+const MAGIC: [u8; 8] = *b"lildb\0\0\x01";
+/// Size of the file header, at the start of page 0's data
+///
+/// Layout:
+/// ```txt
+/// |magic|page_size|compression|encrypted|lsn_epoch|key_check|rekey_lsn|old_key|new_key|catalog|mac_lsn|mac|
+/// 0     8         12          13        14        18        34        42      90      138     142     150 166
+/// ```
+///
+/// `rekey_lsn` is 0 unless a rekey is unfinished, in which case pages with lower LSNs are still encrypted with the
+/// old key, whose check is still `key_check`. The old key is kept sealed with the new key as `old_key`, and the new
+/// key sealed with the old key as `new_key`, each followed by its tag, so either key can open the database.
+///
+/// `catalog` is the first page of the catalog, 0 until the first table is created.
+///
+/// When encryption is on, `mac` authenticates everything before it with the key, and `mac_lsn` is the fresh LSN it
+/// was made with. Otherwise both are 0.
+const FILE_HEADER_SIZE: usize = 166;
+/// Where the fields describing an unfinished rekey end in the file header
+const REKEY_END: usize = 138;
+/// Where the fields authenticating the file header start
+const HEADER_MAC: usize = 142;
+/// Size of the header in front of every page but the first, so pages are that much smaller than their slots
+///
+/// Every page records how it is stored, so pages of tables compressed differently can share a file. Layout:
+/// ```txt
+/// |codec|stored_len|
+/// 0     1          4
+/// ```
+const COMPRESSED_HEADER_SIZE: usize = 4;
+/// Size of the header in front of every page but the first when encryption is on, before the codec header
+///
+/// Layout:
+/// ```txt
+/// |lsn|sealed_len|tag|
+/// 0   8          12  28
+/// ```
+const ENCRYPTED_HEADER_SIZE: usize = 28;
+/// Number of LSNs reserved at a time, by bumping the epoch in the file header
+const LSN_EPOCH_LEN: u64 = 1 << 32;
+/// Additional data authenticated with the key check in the file header
+const KEY_CHECK_AAD: &[u8] = b"lildb key check";
+/// Additional data authenticated with the keys sealed in the file header during a rekey
+const REKEY_AAD: &[u8] = b"lildb rekey";
+/// Codec of a page that is stored as is, because compressing it didn't make it smaller
+const CODEC_RAW: u8 = 0;
+const CODEC_LZ: u8 = 1;
+/// Page headers read at once while totalling compression stats
+const STATS_READ_BATCH: usize = 1_024;
+/// Holes are only punched in whole blocks of this size, the most common file system block size
+const HOLE_ALIGN: u64 = MIN_PAGE_SIZE as u64;
+
+/// Checks that a page size is a power of two within the supported range
+pub fn validate_page_size(page_size: usize) -> Result<()> {
+	if !page_size.is_power_of_two() || !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+		return Err(Error::InvalidArgument(format!(
+			"Page size must be a power of two between {MIN_PAGE_SIZE} and {MAX_PAGE_SIZE}, got {page_size}"
+		)));
+	}
+	Ok(())
+}
 
 /// Manages file operations
 pub struct DiskManager {
 	file: LockedFile,
 	n_pages: u32,
+	/// Space each page takes up in the file, which is more than the page itself to fit the page headers
+	slot_size: usize,
+	/// Compression of the pages written from now on
+	compression: Compression,
+	/// Compression given when the database was created, for tables that don't choose their own
+	default_compression: Compression,
+	key: Option<crypt::Cipher>,
+	/// Key of an unfinished rekey that pages with LSNs below the one given are still encrypted with
+	old_key: Option<(crypt::Cipher, u64)>,
+	/// Next LSN to encrypt a page with, LSNs up to `lsn_end` have been reserved in the file header
+	lsn: u64,
+	lsn_end: u64,
+	/// Decoded pages
+	cache: PageCache,
+	read_only: bool,
+	synchronous: Synchronous,
+	/// Journal of the transaction in progress, if any
+	journal: Option<Journal>,
 }
 impl DiskManager {
-	/// Instantiates a disk manager with a database file
-	pub fn new(f: File) -> Result<DiskManager> {
-		let n_pages = f.metadata()?.len() as u32;
-		let file = LockedFile::new(f);
-		Ok(Self { file, n_pages })
+	/// Instantiates a disk manager with a database file, reading the page size from its header
+	///
+	/// `key` must be given if and only if the database is encrypted. Locks held by other connections are waited for at
+	/// most `busy_timeout`, already while reading the header.
+	pub fn new(
+		f: Box<dyn VfsFile>,
+		key: Option<crypt::Key>,
+		busy_timeout: Option<Duration>,
+	) -> Result<DiskManager> {
+		let mut file = LockedFile::new(f);
+		file.busy_timeout = busy_timeout;
+		let mut header = [0u8; page::HEADER_SIZE + FILE_HEADER_SIZE];
+		file.read(&mut header, 0)?;
+		let header = &header[page::HEADER_SIZE..];
+		if header[0..8] != MAGIC {
+			return Err(Error::Corruption(
+				"File is not a database, or its header is corrupt".to_string(),
+			));
+		}
+		let slot_size = u32::from_le_bytes(util::slice_to_array(&header[8..12])) as usize;
+		validate_page_size(slot_size)?;
+		let compression = match header[12] {
+			0 => Compression::None,
+			1 => Compression::Fast,
+			2 => Compression::High,
+			n => {
+				return Err(Error::Corruption(format!(
+					"Unknown compression {n} in file header"
+				)));
+			}
+		};
+
+		match (header[13], key) {
+			(0, None) => {}
+			(0, Some(_)) => {
+				return Err(Error::InvalidArgument(
+					"Database is not encrypted, but a key was given".to_string(),
+				));
+			}
+			(_, None) => {
+				return Err(Error::InvalidArgument(
+					"Database is encrypted, but no key was given".to_string(),
+				));
+			}
+			(_, Some(_)) => {}
+		}
+		let (key, old_key) = match key {
+			Some(key) => {
+				let (key, old_key) = unlock_keys(&key, header)?;
+				// written with the old key until a rekey has recorded the new one
+				let authentic = [Some(&key), old_key.as_ref().map(|(key, _)| key)]
+					.into_iter()
+					.flatten()
+					.any(|key| header_mac(key, header) == header[(HEADER_MAC + 8)..]);
+				if !authentic {
+					return Err(Error::Corruption(
+						"File header failed authentication".to_string(),
+					));
+				}
+				(Some(key), old_key)
+			}
+			None => (None, None),
+		};
+
+		let n_pages = (file.f.len()? / slot_size as u64) as u32;
+		Ok(Self {
+			file,
+			n_pages,
+			slot_size,
+			compression,
+			default_compression: compression,
+			key,
+			old_key,
+			lsn: 0,
+			lsn_end: 0,
+			cache: PageCache::new(0),
+			read_only: false,
+			synchronous: Synchronous::default(),
+			journal: None,
+		})
 	}
 
 	/// Initializes a file to be a database and creates an owning Disk Manager
-	pub fn init_db(f: File) -> Result<DiskManager> {
+	pub fn init_db(
+		f: Box<dyn VfsFile>,
+		page_size: usize,
+		compression: Compression,
+		key: Option<crypt::Key>,
+		busy_timeout: Option<Duration>,
+	) -> Result<DiskManager> {
+		validate_page_size(page_size)?;
+		let key = key.map(|key| crypt::Cipher::new(&key)).transpose()?;
 		let n_pages = 1;
-		f.set_len((PAGE_SIZE as u64) * (n_pages as u64))?;
+		f.set_len((page_size as u64) * (n_pages as u64))?;
 
+		let mut file = LockedFile::new(f);
+		file.busy_timeout = busy_timeout;
 		let mut dm = DiskManager {
-			file: LockedFile::new(f),
+			file,
 			n_pages,
+			slot_size: page_size,
+			compression,
+			default_compression: compression,
+			key,
+			old_key: None,
+			lsn: 0,
+			lsn_end: 0,
+			cache: PageCache::new(0),
+			read_only: false,
+			synchronous: Synchronous::default(),
+			journal: None,
 		};
 
-		let empty_page = Page::new_empty(0);
-		dm.flush_page(&empty_page)?;
+		let mut header_page = dm.empty_page(0);
+		header_page.data[0..8].copy_from_slice(&MAGIC);
+		header_page.data[8..12].copy_from_slice(&(page_size as u32).to_le_bytes());
+		header_page.data[12] = match compression {
+			Compression::None => 0,
+			Compression::Fast => 1,
+			Compression::High => 2,
+		};
+		if let Some(key) = &dm.key {
+			header_page.data[13] = 1;
+			header_page.data[18..34].copy_from_slice(&key_check(key));
+		}
+		dm.flush_page(&header_page)?;
+		if dm.key.is_some() {
+			// authenticates the header
+			dm.update_header(|_| Ok(()))?;
+		}
 
 		Ok(dm)
 	}
 
-	/// Reads a page from file
-	fn read_page(&mut self, id: PageId) -> Result<Page> {
-		if id > self.n_pages {
-			return Err(Error::Internal(
-				"Tried to read page out of bounds".to_string(),
+	/// Rejects every write with `Error::ReadOnly`
+	pub fn with_read_only(mut self, read_only: bool) -> Self {
+		self.read_only = read_only;
+		self
+	}
+
+	/// When writes are synced to durable storage
+	pub fn with_synchronous(mut self, synchronous: Synchronous) -> Self {
+		self.synchronous = synchronous;
+		self
+	}
+
+	/// Keeps up to `cache_size` recently used pages in memory, so reading them again skips the file
+	pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+		self.cache = PageCache::new(cache_size);
+		self
+	}
+
+	#[inline]
+	pub fn is_read_only(&self) -> bool {
+		self.read_only
+	}
+
+	/// Size of every page, in bytes
+	///
+	/// This is the database's page size, less the headers needed for compression and encryption.
+	#[inline]
+	pub fn page_size(&self) -> usize {
+		let mut size = self.slot_size - COMPRESSED_HEADER_SIZE;
+		if self.key.is_some() {
+			size -= ENCRYPTED_HEADER_SIZE;
+		}
+		size
+	}
+
+	/// An empty page of this file's page size, which isn't written until flushed
+	pub fn empty_page(&self, id: PageId) -> Page {
+		Page::new_empty(id, self.page_size())
+	}
+
+	/// Sets how pages written from now on are compressed, `None` going back to the database's default
+	pub fn set_compression(&mut self, compression: Option<Compression>) {
+		self.compression = compression.unwrap_or(self.default_compression);
+	}
+
+	/// Runs `f` with pages written compressed as given, going back to the database's default afterwards
+	pub fn with_compression<T>(
+		&mut self,
+		compression: Option<Compression>,
+		f: impl FnOnce(&mut DiskManager) -> Result<T>,
+	) -> Result<T> {
+		self.set_compression(compression);
+		let res = f(self);
+		self.set_compression(None);
+		res
+	}
+
+	/// Totals over every page stored in the file but the first, before and after compression
+	///
+	/// Only the headers in front of the pages are read.
+	pub fn compression_stats(&mut self) -> Result<CompressionStats> {
+		let header_size = match self.key {
+			Some(_) => ENCRYPTED_HEADER_SIZE,
+			None => COMPRESSED_HEADER_SIZE,
+		};
+		let mut stats = CompressionStats::default();
+		let ids: Vec<PageId> = (1..self.n_pages).collect();
+		for ids in ids.chunks(STATS_READ_BATCH) {
+			let mut headers = vec![vec![0u8; header_size]; ids.len()];
+			let mut ops: Vec<ReadOp> = ids
+				.iter()
+				.zip(headers.iter_mut())
+				.map(|(&id, buf)| ReadOp {
+					offset: self.offset(id),
+					buf,
+				})
+				.collect();
+			self.file.read_batch(&mut ops)?;
+			for header in headers {
+				let used = match self.key {
+					Some(_) => {
+						ENCRYPTED_HEADER_SIZE
+							+ u32::from_le_bytes(util::slice_to_array(&header[8..12])) as usize
+					}
+					None => {
+						COMPRESSED_HEADER_SIZE
+							+ u32::from_le_bytes([header[1], header[2], header[3], 0]) as usize
+					}
+				};
+				stats.pages += 1;
+				stats.page_bytes += self.page_size() as u64;
+				stats.stored_bytes += used.min(self.slot_size) as u64;
+			}
+		}
+		Ok(stats)
+	}
+
+	#[inline]
+	fn offset(&self, id: PageId) -> u64 {
+		(id as u64) * (self.slot_size as u64)
+	}
+
+	/// Turns a page's bytes into what is stored in its slot, returning how many bytes of the slot are used
+	///
+	/// Page 0 is never compressed or encrypted, so the file header can be read before knowing how to decode pages.
+	fn encode(&mut self, id: PageId, bytes: &[u8]) -> Result<(Vec<u8>, usize)> {
+		let mut slot = vec![0u8; self.slot_size];
+		if id == 0 {
+			slot[..bytes.len()].copy_from_slice(bytes);
+			return Ok((slot, bytes.len()));
+		}
+
+		let compressed = match self.compression {
+			Compression::None => None,
+			Compression::Fast | Compression::High => {
+				Some(compress::compress(bytes, self.compression))
+			}
+		};
+		let (codec, payload) = match &compressed {
+			Some(compressed) if compressed.len() < bytes.len() => (CODEC_LZ, &compressed[..]),
+			_ => (CODEC_RAW, bytes),
+		};
+		let mut body = Vec::with_capacity(COMPRESSED_HEADER_SIZE + payload.len());
+		body.push(codec);
+		body.extend_from_slice(&(payload.len() as u32).to_le_bytes()[..3]);
+		body.extend_from_slice(payload);
+		let Some(key) = self.key.clone() else {
+			slot[..body.len()].copy_from_slice(&body);
+			return Ok((slot, body.len()));
+		};
+
+		let lsn = self.next_lsn()?;
+		let used = ENCRYPTED_HEADER_SIZE + body.len();
+		slot[0..8].copy_from_slice(&lsn.to_le_bytes());
+		slot[8..12].copy_from_slice(&(body.len() as u32).to_le_bytes());
+		let sealed = &mut slot[ENCRYPTED_HEADER_SIZE..used];
+		sealed.copy_from_slice(&body);
+		let tag = key.seal(&page_nonce(id, lsn), &page_aad(id, body.len()), sealed);
+		slot[12..28].copy_from_slice(&tag);
+		Ok((slot, used))
+	}
+
+	/// Turns the contents of a page's slot back into the page's bytes
+	fn decode(&self, id: PageId, mut slot: Vec<u8>) -> Result<Vec<u8>> {
+		let page_size = self.page_size();
+		if id == 0 {
+			slot.truncate(page_size);
+			return Ok(slot);
+		}
+
+		let body = match &self.key {
+			Some(key) => {
+				let lsn = u64::from_le_bytes(util::slice_to_array(&slot[0..8]));
+				let key = match &self.old_key {
+					Some((old_key, rekey_lsn)) if lsn < *rekey_lsn => old_key,
+					_ => key,
+				};
+				let sealed_len = u32::from_le_bytes(util::slice_to_array(&slot[8..12])) as usize;
+				let tag: crypt::Tag = util::slice_to_array(&slot[12..28]);
+				let Some(sealed) =
+					slot.get_mut(ENCRYPTED_HEADER_SIZE..(ENCRYPTED_HEADER_SIZE + sealed_len))
+				else {
+					return Err(Error::Corruption(format!(
+						"Page {id} has an invalid length"
+					)));
+				};
+				if !key.open(
+					&page_nonce(id, lsn),
+					&page_aad(id, sealed_len),
+					sealed,
+					&tag,
+				) {
+					return Err(Error::Corruption(format!(
+						"Page {id} failed authentication"
+					)));
+				}
+				sealed.to_vec()
+			}
+			None => slot,
+		};
+		let stored_len = u32::from_le_bytes([body[1], body[2], body[3], 0]) as usize;
+		let payload = body
+			.get(COMPRESSED_HEADER_SIZE..(COMPRESSED_HEADER_SIZE + stored_len))
+			.ok_or_else(|| Error::Corruption(format!("Page {id} has an invalid length")))?;
+		match body[0] {
+			CODEC_RAW if stored_len == page_size => Ok(payload.to_vec()),
+			CODEC_LZ => compress::decompress(payload, page_size)
+				.ok_or_else(|| Error::Corruption(format!("Page {id} failed to decompress"))),
+			_ => Err(Error::Corruption(format!("Page {id} has an invalid codec"))),
+		}
+	}
+
+	/// Takes a fresh LSN to encrypt a page with, reserving more in the file header once they run out
+	///
+	/// The reservation is synced before any LSN from it is used, so an LSN (and so a nonce) is never reused, even
+	/// after a crash.
+	fn next_lsn(&mut self) -> Result<u64> {
+		if self.lsn == self.lsn_end {
+			let epoch = self.write_header(|header| {
+				let epoch = u32::from_le_bytes(util::slice_to_array(&header[14..18]))
+					.checked_add(1)
+					.ok_or_else(|| Error::Internal("Ran out of LSNs".to_string()))?;
+				header[14..18].copy_from_slice(&epoch.to_le_bytes());
+				// the first LSN of the epoch authenticates the header reserving it
+				let mac_lsn = epoch as u64 * LSN_EPOCH_LEN;
+				header[HEADER_MAC..(HEADER_MAC + 8)].copy_from_slice(&mac_lsn.to_le_bytes());
+				Ok(epoch)
+			})?;
+			self.lsn = epoch as u64 * LSN_EPOCH_LEN + 1;
+			self.lsn_end = epoch as u64 * LSN_EPOCH_LEN + LSN_EPOCH_LEN;
+		}
+		self.lsn += 1;
+		Ok(self.lsn - 1)
+	}
+
+	/// Changes the file header, reading it from the file and writing it back under one exclusive lock, so a change
+	/// another connection makes in between isn't lost
+	///
+	/// The header is synced before the lock is released.
+	fn update_header<T>(&mut self, f: impl FnOnce(&mut [u8]) -> Result<T>) -> Result<T> {
+		// reserved before taking the lock, as reserving LSNs changes the header too
+		let mac_lsn = match self.key {
+			Some(_) => self.next_lsn()?,
+			None => 0,
+		};
+		self.write_header(|header| {
+			let res = f(header)?;
+			header[HEADER_MAC..(HEADER_MAC + 8)].copy_from_slice(&mac_lsn.to_le_bytes());
+			Ok(res)
+		})
+	}
+
+	/// Like `update_header`, but `f` records the LSN the header is authenticated with
+	fn write_header<T>(&mut self, f: impl FnOnce(&mut [u8]) -> Result<T>) -> Result<T> {
+		self.check_writable()?;
+		let key = self.key.clone();
+		let mut res = None;
+		let slot = self.file.update(0, self.slot_size, |slot| {
+			let header = &mut slot[page::HEADER_SIZE..(page::HEADER_SIZE + FILE_HEADER_SIZE)];
+			res = Some(f(header)?);
+			if let Some(key) = &key {
+				let mac = header_mac(key, header);
+				header[(HEADER_MAC + 8)..].copy_from_slice(&mac);
+			}
+			Ok(())
+		})?;
+		self.cache.put(0, &slot[..self.page_size()]);
+		Ok(res.unwrap())
+	}
+
+	/// Re-encrypts every page with a new key
+	///
+	/// Pages written from here on get LSNs from a fresh epoch, and the file header records where that epoch starts
+	/// before any page is rewritten. So if this is interrupted, every page is under one key or the other, the
+	/// database can be opened with either, and [`DiskManager::finish_rekey`] picks up where it left off.
+	pub fn rekey(&mut self, key: crypt::Key) -> Result<()> {
+		self.check_writable()?;
+		self.finish_rekey()?;
+		let Some(old_key) = self.key.clone() else {
+			return Err(Error::InvalidArgument(
+				"Only encrypted databases can be rekeyed".to_string(),
 			));
+		};
+		let new_key = crypt::Cipher::new(&key)?;
+
+		self.lsn_end = self.lsn;
+		let rekey_lsn = self.next_lsn()?;
+		let nonce = page_nonce(0, rekey_lsn);
+		self.update_header(|header| {
+			header[34..42].copy_from_slice(&rekey_lsn.to_le_bytes());
+			for (at, sealer, sealed) in [(42, &new_key, &old_key), (90, &old_key, &new_key)] {
+				let mut sealed_key = *sealed.key();
+				let tag = sealer.seal(&nonce, REKEY_AAD, &mut sealed_key);
+				header[at..(at + 32)].copy_from_slice(&sealed_key);
+				header[(at + 32)..(at + 48)].copy_from_slice(&tag);
+			}
+			Ok(())
+		})?;
+
+		self.key = Some(new_key);
+		self.old_key = Some((old_key, rekey_lsn));
+		self.finish_rekey()
+	}
+
+	/// Re-encrypts the pages an unfinished rekey hasn't gotten to yet, if there was one
+	pub fn finish_rekey(&mut self) -> Result<()> {
+		let Some(&(_, rekey_lsn)) = self.old_key.as_ref() else {
+			return Ok(());
+		};
+		self.check_writable()?;
+		for id in 1..self.n_pages {
+			let mut lsn = [0u8; 8];
+			self.file.read(&mut lsn, self.offset(id))?;
+			if u64::from_le_bytes(lsn) < rekey_lsn {
+				let page = self.read_page(id)?;
+				self.flush_page(&page)?;
+			}
+		}
+		self.file.sync()?;
+
+		let check = key_check(self.key.as_ref().unwrap());
+		self.update_header(|header| {
+			header[18..34].copy_from_slice(&check);
+			header[34..REKEY_END].fill(0);
+			Ok(())
+		})?;
+		self.old_key = None;
+		Ok(())
+	}
+
+	/// First page of the catalog, `None` if no catalog has been created yet
+	pub fn catalog_root(&mut self) -> Result<Option<PageId>> {
+		let header = self.read_page(0)?;
+		let root = PageId::from_le_bytes(util::slice_to_array(&header.data[REKEY_END..HEADER_MAC]));
+		Ok((root != 0).then_some(root))
+	}
+
+	/// Records the first page of the catalog in the file header
+	pub fn set_catalog_root(&mut self, root: PageId) -> Result<()> {
+		self.update_header(|header| {
+			header[REKEY_END..HEADER_MAC].copy_from_slice(&root.to_le_bytes());
+			Ok(())
+		})
+	}
+
+	/// Frees the unused end of a page's slot, once it has been written
+	fn punch_slot(&mut self, id: PageId, used: usize) -> Result<()> {
+		let start = (self.offset(id) + used as u64).next_multiple_of(HOLE_ALIGN);
+		let end = self.offset(id) + self.slot_size as u64;
+		if start < end {
+			self.file.punch_hole(start, end - start)?;
+		}
+		Ok(())
+	}
+
+	/// Creates a fresh in-memory database, for tests
+	#[cfg(test)]
+	pub fn temp(name: &str) -> DiskManager {
+		use crate::vfs::{MemoryVfs, OpenFlags, Vfs};
+
+		let f = MemoryVfs::new()
+			.open(name.as_ref(), OpenFlags::new().create(true))
+			.expect("Failed to create temp file");
+		DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None)
+			.expect("Failed to init db")
+	}
+
+	/// Reads a page from file
+	pub fn read_page(&mut self, id: PageId) -> Result<Page> {
+		if id >= self.n_pages {
+			return Err(Error::Corruption(format!(
+				"Tried to read page {id} of {}",
+				self.n_pages
+			)));
 		}
 
 		// read the bytes
-		let mut page_buf = [0u8; PAGE_SIZE];
-		self.file.read(&mut page_buf, id * (PAGE_SIZE as u32))?;
+		if let Some(bytes) = self.cache.get(id) {
+			return Page::from_bytes(bytes, id);
+		}
+
+		let mut slot = vec![0u8; self.slot_size];
+		self.file.read(&mut slot, self.offset(id))?;
+		let page_buf = self.decode(id, slot)?;
+		self.cache.put(id, &page_buf);
+
+		Page::from_bytes(&page_buf, id)
+	}
+
+	/// Reads many pages at once, in the order given
+	///
+	/// Cheaper than reading the pages one at a time, since the reads are submitted together.
+	pub fn read_pages(&mut self, ids: &[PageId]) -> Result<Vec<Page>> {
+		if let Some(id) = ids.iter().find(|&&id| id >= self.n_pages) {
+			return Err(Error::Corruption(format!(
+				"Tried to read page {id} of {}",
+				self.n_pages
+			)));
+		}
+
+		let mut bufs: Vec<Option<Vec<u8>>> = ids
+			.iter()
+			.map(|&id| self.cache.get(id).map(|bytes| bytes.to_vec()))
+			.collect();
+		let mut missing: Vec<(PageId, Vec<u8>)> = ids
+			.iter()
+			.zip(bufs.iter())
+			.filter(|(_, cached)| cached.is_none())
+			.map(|(&id, _)| (id, vec![0u8; self.slot_size]))
+			.collect();
+		let mut ops: Vec<ReadOp> = missing
+			.iter_mut()
+			.map(|(id, buf)| ReadOp {
+				offset: (*id as u64) * (self.slot_size as u64),
+				buf,
+			})
+			.collect();
+		self.file.read_batch(&mut ops)?;
 
-		Ok(Page::from_bytes(page_buf, id)?)
+		let mut missing = missing.into_iter();
+		for buf in bufs.iter_mut().filter(|buf| buf.is_none()) {
+			let (id, slot) = missing.next().unwrap();
+			let bytes = self.decode(id, slot)?;
+			self.cache.put(id, &bytes);
+			*buf = Some(bytes);
+		}
+		ids.iter()
+			.zip(bufs)
+			.map(|(&id, buf)| Page::from_bytes(&buf.unwrap(), id))
+			.collect()
+	}
+
+	/// Writes many pages at once
+	pub fn flush_pages(&mut self, pages: &[Page]) -> Result<()> {
+		self.check_writable()?;
+		self.save_pages(pages.iter().map(|page| page.id))?;
+		let bufs = pages
+			.iter()
+			.map(|page| page.to_bytes())
+			.collect::<Result<Vec<_>>>()?;
+		let slots = pages
+			.iter()
+			.zip(bufs.iter())
+			.map(|(page, buf)| self.encode(page.id, buf))
+			.collect::<Result<Vec<_>>>()?;
+		let ops: Vec<WriteOp> = pages
+			.iter()
+			.zip(slots.iter())
+			.map(|(page, (slot, _))| WriteOp {
+				offset: self.offset(page.id),
+				buf: slot,
+			})
+			.collect();
+		self.file.write_batch(&ops)?;
+		for ((page, buf), (_, used)) in pages.iter().zip(bufs.iter()).zip(slots.iter()) {
+			self.punch_slot(page.id, *used)?;
+			self.cache.put(page.id, buf);
+		}
+		self.sync_write()
+	}
+
+	/// Number of pages in the file
+	#[inline]
+	pub fn n_pages(&self) -> u32 {
+		self.n_pages
 	}
 
 	/// Writes a page to file
-	fn flush_page(&mut self, page: &Page) -> Result<()> {
-		let mut bytes = page.to_bytes()?;
-		self.file.write(&mut bytes, page.id * (PAGE_SIZE as u32))
+	pub fn flush_page(&mut self, page: &Page) -> Result<()> {
+		self.check_writable()?;
+		debug_assert_eq!(page.size(), self.page_size());
+		self.save_pages([page.id])?;
+		let bytes = page.to_bytes()?;
+		let (mut slot, used) = self.encode(page.id, &bytes)?;
+		self.file.write(&mut slot, self.offset(page.id))?;
+		self.punch_slot(page.id, used)?;
+		self.cache.put(page.id, &bytes);
+		self.sync_write()
+	}
+
+	fn check_writable(&self) -> Result<()> {
+		if self.read_only {
+			return Err(Error::ReadOnly);
+		}
+		Ok(())
+	}
+
+	/// Syncs after a write, if every write should be durable
+	fn sync_write(&mut self) -> Result<()> {
+		match self.synchronous {
+			Synchronous::Full => self.file.sync(),
+			Synchronous::Off | Synchronous::Normal => Ok(()),
+		}
+	}
+
+	/// Serves page reads from a memory mapping of the file instead of read syscalls, where the file supports it
+	///
+	/// Returns whether the file could be mapped. Writes still go through the file, so this mostly pays off for
+	/// read-heavy workloads.
+	pub fn enable_mmap(&mut self) -> Result<bool> {
+		self.file.enable_mmap()
+	}
+
+	/// Makes every page flushed so far durable, unless syncing is turned off
+	pub fn sync(&mut self) -> Result<()> {
+		match self.synchronous {
+			Synchronous::Off => Ok(()),
+			Synchronous::Normal | Synchronous::Full => self.file.sync(),
+		}
+	}
+
+	/// Writes a copy of every page into another file, replacing its contents
+	///
+	/// Fails with `Error::InvalidArgument` if the other file is the database's own, before anything is written.
+	pub fn copy_to(&mut self, dest: Box<dyn VfsFile>) -> Result<()> {
+		if let Some(id) = dest.id()?
+			&& self.file.f.id()? == Some(id)
+		{
+			return Err(Error::InvalidArgument(
+				"Can't copy a database over its own file".to_string(),
+			));
+		}
+		let mut dest = LockedFile::new(dest);
+		dest.set_len(0)?;
+		// slots are copied as they are, compressed and encrypted
+		for id in 0..self.n_pages {
+			let mut slot = vec![0u8; self.slot_size];
+			self.file.read(&mut slot, self.offset(id))?;
+			dest.write(&mut slot, self.offset(id))?;
+			let used = slot.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+			let start = (self.offset(id) + used as u64).next_multiple_of(HOLE_ALIGN);
+			let end = self.offset(id) + self.slot_size as u64;
+			if start < end {
+				dest.punch_hole(start, end - start)?;
+			}
+		}
+		dest.sync()
+	}
+
+	/// Starts a transaction, saving every page to `journal` before it's first overwritten, so the transaction can be
+	/// rolled back with [`DiskManager::rollback`], or after a crash with [`DiskManager::recover`]
+	///
+	/// Fails with `Error::InvalidArgument` if a transaction is already in progress.
+	pub fn begin(&mut self, journal: Box<dyn VfsFile>) -> Result<()> {
+		self.check_writable()?;
+		if self.journal.is_some() {
+			return Err(Error::InvalidArgument(
+				"A transaction is already in progress".to_string(),
+			));
+		}
+		let catalog_root = self.catalog_root()?.unwrap_or(0);
+		self.journal = Some(Journal::create(
+			journal,
+			self.slot_size,
+			self.n_pages,
+			catalog_root,
+		)?);
+		Ok(())
+	}
+
+	/// Ends the transaction in progress, keeping its writes
+	///
+	/// The writes are synced before the journal is emptied, unless syncing is turned off.
+	pub fn commit(&mut self) -> Result<()> {
+		let Some(journal) = self.journal.take() else {
+			return Ok(());
+		};
+		if let Err(e) = self.sync() {
+			self.journal = Some(journal);
+			return Err(e);
+		}
+		journal.finish()
+	}
+
+	/// Ends the transaction in progress, putting back every page it overwrote, cutting off the pages it allocated, and
+	/// restoring the catalog root
+	///
+	/// If this fails, the disk manager stops taking writes, and the rollback is finished once the database is opened
+	/// again.
+	pub fn rollback(&mut self) -> Result<()> {
+		let Some(journal) = self.journal.take() else {
+			return Ok(());
+		};
+		let res = journal
+			.read()
+			.and_then(|saved| {
+				saved.ok_or_else(|| Error::Corruption("Rollback journal has no header".to_string()))
+			})
+			.and_then(|saved| self.restore(saved))
+			.and_then(|()| journal.finish());
+		if res.is_err() {
+			self.read_only = true;
+		}
+		res
+	}
+
+	/// Rolls back the transaction a crashed connection left in a journal, returning whether there was one
+	///
+	/// A journal another connection is still writing is left alone.
+	pub fn recover(&mut self, journal: Box<dyn VfsFile>) -> Result<bool> {
+		let Some(saved) = journal::recover(&*journal, self.slot_size)? else {
+			return Ok(false);
+		};
+		self.check_writable()?;
+		self.restore(saved)?;
+		journal.set_len(0)?;
+		journal.sync()?;
+		Ok(true)
+	}
+
+	/// Writes back the pages a journal saved, and syncs them
+	fn restore(&mut self, saved: journal::Saved) -> Result<()> {
+		for (id, mut slot) in saved.pages {
+			self.file.write(&mut slot, self.offset(id))?;
+		}
+		self.file.set_len(self.offset(saved.n_pages))?;
+		self.n_pages = saved.n_pages;
+		self.cache.clear();
+		self.set_catalog_root(saved.catalog_root)?;
+		self.file.sync()
+	}
+
+	/// Saves pages to the journal of the transaction in progress, if they need to be, before they're overwritten
+	fn save_pages(&mut self, ids: impl IntoIterator<Item = PageId>) -> Result<()> {
+		let Some(journal) = &mut self.journal else {
+			return Ok(());
+		};
+		for id in ids {
+			if journal.needs(id) {
+				let mut slot = vec![0u8; self.slot_size];
+				self.file
+					.read(&mut slot, (id as u64) * (self.slot_size as u64))?;
+				journal.save(id, &slot)?;
+			}
+		}
+		journal.sync()
+	}
+
+	/// Grows the file by one page, returning an empty page with the new ID
+	pub fn allocate_page(&mut self) -> Result<Page> {
+		self.check_writable()?;
+		let page = self.empty_page(self.n_pages);
+		// only counted once it's written, so a failed write doesn't leave a page that can't be read
+		self.flush_page(&page)?;
+		self.n_pages += 1;
+		Ok(page)
+	}
+
+	/// Like `allocate_page`, but leaves writing the page to the caller, for pages about to be written anyway
+	///
+	/// Saves writing every page twice when filling many new pages and flushing them together. Reading the page fails
+	/// until it is written, and if it never is, it is gone once the database is reopened.
+	pub fn reserve_page(&mut self) -> Result<Page> {
+		self.check_writable()?;
+		let page = self.empty_page(self.n_pages);
+		self.n_pages += 1;
+		Ok(page)
+	}
+}
+
+/// Nonce a page is encrypted with, unique as long as LSNs are
+fn page_nonce(id: PageId, lsn: u64) -> crypt::Nonce {
+	let mut nonce = [0u8; 12];
+	nonce[0..4].copy_from_slice(&id.to_le_bytes());
+	nonce[4..12].copy_from_slice(&lsn.to_le_bytes());
+	nonce
+}
+
+/// Additional data authenticated with a page, so a page can't be passed off as another or truncated
+fn page_aad(id: PageId, sealed_len: usize) -> [u8; 8] {
+	let mut aad = [0u8; 8];
+	aad[0..4].copy_from_slice(&id.to_le_bytes());
+	aad[4..8].copy_from_slice(&(sealed_len as u32).to_le_bytes());
+	aad
+}
+
+/// Tag stored in the file header to check keys against, the nonce is never used for a page since LSN 0 never is
+fn key_check(key: &crypt::Cipher) -> crypt::Tag {
+	key.seal(&page_nonce(0, 0), KEY_CHECK_AAD, &mut [])
+}
+
+/// Tag authenticating the file header, made with the LSN recorded in it
+fn header_mac(key: &crypt::Cipher, header: &[u8]) -> crypt::Tag {
+	let mac_lsn = u64::from_le_bytes(util::slice_to_array(&header[HEADER_MAC..(HEADER_MAC + 8)]));
+	key.seal(
+		&page_nonce(0, mac_lsn),
+		&header[..(HEADER_MAC + 8)],
+		&mut [],
+	)
+}
+
+/// Sets up the keys pages are encrypted with from the one a database is opened with
+///
+/// While a rekey is unfinished this can be either the old or the new key, the other one is unsealed from the file
+/// header. Returns the current key and, if a rekey is unfinished, the old key and the LSN it started at.
+fn unlock_keys(
+	key: &crypt::Key,
+	header: &[u8],
+) -> Result<(crypt::Cipher, Option<(crypt::Cipher, u64)>)> {
+	let key = crypt::Cipher::new(key)?;
+	let is_old = key_check(&key) == header[18..34];
+	let rekey_lsn = u64::from_le_bytes(util::slice_to_array(&header[34..42]));
+	if rekey_lsn == 0 {
+		if !is_old {
+			return Err(Error::InvalidArgument("Wrong encryption key".to_string()));
+		}
+		return Ok((key, None));
+	}
+
+	// the other key is sealed with this one, the new key after the old one
+	let at = if is_old { 90 } else { 42 };
+	let mut other: crypt::Key = util::slice_to_array(&header[at..(at + 32)]);
+	let tag: crypt::Tag = util::slice_to_array(&header[(at + 32)..(at + 48)]);
+	if !key.open(&page_nonce(0, rekey_lsn), REKEY_AAD, &mut other, &tag) {
+		return Err(Error::InvalidArgument("Wrong encryption key".to_string()));
 	}
+	let other = crypt::Cipher::new(&other)?;
+	Ok(if is_old {
+		(other, Some((key, rekey_lsn)))
+	} else {
+		(key, Some((other, rekey_lsn)))
+	})
 }
 
+/// Longest to sleep between attempts at taking a busy lock
+const MAX_LOCK_BACKOFF: Duration = Duration::from_millis(50);
+
 /// A wrapper struct around a file, ensuring that file is always accessed behind a synchronized lock
 struct LockedFile {
-	f: File,
+	f: Box<dyn VfsFile>,
+	/// Read path, when mmap is enabled. Left as `None` until there is something to map
+	map: Option<Mmap>,
+	use_map: bool,
+	/// How long to wait for a lock before giving up, forever if `None`
+	busy_timeout: Option<Duration>,
 }
 impl LockedFile {
-	pub fn new(f: File) -> LockedFile {
-		LockedFile { f }
+	pub fn new(f: Box<dyn VfsFile>) -> LockedFile {
+		LockedFile {
+			f,
+			map: None,
+			use_map: false,
+			busy_timeout: None,
+		}
 	}
 
-	/// Writes the whole buffer at the given offset
-	pub fn write(&mut self, buf: &mut [u8], offset: u32) -> Result<()> {
-		self.f.lock()?;
-		self.f.write_all_at(buf, offset as u64)?;
+	/// Acquires the file lock, waiting at most `busy_timeout`
+	fn lock(&self, kind: LockKind) -> Result<()> {
+		let Some(timeout) = self.busy_timeout else {
+			return Ok(self.f.lock(kind)?);
+		};
+		let deadline = Instant::now() + timeout;
+		let mut backoff = Duration::from_millis(1);
+		while !self.f.try_lock(kind)? {
+			let now = Instant::now();
+			if now >= deadline {
+				return Err(Error::Busy);
+			}
+			thread::sleep(backoff.min(deadline - now));
+			backoff = (backoff * 2).min(MAX_LOCK_BACKOFF);
+		}
+		Ok(())
+	}
+
+	/// Switches reads over to a memory mapping, returning `false` if the file can't be mapped
+	pub fn enable_mmap(&mut self) -> Result<bool> {
+		self.use_map = true;
+		self.lock(LockKind::Shared)?;
+		let res = match self.f.len() {
+			Ok(len) => self.remap(len),
+			Err(e) => Err(Error::Io(e)),
+		};
 		self.f.unlock()?;
+		res?;
+		Ok(self.use_map)
+	}
+
+	/// Maps the whole file, given its current length, disabling the mmap read path if the file doesn't support it
+	///
+	/// Must be called under the shared lock, so the length can't change until the mapping is read.
+	fn remap(&mut self, len: u64) -> Result<()> {
+		self.map = None;
+		if len == 0 {
+			return Ok(());
+		}
+		// SAFETY: the mapping is only read from under the shared lock, right after checking it is no longer than the
+		// file, and the file is only written to or truncated under the exclusive lock. So the file can't shrink below
+		// the mapping, nor change, while a slice of it is borrowed
+		self.map = unsafe { self.f.map(len) }?;
+		self.use_map = self.map.is_some();
 		Ok(())
 	}
 
+	/// The mapping, remapped if the file changed length since, `None` if it doesn't cover `end` or mmap is disabled
+	///
+	/// Must be called under the shared lock.
+	fn mapped(&mut self, end: usize) -> Result<Option<&Mmap>> {
+		if !self.use_map {
+			return Ok(None);
+		}
+		let len = self.f.len()?;
+		if self.map.as_ref().map_or(0, |map| map.len() as u64) != len {
+			// the file grew, or another connection shrank it, since it was mapped
+			self.remap(len)?;
+		}
+		Ok(self.map.as_ref().filter(|map| end <= map.len()))
+	}
+
+	/// Grows or shrinks the file, dropping the mapping if it no longer fits
+	pub fn set_len(&mut self, len: u64) -> Result<()> {
+		self.lock(LockKind::Exclusive)?;
+		let res = self.f.set_len(len);
+		if self.map.as_ref().is_some_and(|map| map.len() as u64 > len) {
+			self.map = None;
+		}
+		self.f.unlock()?;
+		Ok(res?)
+	}
+
+	/// Writes the whole buffer at the given offset
+	pub fn write(&mut self, buf: &mut [u8], offset: u64) -> Result<()> {
+		self.lock(LockKind::Exclusive)?;
+		let res = self.f.write_at(buf, offset);
+		self.f.unlock()?;
+		Ok(res?)
+	}
+
+	/// Reads a range of the file, changes it and writes it back, then syncs it, all under one exclusive lock so no
+	/// other connection writes to the file in between
+	///
+	/// Returns the bytes written back. Mapped reads are bypassed, so the range is read as it is in the file.
+	pub fn update(
+		&mut self,
+		offset: u64,
+		len: usize,
+		f: impl FnOnce(&mut [u8]) -> Result<()>,
+	) -> Result<Vec<u8>> {
+		self.lock(LockKind::Exclusive)?;
+		let mut buf = vec![0u8; len];
+		let res = (|| {
+			self.f.read_at(&mut buf, offset)?;
+			f(&mut buf)?;
+			self.f.write_at(&buf, offset)?;
+			Ok(self.f.sync()?)
+		})();
+		self.f.unlock()?;
+		res.map(|_| buf)
+	}
+
 	/// Reads enough bytes to fill buffer, from offset
-	pub fn read(&mut self, buf: &mut [u8], offset: u32) -> Result<()> {
-		self.f.lock_shared()?;
-		self.f.read_exact_at(buf, offset as u64)?;
+	pub fn read(&mut self, buf: &mut [u8], offset: u64) -> Result<()> {
+		let start = offset as usize;
+		let end = start + buf.len();
+		self.lock(LockKind::Shared)?;
+		let res = match self.mapped(end) {
+			Ok(Some(map)) => {
+				buf.copy_from_slice(&map.as_slice()[start..end]);
+				Ok(())
+			}
+			Ok(None) => self.f.read_at(buf, offset).map_err(Error::Io),
+			Err(e) => Err(e),
+		};
 		self.f.unlock()?;
-		Ok(())
+		res
+	}
+
+	/// Writes every buffer at its offset
+	pub fn write_batch(&mut self, ops: &[WriteOp]) -> Result<()> {
+		self.lock(LockKind::Exclusive)?;
+		let res = self.f.write_batch(ops);
+		self.f.unlock()?;
+		Ok(res?)
+	}
+
+	/// Fills every buffer from its offset
+	pub fn read_batch(&mut self, ops: &mut [ReadOp]) -> Result<()> {
+		let end = ops.iter().map(|op| op.offset as usize + op.buf.len()).max();
+		let Some(end) = end else {
+			return Ok(());
+		};
+		self.lock(LockKind::Shared)?;
+		let res = match self.mapped(end) {
+			Ok(Some(map)) => {
+				for op in ops {
+					let start = op.offset as usize;
+					op.buf
+						.copy_from_slice(&map.as_slice()[start..(start + op.buf.len())]);
+				}
+				Ok(())
+			}
+			Ok(None) => self.f.read_batch(ops).map_err(Error::Io),
+			Err(e) => Err(e),
+		};
+		self.f.unlock()?;
+		res
+	}
+
+	/// Frees the storage behind a range of the file that only holds zeroes
+	pub fn punch_hole(&mut self, offset: u64, len: u64) -> Result<()> {
+		self.lock(LockKind::Exclusive)?;
+		let res = self.f.punch_hole(offset, len);
+		self.f.unlock()?;
+		Ok(res?)
+	}
+
+	/// Flushes the file's writes to durable storage
+	pub fn sync(&mut self) -> Result<()> {
+		self.lock(LockKind::Exclusive)?;
+		let res = self.f.sync();
+		self.f.unlock()?;
+		Ok(res?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vfs::{FaultyVfs, MemoryVfs, OpenFlags, OsVfs, Vfs};
+
+	#[test]
+	fn injected_faults() {
+		let vfs = FaultyVfs::new(MemoryVfs::new());
+		let faults = vfs.faults();
+		let f = vfs
+			.open("a.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+
+		faults.fail_writes_after(0);
+		assert!(matches!(disk.allocate_page(), Err(Error::Io(_))));
+		faults.fail_syncs_after(0);
+		assert!(matches!(disk.sync(), Err(Error::Io(_))));
+		faults.fail_reads_after(0);
+		assert!(matches!(disk.read_page(0), Err(Error::Io(_))));
+
+		faults.heal();
+		disk.sync().unwrap();
+		assert_eq!(disk.read_page(0).unwrap().id, 0);
+	}
+
+	#[test]
+	fn batched_io() {
+		let path = util::test_file!();
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+
+		let mut pages = Vec::new();
+		for i in 0..100u32 {
+			let mut page = disk.allocate_page().unwrap();
+			page.data[..4].copy_from_slice(&i.to_le_bytes());
+			pages.push(page);
+		}
+		disk.flush_pages(&pages).unwrap();
+
+		let ids: Vec<PageId> = (1..=100).rev().collect();
+		let read = disk.read_pages(&ids).unwrap();
+		for (page, id) in read.iter().zip(ids) {
+			assert_eq!(page.id, id);
+			assert_eq!(page.data[..4], (id - 1).to_le_bytes());
+		}
+		assert!(disk.read_pages(&[101]).is_err());
+	}
+
+	#[test]
+	fn page_sizes() {
+		let vfs = MemoryVfs::new();
+		for page_size in [MIN_PAGE_SIZE, 32_768, MAX_PAGE_SIZE] {
+			let path = format!("{page_size}.ldb");
+			let f = vfs
+				.open(path.as_ref(), OpenFlags::new().create(true))
+				.unwrap();
+			let mut disk =
+				DiskManager::init_db(f, page_size, Compression::None, None, None).unwrap();
+			let mut page = disk.allocate_page().unwrap();
+			assert_eq!(page.size(), page_size - COMPRESSED_HEADER_SIZE);
+			let last = page.data.len() - 1;
+			page.data[last] = 42;
+			disk.flush_page(&page).unwrap();
+
+			// the page size comes from the header when reopened
+			let f = vfs.open(path.as_ref(), OpenFlags::default()).unwrap();
+			let mut disk = DiskManager::new(f, None, None).unwrap();
+			assert_eq!(disk.page_size(), page_size - COMPRESSED_HEADER_SIZE);
+			assert_eq!(disk.n_pages(), 2);
+			assert_eq!(disk.read_page(1).unwrap().data[last], 42);
+		}
+
+		for page_size in [2_048, 12_288, 131_072] {
+			let f = vfs
+				.open("bad.ldb".as_ref(), OpenFlags::new().create(true))
+				.unwrap();
+			assert!(matches!(
+				DiskManager::init_db(f, page_size, Compression::None, None, None),
+				Err(Error::InvalidArgument(_))
+			));
+		}
+
+		let f = vfs
+			.open("garbage.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		f.write_at(&[7u8; 100], 0).unwrap();
+		assert!(DiskManager::new(f, None, None).is_err());
+	}
+
+	#[test]
+	fn options() {
+		let vfs = FaultyVfs::new(MemoryVfs::new());
+		let faults = vfs.faults();
+		let f = vfs
+			.open("a.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		let mut disk = DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None)
+			.unwrap()
+			.with_cache_size(1)
+			.with_synchronous(Synchronous::Full);
+		let mut page = disk.allocate_page().unwrap();
+
+		// every write syncs
+		faults.fail_syncs_after(0);
+		assert!(matches!(disk.flush_page(&page), Err(Error::Io(_))));
+		faults.heal();
+
+		// cached pages skip the file
+		page.data[0] = 1;
+		disk.flush_page(&page).unwrap();
+		faults.fail_reads_after(0);
+		assert_eq!(disk.read_page(1).unwrap().data[0], 1);
+		assert!(disk.read_page(0).is_err());
+		faults.heal();
+
+		let mut disk = disk.with_synchronous(Synchronous::Off).with_read_only(true);
+		faults.fail_syncs_after(0);
+		disk.sync().unwrap();
+		assert!(matches!(disk.flush_page(&page), Err(Error::ReadOnly)));
+		assert!(matches!(disk.allocate_page(), Err(Error::ReadOnly)));
+	}
+
+	#[test]
+	fn busy_timeout() {
+		let vfs = MemoryVfs::new();
+		let f = vfs
+			.open("a.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		let timeout = Some(Duration::from_millis(20));
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, timeout).unwrap();
+
+		let other = vfs.open("a.ldb".as_ref(), OpenFlags::new()).unwrap();
+		other.lock(LockKind::Exclusive).unwrap();
+		let start = Instant::now();
+		assert!(matches!(disk.read_page(0), Err(Error::Busy)));
+		assert!(start.elapsed() >= Duration::from_millis(20));
+
+		other.unlock().unwrap();
+		disk.read_page(0).unwrap();
+	}
+
+	#[test]
+	fn copy_to() {
+		let vfs = MemoryVfs::new();
+		let mut disk = DiskManager::temp("copy_to");
+		let mut page = disk.allocate_page().unwrap();
+		page.data[0] = 42;
+		disk.flush_page(&page).unwrap();
+
+		let dest = vfs
+			.open("b.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		dest.write_at(&[1u8; 3 * DEFAULT_PAGE_SIZE], 0).unwrap();
+		disk.copy_to(dest).unwrap();
+
+		let dest = vfs.open("b.ldb".as_ref(), OpenFlags::default()).unwrap();
+		let mut copy = DiskManager::new(dest, None, None).unwrap();
+		assert_eq!(copy.n_pages, 2);
+		assert_eq!(copy.read_page(1).unwrap().data[0], 42);
+	}
+
+	#[test]
+	fn rollback_journal() {
+		let vfs = MemoryVfs::new();
+		let f = vfs
+			.open("a.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+		let journal = || {
+			vfs.open("a.ldb-journal".as_ref(), OpenFlags::new().create(true))
+				.unwrap()
+		};
+		let mut page = disk.allocate_page().unwrap();
+		page.data[0] = 1;
+		disk.flush_page(&page).unwrap();
+		let write = |disk: &mut DiskManager| {
+			let mut page = disk.read_page(1).unwrap();
+			page.data[0] = 2;
+			disk.flush_page(&page).unwrap();
+			disk.flush_page(&page).unwrap();
+			let mut new = disk.allocate_page().unwrap();
+			new.data[0] = 3;
+			disk.flush_pages(&[page, new]).unwrap();
+			disk.set_catalog_root(2).unwrap();
+		};
+
+		disk.begin(journal()).unwrap();
+		assert!(matches!(
+			disk.begin(journal()),
+			Err(Error::InvalidArgument(_))
+		));
+		write(&mut disk);
+		disk.rollback().unwrap();
+		assert_eq!(disk.n_pages(), 2);
+		assert_eq!(disk.read_page(1).unwrap().data[0], 1);
+		assert_eq!(disk.catalog_root().unwrap(), None);
+
+		disk.begin(journal()).unwrap();
+		write(&mut disk);
+		disk.commit().unwrap();
+		assert_eq!(disk.n_pages(), 3);
+		assert_eq!(disk.catalog_root().unwrap(), Some(2));
+		assert!(!disk.recover(journal()).unwrap());
+
+		// a crash mid-transaction leaves the journal behind, while a journal still being written is left alone
+		disk.begin(journal()).unwrap();
+		let mut page = disk.read_page(2).unwrap();
+		page.data[0] = 4;
+		disk.flush_page(&page).unwrap();
+		disk.allocate_page().unwrap();
+		let f = vfs.open("a.ldb".as_ref(), OpenFlags::default()).unwrap();
+		let mut other = DiskManager::new(f, None, None).unwrap();
+		assert!(!other.recover(journal()).unwrap());
+		drop(disk);
+		// an entry cut short by the crash is ignored
+		let torn = journal();
+		let len = torn.len().unwrap();
+		torn.write_at(&[7u8; 100], len).unwrap();
+
+		let f = vfs.open("a.ldb".as_ref(), OpenFlags::default()).unwrap();
+		let mut disk = DiskManager::new(f, None, None).unwrap();
+		assert!(disk.recover(journal()).unwrap());
+		assert_eq!(disk.n_pages(), 3);
+		assert_eq!(disk.read_page(2).unwrap().data[0], 3);
+		assert_eq!(disk.catalog_root().unwrap(), Some(2));
+		assert!(!disk.recover(journal()).unwrap());
+	}
+
+	#[test]
+	fn mmap_reads() {
+		let path = util::test_file!();
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+		let mapped = disk.enable_mmap().unwrap();
+		assert_eq!(mapped, cfg!(unix));
+
+		// pages allocated after mapping force a remap
+		for i in 1..10u8 {
+			let mut page = disk.allocate_page().unwrap();
+			page.data[0] = i;
+			disk.flush_page(&page).unwrap();
+			assert_eq!(disk.read_page(page.id).unwrap().data[0], i);
+		}
+		// as do writes to pages already mapped
+		let mut page = disk.read_page(3).unwrap();
+		page.data[1] = 7;
+		disk.flush_page(&page).unwrap();
+		assert_eq!(disk.read_page(3).unwrap().data[1], 7);
+		disk.sync().unwrap();
+
+		let ids = [9, 2, 5];
+		let pages = disk.read_pages(&ids).unwrap();
+		assert!(
+			pages
+				.iter()
+				.zip(ids)
+				.all(|(p, id)| p.id == id && p.data[0] == id as u8)
+		);
+	}
+
+	#[test]
+	fn mmap_after_shrink() {
+		let path = util::test_file!();
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+		for i in 1..10u8 {
+			let mut page = disk.allocate_page().unwrap();
+			page.data[0] = i;
+			disk.flush_page(&page).unwrap();
+		}
+		disk.enable_mmap().unwrap();
+		assert_eq!(disk.read_page(9).unwrap().data[0], 9);
+
+		// another connection truncating the file makes reads past its end fail, instead of faulting on the mapping
+		let other = OsVfs.open(&path, OpenFlags::new()).unwrap();
+		other.set_len(disk.offset(6)).unwrap();
+		assert_eq!(disk.read_page(5).unwrap().data[0], 5);
+		assert!(matches!(disk.read_page(8), Err(Error::Io(_))));
+		assert!(matches!(disk.read_pages(&[2, 7]), Err(Error::Io(_))));
+
+		// as does truncating it through this connection
+		disk.file.set_len(disk.offset(3)).unwrap();
+		assert_eq!(disk.read_page(2).unwrap().data[0], 2);
+		assert!(matches!(disk.read_page(4), Err(Error::Io(_))));
+	}
+
+	#[test]
+	#[cfg(feature = "encryption")]
+	fn encryption() {
+		let vfs = MemoryVfs::new();
+		let path: &std::path::Path = "secret.ldb".as_ref();
+		let key = [7u8; 32];
+		let f = vfs.open(path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::Fast, Some(key), None).unwrap();
+		let mut page = disk.allocate_page().unwrap();
+		page.data[..12].copy_from_slice(b"customer pii");
+		disk.flush_page(&page).unwrap();
+		assert!(
+			!vfs.contents(path)
+				.unwrap()
+				.windows(8)
+				.any(|w| w == b"customer")
+		);
+
+		let open = |key| DiskManager::new(vfs.open(path, OpenFlags::default()).unwrap(), key, None);
+		assert!(matches!(open(None), Err(Error::InvalidArgument(_))));
+		assert!(matches!(
+			open(Some([8u8; 32])),
+			Err(Error::InvalidArgument(_))
+		));
+		let mut disk = open(Some(key)).unwrap();
+		assert_eq!(disk.read_page(1).unwrap().data, page.data);
+
+		// every open reserves new LSNs before writing
+		disk.flush_page(&page).unwrap();
+		let header = disk.read_page(0).unwrap();
+		assert_eq!(header.data[14..18], 2u32.to_le_bytes());
+
+		let new_key = [9u8; 32];
+		disk.rekey(new_key).unwrap();
+		assert!(matches!(open(Some(key)), Err(Error::InvalidArgument(_))));
+		let mut disk = open(Some(new_key)).unwrap();
+		assert_eq!(disk.read_page(1).unwrap().data, page.data);
+
+		// tampering is caught
+		let f = vfs.open(path, OpenFlags::default()).unwrap();
+		f.write_at(&[0xaa], disk.offset(1) + 40).unwrap();
+		assert!(matches!(disk.read_page(1), Err(Error::Corruption(_))));
+		// in the file header too, such as pointing the catalog somewhere else
+		f.write_at(&[0xaa], (page::HEADER_SIZE + REKEY_END) as u64)
+			.unwrap();
+		assert!(matches!(open(Some(new_key)), Err(Error::Corruption(_))));
+
+		let mut plain = DiskManager::temp("plain");
+		assert!(matches!(plain.rekey(key), Err(Error::InvalidArgument(_))));
+	}
+
+	#[test]
+	#[cfg(feature = "encryption")]
+	fn concurrent_lsn_reservations() {
+		let path = util::test_file!();
+		let key = [7u8; 32];
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, Some(key), None).unwrap();
+
+		// both connections keep reserving epochs at once, and must never be handed the same one
+		let barrier = std::sync::Barrier::new(2);
+		let reserve = || {
+			let f = OsVfs.open(&path, OpenFlags::default()).unwrap();
+			let mut disk = DiskManager::new(f, Some(key), None).unwrap();
+			barrier.wait();
+			(0..200)
+				.map(|_| {
+					disk.lsn_end = disk.lsn;
+					disk.next_lsn().unwrap() / LSN_EPOCH_LEN
+				})
+				.collect::<Vec<_>>()
+		};
+		let epochs: Vec<u64> = thread::scope(|s| {
+			let threads = [s.spawn(reserve), s.spawn(reserve)];
+			threads
+				.into_iter()
+				.flat_map(|t| t.join().unwrap())
+				.collect()
+		});
+		let mut unique = epochs.clone();
+		unique.sort_unstable();
+		unique.dedup();
+		assert_eq!(unique.len(), epochs.len());
+		// creating the database reserved the first epoch, to authenticate the header with
+		assert_eq!(unique, (2..=401).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn failed_allocation() {
+		let vfs = FaultyVfs::new(MemoryVfs::new());
+		let faults = vfs.faults();
+		let f = vfs
+			.open("alloc.ldb".as_ref(), OpenFlags::new().create(true))
+			.unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+		let n_pages = disk.n_pages();
+
+		faults.fail_writes_after(0);
+		assert!(matches!(disk.allocate_page(), Err(Error::Io(_))));
+		assert_eq!(disk.n_pages(), n_pages);
+		assert!(matches!(disk.read_page(n_pages), Err(Error::Corruption(_))));
+
+		faults.heal();
+		assert_eq!(disk.allocate_page().unwrap().id, n_pages);
+		assert_eq!(disk.n_pages(), n_pages + 1);
+	}
+
+	#[test]
+	fn reserved_pages() {
+		let vfs = MemoryVfs::new();
+		let path: &std::path::Path = "reserved.ldb".as_ref();
+		let f = vfs.open(path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, None, None).unwrap();
+		let mut pages = vec![disk.reserve_page().unwrap(), disk.reserve_page().unwrap()];
+		assert_eq!(pages[1].id, 2);
+		// nothing is written until the pages are flushed
+		assert_eq!(vfs.contents(path).unwrap().len(), DEFAULT_PAGE_SIZE);
+		assert!(disk.read_page(2).is_err());
+
+		pages[1].data[0] = 7;
+		disk.flush_pages(&pages).unwrap();
+		assert_eq!(vfs.contents(path).unwrap().len(), 3 * DEFAULT_PAGE_SIZE);
+		assert_eq!(disk.read_page(2).unwrap().data, pages[1].data);
+	}
+
+	#[test]
+	#[cfg(feature = "encryption")]
+	fn interrupted_rekey() {
+		let vfs = FaultyVfs::new(MemoryVfs::new());
+		let faults = vfs.faults();
+		let path: &std::path::Path = "secret.ldb".as_ref();
+		let (old_key, new_key) = ([7u8; 32], [9u8; 32]);
+		let f = vfs.open(path, OpenFlags::new().create(true)).unwrap();
+		let mut disk =
+			DiskManager::init_db(f, DEFAULT_PAGE_SIZE, Compression::None, Some(old_key), None)
+				.unwrap();
+		let mut pages = Vec::new();
+		for i in 0..10u8 {
+			let mut page = disk.allocate_page().unwrap();
+			page.data[0] = i;
+			disk.flush_page(&page).unwrap();
+			pages.push(page);
+		}
+
+		// fails after the header and a few pages are written
+		faults.fail_writes_after(5);
+		assert!(matches!(disk.rekey(new_key), Err(Error::Io(_))));
+		faults.heal();
+		drop(disk);
+
+		let open = |key| {
+			DiskManager::new(
+				vfs.open(path, OpenFlags::default()).unwrap(),
+				Some(key),
+				None,
+			)
+		};
+		for key in [old_key, new_key] {
+			let mut disk = open(key).unwrap();
+			assert!(disk.old_key.is_some());
+			for page in &pages {
+				assert_eq!(disk.read_page(page.id).unwrap().data, page.data);
+			}
+		}
+		assert!(matches!(open([8u8; 32]), Err(Error::InvalidArgument(_))));
+
+		open(old_key).unwrap().finish_rekey().unwrap();
+		assert!(matches!(open(old_key), Err(Error::InvalidArgument(_))));
+		let mut disk = open(new_key).unwrap();
+		assert!(disk.old_key.is_none());
+		for page in &pages {
+			assert_eq!(disk.read_page(page.id).unwrap().data, page.data);
+		}
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn compression() {
+		use std::os::unix::fs::MetadataExt;
+
+		let path = util::test_file!();
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		let mut disk = DiskManager::init_db(f, 65_536, Compression::Fast, None, None).unwrap();
+		assert_eq!(disk.page_size(), 65_536 - COMPRESSED_HEADER_SIZE);
+
+		let line = b"GET /api/users 200 12ms\n";
+		let mut pages = Vec::new();
+		for _ in 0..8 {
+			let mut page = disk.allocate_page().unwrap();
+			for (i, b) in page.data.iter_mut().enumerate() {
+				*b = line[i % line.len()];
+			}
+			pages.push(page);
+		}
+		disk.flush_pages(&pages).unwrap();
+		// an incompressible page is stored as is
+		let mut noisy = disk.allocate_page().unwrap();
+		let mut state = 1u64;
+		for b in noisy.data.iter_mut() {
+			state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+			*b = (state >> 56) as u8;
+		}
+		disk.flush_page(&noisy).unwrap();
+		let stats = disk.compression_stats().unwrap();
+		assert_eq!(stats.pages, 9);
+		assert!(stats.ratio() > 1.5);
+		// the noisy page alone doesn't shrink
+		assert!(stats.stored_bytes > stats.page_bytes / 9);
+
+		// pages written without compression share the file with compressed ones
+		disk.set_compression(Some(Compression::None));
+		let mut plain = disk.allocate_page().unwrap();
+		plain.data.copy_from_slice(&pages[0].data);
+		disk.flush_page(&plain).unwrap();
+		disk.set_compression(None);
+		let plain_stats = disk.compression_stats().unwrap();
+		assert_eq!(
+			plain_stats.stored_bytes - stats.stored_bytes,
+			disk.slot_size as u64
+		);
+
+		let f = OsVfs.open(&path, OpenFlags::default()).unwrap();
+		let mut disk = DiskManager::new(f, None, None).unwrap();
+		assert_eq!(disk.n_pages(), 11);
+		assert_eq!(disk.read_page(1).unwrap().data, pages[0].data);
+		assert_eq!(disk.read_page(10).unwrap().data, pages[0].data);
+		let read = disk.read_pages(&[8, 9]).unwrap();
+		assert_eq!(read[0].data, pages[7].data);
+		assert_eq!(read[1].data, noisy.data);
+
+		// garbled slots are reported as corruption
+		let f = OsVfs.open(&path, OpenFlags::default()).unwrap();
+		f.write_at(&[CODEC_LZ, 0xff, 0xff, 0x00], disk.offset(3))
+			.unwrap();
+		assert!(matches!(disk.read_page(3), Err(Error::Corruption(_))));
+
+		if !punches_holes() {
+			eprintln!("skipping the hole check, as the file system can't punch holes");
+			return;
+		}
+		// the compressible pages left holes behind, so less is allocated than the file's length
+		let allocated = std::fs::metadata(&path).unwrap().blocks() * 512;
+		let len = std::fs::metadata(&path).unwrap().len();
+		assert!(allocated + 8 * (disk.slot_size as u64 / 2) < len);
+	}
+
+	/// Whether punching a hole in a file under `test_artifacts` frees its blocks
+	#[cfg(unix)]
+	fn punches_holes() -> bool {
+		use std::os::unix::fs::MetadataExt;
+
+		let path = util::test_file!();
+		let f = OsVfs.open(&path, OpenFlags::new().create(true)).unwrap();
+		f.write_at(&[1u8; 65_536], 0).unwrap();
+		f.sync().unwrap();
+		let before = std::fs::metadata(&path).unwrap().blocks();
+		f.punch_hole(0, 65_536).unwrap();
+		std::fs::metadata(&path).unwrap().blocks() < before
 	}
 }
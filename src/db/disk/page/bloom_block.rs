@@ -0,0 +1,34 @@
+use super::HEADER_SIZE;
+
+/// Number of bits in a block, for a page size
+pub const fn block_bits(page_size: usize) -> u32 {
+	((page_size - HEADER_SIZE) * 8) as u32
+}
+
+/// Wrapper around page, with methods to manage one block of a Bloom filter
+///
+/// The whole of the page's data is used as a bit array
+pub struct BloomBlockPageView<'a> {
+	data: &'a mut [u8],
+}
+impl<'a> BloomBlockPageView<'a> {
+	/// Opens a `BloomBlockPageView` on a page's data
+	pub fn new(data: &'a mut [u8]) -> BloomBlockPageView<'a> {
+		BloomBlockPageView { data }
+	}
+
+	/// Clears every bit
+	pub fn clear(&mut self) {
+		self.data.fill(0);
+	}
+
+	#[inline]
+	pub fn get(&self, bit: u32) -> bool {
+		self.data[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+	}
+
+	#[inline]
+	pub fn set(&mut self, bit: u32) {
+		self.data[(bit / 8) as usize] |= 1 << (bit % 8);
+	}
+}
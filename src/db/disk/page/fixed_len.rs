@@ -1,4 +1,3 @@
-use super::DATA_SIZE;
 use crate::{db::record::*, *};
 
 const FREE_MARKER: u8 = 0;
@@ -12,7 +11,7 @@ const OCCUPIED_MARKER: u8 = 1;
 /// 0          2               ^ records_offset
 /// ```
 pub struct FixedLenPageView<'a> {
-	data: &'a mut [u8; DATA_SIZE],
+	data: &'a mut [u8],
 	schema: &'a Schema,
 	n_slots: u16,
 	rec_size: u16,
@@ -20,13 +19,13 @@ pub struct FixedLenPageView<'a> {
 }
 impl<'a> FixedLenPageView<'a> {
 	/// Opens a `FixedLenPage` view on a page's data
-	pub fn new(data: &'a mut [u8; DATA_SIZE], schema: &'a Schema) -> Result<FixedLenPageView<'a>> {
+	pub fn new(data: &'a mut [u8], schema: &'a Schema) -> Result<FixedLenPageView<'a>> {
 		let Some(rec_size) = schema.size() else {
 			return Err(Error::Internal(
 				"Attempted to instantiate fixed len page with non-fixed len schema".to_string(),
 			));
 		};
-		let n_slots = (DATA_SIZE as u16 - 2) / (rec_size + 1);
+		let n_slots = ((data.len() - 2) / (rec_size as usize + 1)) as u16;
 		let records_offset = 2 + n_slots;
 		Ok(FixedLenPageView {
 			data,
@@ -45,8 +44,8 @@ impl<'a> FixedLenPageView<'a> {
 	/// Attempts to insert a record into this page, returning `Ok(None)` if there is no space, or the slot number if insertion was successful
 	///
 	/// **WARNING**: This function assumes the record conforms to the configured schema
-	pub fn insert_record(&mut self, rec: Record) -> Result<Option<u16>> {
-		debug_assert!(self.schema.validate(&rec));
+	pub fn insert_record(&mut self, rec: &Record) -> Result<Option<u16>> {
+		debug_assert!(self.schema.validate(rec));
 
 		let free_slots = self.get_free_slots();
 		if free_slots == 0 {
@@ -64,36 +63,65 @@ impl<'a> FixedLenPageView<'a> {
 		self.set_free_slots(free_slots - 1);
 
 		// write record data
-		let rec_bytes = rec.to_bytes();
+		let rec_bytes = rec.to_padded_bytes(self.rec_size as usize);
 		debug_assert_eq!(rec_bytes.len() as u16, self.rec_size);
 		let offset = (self.records_offset + (slot * self.rec_size)) as usize;
-		if offset + rec_bytes.len() > DATA_SIZE {
+		if offset + rec_bytes.len() > self.data.len() {
 			return Err(Error::Internal(
 				"Out of bounds page fixed-len record write".to_string(),
 			));
 		}
-		self.data[offset..(offset + rec_bytes.len())].copy_from_slice(&rec_bytes.as_slice());
+		self.data[offset..(offset + rec_bytes.len())].copy_from_slice(rec_bytes.as_slice());
 
 		Ok(Some(slot))
 	}
 
-	/// Gets a record from the page with the assumption that it exists
-	pub fn retrieve_record(&mut self, slot: u16) -> Result<Record> {
-		debug_assert!(!self.is_slot_free(slot));
-
-		// getting bytes
+	/// Reads a record without removing it, returning `None` if the slot is empty
+	pub fn read_record(&self, slot: u16) -> Option<Record> {
+		if slot >= self.n_slots || self.is_slot_free(slot) {
+			return None;
+		}
 		let offset = (self.records_offset + (slot * self.rec_size)) as usize;
-		let mut rec_bytes = vec![0u8; self.rec_size as usize];
-		rec_bytes.copy_from_slice(&self.data[offset..(offset + (self.rec_size as usize))]);
+		Some(Record::from_bytes(
+			&self.data[offset..(offset + (self.rec_size as usize))],
+			self.schema,
+		))
+	}
 
-		// building record
-		let rec = Record::from_bytes(rec_bytes.as_slice(), self.schema);
+	/// Overwrites the record in a slot, returning `false` if the slot is empty
+	///
+	/// **WARNING**: This function assumes the record conforms to the configured schema
+	pub fn update_record(&mut self, slot: u16, rec: &Record) -> bool {
+		debug_assert!(self.schema.validate(rec));
+		if slot >= self.n_slots || self.is_slot_free(slot) {
+			return false;
+		}
+		let offset = (self.records_offset + (slot * self.rec_size)) as usize;
+		self.data[offset..(offset + (self.rec_size as usize))]
+			.copy_from_slice(&rec.to_padded_bytes(self.rec_size as usize));
+		true
+	}
 
-		// marking as free
+	/// Frees a slot, returning `false` if it was already empty
+	pub fn delete_record(&mut self, slot: u16) -> bool {
+		if slot >= self.n_slots || self.is_slot_free(slot) {
+			return false;
+		}
 		self.set_slot_free(slot);
 		self.set_free_slots(self.get_free_slots() + 1);
+		true
+	}
+
+	/// Whether every slot holds a record
+	pub fn is_full(&self) -> bool {
+		self.get_free_slots() == 0
+	}
 
-		return Ok(rec);
+	/// Lists every slot currently holding a record
+	pub fn occupied_slots(&self) -> Vec<u16> {
+		(0..self.n_slots)
+			.filter(|slot| !self.is_slot_free(*slot))
+			.collect()
 	}
 
 	#[inline]
@@ -128,12 +156,13 @@ mod tests {
 	use crate::db::disk::page::Page;
 
 	#[test]
+	#[allow(clippy::drop_non_drop)]
 	pub fn insert_and_retrieve() {
 		let schema = Schema::new()
 			.with(ValueType::U32)
 			.with(ValueType::U32)
 			.with(ValueType::I32);
-		let mut page = Page::new_empty(0);
+		let mut page = Page::new_empty(0, DEFAULT_PAGE_SIZE);
 		let mut view = FixedLenPageView::new(&mut page.data, &schema)
 			.expect("Failed to create fixed len page view");
 		view.init();
@@ -148,7 +177,7 @@ mod tests {
 				.item(Value::U32(i * 2))
 				.item(Value::I32(-(i as i32)));
 			println!("Trying to insert record {rec:?}");
-			if let Some(slot) = view.insert_record(rec).expect("Insertion failed") {
+			if let Some(slot) = view.insert_record(&rec).expect("Insertion failed") {
 				inserted.push(slot);
 				println!("\tInserted into slot {slot}");
 			} else {
@@ -167,9 +196,8 @@ mod tests {
 		i = 0;
 		for slot in inserted {
 			println!("Retreiving record in slot {slot}");
-			let rec = view
-				.retrieve_record(slot)
-				.expect("Failed to retreive record");
+			let rec = view.read_record(slot).expect("Failed to retreive record");
+			assert!(view.delete_record(slot));
 			println!("\tGot {rec:?}");
 			assert_eq!(
 				rec,
@@ -180,5 +208,6 @@ mod tests {
 			);
 			i += 1;
 		}
+		assert_eq!(view.get_free_slots(), n_slots);
 	}
 }
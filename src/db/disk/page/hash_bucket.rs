@@ -0,0 +1,181 @@
+use super::RecordId;
+use crate::{db::record::*, *};
+
+const HEADER_SIZE: usize = 3;
+
+/// Wrapper around page, with methods to manage a bucket of an extendible hash index
+///
+/// Data layout:
+/// ```txt
+/// |local_depth|n_entries|entry1|entry2|...
+/// 0           1         3
+/// ```
+/// Where each entry is a fixed length key, a fixed length payload (which may be empty), then the `RecordId` it points
+/// to. Entries are kept packed, so removing an entry moves the last one into its place.
+pub struct HashBucketPageView<'a> {
+	data: &'a mut [u8],
+	key_schema: &'a Schema,
+	payload_schema: &'a Schema,
+	key_size: usize,
+	payload_size: usize,
+	entry_size: usize,
+	capacity: u16,
+}
+impl<'a> HashBucketPageView<'a> {
+	/// Opens a `HashBucketPageView` on a page's data
+	pub fn new(
+		data: &'a mut [u8],
+		key_schema: &'a Schema,
+		payload_schema: &'a Schema,
+	) -> Result<HashBucketPageView<'a>> {
+		let (Some(key_size), Some(payload_size)) = (key_schema.size(), payload_schema.size())
+		else {
+			return Err(Error::Internal(
+				"Attempted to instantiate hash bucket page with non-fixed len schema".to_string(),
+			));
+		};
+		let key_size = key_size as usize;
+		let payload_size = payload_size as usize;
+		let entry_size = key_size + payload_size + RecordId::SIZE;
+		let capacity = ((data.len() - HEADER_SIZE) / entry_size) as u16;
+		Ok(HashBucketPageView {
+			data,
+			key_schema,
+			payload_schema,
+			key_size,
+			payload_size,
+			entry_size,
+			capacity,
+		})
+	}
+
+	/// Initialize a page as an empty bucket
+	pub fn init(&mut self, local_depth: u8) {
+		self.set_local_depth(local_depth);
+		self.set_len(0);
+	}
+
+	#[inline]
+	pub fn local_depth(&self) -> u8 {
+		self.data[0]
+	}
+
+	#[inline]
+	pub fn set_local_depth(&mut self, depth: u8) {
+		self.data[0] = depth;
+	}
+
+	#[inline]
+	pub fn len(&self) -> u16 {
+		u16::from_le_bytes(self.data[1..3].try_into().unwrap())
+	}
+
+	#[inline]
+	fn set_len(&mut self, len: u16) {
+		self.data[1..3].copy_from_slice(&len.to_le_bytes());
+	}
+
+	#[inline]
+	pub fn is_full(&self) -> bool {
+		self.len() >= self.capacity
+	}
+
+	/// Attempts to add an entry, returning `false` if the bucket is full
+	///
+	/// **WARNING**: This function assumes the key and payload conform to the configured schemas
+	pub fn insert(&mut self, key: &Record, payload: &Record, rid: RecordId) -> bool {
+		debug_assert!(self.key_schema.validate(key));
+		debug_assert!(self.payload_schema.validate(payload));
+		if self.is_full() {
+			return false;
+		}
+		let len = self.len();
+		let offset = self.entry_offset(len);
+		let key_bytes = key.to_padded_bytes(self.key_size);
+		let payload_bytes = payload.to_padded_bytes(self.payload_size);
+		debug_assert_eq!(key_bytes.len(), self.key_size);
+		debug_assert_eq!(payload_bytes.len(), self.payload_size);
+		let payload_offset = offset + self.key_size;
+		let rid_offset = payload_offset + self.payload_size;
+		self.data[offset..payload_offset].copy_from_slice(&key_bytes);
+		self.data[payload_offset..rid_offset].copy_from_slice(&payload_bytes);
+		self.data[rid_offset..(offset + self.entry_size)].copy_from_slice(&rid.to_bytes());
+		self.set_len(len + 1);
+		true
+	}
+
+	/// Finds the payload and record ID of every entry with a matching key
+	pub fn find(&self, key: &Record) -> Vec<(Record, RecordId)> {
+		let key_bytes = key.to_padded_bytes(self.key_size);
+		(0..self.len())
+			.filter(|i| self.key_bytes(*i) == key_bytes.as_slice())
+			.map(|i| (self.payload(i), self.rid(i)))
+			.collect()
+	}
+
+	/// Removes the entry matching both key and record ID, returning `false` if there was none
+	pub fn remove(&mut self, key: &Record, rid: RecordId) -> bool {
+		let key_bytes = key.to_padded_bytes(self.key_size);
+		let Some(i) = (0..self.len())
+			.find(|i| self.key_bytes(*i) == key_bytes.as_slice() && self.rid(*i) == rid)
+		else {
+			return false;
+		};
+
+		// move the last entry into the gap
+		let last = self.len() - 1;
+		if i != last {
+			let src = self.entry_offset(last);
+			let dst = self.entry_offset(i);
+			self.data.copy_within(src..(src + self.entry_size), dst);
+		}
+		self.set_len(last);
+		true
+	}
+
+	/// Reads every entry in the bucket
+	pub fn entries(&self) -> Vec<(Record, Record, RecordId)> {
+		(0..self.len())
+			.map(|i| {
+				(
+					Record::from_bytes(self.key_bytes(i), self.key_schema),
+					self.payload(i),
+					self.rid(i),
+				)
+			})
+			.collect()
+	}
+
+	/// Removes and returns every entry in the bucket
+	pub fn drain(&mut self) -> Vec<(Record, Record, RecordId)> {
+		let entries = self.entries();
+		self.set_len(0);
+		entries
+	}
+
+	#[inline]
+	fn entry_offset(&self, i: u16) -> usize {
+		HEADER_SIZE + (i as usize) * self.entry_size
+	}
+
+	#[inline]
+	fn key_bytes(&self, i: u16) -> &[u8] {
+		let offset = self.entry_offset(i);
+		&self.data[offset..(offset + self.key_size)]
+	}
+
+	#[inline]
+	fn payload(&self, i: u16) -> Record {
+		let offset = self.entry_offset(i) + self.key_size;
+		Record::from_bytes(
+			&self.data[offset..(offset + self.payload_size)],
+			self.payload_schema,
+		)
+	}
+
+	#[inline]
+	fn rid(&self, i: u16) -> RecordId {
+		let offset = self.entry_offset(i) + self.key_size + self.payload_size;
+		RecordId::from_bytes(&self.data[offset..(offset + RecordId::SIZE)])
+	}
+}
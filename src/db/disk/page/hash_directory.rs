@@ -0,0 +1,143 @@
+use super::PageId;
+use crate::util::slice_to_array;
+
+/// Wrapper around page, with methods to manage the root of an extendible hash index's directory
+///
+/// Data layout:
+/// ```txt
+/// |global_depth|n_pages|page0|page1|...
+/// 0            1       3
+/// ```
+/// Where the `2^global_depth` bucket page IDs of the directory are spread over `n_pages` slot pages, in order, each
+/// holding as many slots as `HashSlotsPageView::capacity` gives.
+pub struct HashDirectoryPageView<'a> {
+	data: &'a mut [u8],
+}
+impl<'a> HashDirectoryPageView<'a> {
+	/// Opens a `HashDirectoryPageView` on a page's data
+	pub fn new(data: &'a mut [u8]) -> HashDirectoryPageView<'a> {
+		HashDirectoryPageView { data }
+	}
+
+	/// Initialize a page as a directory with a single slot page
+	pub fn init(&mut self, slots: PageId) {
+		self.set_global_depth(0);
+		self.set_n_pages(0);
+		self.push_page(slots);
+	}
+
+	#[inline]
+	pub fn global_depth(&self) -> u8 {
+		self.data[0]
+	}
+
+	#[inline]
+	pub fn set_global_depth(&mut self, depth: u8) {
+		self.data[0] = depth;
+	}
+
+	/// Deepest the directory can get while its slot pages still fit in the root
+	#[inline]
+	pub fn max_global_depth(&self) -> u8 {
+		let max_pages = (self.data.len() - 3) / size_of::<PageId>();
+		(max_pages.ilog2() + slots_per_page(self.data.len()).ilog2()) as u8
+	}
+
+	/// Number of slots in the directory
+	#[inline]
+	pub fn len(&self) -> usize {
+		1 << self.global_depth()
+	}
+
+	/// Number of slots each slot page holds, the same as `HashSlotsPageView::capacity`
+	#[inline]
+	pub fn slots_per_page(&self) -> usize {
+		slots_per_page(self.data.len())
+	}
+
+	/// Gets the slot that a hash maps to
+	#[inline]
+	pub fn slot_for(&self, hash: u64) -> usize {
+		(hash & ((1 << self.global_depth()) - 1)) as usize
+	}
+
+	#[inline]
+	pub fn n_pages(&self) -> usize {
+		u16::from_le_bytes(slice_to_array(&self.data[1..3])) as usize
+	}
+
+	#[inline]
+	fn set_n_pages(&mut self, n: usize) {
+		self.data[1..3].copy_from_slice(&(n as u16).to_le_bytes());
+	}
+
+	/// ID of the `i`th slot page
+	#[inline]
+	pub fn page(&self, i: usize) -> PageId {
+		let offset = 3 + i * size_of::<PageId>();
+		PageId::from_le_bytes(slice_to_array(
+			&self.data[offset..(offset + size_of::<PageId>())],
+		))
+	}
+
+	/// Adds a slot page after the others
+	pub fn push_page(&mut self, id: PageId) {
+		let n = self.n_pages();
+		let offset = 3 + n * size_of::<PageId>();
+		self.data[offset..(offset + size_of::<PageId>())].copy_from_slice(&id.to_le_bytes());
+		self.set_n_pages(n + 1);
+	}
+}
+
+/// Wrapper around page, with methods to manage a page of an extendible hash index directory's slots
+///
+/// Data layout:
+/// ```txt
+/// |bucket0|bucket1|...
+/// 0
+/// ```
+/// Where the number of bucket page IDs is the largest power of two that fits, so a directory of any depth either fits in
+/// the first slot page or fills whole slot pages.
+pub struct HashSlotsPageView<'a> {
+	data: &'a mut [u8],
+}
+impl<'a> HashSlotsPageView<'a> {
+	/// Opens a `HashSlotsPageView` on a page's data
+	pub fn new(data: &'a mut [u8]) -> HashSlotsPageView<'a> {
+		HashSlotsPageView { data }
+	}
+
+	/// Number of slots that fit in a page
+	#[inline]
+	pub fn capacity(&self) -> usize {
+		slots_per_page(self.data.len())
+	}
+
+	#[inline]
+	pub fn bucket(&self, slot: usize) -> PageId {
+		let offset = slot * size_of::<PageId>();
+		PageId::from_le_bytes(slice_to_array(
+			&self.data[offset..(offset + size_of::<PageId>())],
+		))
+	}
+
+	#[inline]
+	pub fn set_bucket(&mut self, slot: usize, bucket: PageId) {
+		let offset = slot * size_of::<PageId>();
+		self.data[offset..(offset + size_of::<PageId>())].copy_from_slice(&bucket.to_le_bytes());
+	}
+
+	/// Doubles the first `len` slots, with each new slot pointing to the same bucket as its mirror
+	///
+	/// **WARNING**: Assumes twice `len` slots fit in the page
+	pub fn double(&mut self, len: usize) {
+		debug_assert!(len * 2 <= self.capacity());
+		let end = len * size_of::<PageId>();
+		self.data.copy_within(0..end, end);
+	}
+}
+
+#[inline]
+fn slots_per_page(data_len: usize) -> usize {
+	1 << (data_len / size_of::<PageId>()).ilog2()
+}
@@ -0,0 +1,120 @@
+use crate::{db::record::*, util::slice_to_array, *};
+
+const HEADER_SIZE: usize = 6;
+/// Size of the key and tombstone marker preceding each record
+const ENTRY_HEADER_SIZE: usize = 9;
+
+const TOMBSTONE_MARKER: u8 = 0;
+const LIVE_MARKER: u8 = 1;
+
+/// Wrapper around page, with methods to manage one page of the log an LSM tree's memtable writes are appended to
+///
+/// Data layout:
+/// ```txt
+/// |generation|n_entries|entry1|entry2|...
+/// 0          4         6
+/// ```
+/// Where each entry is `|key|marker|record...|`, in the order they were written. Tombstones still take up a full entry,
+/// with the record bytes left zeroed. Only pages of the log's current generation hold entries that still count.
+pub struct MemtableLogPageView<'a> {
+	data: &'a mut [u8],
+	schema: &'a Schema,
+	rec_size: usize,
+	entry_size: usize,
+	capacity: u16,
+}
+impl<'a> MemtableLogPageView<'a> {
+	/// Opens a `MemtableLogPageView` on a page's data
+	pub fn new(data: &'a mut [u8], schema: &'a Schema) -> Result<MemtableLogPageView<'a>> {
+		let Some(rec_size) = schema.size() else {
+			return Err(Error::Internal(
+				"Attempted to instantiate memtable log page with non-fixed len schema".to_string(),
+			));
+		};
+		let rec_size = rec_size as usize;
+		let entry_size = ENTRY_HEADER_SIZE + rec_size;
+		let capacity = ((data.len() - HEADER_SIZE) / entry_size) as u16;
+		Ok(MemtableLogPageView {
+			data,
+			schema,
+			rec_size,
+			entry_size,
+			capacity,
+		})
+	}
+
+	#[inline]
+	pub fn generation(&self) -> u32 {
+		u32::from_le_bytes(slice_to_array(&self.data[0..4]))
+	}
+
+	#[inline]
+	pub fn len(&self) -> u16 {
+		u16::from_le_bytes(slice_to_array(&self.data[4..6]))
+	}
+
+	#[inline]
+	fn set_len(&mut self, len: u16) {
+		self.data[4..6].copy_from_slice(&len.to_le_bytes());
+	}
+
+	/// Empties the page, starting it over in a generation
+	pub fn reset(&mut self, generation: u32) {
+		self.data[0..4].copy_from_slice(&generation.to_le_bytes());
+		self.set_len(0);
+	}
+
+	/// Drops the entries after the first `len`
+	pub fn truncate(&mut self, len: u16) {
+		self.set_len(len.min(self.len()));
+	}
+
+	/// Appends an entry, returning `false` if the page is full
+	pub fn push(&mut self, key: u64, rec: Option<&Record>) -> bool {
+		let len = self.len();
+		if len >= self.capacity {
+			return false;
+		}
+
+		let offset = self.entry_offset(len);
+		self.data[offset..(offset + 8)].copy_from_slice(&key.to_le_bytes());
+		let rec_offset = offset + ENTRY_HEADER_SIZE;
+		match rec {
+			Some(rec) => {
+				debug_assert!(self.schema.validate(rec));
+				self.data[offset + 8] = LIVE_MARKER;
+				self.data[rec_offset..(rec_offset + self.rec_size)]
+					.copy_from_slice(&rec.to_padded_bytes(self.rec_size));
+			}
+			None => {
+				self.data[offset + 8] = TOMBSTONE_MARKER;
+				self.data[rec_offset..(rec_offset + self.rec_size)].fill(0);
+			}
+		}
+		self.set_len(len + 1);
+		true
+	}
+
+	/// Every entry, oldest first, with `None` for tombstones
+	pub fn entries(&self) -> Vec<(u64, Option<Record>)> {
+		(0..self.len())
+			.map(|i| {
+				let offset = self.entry_offset(i);
+				let key = u64::from_le_bytes(slice_to_array(&self.data[offset..(offset + 8)]));
+				let rec_offset = offset + ENTRY_HEADER_SIZE;
+				let rec = (self.data[offset + 8] != TOMBSTONE_MARKER).then(|| {
+					Record::from_bytes(
+						&self.data[rec_offset..(rec_offset + self.rec_size)],
+						self.schema,
+					)
+				});
+				(key, rec)
+			})
+			.collect()
+	}
+
+	#[inline]
+	fn entry_offset(&self, i: u16) -> usize {
+		HEADER_SIZE + i as usize * self.entry_size
+	}
+}
@@ -1,38 +1,75 @@
-mod fixed_len;
+pub mod bloom_block;
+pub mod fixed_len;
+pub mod hash_bucket;
+pub mod hash_directory;
+pub mod memtable_log;
+pub mod postings;
+pub mod rtree_node;
+pub mod sorted_run;
 
-use crate::*;
+use crate::{util::slice_to_array, *};
 
 pub type PageId = u32;
 
 pub const HEADER_SIZE: usize = 8;
-pub const DATA_SIZE: usize = PAGE_SIZE - HEADER_SIZE;
 
 /// Uniquely identifies a `Record`
 ///
 /// **WARNING**: `RecordId`'s may not be stable (remain valid indefinitely) depending on the page wrapper that produced it
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RecordId {
-	page_id: PageId,
-	slot: u16,
+	pub page_id: PageId,
+	pub slot: u16,
+}
+impl RecordId {
+	/// Size of a serialized `RecordId`, in bytes
+	pub const SIZE: usize = 6;
+
+	pub const fn new(page_id: PageId, slot: u16) -> RecordId {
+		RecordId { page_id, slot }
+	}
+
+	pub fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut buf = [0u8; Self::SIZE];
+		buf[0..4].copy_from_slice(&self.page_id.to_le_bytes());
+		buf[4..6].copy_from_slice(&self.slot.to_le_bytes());
+		buf
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> RecordId {
+		RecordId {
+			page_id: PageId::from_le_bytes(slice_to_array(&bytes[0..4])),
+			slot: u16::from_le_bytes(slice_to_array(&bytes[4..6])),
+		}
+	}
 }
 
 /// A page read from disk
+///
+/// `next` and `prev` link pages into chains, a page pointing to itself marks the end of a chain. `data` holds the rest
+/// of the page, so its length depends on the database's page size.
 pub struct Page {
 	pub id: PageId,
-	next: PageId,
-	prev: PageId,
-	data: [u8; DATA_SIZE],
+	pub next: PageId,
+	pub prev: PageId,
+	pub data: Box<[u8]>,
 }
 impl Page {
-	pub const fn new_empty(id: PageId) -> Page {
+	pub fn new_empty(id: PageId, page_size: usize) -> Page {
 		Page {
 			id,
 			next: id,
 			prev: id,
-			data: [0u8; DATA_SIZE],
+			data: vec![0u8; page_size - HEADER_SIZE].into_boxed_slice(),
 		}
 	}
 
+	/// Size of the page on disk, header included
+	#[inline]
+	pub fn size(&self) -> usize {
+		HEADER_SIZE + self.data.len()
+	}
+
 	/*/// Tries to insert a record, returns record ID if successful
 	pub fn insert(&mut self, rec: Record) -> Result<Option<RecordId>> {
 		let rec_size = rec.size() as usize;
@@ -61,8 +98,8 @@ impl Page {
 		}))
 	}*/
 
-	pub fn to_bytes(&self) -> Result<[u8; PAGE_SIZE]> {
-		let mut buf = [0u8; PAGE_SIZE];
+	pub fn to_bytes(&self) -> Result<Vec<u8>> {
+		let mut buf = vec![0u8; self.size()];
 
 		// header
 		buf[0..4].copy_from_slice(&self.next.to_le_bytes());
@@ -73,10 +110,10 @@ impl Page {
 		Ok(buf)
 	}
 
-	pub fn from_bytes(bytes: [u8; PAGE_SIZE], id: PageId) -> Result<Page> {
+	pub fn from_bytes(bytes: &[u8], id: PageId) -> Result<Page> {
 		let next = PageId::from_le_bytes(bytes[0..4].try_into().unwrap());
 		let prev = PageId::from_le_bytes(bytes[4..8].try_into().unwrap());
-		let data: [u8; PAGE_SIZE - HEADER_SIZE] = bytes[HEADER_SIZE..].try_into().unwrap();
+		let data = bytes[HEADER_SIZE..].into();
 		Ok(Page {
 			id,
 			next,
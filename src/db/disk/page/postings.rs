@@ -0,0 +1,77 @@
+use crate::{db::disk::RecordId, util::slice_to_array};
+
+const HEADER_SIZE: usize = 2;
+/// Size of a record ID and the term's frequency in that record
+const ENTRY_SIZE: usize = RecordId::SIZE + 4;
+
+/// Wrapper around page, with methods to manage one page of a term's posting list
+///
+/// Data layout:
+/// ```txt
+/// |n_entries|entry1|entry2|...
+/// 0         2
+/// ```
+/// Where each entry is `|rid|tf|`, in no particular order.
+pub struct PostingsPageView<'a> {
+	data: &'a mut [u8],
+	capacity: u16,
+}
+impl<'a> PostingsPageView<'a> {
+	/// Opens a `PostingsPageView` on a page's data
+	pub fn new(data: &'a mut [u8]) -> PostingsPageView<'a> {
+		let capacity = ((data.len() - HEADER_SIZE) / ENTRY_SIZE) as u16;
+		PostingsPageView { data, capacity }
+	}
+
+	#[inline]
+	pub fn len(&self) -> u16 {
+		u16::from_le_bytes(slice_to_array(&self.data[0..2]))
+	}
+
+	#[inline]
+	fn set_len(&mut self, len: u16) {
+		self.data[0..2].copy_from_slice(&len.to_le_bytes());
+	}
+
+	/// Reads an entry's record ID and term frequency
+	pub fn get(&self, i: u16) -> (RecordId, u32) {
+		let offset = entry_offset(i);
+		(
+			RecordId::from_bytes(&self.data[offset..(offset + RecordId::SIZE)]),
+			u32::from_le_bytes(slice_to_array(
+				&self.data[(offset + RecordId::SIZE)..(offset + ENTRY_SIZE)],
+			)),
+		)
+	}
+
+	/// Appends an entry, returning `false` if the page is full
+	pub fn push(&mut self, rid: RecordId, tf: u32) -> bool {
+		let len = self.len();
+		if len >= self.capacity {
+			return false;
+		}
+		let offset = entry_offset(len);
+		self.data[offset..(offset + RecordId::SIZE)].copy_from_slice(&rid.to_bytes());
+		self.data[(offset + RecordId::SIZE)..(offset + ENTRY_SIZE)]
+			.copy_from_slice(&tf.to_le_bytes());
+		self.set_len(len + 1);
+		true
+	}
+
+	/// Removes the entry of a record, moving the last entry into its place, returning `false` if there is none
+	pub fn remove(&mut self, rid: RecordId) -> bool {
+		let len = self.len();
+		let Some(i) = (0..len).find(|i| self.get(*i).0 == rid) else {
+			return false;
+		};
+		let (last, end) = (entry_offset(len - 1), entry_offset(len));
+		self.data.copy_within(last..end, entry_offset(i));
+		self.set_len(len - 1);
+		true
+	}
+}
+
+#[inline]
+fn entry_offset(i: u16) -> usize {
+	HEADER_SIZE + i as usize * ENTRY_SIZE
+}
@@ -0,0 +1,77 @@
+use super::RecordId;
+use crate::db::geometry::Rect;
+
+const HEADER_SIZE: usize = 3;
+const ENTRY_SIZE: usize = Rect::SIZE + RecordId::SIZE;
+
+/// Most entries a node can hold, for a page size
+pub const fn node_capacity(page_size: usize) -> usize {
+	(page_size - super::HEADER_SIZE - HEADER_SIZE) / ENTRY_SIZE
+}
+
+/// An entry of an R-tree node, a bounding rectangle and what it bounds
+///
+/// In leaves `ptr` is the record being indexed, in internal nodes `ptr.page_id` is the child node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RTreeEntry {
+	pub rect: Rect,
+	pub ptr: RecordId,
+}
+
+/// Wrapper around page, with methods to manage a node of an R-tree
+///
+/// Data layout:
+/// ```txt
+/// |is_leaf|n_entries|entry1|entry2|...
+/// 0       1         3
+/// ```
+/// Where each entry is a bounding `Rect` followed by a `RecordId`
+pub struct RTreeNodePageView<'a> {
+	data: &'a mut [u8],
+}
+impl<'a> RTreeNodePageView<'a> {
+	/// Opens a `RTreeNodePageView` on a page's data
+	pub fn new(data: &'a mut [u8]) -> RTreeNodePageView<'a> {
+		RTreeNodePageView { data }
+	}
+
+	#[inline]
+	pub fn is_leaf(&self) -> bool {
+		self.data[0] != 0
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize {
+		u16::from_le_bytes(self.data[1..3].try_into().unwrap()) as usize
+	}
+
+	/// Reads every entry in the node
+	pub fn entries(&self) -> Vec<RTreeEntry> {
+		(0..self.len())
+			.map(|i| {
+				let offset = HEADER_SIZE + i * ENTRY_SIZE;
+				RTreeEntry {
+					rect: Rect::from_bytes(&self.data[offset..(offset + Rect::SIZE)]),
+					ptr: RecordId::from_bytes(
+						&self.data[(offset + Rect::SIZE)..(offset + ENTRY_SIZE)],
+					),
+				}
+			})
+			.collect()
+	}
+
+	/// Overwrites the node's contents
+	///
+	/// **WARNING**: Assumes the entries fit, see [`node_capacity`]
+	pub fn write(&mut self, is_leaf: bool, entries: &[RTreeEntry]) {
+		debug_assert!(HEADER_SIZE + entries.len() * ENTRY_SIZE <= self.data.len());
+		self.data[0] = is_leaf as u8;
+		self.data[1..3].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+		for (i, entry) in entries.iter().enumerate() {
+			let offset = HEADER_SIZE + i * ENTRY_SIZE;
+			self.data[offset..(offset + Rect::SIZE)].copy_from_slice(&entry.rect.to_bytes());
+			self.data[(offset + Rect::SIZE)..(offset + ENTRY_SIZE)]
+				.copy_from_slice(&entry.ptr.to_bytes());
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use crate::{db::record::*, util::slice_to_array, *};
+
+const HEADER_SIZE: usize = 2;
+/// Size of the key and tombstone marker preceding each record
+const ENTRY_HEADER_SIZE: usize = 9;
+
+const TOMBSTONE_MARKER: u8 = 0;
+const LIVE_MARKER: u8 = 1;
+
+/// Wrapper around page, with methods to manage one page of an LSM tree's sorted run
+///
+/// Data layout:
+/// ```txt
+/// |n_entries|entry1|entry2|...
+/// 0         2
+/// ```
+/// Where each entry is `|key|marker|record...|`, and entries are sorted by key. Tombstones still take up a full entry,
+/// with the record bytes left zeroed.
+pub struct SortedRunPageView<'a> {
+	data: &'a mut [u8],
+	schema: &'a Schema,
+	rec_size: usize,
+	entry_size: usize,
+	capacity: u16,
+}
+impl<'a> SortedRunPageView<'a> {
+	/// Opens a `SortedRunPageView` on a page's data
+	pub fn new(data: &'a mut [u8], schema: &'a Schema) -> Result<SortedRunPageView<'a>> {
+		let Some(rec_size) = schema.size() else {
+			return Err(Error::Internal(
+				"Attempted to instantiate sorted run page with non-fixed len schema".to_string(),
+			));
+		};
+		let rec_size = rec_size as usize;
+		let entry_size = ENTRY_HEADER_SIZE + rec_size;
+		let capacity = ((data.len() - HEADER_SIZE) / entry_size) as u16;
+		Ok(SortedRunPageView {
+			data,
+			schema,
+			rec_size,
+			entry_size,
+			capacity,
+		})
+	}
+
+	#[inline]
+	pub fn len(&self) -> u16 {
+		u16::from_le_bytes(self.data[0..2].try_into().unwrap())
+	}
+
+	#[inline]
+	fn set_len(&mut self, len: u16) {
+		self.data[0..2].copy_from_slice(&len.to_le_bytes());
+	}
+
+	/// Appends an entry, returning `false` if the page is full
+	///
+	/// **WARNING**: Assumes the key is greater than every key already in the page
+	pub fn push(&mut self, key: u64, rec: Option<&Record>) -> bool {
+		let len = self.len();
+		if len >= self.capacity {
+			return false;
+		}
+		debug_assert!(len == 0 || self.key(len - 1) < key);
+
+		let offset = self.entry_offset(len);
+		self.data[offset..(offset + 8)].copy_from_slice(&key.to_le_bytes());
+		let rec_offset = offset + ENTRY_HEADER_SIZE;
+		match rec {
+			Some(rec) => {
+				debug_assert!(self.schema.validate(rec));
+				self.data[offset + 8] = LIVE_MARKER;
+				self.data[rec_offset..(rec_offset + self.rec_size)]
+					.copy_from_slice(&rec.to_padded_bytes(self.rec_size));
+			}
+			None => {
+				self.data[offset + 8] = TOMBSTONE_MARKER;
+				self.data[rec_offset..(rec_offset + self.rec_size)].fill(0);
+			}
+		}
+		self.set_len(len + 1);
+		true
+	}
+
+	/// Number of entries that fit in the page
+	#[inline]
+	pub fn capacity(&self) -> u16 {
+		self.capacity
+	}
+
+	#[inline]
+	pub fn key(&self, i: u16) -> u64 {
+		let offset = self.entry_offset(i);
+		u64::from_le_bytes(slice_to_array(&self.data[offset..(offset + 8)]))
+	}
+
+	/// Reads an entry's record, `None` if it is a tombstone
+	pub fn record(&self, i: u16) -> Option<Record> {
+		let offset = self.entry_offset(i);
+		if self.data[offset + 8] == TOMBSTONE_MARKER {
+			return None;
+		}
+		let rec_offset = offset + ENTRY_HEADER_SIZE;
+		Some(Record::from_bytes(
+			&self.data[rec_offset..(rec_offset + self.rec_size)],
+			self.schema,
+		))
+	}
+
+	/// Binary searches for a key, returning `None` if it is not in this page and `Some(None)` if it is a tombstone
+	pub fn find(&self, key: u64) -> Option<Option<Record>> {
+		let (mut lo, mut hi) = (0, self.len());
+		while lo < hi {
+			let mid = lo + (hi - lo) / 2;
+			match self.key(mid).cmp(&key) {
+				std::cmp::Ordering::Less => lo = mid + 1,
+				std::cmp::Ordering::Greater => hi = mid,
+				std::cmp::Ordering::Equal => return Some(self.record(mid)),
+			}
+		}
+		None
+	}
+
+	/// Reads every entry in the page
+	pub fn entries(&self) -> Vec<(u64, Option<Record>)> {
+		(0..self.len())
+			.map(|i| (self.key(i), self.record(i)))
+			.collect()
+	}
+
+	#[inline]
+	fn entry_offset(&self, i: u16) -> usize {
+		HEADER_SIZE + (i as usize) * self.entry_size
+	}
+}
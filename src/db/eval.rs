@@ -0,0 +1,815 @@
+use std::cmp::Ordering;
+
+use super::{
+	geometry::{Point, Rect},
+	record::{Record, Value, ValueType},
+	table::TableDef,
+};
+use crate::{
+	query::{self, BinaryOp, Expr, UnaryOp},
+	*,
+};
+
+/// A value an expression evaluates to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Point(Point),
+	Rect(Rect),
+	String(String),
+}
+impl From<&Value> for Datum {
+	fn from(value: &Value) -> Self {
+		match value {
+			Value::U32(n) => Datum::Int(*n as i64),
+			Value::I32(n) => Datum::Int(*n as i64),
+			Value::Point(p) => Datum::Point(*p),
+			Value::Rect(r) => Datum::Rect(*r),
+			Value::String(s) => Datum::String(s.clone()),
+		}
+	}
+}
+
+/// What an expression evaluates to, known before evaluating it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+	/// Only ever null, which fits wherever any other kind does
+	Null,
+	Bool,
+	Number,
+	Point,
+	Rect,
+	String,
+}
+impl From<ValueType> for Kind {
+	fn from(ty: ValueType) -> Self {
+		match ty {
+			ValueType::U32 | ValueType::I32 => Kind::Number,
+			ValueType::Point => Kind::Point,
+			ValueType::Rect => Kind::Rect,
+			ValueType::String(_) => Kind::String,
+		}
+	}
+}
+impl Kind {
+	pub fn of(datum: &Datum) -> Kind {
+		match datum {
+			Datum::Null => Kind::Null,
+			Datum::Bool(_) => Kind::Bool,
+			Datum::Int(_) | Datum::Float(_) => Kind::Number,
+			Datum::Point(_) => Kind::Point,
+			Datum::Rect(_) => Kind::Rect,
+			Datum::String(_) => Kind::String,
+		}
+	}
+
+	pub fn is(self, kind: Kind) -> bool {
+		self == kind || self == Kind::Null
+	}
+
+	/// Whether values of the two kinds can be ordered, both being numbers or both strings
+	pub fn ordered(self, other: Kind) -> bool {
+		(self.is(Kind::Number) && other.is(Kind::Number))
+			|| (self.is(Kind::String) && other.is(Kind::String))
+	}
+
+	/// Whether values of the two kinds can be compared for equality
+	fn comparable(self, other: Kind) -> bool {
+		self == other || self == Kind::Null || other == Kind::Null
+	}
+
+	pub fn describe(self) -> &'static str {
+		match self {
+			Kind::Null => "null",
+			Kind::Bool => "a boolean",
+			Kind::Number => "a number",
+			Kind::Point => "a point",
+			Kind::Rect => "a rect",
+			Kind::String => "a string",
+		}
+	}
+}
+
+/// Columns an expression can refer to, such as those of a table's records, or of the rows a query computed
+#[derive(Debug, Clone)]
+pub struct Scope {
+	/// What the columns belong to, such as `table "Users"`, for errors about missing columns
+	what: String,
+	columns: Vec<ScopeColumn>,
+}
+#[derive(Debug, Clone)]
+pub struct ScopeColumn {
+	/// Object the column belongs to, which qualified names such as `Users.id` refer to it by
+	pub object: Option<String>,
+	/// Name bare words refer to the column by, `None` for a column that can't be named
+	pub name: Option<String>,
+	pub kind: Kind,
+	/// Expression the column was computed from, which refers to the column wherever it is found again, such as a
+	/// group key or an aggregate
+	pub expr: Option<Expr>,
+}
+impl Scope {
+	/// A scope without columns yet, describing what they will belong to
+	pub fn new<S: Into<String>>(what: S) -> Scope {
+		Scope {
+			what: what.into(),
+			columns: Vec::new(),
+		}
+	}
+
+	/// The columns of a table's records
+	pub fn table(table: &str, def: &TableDef) -> Scope {
+		let mut scope = Scope::new(format!("table \"{table}\""));
+		for (name, ty) in def.columns() {
+			scope.push(ScopeColumn {
+				object: Some(table.to_string()),
+				name: Some(name.clone()),
+				kind: Kind::from(*ty),
+				expr: None,
+			});
+		}
+		scope
+	}
+
+	/// The same columns, belonging to an object with a new name, such as a named query
+	pub fn with_object(mut self, object: &str) -> Scope {
+		self.what = format!("\"{object}\"");
+		for column in &mut self.columns {
+			column.object = Some(object.to_string());
+		}
+		self
+	}
+
+	/// Adds a column after the others
+	pub fn push(&mut self, column: ScopeColumn) {
+		self.columns.push(column);
+	}
+
+	#[inline]
+	pub fn columns(&self) -> &[ScopeColumn] {
+		&self.columns
+	}
+
+	/// Finds the column an expression refers to, `None` if it doesn't refer to one, failing if it refers to a
+	/// column that doesn't exist or isn't the only one with its name
+	pub fn resolve(&self, expr: &Expr) -> Result<Option<usize>> {
+		if let Some(i) = self
+			.columns
+			.iter()
+			.position(|column| column.expr.as_ref() == Some(expr))
+		{
+			return Ok(Some(i));
+		}
+		let (object, name) = match expr {
+			Expr::Value(query::Value::Identifier(name)) => (None, name),
+			Expr::Column { object, name } => (Some(object), name),
+			_ => return Ok(None),
+		};
+		let mut found = self.columns.iter().enumerate().filter(|(_, column)| {
+			column.name.as_ref() == Some(name)
+				&& object.is_none_or(|object| column.object.as_ref() == Some(object))
+		});
+		match (found.next(), found.next()) {
+			(Some((i, _)), None) => Ok(Some(i)),
+			(Some(_), Some(_)) => Err(Error::InvalidArgument(format!(
+				"Column \"{name}\" is ambiguous, it needs to be qualified with the name of its object"
+			))),
+			(None, _) => match object {
+				Some(object)
+					if !self
+						.columns
+						.iter()
+						.any(|column| column.object.as_ref() == Some(object)) =>
+				{
+					Err(Error::InvalidArgument(format!(
+						"\"{object}.{name}\" refers to an object other than those of {}",
+						self.what
+					)))
+				}
+				_ => Err(Error::NotFound(format!(
+					"column \"{name}\" of {}",
+					self.what
+				))),
+			},
+		}
+	}
+}
+
+/// Values of the columns of a scope, that compiled expressions are evaluated against
+pub trait Row {
+	/// Value of a column, `None` if the row has no such column
+	fn datum(&self, column: usize) -> Option<Datum>;
+}
+impl Row for Record {
+	fn datum(&self, column: usize) -> Option<Datum> {
+		self.get(column).map(Datum::from)
+	}
+}
+impl Row for [Datum] {
+	fn datum(&self, column: usize) -> Option<Datum> {
+		self.get(column).cloned()
+	}
+}
+
+/// An expression whose columns have been resolved against a scope, ready to be evaluated against its rows
+///
+/// Only expressions made of literals, the scope's columns and operators can be evaluated, comparisons with null are
+/// null like in SQL. Strings compare by their bytes, and `like` matches them against patterns where `%` stands for any
+/// run of characters and `_` for a single one.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+	node: Node,
+}
+#[derive(Debug, Clone)]
+enum Node {
+	Const(Datum),
+	Column(usize),
+	Unary(UnaryOp, Box<Node>),
+	Binary(BinaryOp, Box<Node>, Box<Node>),
+	In {
+		expr: Box<Node>,
+		list: Vec<Node>,
+		negated: bool,
+	},
+	Between {
+		expr: Box<Node>,
+		low: Box<Node>,
+		high: Box<Node>,
+		negated: bool,
+	},
+	IsNull {
+		expr: Box<Node>,
+		negated: bool,
+	},
+}
+impl CompiledExpr {
+	/// Compiles an expression, returning what it evaluates to along with it
+	pub fn new(expr: &Expr, scope: &Scope) -> Result<(CompiledExpr, Kind)> {
+		let (node, kind) = compile(expr, scope)?;
+		Ok((CompiledExpr { node }, kind))
+	}
+
+	/// Compiles an expression that has to be true or false, such as a `where` condition
+	pub fn condition(expr: &Expr, scope: &Scope) -> Result<CompiledExpr> {
+		let (compiled, kind) = CompiledExpr::new(expr, scope)?;
+		if !kind.is(Kind::Bool) {
+			return Err(Error::TypeMismatch(format!(
+				"Expected a condition, found {}",
+				kind.describe()
+			)));
+		}
+		Ok(compiled)
+	}
+
+	/// Evaluates the expression against a row of the scope it was compiled for
+	pub fn eval<R: Row + ?Sized>(&self, row: &R) -> Result<Datum> {
+		eval(&self.node, row)
+	}
+
+	/// Whether the expression is true for a row, so false and null are not
+	pub fn is_true<R: Row + ?Sized>(&self, row: &R) -> Result<bool> {
+		Ok(matches!(self.eval(row)?, Datum::Bool(true)))
+	}
+}
+
+/// Resolves an expression's columns, checking that the kinds of values it combines fit together
+fn compile(expr: &Expr, scope: &Scope) -> Result<(Node, Kind)> {
+	let unsupported = |what: &str| {
+		Err(Error::InvalidArgument(format!(
+			"{what} can't be evaluated against the rows of {} yet",
+			scope.what
+		)))
+	};
+
+	if let Some(i) = scope.resolve(expr)? {
+		return Ok((Node::Column(i), scope.columns[i].kind));
+	}
+	match expr {
+		Expr::Value(query::Value::Identifier(_)) | Expr::Column { .. } => unreachable!(),
+		Expr::Value(value) => {
+			let datum = match value {
+				query::Value::Integer(n) => Datum::Int(*n),
+				query::Value::Float(n) => Datum::Float(*n),
+				query::Value::Bool(b) => Datum::Bool(*b),
+				query::Value::Null => Datum::Null,
+				query::Value::Point(p) => Datum::Point(*p),
+				query::Value::Rect(r) => Datum::Rect(*r),
+				query::Value::String(s) => Datum::String(s.clone()),
+				query::Value::Bytes(_) => return unsupported("Byte strings"),
+				query::Value::Identifier(_) => unreachable!(),
+			};
+			let kind = Kind::of(&datum);
+			Ok((Node::Const(datum), kind))
+		}
+		Expr::Unary { op, expr } => {
+			let (node, kind) = compile(expr, scope)?;
+			let expected = match op {
+				UnaryOp::Neg => Kind::Number,
+				UnaryOp::Not => Kind::Bool,
+			};
+			if !kind.is(expected) {
+				return Err(Error::TypeMismatch(format!(
+					"Can't apply {} to {}",
+					unary_name(*op),
+					kind.describe()
+				)));
+			}
+			Ok((Node::Unary(*op, Box::new(node)), expected))
+		}
+		Expr::Binary { op, left, right } => {
+			let (left, left_kind) = compile(left, scope)?;
+			let (right, right_kind) = compile(right, scope)?;
+			let kind = match op {
+				BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem
+					if left_kind.is(Kind::Number) && right_kind.is(Kind::Number) =>
+				{
+					Some(Kind::Number)
+				}
+				BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq
+					if left_kind.ordered(right_kind) =>
+				{
+					Some(Kind::Bool)
+				}
+				BinaryOp::Eq | BinaryOp::NotEq if left_kind.comparable(right_kind) => {
+					Some(Kind::Bool)
+				}
+				BinaryOp::And | BinaryOp::Or
+					if left_kind.is(Kind::Bool) && right_kind.is(Kind::Bool) =>
+				{
+					Some(Kind::Bool)
+				}
+				BinaryOp::Like | BinaryOp::NotLike
+					if left_kind.is(Kind::String) && right_kind.is(Kind::String) =>
+				{
+					Some(Kind::Bool)
+				}
+				_ => None,
+			};
+			let Some(kind) = kind else {
+				return Err(Error::TypeMismatch(format!(
+					"Can't apply {} to {} and {}",
+					binary_name(*op),
+					left_kind.describe(),
+					right_kind.describe()
+				)));
+			};
+			Ok((Node::Binary(*op, Box::new(left), Box::new(right)), kind))
+		}
+		Expr::In {
+			expr,
+			list,
+			negated,
+		} => {
+			let (expr, kind) = compile(expr, scope)?;
+			let mut items = Vec::with_capacity(list.len());
+			for item in list {
+				let (item, item_kind) = compile(item, scope)?;
+				if !kind.comparable(item_kind) {
+					return Err(Error::TypeMismatch(format!(
+						"Can't look for {} in a list holding {}",
+						kind.describe(),
+						item_kind.describe()
+					)));
+				}
+				items.push(item);
+			}
+			Ok((
+				Node::In {
+					expr: Box::new(expr),
+					list: items,
+					negated: *negated,
+				},
+				Kind::Bool,
+			))
+		}
+		Expr::Between {
+			expr,
+			low,
+			high,
+			negated,
+		} => {
+			let mut compiled = Vec::with_capacity(3);
+			let mut kinds = Vec::with_capacity(3);
+			for expr in [expr, low, high] {
+				let (node, kind) = compile(expr, scope)?;
+				compiled.push(Box::new(node));
+				kinds.push(kind);
+			}
+			if !(kinds[0].ordered(kinds[1]) && kinds[0].ordered(kinds[2])) {
+				return Err(Error::TypeMismatch(format!(
+					"Can't apply \"between\" to {}",
+					kinds
+						.iter()
+						.map(|kind| kind.describe())
+						.collect::<Vec<_>>()
+						.join(", ")
+				)));
+			}
+			let [expr, low, high] = compiled.try_into().unwrap();
+			Ok((
+				Node::Between {
+					expr,
+					low,
+					high,
+					negated: *negated,
+				},
+				Kind::Bool,
+			))
+		}
+		Expr::IsNull { expr, negated } => {
+			let (expr, _) = compile(expr, scope)?;
+			Ok((
+				Node::IsNull {
+					expr: Box::new(expr),
+					negated: *negated,
+				},
+				Kind::Bool,
+			))
+		}
+		Expr::InQuery { .. } | Expr::Subquery(_) | Expr::Exists(_) => unsupported("Subqueries"),
+		Expr::Record(_) => unsupported("Record literals"),
+		Expr::List(_) => unsupported("Lists"),
+		Expr::Aggregate { .. } => Err(Error::InvalidArgument(
+			"Aggregate functions can only be used in \"having\" and \"aggregate\"".to_string(),
+		)),
+		Expr::Window { .. } => unsupported("Window functions"),
+		Expr::Call { name, .. } => unsupported(&format!("{name}()")),
+		Expr::Alias { .. } | Expr::SortKey { .. } => unsupported("Named or sorted expressions"),
+	}
+}
+
+fn eval<R: Row + ?Sized>(node: &Node, rec: &R) -> Result<Datum> {
+	Ok(match node {
+		Node::Const(datum) => datum.clone(),
+		Node::Column(i) => match rec.datum(*i) {
+			Some(datum) => datum,
+			None => {
+				return Err(Error::TypeMismatch(
+					"Record does not match table schema".to_string(),
+				));
+			}
+		},
+		Node::Unary(op, expr) => match (op, eval(expr, rec)?) {
+			(_, Datum::Null) => Datum::Null,
+			(UnaryOp::Neg, Datum::Int(n)) => Datum::Int(n.checked_neg().ok_or_else(overflow)?),
+			(UnaryOp::Neg, Datum::Float(n)) => Datum::Float(-n),
+			(UnaryOp::Not, Datum::Bool(b)) => Datum::Bool(!b),
+			(_, datum) => return Err(mismatch(unary_name(*op), &[datum])),
+		},
+		Node::Binary(op, left, right) => {
+			let (left, right) = (eval(left, rec)?, eval(right, rec)?);
+			binary(*op, left, right)?
+		}
+		Node::In {
+			expr,
+			list,
+			negated,
+		} => {
+			let value = eval(expr, rec)?;
+			let mut found = Some(false);
+			for item in list {
+				match equals(&value, &eval(item, rec)?) {
+					Some(true) => {
+						found = Some(true);
+						break;
+					}
+					Some(false) => {}
+					// not being in a list holding null is unknown
+					None => found = None,
+				}
+			}
+			found.map_or(Datum::Null, |found| Datum::Bool(found != *negated))
+		}
+		Node::Between {
+			expr,
+			low,
+			high,
+			negated,
+		} => {
+			let value = eval(expr, rec)?;
+			let (low, high) = (eval(low, rec)?, eval(high, rec)?);
+			match (compare(&low, &value), compare(&value, &high)) {
+				(Some(a), Some(b)) => Datum::Bool((a.is_le() && b.is_le()) != *negated),
+				_ => Datum::Null,
+			}
+		}
+		Node::IsNull { expr, negated } => {
+			Datum::Bool((eval(expr, rec)? == Datum::Null) != *negated)
+		}
+	})
+}
+
+fn binary(op: BinaryOp, left: Datum, right: Datum) -> Result<Datum> {
+	use Datum::*;
+	Ok(match op {
+		// false and anything is false, even null, likewise for true or anything
+		BinaryOp::And => match (left, right) {
+			(Bool(false), _) | (_, Bool(false)) => Bool(false),
+			(Bool(true), Bool(true)) => Bool(true),
+			_ => Null,
+		},
+		BinaryOp::Or => match (left, right) {
+			(Bool(true), _) | (_, Bool(true)) => Bool(true),
+			(Bool(false), Bool(false)) => Bool(false),
+			_ => Null,
+		},
+		_ if left == Null || right == Null => Null,
+		BinaryOp::Eq => equals(&left, &right).map_or(Null, Bool),
+		BinaryOp::NotEq => equals(&left, &right).map_or(Null, |eq| Bool(!eq)),
+		BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => {
+			let Some(ord) = compare(&left, &right) else {
+				return Err(mismatch(binary_name(op), &[left, right]));
+			};
+			Bool(match op {
+				BinaryOp::Lt => ord.is_lt(),
+				BinaryOp::LtEq => ord.is_le(),
+				BinaryOp::Gt => ord.is_gt(),
+				_ => ord.is_ge(),
+			})
+		}
+		BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => {
+			match (&left, &right) {
+				(&Int(a), &Int(b)) => {
+					if matches!(op, BinaryOp::Div | BinaryOp::Rem) && b == 0 {
+						return Err(Error::InvalidArgument("Division by zero".to_string()));
+					}
+					let n = match op {
+						BinaryOp::Add => a.checked_add(b),
+						BinaryOp::Sub => a.checked_sub(b),
+						BinaryOp::Mul => a.checked_mul(b),
+						BinaryOp::Div => a.checked_div(b),
+						_ => a.checked_rem(b),
+					};
+					Int(n.ok_or_else(overflow)?)
+				}
+				(Int(_) | Float(_), Int(_) | Float(_)) => {
+					let (a, b) = (as_float(&left), as_float(&right));
+					Float(match op {
+						BinaryOp::Add => a + b,
+						BinaryOp::Sub => a - b,
+						BinaryOp::Mul => a * b,
+						BinaryOp::Div => a / b,
+						_ => a % b,
+					})
+				}
+				_ => return Err(mismatch(binary_name(op), &[left, right])),
+			}
+		}
+		BinaryOp::Like | BinaryOp::NotLike => match (&left, &right) {
+			(String(text), String(pattern)) => {
+				let text: Vec<char> = text.chars().collect();
+				let pattern: Vec<char> = pattern.chars().collect();
+				Bool(like(&text, &pattern) == (op == BinaryOp::Like))
+			}
+			_ => return Err(mismatch(binary_name(op), &[left, right])),
+		},
+	})
+}
+
+/// Whether two values are equal, `None` if either is null
+fn equals(a: &Datum, b: &Datum) -> Option<bool> {
+	match (a, b) {
+		(Datum::Null, _) | (_, Datum::Null) => None,
+		(Datum::Int(_) | Datum::Float(_), Datum::Int(_) | Datum::Float(_)) => {
+			Some(compare(a, b) == Some(Ordering::Equal))
+		}
+		(a, b) => Some(a == b),
+	}
+}
+
+/// Orders two numbers or two strings, `None` if they aren't
+pub fn compare(a: &Datum, b: &Datum) -> Option<Ordering> {
+	match (a, b) {
+		(Datum::Int(a), Datum::Int(b)) => Some(a.cmp(b)),
+		(Datum::Int(_) | Datum::Float(_), Datum::Int(_) | Datum::Float(_)) => {
+			as_float(a).partial_cmp(&as_float(b))
+		}
+		(Datum::String(a), Datum::String(b)) => Some(a.cmp(b)),
+		_ => None,
+	}
+}
+
+fn as_float(datum: &Datum) -> f64 {
+	match datum {
+		Datum::Int(n) => *n as f64,
+		Datum::Float(n) => *n,
+		_ => f64::NAN,
+	}
+}
+
+/// Whether text matches a `like` pattern, trying every run a `%` could stand for
+fn like(text: &[char], pattern: &[char]) -> bool {
+	match pattern.split_first() {
+		None => text.is_empty(),
+		Some(('%', rest)) => (0..=text.len()).any(|skip| like(&text[skip..], rest)),
+		Some((c, rest)) => match text.split_first() {
+			Some((first, text)) => (*c == '_' || c == first) && like(text, rest),
+			None => false,
+		},
+	}
+}
+
+fn overflow() -> Error {
+	Error::InvalidArgument("Integer overflow".to_string())
+}
+
+fn mismatch(op: &str, operands: &[Datum]) -> Error {
+	let kinds: Vec<&str> = operands
+		.iter()
+		.map(|datum| Kind::of(datum).describe())
+		.collect();
+	Error::TypeMismatch(format!("Can't apply {op} to {}", kinds.join(" and ")))
+}
+
+fn unary_name(op: UnaryOp) -> &'static str {
+	match op {
+		UnaryOp::Neg => "\"-\"",
+		UnaryOp::Not => "\"not\"",
+	}
+}
+
+fn binary_name(op: BinaryOp) -> &'static str {
+	match op {
+		BinaryOp::Add => "\"+\"",
+		BinaryOp::Sub => "\"-\"",
+		BinaryOp::Mul => "\"*\"",
+		BinaryOp::Div => "\"/\"",
+		BinaryOp::Rem => "\"%\"",
+		BinaryOp::Eq => "\"=\"",
+		BinaryOp::NotEq => "\"!=\"",
+		BinaryOp::Lt => "\"<\"",
+		BinaryOp::LtEq => "\"<=\"",
+		BinaryOp::Gt => "\">\"",
+		BinaryOp::GtEq => "\">=\"",
+		BinaryOp::And => "\"and\"",
+		BinaryOp::Or => "\"or\"",
+		BinaryOp::Like => "\"like\"",
+		BinaryOp::NotLike => "\"not like\"",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn users() -> TableDef {
+		TableDef::new()
+			.column("id", ValueType::U32)
+			.column("score", ValueType::I32)
+	}
+
+	fn eval_on(expr: Expr, id: u32, score: i32) -> Result<Datum> {
+		let rec = Record::new().item(Value::U32(id)).item(Value::I32(score));
+		let (node, _) = compile(&expr, &Scope::table("Users", &users()))?;
+		eval(&node, &rec)
+	}
+
+	#[test]
+	fn operators() {
+		use query::Value::*;
+		let int = |n| Expr::from(Integer(n));
+		let cmp = |op, left, right| Expr::binary(op, left, right);
+
+		let expr = cmp(
+			BinaryOp::And,
+			cmp(BinaryOp::Gt, Expr::ident("score"), int(-5)),
+			cmp(
+				BinaryOp::Eq,
+				cmp(BinaryOp::Rem, Expr::ident("id"), int(2)),
+				int(0),
+			),
+		);
+		assert_eq!(eval_on(expr.clone(), 4, 0).unwrap(), Datum::Bool(true));
+		assert_eq!(eval_on(expr, 3, 0).unwrap(), Datum::Bool(false));
+
+		let qualified = Expr::Column {
+			object: "Users".to_string(),
+			name: "id".to_string(),
+		};
+		let expr = Expr::Between {
+			expr: Box::new(cmp(BinaryOp::Mul, qualified, Float(1.5).into())),
+			low: Box::new(int(3)),
+			high: Box::new(int(6)),
+			negated: false,
+		};
+		assert_eq!(eval_on(expr, 4, 0).unwrap(), Datum::Bool(true));
+
+		// null is unknown, unless the other side decides the outcome
+		let expr = cmp(BinaryOp::Eq, Expr::ident("id"), Null.into());
+		assert_eq!(eval_on(expr.clone(), 1, 0).unwrap(), Datum::Null);
+		let expr = cmp(BinaryOp::Or, expr, Bool(true).into());
+		assert_eq!(eval_on(expr, 1, 0).unwrap(), Datum::Bool(true));
+		let expr = Expr::In {
+			expr: Box::new(Expr::ident("id")),
+			list: vec![int(2), Null.into()],
+			negated: true,
+		};
+		assert_eq!(eval_on(expr, 1, 0).unwrap(), Datum::Null);
+
+		let expr = cmp(BinaryOp::Div, Expr::ident("id"), int(0));
+		assert!(matches!(
+			eval_on(expr, 1, 0),
+			Err(Error::InvalidArgument(_))
+		));
+	}
+
+	#[test]
+	fn strings() {
+		let def = TableDef::new().column("name", ValueType::String(16));
+		let eval_on = |expr: Expr, name: &str| {
+			let rec = Record::new().item(Value::String(name.to_string()));
+			CompiledExpr::condition(&expr, &Scope::table("Users", &def))
+				.unwrap()
+				.eval(&rec)
+				.unwrap()
+		};
+		let string = |s: &str| Expr::from(query::Value::String(s.to_string()));
+		let cmp = |op, right| Expr::binary(op, Expr::ident("name"), right);
+
+		assert_eq!(
+			eval_on(cmp(BinaryOp::Eq, string("ann")), "ann"),
+			Datum::Bool(true)
+		);
+		assert_eq!(
+			eval_on(cmp(BinaryOp::Lt, string("bob")), "ann"),
+			Datum::Bool(true)
+		);
+		assert_eq!(
+			eval_on(cmp(BinaryOp::Lt, string("ann")), "anna"),
+			Datum::Bool(false)
+		);
+		let pattern = cmp(BinaryOp::Like, string("a_n%"));
+		assert_eq!(eval_on(pattern.clone(), "ann"), Datum::Bool(true));
+		assert_eq!(eval_on(pattern.clone(), "änna"), Datum::Bool(false));
+		assert_eq!(eval_on(pattern, "aönne"), Datum::Bool(true));
+		assert_eq!(
+			eval_on(cmp(BinaryOp::NotLike, string("%b%")), "bob"),
+			Datum::Bool(false)
+		);
+		let expr = Expr::Between {
+			expr: Box::new(Expr::ident("name")),
+			low: Box::new(string("a")),
+			high: Box::new(string("b")),
+			negated: false,
+		};
+		assert_eq!(eval_on(expr, "anna"), Datum::Bool(true));
+
+		assert!(matches!(
+			CompiledExpr::condition(
+				&cmp(BinaryOp::Like, Expr::from(query::Value::Integer(1))),
+				&Scope::table("Users", &def)
+			),
+			Err(Error::TypeMismatch(_))
+		));
+	}
+
+	#[test]
+	fn rejected() {
+		let def = users();
+		let compile_err =
+			|expr: Expr| CompiledExpr::condition(&expr, &Scope::table("Users", &def)).unwrap_err();
+
+		assert!(matches!(
+			compile_err(Expr::ident("name")),
+			Error::NotFound(_)
+		));
+		assert!(matches!(
+			compile_err(Expr::ident("id")),
+			Error::TypeMismatch(_)
+		));
+		assert!(matches!(
+			compile_err(Expr::binary(
+				BinaryOp::And,
+				Expr::ident("id"),
+				query::Value::Bool(true).into()
+			)),
+			Error::TypeMismatch(_)
+		));
+		assert!(matches!(
+			compile_err(Expr::binary(
+				BinaryOp::Eq,
+				Expr::ident("id"),
+				query::Value::String("1".to_string()).into()
+			)),
+			Error::TypeMismatch(_)
+		));
+		assert!(matches!(
+			compile_err(Expr::binary(
+				BinaryOp::Eq,
+				Expr::ident("id"),
+				query::Value::Point(Point::new(0.0, 0.0)).into()
+			)),
+			Error::TypeMismatch(_)
+		));
+		assert!(matches!(
+			compile_err(Expr::Column {
+				object: "Orders".to_string(),
+				name: "id".to_string()
+			}),
+			Error::InvalidArgument(_)
+		));
+	}
+}
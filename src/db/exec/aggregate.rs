@@ -0,0 +1,619 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+	output_column,
+	sort::{ExternalSort, TempFiles, encode_key, row_size},
+};
+use crate::{
+	db::eval::{self, CompiledExpr, Datum, Kind, Scope, ScopeColumn},
+	query::{self, AggregateFunction, Expr},
+	*,
+};
+
+/// Estimated bytes a group takes up in memory besides its key and the values its aggregates collect
+const GROUP_OVERHEAD: usize = 64;
+
+/// How `group_by`, `having` and `aggregate` reduce rows to one row per group
+pub(crate) struct AggregatePlan {
+	keys: Vec<CompiledExpr>,
+	aggregates: Vec<Aggregate>,
+	/// Evaluated against the groups, each row of which is the group's keys followed by its aggregates
+	having: Vec<CompiledExpr>,
+	outputs: Vec<CompiledExpr>,
+	/// Whether rows were grouped by keys, otherwise all of them are one group, even when there are none
+	grouped: bool,
+	/// Columns of the rows `aggregate` returns
+	scope: Scope,
+}
+impl AggregatePlan {
+	pub fn new(
+		input: &Scope,
+		keys: &[Expr],
+		having: &[Expr],
+		outputs: &[Expr],
+		grouped: bool,
+	) -> Result<AggregatePlan> {
+		let mut groups = Scope::new("the groups");
+		let mut compiled_keys = Vec::with_capacity(keys.len());
+		for key in keys {
+			let (compiled, mut column) = output_column(key, input)?;
+			column.expr = Some(unaliased(key).clone());
+			compiled_keys.push(compiled);
+			groups.push(column);
+		}
+
+		let mut found = Vec::new();
+		for expr in having.iter().chain(outputs) {
+			collect_aggregates(expr, &mut found)?;
+		}
+		let mut aggregates = Vec::with_capacity(found.len());
+		for expr in found {
+			let (aggregate, kind) = Aggregate::new(&expr, input)?;
+			aggregates.push(aggregate);
+			groups.push(ScopeColumn {
+				object: None,
+				name: None,
+				kind,
+				expr: Some(expr),
+			});
+		}
+
+		let having = having
+			.iter()
+			.map(|condition| CompiledExpr::condition(condition, &groups))
+			.collect::<Result<_>>()?;
+		let mut scope = Scope::new("the aggregated columns");
+		let mut compiled_outputs = Vec::with_capacity(outputs.len());
+		for output in outputs {
+			let (compiled, column) = output_column(output, &groups)?;
+			compiled_outputs.push(compiled);
+			scope.push(column);
+		}
+		Ok(AggregatePlan {
+			keys: compiled_keys,
+			aggregates,
+			having,
+			outputs: compiled_outputs,
+			grouped,
+			scope,
+		})
+	}
+
+	pub fn scope(&self) -> &Scope {
+		&self.scope
+	}
+
+	/// Reduces rows to one row per group, which are kept in a hash table while they fit in `budget` bytes, or else
+	/// sorted by their keys with an external sort spilling to temporary files
+	pub fn run(
+		&self,
+		rows: Vec<Vec<Datum>>,
+		temp: &TempFiles,
+		budget: usize,
+	) -> Result<Vec<Vec<Datum>>> {
+		let groups = match self.hash(&rows, budget)? {
+			Some(groups) => groups,
+			None => self.sort(rows, temp, budget)?,
+		};
+		let mut out = Vec::new();
+		for (key, states) in groups {
+			let mut row = key;
+			row.extend(states.into_iter().map(State::finish));
+			let mut kept = true;
+			for condition in &self.having {
+				kept &= condition.is_true(row.as_slice())?;
+			}
+			if kept {
+				out.push(
+					self.outputs
+						.iter()
+						.map(|output| output.eval(row.as_slice()))
+						.collect::<Result<_>>()?,
+				);
+			}
+		}
+		Ok(out)
+	}
+
+	/// Groups rows in a hash table, in the order groups are first seen, `None` if it outgrows the budget
+	fn hash(&self, rows: &[Vec<Datum>], budget: usize) -> Result<Option<Vec<Group>>> {
+		let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+		let mut groups: Vec<Group> = Vec::new();
+		let mut used = 0;
+		for row in rows {
+			let key = self.key_of(row)?;
+			let encoded = encode_key(&key);
+			let i = match index.get(&encoded) {
+				Some(&i) => i,
+				None => {
+					used += 2 * encoded.len() + row_size(&key) + self.group_size();
+					index.insert(encoded, groups.len());
+					groups.push((key, self.states()));
+					groups.len() - 1
+				}
+			};
+			used += self.update(&mut groups[i].1, row)?;
+			if used > budget {
+				return Ok(None);
+			}
+		}
+		if groups.is_empty() && !self.grouped {
+			groups.push((Vec::new(), self.states()));
+		}
+		Ok(Some(groups))
+	}
+
+	/// Groups rows by sorting them by their keys, so only one group is held in memory at a time
+	fn sort(&self, rows: Vec<Vec<Datum>>, temp: &TempFiles, budget: usize) -> Result<Vec<Group>> {
+		let mut sort = ExternalSort::new(temp, budget);
+		for row in rows {
+			let mut keyed = self.key_of(&row)?;
+			let encoded = encode_key(&keyed);
+			keyed.extend(row);
+			sort.push(encoded, keyed)?;
+		}
+
+		let mut groups = Vec::new();
+		let mut current: Option<(Vec<u8>, Group)> = None;
+		for entry in sort.finish()? {
+			let (encoded, mut key) = entry?;
+			let row = key.split_off(self.keys.len());
+			let group = match &mut current {
+				Some((current_key, group)) if *current_key == encoded => group,
+				_ => {
+					groups.extend(current.take().map(|(_, group)| group));
+					&mut current.insert((encoded, (key, self.states()))).1
+				}
+			};
+			self.update(&mut group.1, &row)?;
+		}
+		groups.extend(current.map(|(_, group)| group));
+		if groups.is_empty() && !self.grouped {
+			groups.push((Vec::new(), self.states()));
+		}
+		Ok(groups)
+	}
+
+	fn key_of(&self, row: &[Datum]) -> Result<Vec<Datum>> {
+		self.keys.iter().map(|key| key.eval(row)).collect()
+	}
+
+	fn states(&self) -> Vec<State> {
+		self.aggregates.iter().map(State::new).collect()
+	}
+
+	fn group_size(&self) -> usize {
+		GROUP_OVERHEAD + self.aggregates.len() * size_of::<State>()
+	}
+
+	/// Adds a row to a group's aggregates, returning how many more bytes they take up
+	fn update(&self, states: &mut [State], row: &[Datum]) -> Result<usize> {
+		let mut grown = 0;
+		for (state, aggregate) in states.iter_mut().zip(&self.aggregates) {
+			grown += state.update(aggregate, row)?;
+		}
+		Ok(grown)
+	}
+}
+
+/// Values of a group's keys, and the state of each of its aggregates
+type Group = (Vec<Datum>, Vec<State>);
+
+/// An aggregate function, with its argument compiled against the rows being grouped
+struct Aggregate {
+	function: AggregateFunction,
+	/// `None` for `count()`
+	arg: Option<CompiledExpr>,
+	/// Separator of `string_agg`
+	separator: String,
+}
+impl Aggregate {
+	fn new(expr: &Expr, input: &Scope) -> Result<(Aggregate, Kind)> {
+		let Expr::Aggregate { function, args } = expr else {
+			unreachable!()
+		};
+		let function = *function;
+		let mut arg = None;
+		let mut kind = Kind::Number;
+		if let Some(first) = args.first() {
+			let (compiled, arg_kind) = CompiledExpr::new(first, input)?;
+			let fits = match function {
+				AggregateFunction::Count | AggregateFunction::CountDistinct => true,
+				AggregateFunction::Sum | AggregateFunction::Avg => arg_kind.is(Kind::Number),
+				AggregateFunction::Min | AggregateFunction::Max => {
+					kind = arg_kind;
+					arg_kind.ordered(arg_kind)
+				}
+				AggregateFunction::StringAgg => {
+					kind = Kind::String;
+					arg_kind.is(Kind::String)
+				}
+			};
+			if !fits {
+				return Err(Error::TypeMismatch(format!(
+					"Can't apply {}() to {}",
+					function.name(),
+					arg_kind.describe()
+				)));
+			}
+			arg = Some(compiled);
+		}
+		let separator = match (function, args.get(1)) {
+			(AggregateFunction::StringAgg, Some(Expr::Value(query::Value::String(s)))) => s.clone(),
+			(AggregateFunction::StringAgg, _) => {
+				return Err(Error::InvalidArgument(
+					"The separator of string_agg() has to be a string literal".to_string(),
+				));
+			}
+			_ => String::new(),
+		};
+		Ok((
+			Aggregate {
+				function,
+				arg,
+				separator,
+			},
+			kind,
+		))
+	}
+}
+
+/// What an aggregate has collected from the rows of a group so far
+enum State {
+	Count(i64),
+	Distinct(HashSet<Vec<u8>>),
+	/// An integer until a float is added
+	Sum(Option<Datum>),
+	Avg {
+		sum: f64,
+		count: i64,
+	},
+	Min(Option<Datum>),
+	Max(Option<Datum>),
+	StringAgg(Option<String>),
+}
+impl State {
+	fn new(aggregate: &Aggregate) -> State {
+		match aggregate.function {
+			AggregateFunction::Count => State::Count(0),
+			AggregateFunction::CountDistinct => State::Distinct(HashSet::new()),
+			AggregateFunction::Sum => State::Sum(None),
+			AggregateFunction::Avg => State::Avg { sum: 0.0, count: 0 },
+			AggregateFunction::Min => State::Min(None),
+			AggregateFunction::Max => State::Max(None),
+			AggregateFunction::StringAgg => State::StringAgg(None),
+		}
+	}
+
+	/// Adds a row, returning how many more bytes the state takes up. Nulls are skipped, except by `count()`
+	fn update(&mut self, aggregate: &Aggregate, row: &[Datum]) -> Result<usize> {
+		let value = match &aggregate.arg {
+			Some(arg) => arg.eval(row)?,
+			None => Datum::Bool(true),
+		};
+		if value == Datum::Null {
+			return Ok(0);
+		}
+		match self {
+			State::Count(n) => *n += 1,
+			State::Distinct(seen) => {
+				let key = encode_key(std::slice::from_ref(&value));
+				let len = key.len();
+				if seen.insert(key) {
+					return Ok(len + size_of::<Vec<u8>>());
+				}
+			}
+			State::Sum(sum) => {
+				*sum = Some(match (sum.take(), value) {
+					(None, value) => value,
+					(Some(Datum::Int(a)), Datum::Int(b)) => {
+						Datum::Int(a.checked_add(b).ok_or_else(|| {
+							Error::InvalidArgument("Integer overflow".to_string())
+						})?)
+					}
+					(Some(a), b) => Datum::Float(as_float(&a) + as_float(&b)),
+				});
+			}
+			State::Avg { sum, count } => {
+				*sum += as_float(&value);
+				*count += 1;
+			}
+			State::Min(min) => {
+				if min
+					.as_ref()
+					.is_none_or(|min| eval::compare(&value, min).is_some_and(|ord| ord.is_lt()))
+				{
+					*min = Some(value);
+				}
+			}
+			State::Max(max) => {
+				if max
+					.as_ref()
+					.is_none_or(|max| eval::compare(&value, max).is_some_and(|ord| ord.is_gt()))
+				{
+					*max = Some(value);
+				}
+			}
+			State::StringAgg(text) => {
+				let Datum::String(value) = value else {
+					unreachable!()
+				};
+				let grown = match text {
+					Some(text) => {
+						text.push_str(&aggregate.separator);
+						text.push_str(&value);
+						aggregate.separator.len() + value.len()
+					}
+					None => {
+						let len = value.len();
+						*text = Some(value);
+						len
+					}
+				};
+				return Ok(grown);
+			}
+		}
+		Ok(0)
+	}
+
+	fn finish(self) -> Datum {
+		match self {
+			State::Count(n) => Datum::Int(n),
+			State::Distinct(seen) => Datum::Int(seen.len() as i64),
+			State::Avg { count: 0, .. } => Datum::Null,
+			State::Avg { sum, count } => Datum::Float(sum / count as f64),
+			State::Sum(value) | State::Min(value) | State::Max(value) => {
+				value.unwrap_or(Datum::Null)
+			}
+			State::StringAgg(text) => text.map_or(Datum::Null, Datum::String),
+		}
+	}
+}
+
+fn as_float(datum: &Datum) -> f64 {
+	match datum {
+		Datum::Int(n) => *n as f64,
+		Datum::Float(n) => *n,
+		_ => f64::NAN,
+	}
+}
+
+fn unaliased(expr: &Expr) -> &Expr {
+	match expr {
+		Expr::Alias { expr, .. } => expr,
+		expr => expr,
+	}
+}
+
+/// Adds the aggregate functions an expression uses to those found so far, unless they already were
+fn collect_aggregates(expr: &Expr, found: &mut Vec<Expr>) -> Result<()> {
+	match expr {
+		Expr::Aggregate { args, .. } => {
+			let mut nested = Vec::new();
+			for arg in args {
+				collect_aggregates(arg, &mut nested)?;
+			}
+			if !nested.is_empty() {
+				return Err(Error::InvalidArgument(
+					"Aggregate functions can't be nested".to_string(),
+				));
+			}
+			if !found.contains(expr) {
+				found.push(expr.clone());
+			}
+		}
+		Expr::Unary { expr, .. }
+		| Expr::IsNull { expr, .. }
+		| Expr::Alias { expr, .. }
+		| Expr::SortKey { expr, .. } => collect_aggregates(expr, found)?,
+		Expr::Binary { left, right, .. } => {
+			collect_aggregates(left, found)?;
+			collect_aggregates(right, found)?;
+		}
+		Expr::In { expr, list, .. } => {
+			collect_aggregates(expr, found)?;
+			for item in list {
+				collect_aggregates(item, found)?;
+			}
+		}
+		Expr::Between {
+			expr, low, high, ..
+		} => {
+			for expr in [expr, low, high] {
+				collect_aggregates(expr, found)?;
+			}
+		}
+		Expr::List(items) | Expr::Call { args: items, .. } => {
+			for item in items {
+				collect_aggregates(item, found)?;
+			}
+		}
+		Expr::Record(fields) => {
+			for (_, value) in fields {
+				collect_aggregates(value, found)?;
+			}
+		}
+		Expr::Value(_)
+		| Expr::Column { .. }
+		| Expr::InQuery { .. }
+		| Expr::Subquery(_)
+		| Expr::Exists(_)
+		| Expr::Window { .. } => {}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{path::Path, sync::Arc};
+
+	use super::*;
+	use crate::{query::BinaryOp, vfs::MemoryVfs};
+
+	fn people() -> (Scope, Vec<Vec<Datum>>) {
+		let mut scope = Scope::new("people");
+		for (name, kind) in [
+			("name", Kind::String),
+			("country", Kind::String),
+			("age", Kind::Number),
+		] {
+			scope.push(ScopeColumn {
+				object: Some("People".to_string()),
+				name: Some(name.to_string()),
+				kind,
+				expr: None,
+			});
+		}
+		let rows = (0..300)
+			.map(|i| {
+				vec![
+					Datum::String(format!("person {i}")),
+					Datum::String(["nz", "fr", "jp", "br"][i % 4].to_string()),
+					if i % 10 == 0 {
+						Datum::Null
+					} else {
+						Datum::Int(i as i64 % 50)
+					},
+				]
+			})
+			.collect();
+		(scope, rows)
+	}
+
+	fn aggregate(function: AggregateFunction, args: Vec<Expr>) -> Expr {
+		Expr::Aggregate { function, args }
+	}
+
+	fn sorted(mut rows: Vec<Vec<Datum>>) -> Vec<Vec<Datum>> {
+		rows.sort_by_key(|row| encode_key(row));
+		rows
+	}
+
+	#[test]
+	fn spills_to_sort() {
+		let (scope, rows) = people();
+		let age = Expr::ident("age");
+		let outputs = [
+			Expr::ident("country"),
+			aggregate(AggregateFunction::Count, vec![]),
+			aggregate(AggregateFunction::Count, vec![age.clone()]),
+			aggregate(AggregateFunction::CountDistinct, vec![age.clone()]),
+			aggregate(AggregateFunction::Sum, vec![age.clone()]),
+			aggregate(AggregateFunction::Avg, vec![age.clone()]),
+			aggregate(AggregateFunction::Max, vec![Expr::ident("name")]),
+			aggregate(
+				AggregateFunction::StringAgg,
+				vec![
+					Expr::ident("name"),
+					Expr::from(query::Value::String(",".to_string())),
+				],
+			),
+		];
+		let having = [Expr::binary(
+			BinaryOp::NotEq,
+			Expr::ident("country"),
+			Expr::from(query::Value::String("jp".to_string())),
+		)];
+		let plan =
+			AggregatePlan::new(&scope, &[Expr::ident("country")], &having, &outputs, true).unwrap();
+		let vfs = MemoryVfs::new();
+		let temp = TempFiles::new(Arc::new(vfs), Path::new("a.ldb"));
+
+		let hashed = plan.run(rows.clone(), &temp, usize::MAX).unwrap();
+		assert_eq!(hashed.len(), 3);
+		assert_eq!(
+			&hashed[0][..6],
+			&[
+				Datum::String("nz".to_string()),
+				Datum::Int(75),
+				Datum::Int(60),
+				Datum::Int(20),
+				Datum::Int(1500),
+				Datum::Float(25.0),
+			]
+		);
+		assert_eq!(hashed[0][6], Datum::String("person 96".to_string()));
+		let Datum::String(names) = &hashed[0][7] else {
+			panic!()
+		};
+		assert!(names.starts_with("person 0,person 4,person 8,"));
+
+		// too little memory for a hash table, and for more than a row or so of each sorted run
+		let spilled = plan.run(rows, &temp, 200).unwrap();
+		assert_eq!(sorted(spilled), sorted(hashed));
+	}
+
+	#[test]
+	fn ungrouped() {
+		let (scope, _) = people();
+		let outputs = [
+			Expr::Alias {
+				expr: Box::new(aggregate(AggregateFunction::Count, vec![])),
+				name: "people".to_string(),
+			},
+			aggregate(AggregateFunction::Sum, vec![Expr::ident("age")]),
+			aggregate(AggregateFunction::Min, vec![Expr::ident("age")]),
+		];
+		let plan = AggregatePlan::new(&scope, &[], &[], &outputs, false).unwrap();
+		let names: Vec<_> = plan
+			.scope()
+			.columns()
+			.iter()
+			.map(|column| column.name.as_deref())
+			.collect();
+		assert_eq!(names, [Some("people"), Some("sum"), Some("min")]);
+
+		// even without rows there is one group
+		let temp = TempFiles::new(Arc::new(MemoryVfs::new()), Path::new("a.ldb"));
+		assert_eq!(
+			plan.run(Vec::new(), &temp, usize::MAX).unwrap(),
+			[[Datum::Int(0), Datum::Null, Datum::Null]]
+		);
+		assert_eq!(
+			plan.run(Vec::new(), &temp, 0).unwrap(),
+			[[Datum::Int(0), Datum::Null, Datum::Null]]
+		);
+	}
+
+	#[test]
+	fn rejected() {
+		let (scope, _) = people();
+		let plan = |keys: &[Expr], outputs: &[Expr]| {
+			AggregatePlan::new(&scope, keys, &[], outputs, true)
+				.err()
+				.unwrap()
+		};
+		let count = aggregate(AggregateFunction::Count, vec![]);
+
+		// only keys and aggregates can be read from groups
+		assert!(matches!(
+			plan(&[Expr::ident("country")], &[Expr::ident("name")]),
+			Error::NotFound(_)
+		));
+		assert!(matches!(
+			plan(
+				&[],
+				&[aggregate(AggregateFunction::Sum, vec![count.clone()])]
+			),
+			Error::InvalidArgument(_)
+		));
+		assert!(matches!(
+			plan(
+				&[],
+				&[aggregate(AggregateFunction::Avg, vec![Expr::ident("name")])]
+			),
+			Error::TypeMismatch(_)
+		));
+		assert!(matches!(
+			plan(
+				&[],
+				&[aggregate(
+					AggregateFunction::StringAgg,
+					vec![Expr::ident("name"), Expr::ident("country")]
+				)]
+			),
+			Error::InvalidArgument(_)
+		));
+		assert!(matches!(plan(&[count], &[]), Error::InvalidArgument(_)));
+	}
+}
@@ -0,0 +1,664 @@
+use std::{collections::HashMap, iter::Peekable};
+
+use super::sort::{ExternalSort, Sorted, TempFiles, encode_key, row_size};
+use crate::{
+	db::{
+		catalog::IndexEntry,
+		eval::{CompiledExpr, Datum, Scope},
+	},
+	query::{BinaryOp, Expr},
+	*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JoinKind {
+	Inner,
+	Left,
+	Right,
+	Full,
+	Cross,
+	Semi,
+	Anti,
+}
+impl JoinKind {
+	/// The kind of join an LQL function does, such as `left_join`
+	pub fn of_function(name: &str) -> Option<JoinKind> {
+		Some(match name {
+			"join" => JoinKind::Inner,
+			"left_join" => JoinKind::Left,
+			"right_join" => JoinKind::Right,
+			"full_join" => JoinKind::Full,
+			"cross_join" => JoinKind::Cross,
+			"semi_join" => JoinKind::Semi,
+			"anti_join" => JoinKind::Anti,
+			_ => return None,
+		})
+	}
+
+	/// Whether left rows are returned alone, depending on whether they have a match, instead of paired up
+	fn filters(self) -> bool {
+		matches!(self, JoinKind::Semi | JoinKind::Anti)
+	}
+
+	fn keeps_unmatched_left(self) -> bool {
+		matches!(self, JoinKind::Left | JoinKind::Full)
+	}
+
+	fn keeps_unmatched_right(self) -> bool {
+		matches!(self, JoinKind::Right | JoinKind::Full)
+	}
+
+	/// Whether the matches of each left row can be looked up on their own, as the join never returns right rows
+	/// without a match
+	pub fn probes(self) -> bool {
+		!self.keeps_unmatched_right() && self != JoinKind::Cross
+	}
+}
+
+/// How a join pairs up rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JoinStrategy {
+	/// Evaluates the condition for every pair of rows, the only way for conditions without equal keys
+	NestedLoop,
+	/// Looks up the right rows matching each left row in an index of the right side's table
+	IndexNestedLoop,
+	/// Puts the right rows in a hash table by their keys, then looks up each left row's
+	Hash,
+	/// Sorts both sides by their keys, spilling to temporary files, then merges them
+	SortMerge,
+}
+
+/// Picks how to join two sides, given whether the condition equates keys of the two, whether an index of the right
+/// side's table covers those keys, and the estimated bytes of the right side's rows
+///
+/// An index saves reading the right side at all, so it's chosen whenever the join can use one, which `right_bytes`
+/// isn't needed for. Otherwise a hash table of the right side is built if it fits in the query's memory, and the
+/// sides are sorted if not.
+pub(crate) fn choose_strategy(
+	kind: JoinKind,
+	has_keys: bool,
+	has_index: bool,
+	right_bytes: impl FnOnce() -> usize,
+	budget: usize,
+) -> JoinStrategy {
+	if kind == JoinKind::Cross || !has_keys {
+		JoinStrategy::NestedLoop
+	} else if has_index && kind.probes() {
+		JoinStrategy::IndexNestedLoop
+	} else if right_bytes() <= budget {
+		JoinStrategy::Hash
+	} else {
+		JoinStrategy::SortMerge
+	}
+}
+
+/// Finds a hash index over all its records whose key columns are exactly the right side's key columns, returning its
+/// name and, for each of its key columns, which of the join's keys gives its value
+///
+/// `key_columns` holds the column of the right side's table each key reads, `None` for keys that are computed.
+pub(crate) fn key_index<'a>(
+	indexes: impl Iterator<Item = &'a IndexEntry>,
+	key_columns: &[Option<&str>],
+) -> Option<(&'a str, Vec<usize>)> {
+	indexes
+		.filter(|index| index.def.kind == IndexKind::Hash && index.def.predicate.is_none())
+		.find_map(|index| {
+			let key_order: Vec<usize> = index
+				.def
+				.key_columns
+				.iter()
+				.map(|column| {
+					key_columns
+						.iter()
+						.position(|key| *key == Some(column.as_str()))
+				})
+				.collect::<Option<_>>()?;
+			let mut used = key_order.clone();
+			used.sort_unstable();
+			used.dedup();
+			(used.len() == key_columns.len()).then_some((index.name.as_str(), key_order))
+		})
+}
+
+/// How to join rows of two sides, from the join's condition split into keys the two sides have to have equal and
+/// whatever else has to be true
+pub(crate) struct JoinPlan {
+	kind: JoinKind,
+	left_keys: Vec<CompiledExpr>,
+	right_keys: Vec<CompiledExpr>,
+	/// Right key expressions, to find the columns they read
+	right_key_exprs: Vec<Expr>,
+	/// The whole condition, evaluated against a left row followed by a right row
+	condition: Option<CompiledExpr>,
+	/// Rest of the condition once the keys are known to be equal
+	residual: Option<CompiledExpr>,
+	left_width: usize,
+	right_width: usize,
+	/// Columns of the rows the join returns
+	scope: Scope,
+}
+impl JoinPlan {
+	pub fn new(
+		kind: JoinKind,
+		left: &Scope,
+		right: &Scope,
+		condition: Option<&Expr>,
+	) -> Result<JoinPlan> {
+		let mut joined = Scope::new("the joined rows");
+		for column in left.columns().iter().chain(right.columns()) {
+			joined.push(column.clone());
+		}
+		let mut plan = JoinPlan {
+			kind,
+			left_keys: Vec::new(),
+			right_keys: Vec::new(),
+			right_key_exprs: Vec::new(),
+			condition: None,
+			residual: None,
+			left_width: left.columns().len(),
+			right_width: right.columns().len(),
+			scope: if kind.filters() {
+				left.clone()
+			} else {
+				joined.clone()
+			},
+		};
+		let Some(condition) = condition else {
+			return Ok(plan);
+		};
+		plan.condition = Some(CompiledExpr::condition(condition, &joined)?);
+
+		let mut rest = Vec::new();
+		for conjunct in conjuncts(condition) {
+			if let Expr::Binary {
+				op: BinaryOp::Eq,
+				left: a,
+				right: b,
+			} = conjunct
+			{
+				let sides = (
+					CompiledExpr::new(a, left).ok(),
+					CompiledExpr::new(a, right).ok(),
+					CompiledExpr::new(b, left).ok(),
+					CompiledExpr::new(b, right).ok(),
+				);
+				let keys = match sides {
+					(Some(a), None, None, Some(b)) => Some((a, b, right_key_of(conjunct, false))),
+					(None, Some(a), Some(b), None) => Some((b, a, right_key_of(conjunct, true))),
+					_ => None,
+				};
+				if let Some(((left_key, _), (right_key, _), right_expr)) = keys {
+					plan.left_keys.push(left_key);
+					plan.right_keys.push(right_key);
+					plan.right_key_exprs.push(right_expr.clone());
+					continue;
+				}
+			}
+			rest.push(conjunct.clone());
+		}
+		plan.residual = rest
+			.into_iter()
+			.reduce(|a, b| Expr::binary(BinaryOp::And, a, b))
+			.map(|residual| CompiledExpr::condition(&residual, &joined))
+			.transpose()?;
+		Ok(plan)
+	}
+
+	pub fn scope(&self) -> &Scope {
+		&self.scope
+	}
+
+	pub fn has_keys(&self) -> bool {
+		!self.left_keys.is_empty()
+	}
+
+	/// Position of the column of the right side each key reads, `None` for keys that are computed
+	pub fn right_key_columns(&self, right: &Scope) -> Vec<Option<usize>> {
+		self.right_key_exprs
+			.iter()
+			.map(|expr| match expr {
+				Expr::Value(query::Value::Identifier(_)) | Expr::Column { .. } => {
+					right.resolve(expr).ok().flatten()
+				}
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Values of a left row's keys, `None` if any is null, as null never equals anything
+	pub fn left_key(&self, row: &[Datum]) -> Result<Option<Vec<Datum>>> {
+		let key: Vec<Datum> = self
+			.left_keys
+			.iter()
+			.map(|key| key.eval(row))
+			.collect::<Result<_>>()?;
+		Ok((!key.contains(&Datum::Null)).then_some(key))
+	}
+
+	/// Joins the rows of the two sides with a strategy other than an index nested-loop join
+	pub fn run(
+		&self,
+		strategy: JoinStrategy,
+		left: Vec<Vec<Datum>>,
+		right: Vec<Vec<Datum>>,
+		temp: &TempFiles,
+		budget: usize,
+	) -> Result<Vec<Vec<Datum>>> {
+		match strategy {
+			JoinStrategy::NestedLoop => self.nested_loop(left, right),
+			JoinStrategy::Hash => self.hash(left, right),
+			JoinStrategy::SortMerge => self.sort_merge(left, right, temp, budget),
+			JoinStrategy::IndexNestedLoop => unreachable!(),
+		}
+	}
+
+	fn nested_loop(
+		&self,
+		left: Vec<Vec<Datum>>,
+		right: Vec<Vec<Datum>>,
+	) -> Result<Vec<Vec<Datum>>> {
+		let mut out = Vec::new();
+		let mut matched = self.matched(right.len());
+		for row in left {
+			let candidates = right.iter().map(Vec::as_slice).enumerate();
+			self.join_row(row, candidates, false, &mut matched, &mut out)?;
+		}
+		self.unmatched_right(right, &matched, &mut out);
+		Ok(out)
+	}
+
+	fn hash(&self, left: Vec<Vec<Datum>>, right: Vec<Vec<Datum>>) -> Result<Vec<Vec<Datum>>> {
+		let mut table: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+		for (i, row) in right.iter().enumerate() {
+			if let Some(key) = key_of(&self.right_keys, row)? {
+				table.entry(key).or_default().push(i);
+			}
+		}
+		let mut out = Vec::new();
+		let mut matched = self.matched(right.len());
+		for row in left {
+			let found = match key_of(&self.left_keys, &row)? {
+				Some(key) => table.get(&key).map(Vec::as_slice).unwrap_or_default(),
+				None => &[],
+			};
+			let candidates = found.iter().map(|&i| (i, right[i].as_slice()));
+			self.join_row(row, candidates, true, &mut matched, &mut out)?;
+		}
+		self.unmatched_right(right, &matched, &mut out);
+		Ok(out)
+	}
+
+	fn sort_merge(
+		&self,
+		left: Vec<Vec<Datum>>,
+		right: Vec<Vec<Datum>>,
+		temp: &TempFiles,
+		budget: usize,
+	) -> Result<Vec<Vec<Datum>>> {
+		let mut out = Vec::new();
+		let mut left_sort = ExternalSort::new(temp, budget / 2);
+		for row in left {
+			match key_of(&self.left_keys, &row)? {
+				Some(key) => left_sort.push(key, row)?,
+				None => self.join_row(row, std::iter::empty(), true, &mut [], &mut out)?,
+			}
+		}
+		let mut right_sort = ExternalSort::new(temp, budget / 2);
+		for row in right {
+			match key_of(&self.right_keys, &row)? {
+				Some(key) => right_sort.push(key, row)?,
+				None => self.unmatched_right(vec![row], &[false], &mut out),
+			}
+		}
+
+		let mut lefts = Groups(left_sort.finish()?.peekable());
+		let mut rights = Groups(right_sort.finish()?.peekable());
+		let (mut left, mut right) = (lefts.next()?, rights.next()?);
+		loop {
+			let ord = match (&left, &right) {
+				(None, None) => break,
+				(Some(_), None) => std::cmp::Ordering::Less,
+				(None, Some(_)) => std::cmp::Ordering::Greater,
+				(Some((a, _)), Some((b, _))) => a.cmp(b),
+			};
+			if ord.is_le() {
+				let (_, group) = right.take_if(|_| ord.is_eq()).unwrap_or_default();
+				let mut matched = self.matched(group.len());
+				for row in left.take().unwrap().1 {
+					let candidates = group.iter().map(Vec::as_slice).enumerate();
+					self.join_row(row, candidates, true, &mut matched, &mut out)?;
+				}
+				self.unmatched_right(group, &matched, &mut out);
+				left = lefts.next()?;
+				if ord.is_eq() {
+					right = rights.next()?;
+				}
+			} else {
+				let (_, group) = right.take().unwrap();
+				let matched = vec![false; group.len()];
+				self.unmatched_right(group, &matched, &mut out);
+				right = rights.next()?;
+			}
+		}
+		Ok(out)
+	}
+
+	/// Joins a left row with the right rows it may match, marking those it did in `matched`, which is empty unless
+	/// the join returns right rows without a match. `keys_equal` is whether the candidates were found by their keys,
+	/// leaving only the rest of the condition to check
+	pub fn join_row<'r>(
+		&self,
+		left: Vec<Datum>,
+		candidates: impl Iterator<Item = (usize, &'r [Datum])>,
+		keys_equal: bool,
+		matched: &mut [bool],
+		out: &mut Vec<Vec<Datum>>,
+	) -> Result<()> {
+		let condition = match keys_equal {
+			true => &self.residual,
+			false => &self.condition,
+		};
+		let mut found = false;
+		for (i, right) in candidates {
+			let mut row = Vec::with_capacity(self.left_width + self.right_width);
+			row.extend_from_slice(&left);
+			row.extend_from_slice(right);
+			if let Some(condition) = condition
+				&& !condition.is_true(row.as_slice())?
+			{
+				continue;
+			}
+			found = true;
+			if let Some(matched) = matched.get_mut(i) {
+				*matched = true;
+			}
+			if self.kind.filters() {
+				break;
+			}
+			out.push(row);
+		}
+		match self.kind {
+			JoinKind::Semi if found => out.push(left),
+			JoinKind::Anti if !found => out.push(left),
+			kind if kind.keeps_unmatched_left() && !found => {
+				let mut row = left;
+				row.resize(self.left_width + self.right_width, Datum::Null);
+				out.push(row);
+			}
+			_ => {}
+		}
+		Ok(())
+	}
+
+	/// Which right rows were matched, only tracked if the join returns those that weren't
+	fn matched(&self, rows: usize) -> Vec<bool> {
+		match self.kind.keeps_unmatched_right() {
+			true => vec![false; rows],
+			false => Vec::new(),
+		}
+	}
+
+	/// Adds the right rows without a match paired with nulls, if the join returns them
+	fn unmatched_right(&self, rows: Vec<Vec<Datum>>, matched: &[bool], out: &mut Vec<Vec<Datum>>) {
+		if !self.kind.keeps_unmatched_right() {
+			return;
+		}
+		for (row, matched) in rows.into_iter().zip(matched) {
+			if !matched {
+				let mut padded = vec![Datum::Null; self.left_width];
+				padded.extend(row);
+				out.push(padded);
+			}
+		}
+	}
+}
+
+/// Estimated bytes a hash table of rows takes up, counting each row and a rough size of its key and entry
+pub(crate) fn rows_size(rows: &[Vec<Datum>]) -> usize {
+	rows.iter().map(|row| row_size(row) + 64).sum()
+}
+
+/// Encoded values of keys for a row, `None` if any is null
+fn key_of(keys: &[CompiledExpr], row: &[Datum]) -> Result<Option<Vec<u8>>> {
+	let key: Vec<Datum> = keys
+		.iter()
+		.map(|key| key.eval(row))
+		.collect::<Result<_>>()?;
+	Ok((!key.contains(&Datum::Null)).then(|| encode_key(&key)))
+}
+
+/// Side of an equality a join key of the right side is on
+fn right_key_of(conjunct: &Expr, swapped: bool) -> &Expr {
+	let Expr::Binary { left, right, .. } = conjunct else {
+		unreachable!()
+	};
+	if swapped { left } else { right }
+}
+
+/// Conditions that all have to be true for an expression to be, split at its `and`s
+fn conjuncts(expr: &Expr) -> Vec<&Expr> {
+	match expr {
+		Expr::Binary {
+			op: BinaryOp::And,
+			left,
+			right,
+		} => {
+			let mut found = conjuncts(left);
+			found.extend(conjuncts(right));
+			found
+		}
+		expr => vec![expr],
+	}
+}
+
+/// Encoded key of a run of rows, and the rows
+type Group = (Vec<u8>, Vec<Vec<Datum>>);
+
+/// Rows of a sorted side, a run of rows with equal keys at a time
+struct Groups(Peekable<Sorted>);
+impl Groups {
+	fn next(&mut self) -> Result<Option<Group>> {
+		let Some(first) = self.0.next() else {
+			return Ok(None);
+		};
+		let (key, row) = first?;
+		let mut rows = vec![row];
+		// an error is returned by the call after
+		while let Some(Ok((next, _))) = self.0.peek()
+			&& *next == key
+		{
+			rows.push(self.0.next().unwrap()?.1);
+		}
+		Ok(Some((key, rows)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{path::Path, sync::Arc};
+
+	use super::*;
+	use crate::{
+		db::eval::{Kind, ScopeColumn},
+		vfs::MemoryVfs,
+	};
+
+	fn scope(object: &str, columns: &[&str]) -> Scope {
+		let mut scope = Scope::new(object);
+		for name in columns {
+			scope.push(ScopeColumn {
+				object: Some(object.to_string()),
+				name: Some(name.to_string()),
+				kind: Kind::Number,
+				expr: None,
+			});
+		}
+		scope
+	}
+
+	fn column(object: &str, name: &str) -> Expr {
+		Expr::Column {
+			object: object.to_string(),
+			name: name.to_string(),
+		}
+	}
+
+	fn ints(rows: &[&[Option<i64>]]) -> Vec<Vec<Datum>> {
+		rows.iter()
+			.map(|row| {
+				row.iter()
+					.map(|n| n.map_or(Datum::Null, Datum::Int))
+					.collect()
+			})
+			.collect()
+	}
+
+	fn sorted(mut rows: Vec<Vec<Datum>>) -> Vec<Vec<Datum>> {
+		rows.sort_by_key(|row| encode_key(row));
+		rows
+	}
+
+	#[test]
+	fn planner() {
+		use JoinKind::*;
+		use JoinStrategy::*;
+
+		let small = || 100;
+		assert_eq!(
+			choose_strategy(Cross, false, false, small, 1000),
+			NestedLoop
+		);
+		assert_eq!(choose_strategy(Inner, false, true, small, 1000), NestedLoop);
+		for kind in [Inner, Left, Semi, Anti] {
+			assert_eq!(
+				choose_strategy(kind, true, true, || unreachable!(), 1000),
+				IndexNestedLoop
+			);
+		}
+		// right rows without a match are only found by reading the right side
+		assert_eq!(choose_strategy(Right, true, true, small, 1000), Hash);
+		assert_eq!(choose_strategy(Full, true, false, small, 1000), Hash);
+		assert_eq!(
+			choose_strategy(Inner, true, false, || 1001, 1000),
+			SortMerge
+		);
+
+		let users = scope("Users", &["id", "group"]);
+		let orders = scope("Orders", &["user_id", "group", "amount"]);
+		let condition = Expr::binary(
+			BinaryOp::And,
+			Expr::binary(
+				BinaryOp::Eq,
+				column("Orders", "user_id"),
+				column("Users", "id"),
+			),
+			Expr::binary(
+				BinaryOp::And,
+				Expr::binary(
+					BinaryOp::Gt,
+					Expr::ident("amount"),
+					Expr::from(query::Value::Integer(5)),
+				),
+				Expr::binary(
+					BinaryOp::Eq,
+					column("Users", "group"),
+					Expr::binary(
+						BinaryOp::Add,
+						column("Orders", "group"),
+						Expr::from(query::Value::Integer(1)),
+					),
+				),
+			),
+		);
+		let plan = JoinPlan::new(Inner, &users, &orders, Some(&condition)).unwrap();
+		assert_eq!(plan.left_keys.len(), 2);
+		assert!(plan.residual.is_some());
+		assert_eq!(plan.right_key_columns(&orders), [Some(0), None]);
+		assert_eq!(plan.scope().columns().len(), 5);
+
+		let or = Expr::binary(
+			BinaryOp::Or,
+			Expr::binary(
+				BinaryOp::Eq,
+				column("Orders", "user_id"),
+				column("Users", "id"),
+			),
+			Expr::from(query::Value::Bool(false)),
+		);
+		assert!(
+			!JoinPlan::new(Inner, &users, &orders, Some(&or))
+				.unwrap()
+				.has_keys()
+		);
+		let ambiguous = Expr::binary(BinaryOp::Eq, Expr::ident("group"), column("Users", "id"));
+		assert!(JoinPlan::new(Inner, &users, &orders, Some(&ambiguous)).is_err());
+	}
+
+	#[test]
+	fn strategies_agree() {
+		let users = scope("Users", &["id", "group"]);
+		let orders = scope("Orders", &["user_id", "amount"]);
+		let left = ints(&[
+			&[Some(1), Some(10)],
+			&[Some(2), Some(10)],
+			&[Some(3), None],
+			&[None, Some(20)],
+			&[Some(2), Some(30)],
+		]);
+		let right = ints(&[
+			&[Some(2), Some(5)],
+			&[Some(2), Some(50)],
+			&[Some(3), Some(7)],
+			&[Some(4), Some(1)],
+			&[None, Some(9)],
+			&[Some(1), Some(2)],
+		]);
+		let condition = Expr::binary(
+			BinaryOp::And,
+			Expr::binary(
+				BinaryOp::Eq,
+				column("Users", "id"),
+				column("Orders", "user_id"),
+			),
+			Expr::binary(
+				BinaryOp::GtEq,
+				Expr::ident("amount"),
+				Expr::from(query::Value::Integer(5)),
+			),
+		);
+		let temp = TempFiles::new(Arc::new(MemoryVfs::new()), Path::new("a.ldb"));
+		let expected_lengths = [
+			(JoinKind::Inner, 5),
+			(JoinKind::Left, 7),
+			(JoinKind::Right, 8),
+			(JoinKind::Full, 10),
+			(JoinKind::Semi, 3),
+			(JoinKind::Anti, 2),
+		];
+		for (kind, expected) in expected_lengths {
+			let plan = JoinPlan::new(kind, &users, &orders, Some(&condition)).unwrap();
+			let joined = |strategy, budget| {
+				sorted(
+					plan.run(strategy, left.clone(), right.clone(), &temp, budget)
+						.unwrap(),
+				)
+			};
+			let nested = joined(JoinStrategy::NestedLoop, usize::MAX);
+			assert_eq!(nested.len(), expected, "{kind:?}");
+			assert_eq!(joined(JoinStrategy::Hash, usize::MAX), nested, "{kind:?}");
+			assert_eq!(
+				joined(JoinStrategy::SortMerge, usize::MAX),
+				nested,
+				"{kind:?}"
+			);
+			// spilling every row to its own run
+			assert_eq!(joined(JoinStrategy::SortMerge, 0), nested, "{kind:?}");
+		}
+
+		let cross = JoinPlan::new(JoinKind::Cross, &users, &orders, None).unwrap();
+		let pairs = cross
+			.run(JoinStrategy::NestedLoop, left, right, &temp, usize::MAX)
+			.unwrap();
+		assert_eq!(pairs.len(), 30);
+	}
+}
@@ -0,0 +1,897 @@
+mod aggregate;
+mod join;
+mod sort;
+
+use std::collections::{HashMap, HashSet};
+
+use super::{
+	LilDbConnection,
+	disk::RecordId,
+	eval::{self, CompiledExpr, Datum, Kind, Scope, ScopeColumn},
+	record::{Record, Value, ValueType},
+	table::TableDef,
+};
+use crate::{
+	query::{self, Expr, Query},
+	*,
+};
+use aggregate::AggregatePlan;
+use join::{JoinKind, JoinPlan, JoinStrategy};
+use sort::encode_key;
+
+pub(crate) use sort::TempFiles;
+
+/// What a query returned, the rows it read, or how many records it changed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct QueryResult {
+	/// Names of the columns of the rows, in order. Computed columns that weren't named with `as` are called
+	/// `column1`, `column2` and so on, by their position
+	pub columns: Vec<String>,
+	pub rows: Vec<Vec<query::Value>>,
+	/// Records inserted, updated or deleted
+	pub changed: u64,
+}
+
+/// Rows a query has computed so far, and the columns they hold
+#[derive(Clone)]
+struct Relation {
+	scope: Scope,
+	rows: Vec<Vec<Datum>>,
+	/// Table the rows are still the unchanged records of, with their IDs, for functions that go through the table
+	source: Option<Source>,
+	/// Query `matches` found the rows with, which `rank` orders them by
+	text_query: Option<String>,
+}
+#[derive(Clone)]
+struct Source {
+	table: String,
+	rids: Vec<RecordId>,
+}
+impl Relation {
+	fn new(scope: Scope, rows: Vec<Vec<Datum>>) -> Relation {
+		Relation {
+			scope,
+			rows,
+			source: None,
+			text_query: None,
+		}
+	}
+
+	/// The table the rows are the records of, failing for rows a function computed
+	fn source(&self, function: &str) -> Result<&Source> {
+		self.source.as_ref().ok_or_else(|| {
+			Error::InvalidArgument(format!(
+				"\"{function}\" can only be called on the records of a table"
+			))
+		})
+	}
+
+	/// Keeps the rows `keep` is true for
+	fn retain(&mut self, keep: &[bool]) {
+		let mut kept = keep.iter();
+		self.rows.retain(|_| *kept.next().unwrap());
+		if let Some(source) = &mut self.source {
+			let mut kept = keep.iter();
+			source.rids.retain(|_| *kept.next().unwrap());
+		}
+	}
+
+	/// Puts the rows in a new order, given by their positions, leaving out those it doesn't list
+	fn reorder(&mut self, order: &[usize]) {
+		let mut rows: Vec<Option<Vec<Datum>>> = self.rows.drain(..).map(Some).collect();
+		self.rows = order.iter().map(|&i| rows[i].take().unwrap()).collect();
+		if let Some(source) = &mut self.source {
+			source.rids = order.iter().map(|&i| source.rids[i]).collect();
+		}
+	}
+
+	fn into_result(self) -> QueryResult {
+		QueryResult {
+			columns: self
+				.scope
+				.columns()
+				.iter()
+				.enumerate()
+				.map(|(i, column)| {
+					column
+						.name
+						.clone()
+						.unwrap_or_else(|| format!("column{}", i + 1))
+				})
+				.collect(),
+			rows: self
+				.rows
+				.into_iter()
+				.map(|row| row.into_iter().map(to_query_value).collect())
+				.collect(),
+			changed: 0,
+		}
+	}
+}
+
+/// What running a query gave
+enum Output {
+	Rows(Relation),
+	/// Records written by `insert`, `update` or `delete_records`
+	Changed(u64),
+}
+
+/// Keys and conditions of groups, until `aggregate` reduces them to rows
+#[derive(Default)]
+struct Grouping {
+	keys: Vec<Expr>,
+	having: Vec<Expr>,
+	grouped: bool,
+}
+
+impl LilDbConnection {
+	/// Runs a query, such as one parsed with `lql::parse`, returning the rows it reads or how many records it writes
+	///
+	/// Tables are read with a full scan, then each function runs on the rows the one before it returned. `group_by`
+	/// keeps groups in a hash table while they fit in the query memory set with [`LilDbOpts::query_memory`], and
+	/// otherwise sorts the rows by their keys, spilling to temporary files. `matches` and `rank` go through the table's
+	/// only full-text index, and `within` and `nearest` through its only spatial index. Joins look up matches in a hash
+	/// index of the joined table's keys when there is one, and otherwise hash or sort-merge join the same way.
+	///
+	/// Fails with `Error::InvalidArgument` for what can't be run yet: window functions, subqueries, recursive
+	/// `let` queries, `get` and `upsert` (tables have no primary keys), and `create`, `ensure_exists` and `delete`
+	/// (tables are created with [`LilDbConnection::create_table`]). Writes made before a query fails are kept, unless
+	/// it's run with [`LilDbConnection::run_script`].
+	pub fn execute(&mut self, query: &Query) -> Result<QueryResult> {
+		match self.run(query, &HashMap::new())? {
+			Output::Rows(relation) => Ok(relation.into_result()),
+			Output::Changed(changed) => Ok(QueryResult {
+				changed,
+				..QueryResult::default()
+			}),
+		}
+	}
+
+	/// Runs the queries of a script, such as a migration parsed with `lql::parse_script`, in order and in a single
+	/// transaction, returning what each query returned
+	///
+	/// If a query fails, every write the script made is rolled back before its error is returned. Pages are saved to a
+	/// journal next to the database before the script first overwrites them, so a script interrupted by a crash is
+	/// rolled back once the database is opened again, which a read-only connection can't do, failing with
+	/// `Error::ReadOnly` instead. Other connections see the script's writes as they're made.
+	pub fn run_script(&mut self, script: &[Query]) -> Result<Vec<QueryResult>> {
+		if self.is_read_only() {
+			// nothing can be written, so there is nothing to roll back
+			return script.iter().map(|query| self.execute(query)).collect();
+		}
+		self.begin()?;
+		let results = script
+			.iter()
+			.map(|query| self.execute(query))
+			.collect::<Result<Vec<_>>>()
+			.and_then(|results| self.commit().map(|()| results));
+		if results.is_err() {
+			self.rollback()?;
+		}
+		results
+	}
+
+	/// Runs a query, which can read from the named queries of the queries it's part of
+	fn run(&mut self, query: &Query, named: &HashMap<String, Relation>) -> Result<Output> {
+		let mut with_ctes;
+		let mut named = named;
+		if !query.ctes().is_empty() {
+			with_ctes = named.clone();
+			for cte in query.ctes() {
+				if cte.recursive {
+					return Err(Error::InvalidArgument(
+						"Recursive \"let\" queries can't be run yet".to_string(),
+					));
+				}
+				let relation = self.rows_of(&cte.query, &with_ctes)?;
+				let scope = relation.scope.with_object(&cte.name);
+				with_ctes.insert(cte.name.clone(), Relation::new(scope, relation.rows));
+			}
+			named = &with_ctes;
+		}
+
+		let object = query.object_name();
+		let mut call = query.function();
+		if let Some(first) = call {
+			match first.function().name {
+				"insert" => return self.insert(object, first.args()).map(Output::Changed),
+				name @ ("upsert" | "get") => {
+					return Err(Error::InvalidArgument(format!(
+						"Tables don't have primary keys, so \"{name}\" can't be run on them"
+					)));
+				}
+				"create" | "ensure_exists" | "delete" => {
+					return Err(Error::InvalidArgument(
+						"Objects can't be created or deleted by queries yet, tables are created with \
+						 LilDbConnection::create_table"
+							.to_string(),
+					));
+				}
+				_ => {}
+			}
+		}
+
+		let mut relation = self.read(object, named)?;
+		let mut grouping: Option<Grouping> = None;
+		while let Some(function) = call {
+			let name = function.function().name;
+			let args = function.args();
+			let text_query = relation.text_query.take();
+			if grouping.is_some() && !matches!(name, "having" | "aggregate") {
+				return Err(groups_unread());
+			}
+			match name {
+				"where" => {
+					let condition = CompiledExpr::condition(&args[0], &relation.scope)?;
+					let keep = relation
+						.rows
+						.iter()
+						.map(|row| condition.is_true(row.as_slice()))
+						.collect::<Result<Vec<_>>>()?;
+					relation.retain(&keep);
+				}
+				"select" => {
+					let mut scope = Scope::new("the selected columns");
+					let mut columns = Vec::with_capacity(args.len());
+					for arg in args {
+						let (compiled, column) = output_column(arg, &relation.scope)?;
+						columns.push(compiled);
+						scope.push(column);
+					}
+					let rows = relation
+						.rows
+						.iter()
+						.map(|row| {
+							columns
+								.iter()
+								.map(|column| column.eval(row.as_slice()))
+								.collect()
+						})
+						.collect::<Result<_>>()?;
+					relation = Relation::new(scope, rows);
+				}
+				"order_by" => order_by(&mut relation, args)?,
+				"limit" => {
+					let n = count_arg(name, &args[0])?.min(relation.rows.len());
+					relation.reorder(&(0..n).collect::<Vec<_>>());
+				}
+				"offset" => {
+					let n = count_arg(name, &args[0])?.min(relation.rows.len());
+					relation.reorder(&(n..relation.rows.len()).collect::<Vec<_>>());
+				}
+				"group_by" => {
+					grouping = Some(Grouping {
+						keys: args.to_vec(),
+						having: Vec::new(),
+						grouped: true,
+					});
+				}
+				"having" => grouping
+					.get_or_insert_default()
+					.having
+					.push(args[0].clone()),
+				"aggregate" => {
+					let grouping = grouping.take().unwrap_or_default();
+					let plan = AggregatePlan::new(
+						&relation.scope,
+						&grouping.keys,
+						&grouping.having,
+						args,
+						grouping.grouped,
+					)?;
+					let rows = plan.run(relation.rows, &self.temp, self.opts.query_memory)?;
+					relation = Relation::new(plan.scope().clone(), rows);
+				}
+				"matches" => {
+					let text = string_arg(name, &args[0])?;
+					let table = relation.source(name)?.table.clone();
+					let index = self.only_index(&table, "full-text", |connection, index| {
+						connection.catalog.text_index(index).is_some()
+					})?;
+					let found: HashSet<RecordId> = self.tables[&table]
+						.index(&index)?
+						.matches(&mut self.disk, &text)?
+						.into_iter()
+						.collect();
+					let keep: Vec<bool> = relation
+						.source(name)?
+						.rids
+						.iter()
+						.map(|rid| found.contains(rid))
+						.collect();
+					relation.retain(&keep);
+					relation.text_query = Some(text);
+				}
+				"rank" => {
+					let Some(text) = text_query else {
+						return Err(Error::InvalidArgument(
+							"\"rank\" can only follow \"matches\"".to_string(),
+						));
+					};
+					let table = relation.source(name)?.table.clone();
+					let index = self.only_index(&table, "full-text", |connection, index| {
+						connection.catalog.text_index(index).is_some()
+					})?;
+					let scores: HashMap<RecordId, f64> = self.tables[&table]
+						.index(&index)?
+						.rank(&mut self.disk, &text)?
+						.into_iter()
+						.collect();
+					let rids = &relation.source(name)?.rids;
+					let mut order: Vec<usize> = (0..rids.len()).collect();
+					order.sort_by(|&a, &b| {
+						let score = |i: usize| scores.get(&rids[i]).copied().unwrap_or(0.0);
+						score(b).total_cmp(&score(a))
+					});
+					relation.reorder(&order);
+				}
+				"within" => {
+					let Expr::Value(query::Value::Rect(window)) = &args[0] else {
+						return Err(Error::InvalidArgument(
+							"\"within\" takes a rect, such as rect(0, 0, 10, 5)".to_string(),
+						));
+					};
+					let table = relation.source(name)?.table.clone();
+					let index = self.only_index(&table, "spatial", spatial)?;
+					let found: HashSet<RecordId> = self.tables[&table]
+						.index(&index)?
+						.within(&mut self.disk, window)?
+						.into_iter()
+						.collect();
+					let keep: Vec<bool> = relation
+						.source(name)?
+						.rids
+						.iter()
+						.map(|rid| found.contains(rid))
+						.collect();
+					relation.retain(&keep);
+				}
+				"nearest" => {
+					let (Expr::Value(query::Value::Point(point)), Some(n)) =
+						(&args[0], args.get(1))
+					else {
+						return Err(Error::InvalidArgument(
+							"\"nearest\" takes a point and a count, such as nearest(point(1, 2), 10)"
+								.to_string(),
+						));
+					};
+					let n = count_arg(name, n)?;
+					let table = relation.source(name)?.table.clone();
+					let index = self.only_index(&table, "spatial", spatial)?;
+					// every record is ranked, so records left out before don't take the place of those kept
+					let nearest = self.tables[&table].index(&index)?.nearest(
+						&mut self.disk,
+						point,
+						relation.rows.len(),
+					)?;
+					let positions: HashMap<RecordId, usize> = relation
+						.source(name)?
+						.rids
+						.iter()
+						.enumerate()
+						.map(|(i, rid)| (*rid, i))
+						.collect();
+					let order: Vec<usize> = nearest
+						.iter()
+						.filter_map(|rid| positions.get(rid).copied())
+						.take(n)
+						.collect();
+					relation.reorder(&order);
+				}
+				"union" | "union_all" => {
+					let other = match &args[0] {
+						Expr::Subquery(query) => self.rows_of(query, named)?,
+						Expr::Value(query::Value::Identifier(object)) => {
+							self.rows_of(&Query::new(object.as_str(), None), named)?
+						}
+						_ => {
+							return Err(Error::InvalidArgument(format!(
+								"\"{name}\" takes a query"
+							)));
+						}
+					};
+					relation = union(relation, other, name == "union")?;
+				}
+				"update" => return self.update(relation, args).map(Output::Changed),
+				"delete_records" => {
+					let source = relation.source(name)?;
+					let mut table = self.table(&source.table)?;
+					for rid in &source.rids {
+						table.delete(*rid)?;
+					}
+					return Ok(Output::Changed(source.rids.len() as u64));
+				}
+				"join" | "left_join" | "right_join" | "full_join" | "cross_join" | "semi_join"
+				| "anti_join" => {
+					let kind = JoinKind::of_function(name).unwrap();
+					relation = self.join(relation, kind, args, named)?;
+				}
+				_ => {
+					return Err(Error::InvalidArgument(format!(
+						"\"{name}\" can't be run on records"
+					)));
+				}
+			}
+			call = function.chained();
+		}
+		if grouping.is_some() {
+			return Err(groups_unread());
+		}
+		Ok(Output::Rows(relation))
+	}
+
+	/// Runs a query that has to return rows, such as one named with `let`
+	fn rows_of(&mut self, query: &Query, named: &HashMap<String, Relation>) -> Result<Relation> {
+		match self.run(query, named)? {
+			Output::Rows(relation) => Ok(relation),
+			Output::Changed(_) => Err(Error::InvalidArgument(
+				"Queries read from by other queries can't write records".to_string(),
+			)),
+		}
+	}
+
+	/// Reads every record of a table, or the rows of a named query
+	fn read(&mut self, object: &str, named: &HashMap<String, Relation>) -> Result<Relation> {
+		if let Some(relation) = named.get(object) {
+			return Ok(relation.clone());
+		}
+		self.open_table(object)?;
+		let open = &self.tables[object];
+		let mut rows = Vec::new();
+		let mut rids = Vec::new();
+		for (rid, rec) in open.storage.scan(&mut self.disk)? {
+			rows.push(rec.items().iter().map(Datum::from).collect());
+			rids.push(rid);
+		}
+		Ok(Relation {
+			scope: Scope::table(object, &open.def),
+			rows,
+			source: Some(Source {
+				table: object.to_string(),
+				rids,
+			}),
+			text_query: None,
+		})
+	}
+
+	/// Joins rows with those of another table or named query, which `args` name and give the condition of
+	fn join(
+		&mut self,
+		left: Relation,
+		kind: JoinKind,
+		args: &[Expr],
+		named: &HashMap<String, Relation>,
+	) -> Result<Relation> {
+		let (object, alias) = match &args[0] {
+			Expr::Value(query::Value::Identifier(object)) => (object, None),
+			Expr::Alias { expr, name } => match &**expr {
+				Expr::Value(query::Value::Identifier(object)) => (object, Some(name)),
+				_ => return Err(join_object()),
+			},
+			_ => return Err(join_object()),
+		};
+		let table = !named.contains_key(object);
+		let mut right_scope = match named.get(object) {
+			Some(relation) => relation.scope.clone(),
+			None => {
+				self.open_table(object)?;
+				Scope::table(object, &self.tables[object].def)
+			}
+		};
+		if let Some(alias) = alias {
+			right_scope = right_scope.with_object(alias);
+		}
+		let plan = JoinPlan::new(kind, &left.scope, &right_scope, args.get(1))?;
+
+		let mut index = None;
+		if table {
+			let def = &self.tables[object].def;
+			let key_columns: Vec<Option<&str>> = plan
+				.right_key_columns(&right_scope)
+				.into_iter()
+				.map(|column| column.map(|i| def.columns()[i].0.as_str()))
+				.collect();
+			let entries = self.tables[object]
+				.indexes
+				.iter()
+				.filter_map(|(name, _)| self.catalog.index(name));
+			index = join::key_index(entries, &key_columns)
+				.map(|(name, key_order)| (name.to_string(), key_order));
+		}
+		let budget = self.opts.query_memory;
+		// the right side is only read if no index is used, keeping any error for when its rows are needed
+		let mut right = None;
+		let strategy = join::choose_strategy(
+			kind,
+			plan.has_keys(),
+			index.is_some(),
+			|| {
+				right
+					.insert(self.read(object, named))
+					.as_ref()
+					.map_or(0, |right| join::rows_size(&right.rows))
+			},
+			budget,
+		);
+		let rows = match strategy {
+			JoinStrategy::IndexNestedLoop => {
+				let (index, key_order) = index.unwrap();
+				self.index_nested_loop(object, &index, &key_order, &plan, left.rows)?
+			}
+			strategy => {
+				let right = right.unwrap_or_else(|| self.read(object, named))?;
+				plan.run(strategy, left.rows, right.rows, &self.temp, budget)?
+			}
+		};
+		Ok(Relation::new(plan.scope().clone(), rows))
+	}
+
+	/// Joins rows with a table by looking up the records matching each in an index of the table's join keys
+	fn index_nested_loop(
+		&mut self,
+		table: &str,
+		index: &str,
+		key_order: &[usize],
+		plan: &JoinPlan,
+		left: Vec<Vec<Datum>>,
+	) -> Result<Vec<Vec<Datum>>> {
+		let open = &self.tables[table];
+		let def = &open.def;
+		let entry = self.catalog.index(index).unwrap();
+		let index = open.index(index)?;
+		let key_types: Vec<(&str, ValueType)> = entry
+			.def
+			.key_columns
+			.iter()
+			.map(|column| {
+				let i = def.column_index(column).unwrap();
+				(column.as_str(), def.columns()[i].1)
+			})
+			.collect();
+		let mut out = Vec::new();
+		for row in left {
+			let mut found = Vec::new();
+			if let Some(key) = plan.left_key(&row)? {
+				// a value that doesn't fit the column's type can't equal any of its values
+				let key: Option<Record> = key_order
+					.iter()
+					.zip(&key_types)
+					.map(|(&i, (column, ty))| to_value(column, *ty, key[i].clone()).ok())
+					.collect::<Option<Vec<_>>>()
+					.map(|values| values.into_iter().fold(Record::new(), Record::item));
+				if let Some(key) = key {
+					for rid in index.get(&mut self.disk, &key)? {
+						if let Some(rec) = open.storage.get(&mut self.disk, rid)? {
+							found.push(rec.items().iter().map(Datum::from).collect::<Vec<_>>());
+						}
+					}
+				}
+			}
+			let candidates = found.iter().map(Vec::as_slice).enumerate();
+			plan.join_row(row, candidates, true, &mut [], &mut out)?;
+		}
+		Ok(out)
+	}
+
+	/// Name of a table's index of a kind, failing unless it has exactly one
+	fn only_index(
+		&self,
+		table: &str,
+		kind: &str,
+		is_kind: impl Fn(&LilDbConnection, &str) -> bool,
+	) -> Result<String> {
+		let mut found = self.tables[table]
+			.indexes
+			.iter()
+			.map(|(name, _)| name)
+			.filter(|name| is_kind(self, name));
+		match (found.next(), found.next()) {
+			(Some(name), None) => Ok(name.clone()),
+			(None, _) => Err(Error::InvalidArgument(format!(
+				"Table \"{table}\" has no {kind} index"
+			))),
+			(Some(_), Some(_)) => Err(Error::InvalidArgument(format!(
+				"Table \"{table}\" has more than one {kind} index, so which to use is unclear"
+			))),
+		}
+	}
+
+	fn insert(&mut self, table: &str, args: &[Expr]) -> Result<u64> {
+		let literals: Vec<&Expr> = match &args[0] {
+			Expr::List(items) => items.iter().collect(),
+			literal => vec![literal],
+		};
+		let def = self.table(table)?.def().clone();
+		let scope = Scope::new("a record literal");
+		let mut records = Vec::with_capacity(literals.len());
+		for literal in literals {
+			let Expr::Record(fields) = literal else {
+				return Err(Error::InvalidArgument(
+					"\"insert\" takes a record, such as {name: \"a\", age: 3}, or a list of them"
+						.to_string(),
+				));
+			};
+			let mut values = vec![None; def.columns().len()];
+			for (i, expr) in assignments(table, &def, fields)? {
+				let (compiled, _) = CompiledExpr::new(expr, &scope)?;
+				values[i] = Some(compiled.eval(&[] as &[Datum])?);
+			}
+			let values = values
+				.into_iter()
+				.zip(def.columns())
+				.map(|(value, (column, _))| {
+					value.ok_or_else(|| {
+						Error::InvalidArgument(format!("No value for column \"{column}\""))
+					})
+				})
+				.collect::<Result<_>>()?;
+			records.push(to_record(&def, values)?);
+		}
+		let mut table = self.table(table)?;
+		for rec in &records {
+			table.insert(rec.clone())?;
+		}
+		Ok(records.len() as u64)
+	}
+
+	fn update(&mut self, relation: Relation, args: &[Expr]) -> Result<u64> {
+		let source = relation.source("update")?;
+		let Expr::Record(fields) = &args[0] else {
+			return Err(Error::InvalidArgument(
+				"\"update\" takes a record, such as {active: false, age: age + 1}".to_string(),
+			));
+		};
+		let def = self.table(&source.table)?.def().clone();
+		let mut compiled = Vec::with_capacity(fields.len());
+		for (i, expr) in assignments(&source.table, &def, fields)? {
+			compiled.push((i, CompiledExpr::new(expr, &relation.scope)?.0));
+		}
+		// every record is computed before any is written, so a failing expression changes nothing
+		let mut records = Vec::with_capacity(relation.rows.len());
+		for row in &relation.rows {
+			let mut values = row.clone();
+			for (i, expr) in &compiled {
+				values[*i] = expr.eval(row.as_slice())?;
+			}
+			records.push(to_record(&def, values)?);
+		}
+		let mut table = self.table(&source.table)?;
+		for (rid, rec) in source.rids.iter().zip(records) {
+			table.update(*rid, rec)?;
+		}
+		Ok(source.rids.len() as u64)
+	}
+}
+
+/// Compiles a column of the rows computed by `select`, `group_by` or `aggregate`, named after its alias or the column
+/// it reads
+fn output_column(expr: &Expr, scope: &Scope) -> Result<(CompiledExpr, ScopeColumn)> {
+	let (expr, alias) = match expr {
+		Expr::Alias { expr, name } => (&**expr, Some(name)),
+		expr => (expr, None),
+	};
+	let (compiled, kind) = CompiledExpr::new(expr, scope)?;
+	let read = scope.resolve(expr)?.map(|i| &scope.columns()[i]);
+	let column = ScopeColumn {
+		object: match alias {
+			Some(_) => None,
+			None => read.and_then(|column| column.object.clone()),
+		},
+		name: alias
+			.cloned()
+			.or_else(|| read.and_then(|column| column.name.clone()))
+			.or_else(|| match expr {
+				Expr::Aggregate { function, .. } => Some(function.name().to_string()),
+				_ => None,
+			}),
+		kind,
+		expr: None,
+	};
+	Ok((compiled, column))
+}
+
+fn order_by(relation: &mut Relation, args: &[Expr]) -> Result<()> {
+	let mut keys = Vec::with_capacity(args.len());
+	for arg in args {
+		let (expr, descending, nulls_first) = match arg {
+			Expr::SortKey {
+				expr,
+				descending,
+				nulls_first,
+			} => (&**expr, *descending, *nulls_first),
+			expr => (expr, false, false),
+		};
+		let (compiled, kind) = CompiledExpr::new(expr, &relation.scope)?;
+		if !kind.ordered(kind) && !kind.is(Kind::Bool) {
+			return Err(Error::TypeMismatch(format!(
+				"Can't order by {}",
+				kind.describe()
+			)));
+		}
+		keys.push((compiled, descending, nulls_first));
+	}
+	let values = relation
+		.rows
+		.iter()
+		.map(|row| {
+			keys.iter()
+				.map(|(key, ..)| key.eval(row.as_slice()))
+				.collect::<Result<Vec<_>>>()
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let mut order: Vec<usize> = (0..relation.rows.len()).collect();
+	order.sort_by(|&a, &b| {
+		keys.iter()
+			.enumerate()
+			.map(|(i, (_, descending, nulls_first))| {
+				let (a, b) = (&values[a][i], &values[b][i]);
+				match (a, b) {
+					(Datum::Null, Datum::Null) => std::cmp::Ordering::Equal,
+					(Datum::Null, _) if *nulls_first => std::cmp::Ordering::Less,
+					(Datum::Null, _) => std::cmp::Ordering::Greater,
+					(_, Datum::Null) if *nulls_first => std::cmp::Ordering::Greater,
+					(_, Datum::Null) => std::cmp::Ordering::Less,
+					(Datum::Bool(a), Datum::Bool(b)) => a.cmp(b),
+					_ => {
+						let ord = eval::compare(a, b).unwrap_or(std::cmp::Ordering::Equal);
+						if *descending { ord.reverse() } else { ord }
+					}
+				}
+			})
+			.find(|ord| ord.is_ne())
+			.unwrap_or(std::cmp::Ordering::Equal)
+	});
+	relation.reorder(&order);
+	Ok(())
+}
+
+/// Adds the rows of another query, dropping rows equal to one before them unless keeping duplicates
+fn union(mut relation: Relation, other: Relation, distinct: bool) -> Result<Relation> {
+	let (ours, theirs) = (relation.scope.columns(), other.scope.columns());
+	if ours.len() != theirs.len() {
+		return Err(Error::InvalidArgument(format!(
+			"Can't add rows of {} columns to rows of {}",
+			theirs.len(),
+			ours.len()
+		)));
+	}
+	let mut scope = Scope::new("the united rows");
+	for (ours, theirs) in ours.iter().zip(theirs) {
+		if !ours.kind.is(theirs.kind) && !theirs.kind.is(ours.kind) {
+			return Err(Error::TypeMismatch(format!(
+				"Can't add {} to a column of {}",
+				theirs.kind.describe(),
+				ours.kind.describe()
+			)));
+		}
+		scope.push(ScopeColumn {
+			kind: if ours.kind == Kind::Null {
+				theirs.kind
+			} else {
+				ours.kind
+			},
+			expr: None,
+			..ours.clone()
+		});
+	}
+	relation.rows.extend(other.rows);
+	if distinct {
+		let mut seen = HashSet::new();
+		relation.rows.retain(|row| seen.insert(encode_key(row)));
+	}
+	Ok(Relation::new(scope, relation.rows))
+}
+
+/// Columns a record literal sets, by their position in a table's records
+fn assignments<'a>(
+	table: &str,
+	def: &TableDef,
+	fields: &'a [(String, Expr)],
+) -> Result<Vec<(usize, &'a Expr)>> {
+	let mut found: Vec<(usize, &Expr)> = Vec::with_capacity(fields.len());
+	for (column, expr) in fields {
+		let Some(i) = def.column_index(column) else {
+			return Err(Error::NotFound(format!(
+				"column \"{column}\" of table \"{table}\""
+			)));
+		};
+		if found.iter().any(|(other, _)| *other == i) {
+			return Err(Error::InvalidArgument(format!(
+				"Column \"{column}\" is set twice"
+			)));
+		}
+		found.push((i, expr));
+	}
+	Ok(found)
+}
+
+/// Makes a record of a table from the values of its columns, which have to fit their types
+fn to_record(def: &TableDef, values: Vec<Datum>) -> Result<Record> {
+	let mut rec = Record::new();
+	for ((column, ty), datum) in def.columns().iter().zip(values) {
+		rec = rec.item(to_value(column, *ty, datum)?);
+	}
+	Ok(rec)
+}
+
+/// Makes the value of a column from a datum, which has to fit its type
+fn to_value(column: &str, ty: ValueType, datum: Datum) -> Result<Value> {
+	let datum = match datum {
+		// integral floats, such as those computed by division, fit integer columns
+		Datum::Float(n)
+			if matches!(ty, ValueType::U32 | ValueType::I32)
+				&& n.fract() == 0.0
+				&& n.abs() <= u32::MAX as f64 =>
+		{
+			Datum::Int(n as i64)
+		}
+		datum => datum,
+	};
+	let value = match (ty, &datum) {
+		(ValueType::U32, Datum::Int(n)) => u32::try_from(*n).ok().map(Value::U32),
+		(ValueType::I32, Datum::Int(n)) => i32::try_from(*n).ok().map(Value::I32),
+		(ValueType::Point, Datum::Point(p)) => Some(Value::Point(*p)),
+		(ValueType::Rect, Datum::Rect(r)) => Some(Value::Rect(*r)),
+		(ValueType::String(max_len), Datum::String(s)) => {
+			(s.len() <= max_len as usize).then(|| Value::String(s.clone()))
+		}
+		_ => {
+			return Err(Error::TypeMismatch(format!(
+				"Column \"{column}\" can't hold {}",
+				Kind::of(&datum).describe()
+			)));
+		}
+	};
+	value.ok_or_else(|| Error::InvalidArgument(format!("Value doesn't fit in column \"{column}\"")))
+}
+
+fn to_query_value(datum: Datum) -> query::Value {
+	match datum {
+		Datum::Null => query::Value::Null,
+		Datum::Bool(b) => query::Value::Bool(b),
+		Datum::Int(n) => query::Value::Integer(n),
+		Datum::Float(n) => query::Value::Float(n),
+		Datum::Point(p) => query::Value::Point(p),
+		Datum::Rect(r) => query::Value::Rect(r),
+		Datum::String(s) => query::Value::String(s),
+	}
+}
+
+/// Whether an index of a table is a spatial one
+fn spatial(connection: &LilDbConnection, index: &str) -> bool {
+	connection
+		.catalog
+		.index(index)
+		.is_some_and(|entry| entry.def.kind == IndexKind::Spatial)
+}
+
+fn count_arg(function: &str, arg: &Expr) -> Result<usize> {
+	match arg {
+		Expr::Value(query::Value::Integer(n)) if *n >= 0 => Ok(*n as usize),
+		_ => Err(Error::InvalidArgument(format!(
+			"\"{function}\" takes a count, which can't be negative"
+		))),
+	}
+}
+
+fn string_arg(function: &str, arg: &Expr) -> Result<String> {
+	match arg {
+		Expr::Value(query::Value::String(s)) => Ok(s.clone()),
+		_ => Err(Error::InvalidArgument(format!(
+			"\"{function}\" takes a string literal"
+		))),
+	}
+}
+
+fn join_object() -> Error {
+	Error::InvalidArgument(
+		"Joins take a table or named query, such as Orders or Users as managers".to_string(),
+	)
+}
+
+fn groups_unread() -> Error {
+	Error::InvalidArgument(
+		"Groups can't be read as records, \"group_by\" has to be followed by \"having\" or \"aggregate\""
+			.to_string(),
+	)
+}
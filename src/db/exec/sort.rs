@@ -0,0 +1,446 @@
+use std::{
+	cmp::Reverse,
+	collections::BinaryHeap,
+	path::{Path, PathBuf},
+	sync::{
+		Arc,
+		atomic::{self, AtomicU64},
+	},
+};
+
+use crate::{
+	db::{
+		eval::Datum,
+		geometry::{Point, Rect},
+	},
+	vfs::{OpenFlags, Vfs, VfsFile},
+	*,
+};
+
+/// Bytes read from a spilled run at a time while merging runs
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Numbers the temporary files of every connection in the process, so they never clash
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Where a connection's queries spill what doesn't fit in memory
+pub(crate) struct TempFiles {
+	vfs: Arc<dyn Vfs>,
+	/// Path of the database, which temporary files are named after
+	db_path: PathBuf,
+}
+impl TempFiles {
+	pub fn new(vfs: Arc<dyn Vfs>, db_path: &Path) -> TempFiles {
+		TempFiles {
+			vfs,
+			db_path: db_path.to_path_buf(),
+		}
+	}
+
+	/// Creates a new, empty temporary file, removed once it's dropped
+	pub fn create(&self) -> Result<TempFile> {
+		let n = TEMP_FILES.fetch_add(1, atomic::Ordering::Relaxed);
+		let mut name = self.db_path.clone().into_os_string();
+		name.push(format!("-spill-{}-{n}", std::process::id()));
+		let path = PathBuf::from(name);
+		let file = self.vfs.open(&path, OpenFlags::new().create_new(true))?;
+		Ok(TempFile {
+			vfs: self.vfs.clone(),
+			path,
+			file: Some(file),
+			len: 0,
+		})
+	}
+}
+
+/// A file that only lives as long as the query using it
+pub(crate) struct TempFile {
+	vfs: Arc<dyn Vfs>,
+	path: PathBuf,
+	/// Only `None` while being dropped, so the file is closed before it's removed
+	file: Option<Box<dyn VfsFile>>,
+	len: u64,
+}
+impl TempFile {
+	/// Writes bytes after those written so far, returning where they start
+	fn append(&mut self, bytes: &[u8]) -> Result<u64> {
+		let offset = self.len;
+		self.file.as_ref().unwrap().write_at(bytes, offset)?;
+		self.len += bytes.len() as u64;
+		Ok(offset)
+	}
+
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+		Ok(self.file.as_ref().unwrap().read_at(buf, offset)?)
+	}
+}
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		self.file = None;
+		// a file left behind only takes up space, so there is nothing to report
+		let _ = self.vfs.remove(&self.path);
+	}
+}
+
+/// Sorts rows by a key, stably, holding up to a budget of bytes of them in memory and spilling sorted runs to a
+/// temporary file beyond it
+///
+/// Keys compare by their bytes, so they are made with [`encode_key`] when rows are only grouped by them, not ordered.
+pub(crate) struct ExternalSort<'a> {
+	temp: &'a TempFiles,
+	budget: usize,
+	buffer: Vec<(Vec<u8>, Vec<Datum>)>,
+	/// Estimated bytes the buffer holds
+	buffered: usize,
+	file: Option<TempFile>,
+	/// Start and end of each run in the file, in the order they were written
+	runs: Vec<(u64, u64)>,
+}
+impl<'a> ExternalSort<'a> {
+	pub fn new(temp: &'a TempFiles, budget: usize) -> ExternalSort<'a> {
+		ExternalSort {
+			temp,
+			budget,
+			buffer: Vec::new(),
+			buffered: 0,
+			file: None,
+			runs: Vec::new(),
+		}
+	}
+
+	pub fn push(&mut self, key: Vec<u8>, row: Vec<Datum>) -> Result<()> {
+		self.buffered += key.len() + row_size(&row);
+		self.buffer.push((key, row));
+		if self.buffered > self.budget {
+			self.spill()?;
+		}
+		Ok(())
+	}
+
+	/// Sorts the rows pushed so far, reading back any runs that were spilled
+	pub fn finish(mut self) -> Result<Sorted> {
+		self.buffer.sort_by(|a, b| a.0.cmp(&b.0));
+		if self.runs.is_empty() {
+			return Ok(Sorted::Memory(self.buffer.into_iter()));
+		}
+		self.spill()?;
+		let file = self.file.take().unwrap();
+		let mut readers: Vec<RunReader> = self
+			.runs
+			.iter()
+			.map(|&(start, end)| RunReader::new(start, end))
+			.collect();
+		let mut heap = BinaryHeap::with_capacity(readers.len());
+		for (run, reader) in readers.iter_mut().enumerate() {
+			if let Some((key, row)) = reader.next(&file)? {
+				heap.push(Reverse(MergeEntry { key, run, row }));
+			}
+		}
+		Ok(Sorted::Merge {
+			file,
+			readers,
+			heap,
+		})
+	}
+
+	/// Writes the buffered rows to the file as a sorted run
+	fn spill(&mut self) -> Result<()> {
+		if self.buffer.is_empty() {
+			return Ok(());
+		}
+		self.buffer.sort_by(|a, b| a.0.cmp(&b.0));
+		let mut bytes = Vec::with_capacity(self.buffered);
+		for (key, row) in self.buffer.drain(..) {
+			let mut row_bytes = Vec::new();
+			encode_row(&row, &mut row_bytes);
+			bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(&(row_bytes.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(&key);
+			bytes.extend_from_slice(&row_bytes);
+		}
+		self.buffered = 0;
+		if self.file.is_none() {
+			self.file = Some(self.temp.create()?);
+		}
+		let file = self.file.as_mut().unwrap();
+		let start = file.append(&bytes)?;
+		self.runs.push((start, start + bytes.len() as u64));
+		Ok(())
+	}
+}
+
+/// Rows an [`ExternalSort`] sorted, with their keys
+pub(crate) enum Sorted {
+	Memory(std::vec::IntoIter<(Vec<u8>, Vec<Datum>)>),
+	/// Merges spilled runs, taking the row of the earliest run first among equal keys to keep the sort stable
+	Merge {
+		file: TempFile,
+		readers: Vec<RunReader>,
+		heap: BinaryHeap<Reverse<MergeEntry>>,
+	},
+}
+impl Iterator for Sorted {
+	type Item = Result<(Vec<u8>, Vec<Datum>)>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		match self {
+			Sorted::Memory(rows) => rows.next().map(Ok),
+			Sorted::Merge {
+				file,
+				readers,
+				heap,
+			} => {
+				let Reverse(entry) = heap.pop()?;
+				match readers[entry.run].next(file) {
+					Ok(Some((key, row))) => heap.push(Reverse(MergeEntry {
+						key,
+						run: entry.run,
+						row,
+					})),
+					Ok(None) => {}
+					Err(e) => return Some(Err(e)),
+				}
+				Some(Ok((entry.key, entry.row)))
+			}
+		}
+	}
+}
+
+/// The next row of a run, ordered by its key and then its run
+pub(crate) struct MergeEntry {
+	key: Vec<u8>,
+	run: usize,
+	row: Vec<Datum>,
+}
+impl PartialEq for MergeEntry {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other).is_eq()
+	}
+}
+impl Eq for MergeEntry {}
+impl PartialOrd for MergeEntry {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl Ord for MergeEntry {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		(&self.key, self.run).cmp(&(&other.key, other.run))
+	}
+}
+
+/// Reads the entries of a spilled run in order, `|key len u32|row len u32|key|row|` each, a chunk at a time
+pub(crate) struct RunReader {
+	/// Offset in the file of the first byte not yet read into the buffer
+	pos: u64,
+	end: u64,
+	buf: Vec<u8>,
+	/// Bytes of the buffer already taken
+	taken: usize,
+}
+impl RunReader {
+	fn new(start: u64, end: u64) -> RunReader {
+		RunReader {
+			pos: start,
+			end,
+			buf: Vec::new(),
+			taken: 0,
+		}
+	}
+
+	fn next(&mut self, file: &TempFile) -> Result<Option<(Vec<u8>, Vec<Datum>)>> {
+		if self.taken == self.buf.len() && self.pos == self.end {
+			return Ok(None);
+		}
+		let lens = self.take(file, 8)?;
+		let key_len = u32::from_le_bytes(lens[..4].try_into().unwrap()) as usize;
+		let row_len = u32::from_le_bytes(lens[4..].try_into().unwrap()) as usize;
+		let key = self.take(file, key_len)?;
+		let row = decode_row(&self.take(file, row_len)?)?;
+		Ok(Some((key, row)))
+	}
+
+	/// Takes the next `n` bytes of the run, reading more of it as needed
+	fn take(&mut self, file: &TempFile, n: usize) -> Result<Vec<u8>> {
+		while self.buf.len() - self.taken < n {
+			if self.pos == self.end {
+				return Err(Error::Corruption(
+					"Spilled run ends part way through a row".to_string(),
+				));
+			}
+			self.buf.drain(..self.taken);
+			self.taken = 0;
+			let len = (self.end - self.pos).min(READ_CHUNK.max(n) as u64) as usize;
+			let start = self.buf.len();
+			self.buf.resize(start + len, 0);
+			file.read_at(&mut self.buf[start..], self.pos)?;
+			self.pos += len as u64;
+		}
+		let bytes = self.buf[self.taken..(self.taken + n)].to_vec();
+		self.taken += n;
+		Ok(bytes)
+	}
+}
+
+/// Estimated bytes a row takes up in memory
+pub(crate) fn row_size(row: &[Datum]) -> usize {
+	let strings: usize = row
+		.iter()
+		.map(|datum| match datum {
+			Datum::String(s) => s.len(),
+			_ => 0,
+		})
+		.sum();
+	size_of::<Vec<Datum>>() + size_of_val(row) + strings
+}
+
+/// Encodes values so equal ones, such as `1` and `1.0`, have equal bytes, to group or deduplicate rows by them
+pub(crate) fn encode_key(values: &[Datum]) -> Vec<u8> {
+	let canonical: Vec<Datum> = values
+		.iter()
+		.map(|datum| match *datum {
+			Datum::Float(n) if n.fract() == 0.0 && n >= i64::MIN as f64 && n < i64::MAX as f64 => {
+				Datum::Int(n as i64)
+			}
+			Datum::Float(n) if n.is_nan() => Datum::Float(f64::NAN),
+			_ => datum.clone(),
+		})
+		.collect();
+	let mut bytes = Vec::new();
+	encode_row(&canonical, &mut bytes);
+	bytes
+}
+
+/// Appends the bytes of a row, a tag followed by the value for each datum
+pub(crate) fn encode_row(row: &[Datum], bytes: &mut Vec<u8>) {
+	for datum in row {
+		match datum {
+			Datum::Null => bytes.push(0),
+			Datum::Bool(b) => bytes.extend_from_slice(&[1, *b as u8]),
+			Datum::Int(n) => {
+				bytes.push(2);
+				bytes.extend_from_slice(&n.to_le_bytes());
+			}
+			Datum::Float(n) => {
+				bytes.push(3);
+				bytes.extend_from_slice(&n.to_le_bytes());
+			}
+			Datum::Point(p) => {
+				bytes.push(4);
+				bytes.extend_from_slice(&p.to_bytes());
+			}
+			Datum::Rect(r) => {
+				bytes.push(5);
+				bytes.extend_from_slice(&r.to_bytes());
+			}
+			Datum::String(s) => {
+				bytes.push(6);
+				bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+				bytes.extend_from_slice(s.as_bytes());
+			}
+		}
+	}
+}
+
+/// Reads back a row written by [`encode_row`]
+pub(crate) fn decode_row(mut bytes: &[u8]) -> Result<Vec<Datum>> {
+	let corrupt = || Error::Corruption("Spilled row is malformed".to_string());
+	let take = |bytes: &mut &[u8], n: usize| -> Result<Vec<u8>> {
+		if bytes.len() < n {
+			return Err(corrupt());
+		}
+		let (taken, rest) = bytes.split_at(n);
+		*bytes = rest;
+		Ok(taken.to_vec())
+	};
+	let mut row = Vec::new();
+	while let Some((&tag, rest)) = bytes.split_first() {
+		bytes = rest;
+		row.push(match tag {
+			0 => Datum::Null,
+			1 => Datum::Bool(take(&mut bytes, 1)?[0] != 0),
+			2 => Datum::Int(i64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+			3 => Datum::Float(f64::from_le_bytes(take(&mut bytes, 8)?.try_into().unwrap())),
+			4 => Datum::Point(Point::from_bytes(&take(&mut bytes, Point::SIZE)?)),
+			5 => Datum::Rect(Rect::from_bytes(&take(&mut bytes, Rect::SIZE)?)),
+			6 => {
+				let len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+				let text = take(&mut bytes, len as usize)?;
+				Datum::String(String::from_utf8(text).map_err(|_| corrupt())?)
+			}
+			_ => return Err(corrupt()),
+		});
+	}
+	Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vfs::MemoryVfs;
+
+	#[test]
+	fn spills_runs() {
+		let vfs = MemoryVfs::new();
+		let temp = TempFiles::new(Arc::new(vfs.clone()), Path::new("a.ldb"));
+		let mut sort = ExternalSort::new(&temp, 256);
+		for i in 0..200i64 {
+			let row = vec![
+				Datum::Int(i),
+				Datum::String(format!("row {i}")),
+				Datum::Null,
+			];
+			sort.push(encode_key(&[Datum::Int(i % 7)]), row).unwrap();
+		}
+		let spill_files = || {
+			(0..TEMP_FILES.load(atomic::Ordering::Relaxed))
+				.filter(|n| vfs.exists(format!("a.ldb-spill-{}-{n}", std::process::id())))
+				.count()
+		};
+		assert_eq!(spill_files(), 1);
+		let sorted: Vec<_> = sort.finish().unwrap().map(Result::unwrap).collect();
+		assert_eq!(sorted.len(), 200);
+
+		// grouped by key, in the order the rows were pushed within each group
+		let ids: Vec<i64> = sorted
+			.iter()
+			.map(|(_, row)| match row[0] {
+				Datum::Int(i) => i,
+				_ => panic!(),
+			})
+			.collect();
+		let mut expected: Vec<i64> = (0..200).collect();
+		expected.sort_by_key(|i| encode_key(&[Datum::Int(i % 7)]));
+		assert_eq!(ids, expected);
+		assert_eq!(sorted[0].1[1], Datum::String("row 0".to_string()));
+
+		// the spill file is gone once the rows are read
+		assert_eq!(spill_files(), 0);
+	}
+
+	#[test]
+	fn keys() {
+		assert_eq!(
+			encode_key(&[Datum::Float(2.0), Datum::Float(-0.0)]),
+			encode_key(&[Datum::Int(2), Datum::Int(0)])
+		);
+		assert_ne!(
+			encode_key(&[Datum::Float(2.5)]),
+			encode_key(&[Datum::Int(2)])
+		);
+		assert_eq!(
+			encode_key(&[Datum::Float(f64::NAN)]),
+			encode_key(&[Datum::Float(-f64::NAN)])
+		);
+		let row = vec![
+			Datum::Bool(true),
+			Datum::Float(1.5),
+			Datum::Point(Point::new(1.0, 2.0)),
+			Datum::String("héllo".to_string()),
+		];
+		let mut bytes = Vec::new();
+		encode_row(&row, &mut bytes);
+		assert_eq!(decode_row(&bytes).unwrap(), row);
+		assert!(decode_row(&bytes[..bytes.len() - 1]).is_err());
+	}
+}
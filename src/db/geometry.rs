@@ -0,0 +1,106 @@
+use crate::util::slice_to_array;
+
+/// A point on a plane
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Point {
+	pub x: f64,
+	pub y: f64,
+}
+impl Point {
+	pub const SIZE: usize = 16;
+
+	pub const fn new(x: f64, y: f64) -> Point {
+		Point { x, y }
+	}
+
+	pub fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut buf = [0u8; Self::SIZE];
+		buf[0..8].copy_from_slice(&self.x.to_le_bytes());
+		buf[8..16].copy_from_slice(&self.y.to_le_bytes());
+		buf
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Point {
+		Point {
+			x: f64::from_le_bytes(slice_to_array(&bytes[0..8])),
+			y: f64::from_le_bytes(slice_to_array(&bytes[8..16])),
+		}
+	}
+}
+
+/// An axis aligned rectangle, with `min` as its lower left corner and `max` its upper right
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Rect {
+	pub min: Point,
+	pub max: Point,
+}
+impl Rect {
+	pub const SIZE: usize = 2 * Point::SIZE;
+
+	/// Creates a rectangle from any two opposite corners
+	pub fn new(a: Point, b: Point) -> Rect {
+		Rect {
+			min: Point::new(a.x.min(b.x), a.y.min(b.y)),
+			max: Point::new(a.x.max(b.x), a.y.max(b.y)),
+		}
+	}
+
+	/// A zero sized rectangle covering a single point
+	pub const fn point(p: Point) -> Rect {
+		Rect { min: p, max: p }
+	}
+
+	#[inline]
+	pub fn area(&self) -> f64 {
+		(self.max.x - self.min.x) * (self.max.y - self.min.y)
+	}
+
+	/// Smallest rectangle covering both rectangles
+	pub fn union(&self, other: &Rect) -> Rect {
+		Rect {
+			min: Point::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+			max: Point::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+		}
+	}
+
+	/// How much area would be added by growing this rectangle to cover another
+	#[inline]
+	pub fn enlargement(&self, other: &Rect) -> f64 {
+		self.union(other).area() - self.area()
+	}
+
+	pub fn intersects(&self, other: &Rect) -> bool {
+		self.min.x <= other.max.x
+			&& other.min.x <= self.max.x
+			&& self.min.y <= other.max.y
+			&& other.min.y <= self.max.y
+	}
+
+	pub fn contains(&self, other: &Rect) -> bool {
+		self.min.x <= other.min.x
+			&& self.min.y <= other.min.y
+			&& other.max.x <= self.max.x
+			&& other.max.y <= self.max.y
+	}
+
+	/// Euclidean distance from a point to the closest point of this rectangle, zero if the point is inside
+	pub fn distance_to(&self, p: &Point) -> f64 {
+		let dx = (self.min.x - p.x).max(p.x - self.max.x).max(0.0);
+		let dy = (self.min.y - p.y).max(p.y - self.max.y).max(0.0);
+		dx.hypot(dy)
+	}
+
+	pub fn to_bytes(self) -> [u8; Self::SIZE] {
+		let mut buf = [0u8; Self::SIZE];
+		buf[0..Point::SIZE].copy_from_slice(&self.min.to_bytes());
+		buf[Point::SIZE..].copy_from_slice(&self.max.to_bytes());
+		buf
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Rect {
+		Rect {
+			min: Point::from_bytes(&bytes[0..Point::SIZE]),
+			max: Point::from_bytes(&bytes[Point::SIZE..Self::SIZE]),
+		}
+	}
+}
@@ -1,24 +1,401 @@
+mod catalog;
 mod disk;
+mod eval;
+mod exec;
+mod geometry;
 mod objects;
 mod record;
+mod table;
 
-use std::{fs, path::PathBuf};
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
-use crate::*;
+use crate::{
+	vfs::{MemoryVfs, OpenFlags, Vfs},
+	*,
+};
+use catalog::{Catalog, IndexEntry, TableEntry, TextIndexEntry};
 use disk::DiskManager;
+use exec::TempFiles;
+use objects::secondary_index::SecondaryIndex;
+use table::OpenTable;
+
+pub use disk::RecordId;
+pub use exec::QueryResult;
+pub use geometry::{Point, Rect};
+pub use objects::{
+	fulltext::{StemmerKind, TextIndexDef, TokenizerKind},
+	secondary_index::{BloomFilterDef, IndexDef, IndexKind},
+	storage::StorageKind,
+};
+pub use record::{Record, Value, ValueType};
+pub use table::{Table, TableDef};
 
 pub struct LilDbConnection {
 	opts: LilDbOpts,
 	disk: DiskManager,
+	catalog: Catalog,
+	/// Tables opened so far, which stay open until the connection is dropped
+	tables: HashMap<String, OpenTable>,
+	/// Where queries spill what doesn't fit in their memory
+	temp: TempFiles,
+	/// File system of the database, or a private one for in-memory databases, which the journal goes in
+	files: Arc<dyn Vfs>,
+	/// Path of the rollback journal of scripts, next to the database
+	journal: PathBuf,
 }
 impl LilDbConnection {
 	pub fn open_db(path: PathBuf, opts: LilDbOpts) -> Result<LilDbConnection> {
-		let f = fs::OpenOptions::new()
-			.read(true)
-			.write(true)
-			.create(opts.create)
-			.open(path)?;
-		let disk = DiskManager::new(f)?;
-		Ok(LilDbConnection { opts, disk })
+		opts.validate()?;
+		let in_memory = opts.in_memory || path.as_os_str() == MEMORY_PATH;
+		let temp_vfs: Arc<dyn Vfs> = if in_memory {
+			Arc::new(MemoryVfs::new())
+		} else {
+			opts.vfs.clone()
+		};
+		let f = if in_memory {
+			// every in-memory database gets its own file system, so they never share pages
+			temp_vfs.open(&path, OpenFlags::new().create(true))?
+		} else {
+			opts.vfs
+				.open(
+					&path,
+					OpenFlags::new()
+						.create(opts.create)
+						.create_new(opts.create_new)
+						.read_only(opts.read_only),
+				)
+				.map_err(|e| match e.kind() {
+					std::io::ErrorKind::NotFound => Error::NotFound(path.display().to_string()),
+					std::io::ErrorKind::AlreadyExists => {
+						Error::AlreadyExists(path.display().to_string())
+					}
+					_ => Error::Io(e),
+				})?
+		};
+		let disk = if f.is_empty()? {
+			if opts.read_only {
+				return Err(Error::ReadOnly);
+			}
+			DiskManager::init_db(
+				f,
+				opts.page_size,
+				opts.compression,
+				opts.encryption_key,
+				opts.busy_timeout,
+			)?
+		} else {
+			DiskManager::new(f, opts.encryption_key, opts.busy_timeout)?
+		};
+		let mut disk = disk
+			.with_read_only(opts.read_only)
+			.with_synchronous(opts.synchronous)
+			.with_cache_size(opts.cache_size);
+		if opts.mmap {
+			disk.enable_mmap()?;
+		}
+		// a script a crash interrupted is rolled back, which read-only connections can't do
+		let journal = journal_path(&path);
+		match temp_vfs.open(&journal, OpenFlags::new().read_only(opts.read_only)) {
+			Ok(f) => {
+				if disk.recover(f)? {
+					temp_vfs.remove(&journal)?;
+				}
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e.into()),
+		}
+		if !opts.read_only {
+			disk.finish_rekey()?;
+		}
+		let catalog = Catalog::load(&mut disk)?;
+		Ok(LilDbConnection {
+			opts,
+			disk,
+			catalog,
+			tables: HashMap::new(),
+			temp: TempFiles::new(temp_vfs.clone(), &path),
+			files: temp_vfs,
+			journal,
+		})
+	}
+
+	/// Creates a new, empty table
+	///
+	/// Fails with `Error::AlreadyExists` if there already is a table with this name.
+	pub fn create_table(&mut self, name: &str, def: TableDef) -> Result<()> {
+		def.validate(self.disk.page_size())?;
+		if self.catalog.table(name).is_some() {
+			return Err(Error::AlreadyExists(format!("table \"{name}\"")));
+		}
+		let storage = self
+			.disk
+			.with_compression(def.compression_override(), |disk| {
+				def.storage_kind().create(disk, def.schema())
+			})?;
+		let entry = TableEntry {
+			name: name.to_string(),
+			def: def.clone(),
+			root: storage.root(),
+		};
+		self.catalog.add_table(&mut self.disk, entry)?;
+		self.tables.insert(
+			name.to_string(),
+			OpenTable {
+				name: name.to_string(),
+				def,
+				storage,
+				indexes: Vec::new(),
+			},
+		);
+		Ok(())
+	}
+
+	/// Creates a secondary index over a table's records, which writes through `Table` keep up to date from then on
+	///
+	/// Fails with `Error::AlreadyExists` if there already is an index with this name, and `Error::NotFound` if the table
+	/// or any column the definition refers to doesn't exist. A predicate that can't be evaluated against the table's
+	/// records or stored in the catalog fails with `Error::InvalidArgument` or `Error::TypeMismatch`, before anything
+	/// is created.
+	pub fn create_index(&mut self, name: &str, table: &str, def: IndexDef) -> Result<()> {
+		if self.catalog.has_index_named(name) {
+			return Err(Error::AlreadyExists(format!("index \"{name}\"")));
+		}
+		self.open_table(table)?;
+		let open = self.tables.get_mut(table).unwrap();
+		let index = self
+			.disk
+			.with_compression(open.def.compression_override(), |disk| {
+				let index = SecondaryIndex::create(disk, table, &open.def, def.clone())?;
+				for (rid, rec) in open.storage.scan(disk)? {
+					index.insert(disk, &rec, rid)?;
+				}
+				Ok(index)
+			})?;
+		let entry = IndexEntry {
+			name: name.to_string(),
+			table: table.to_string(),
+			def,
+			root: index.root(),
+			bloom: index.bloom_filter(),
+		};
+		self.catalog.add_index(&mut self.disk, entry)?;
+		open.indexes.push((name.to_string(), index));
+		Ok(())
+	}
+
+	/// Creates a full-text index over a string column of a table, which writes through `Table` keep up to date from
+	/// then on, and which shares names with the other indexes
+	///
+	/// Fails with `Error::AlreadyExists` if there already is an index with this name, `Error::NotFound` if the table or
+	/// column doesn't exist, and `Error::TypeMismatch` if the column doesn't hold strings.
+	pub fn create_text_index(&mut self, name: &str, table: &str, def: TextIndexDef) -> Result<()> {
+		if self.catalog.has_index_named(name) {
+			return Err(Error::AlreadyExists(format!("index \"{name}\"")));
+		}
+		self.open_table(table)?;
+		let open = self.tables.get_mut(table).unwrap();
+		let index = self
+			.disk
+			.with_compression(open.def.compression_override(), |disk| {
+				let index = SecondaryIndex::create_text(disk, table, &open.def, &def)?;
+				for (rid, rec) in open.storage.scan(disk)? {
+					index.insert(disk, &rec, rid)?;
+				}
+				Ok(index)
+			})?;
+		let entry = TextIndexEntry {
+			name: name.to_string(),
+			table: table.to_string(),
+			def,
+			root: index.root(),
+		};
+		self.catalog.add_text_index(&mut self.disk, entry)?;
+		open.indexes.push((name.to_string(), index));
+		Ok(())
+	}
+
+	/// Opens a table to read and write its records
+	///
+	/// Fails with `Error::NotFound` if there is no table with this name.
+	pub fn table(&mut self, name: &str) -> Result<Table<'_>> {
+		self.open_table(name)?;
+		Ok(Table::new(
+			&mut self.disk,
+			self.tables.get_mut(name).unwrap(),
+		))
+	}
+
+	/// Rebuilds the Bloom filters of every index, so keys of records deleted or changed since no longer test positive
+	pub fn vacuum(&mut self) -> Result<()> {
+		let tables: Vec<String> = self
+			.catalog
+			.tables()
+			.map(|table| table.name.clone())
+			.collect();
+		for name in tables {
+			self.open_table(&name)?;
+			let open = &self.tables[&name];
+			self.disk
+				.with_compression(open.def.compression_override(), |disk| {
+					for (_, index) in open.indexes.iter() {
+						index.rebuild_bloom_filter(disk)?;
+					}
+					Ok(())
+				})?;
+		}
+		Ok(())
+	}
+
+	/// Loads a table's storage and indexes, unless it's already open
+	fn open_table(&mut self, name: &str) -> Result<()> {
+		if self.tables.contains_key(name) {
+			return Ok(());
+		}
+		let Some(entry) = self.catalog.table(name) else {
+			return Err(Error::NotFound(format!("table \"{name}\"")));
+		};
+		let storage =
+			entry
+				.def
+				.storage_kind()
+				.open(&mut self.disk, entry.root, entry.def.schema())?;
+		let mut indexes: Vec<_> = self
+			.catalog
+			.indexes_of(name)
+			.map(|index| {
+				SecondaryIndex::open(
+					&mut self.disk,
+					index.root,
+					index.bloom,
+					name,
+					&entry.def,
+					index.def.clone(),
+				)
+				.map(|opened| (index.name.clone(), opened))
+			})
+			.collect::<Result<_>>()?;
+		for index in self.catalog.text_indexes_of(name) {
+			let opened = SecondaryIndex::open_text(
+				&mut self.disk,
+				index.root,
+				name,
+				&entry.def,
+				&index.def,
+			)?;
+			indexes.push((index.name.clone(), opened));
+		}
+		self.tables.insert(
+			name.to_string(),
+			OpenTable {
+				name: name.to_string(),
+				def: entry.def.clone(),
+				storage,
+				indexes,
+			},
+		);
+		Ok(())
+	}
+
+	/// Starts a transaction, after writing out what open tables hold in memory so it isn't rolled back with it
+	fn begin(&mut self) -> Result<()> {
+		self.flush_tables()?;
+		let journal = self
+			.files
+			.open(&self.journal, OpenFlags::new().create(true))?;
+		self.disk.begin(journal)
+	}
+
+	/// Ends the transaction in progress, keeping its writes
+	fn commit(&mut self) -> Result<()> {
+		self.flush_tables()?;
+		self.disk.commit()?;
+		self.remove_journal()
+	}
+
+	/// Ends the transaction in progress, undoing its writes
+	///
+	/// Open tables are closed, as what they hold in memory may be from the transaction, and the catalog is read again.
+	fn rollback(&mut self) -> Result<()> {
+		self.tables.clear();
+		self.disk.rollback()?;
+		self.catalog = Catalog::load(&mut self.disk)?;
+		self.remove_journal()
+	}
+
+	fn remove_journal(&self) -> Result<()> {
+		match self.files.remove(&self.journal) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+
+	/// Writes out whatever open tables hold in memory
+	fn flush_tables(&mut self) -> Result<()> {
+		if self.disk.is_read_only() {
+			return Ok(());
+		}
+		for table in self.tables.values_mut() {
+			table.flush(&mut self.disk)?;
+		}
+		Ok(())
+	}
+
+	/// Whether the connection can write to the database
+	pub fn is_read_only(&self) -> bool {
+		self.disk.is_read_only()
+	}
+
+	/// Totals over the pages stored in the database, before and after compression
+	///
+	/// Reads the header of every page, so it takes a while on large databases.
+	pub fn compression_stats(&mut self) -> Result<CompressionStats> {
+		self.disk.compression_stats()
+	}
+
+	/// Re-encrypts the database with a new key, which it has to be opened with from then on
+	///
+	/// Fails with `Error::InvalidArgument` if the database isn't encrypted. If the rekey is interrupted, the database
+	/// can be opened with either key, and opening it with write access finishes the rekey.
+	pub fn rekey(&mut self, key: [u8; 32]) -> Result<()> {
+		self.disk.rekey(key)?;
+		self.opts.encryption_key = Some(key);
+		Ok(())
+	}
+
+	/// Makes every write so far durable, according to the synchronous option
+	pub fn sync(&mut self) -> Result<()> {
+		self.flush_tables()?;
+		self.disk.sync()
+	}
+
+	/// Writes a snapshot of the database to a file in the configured VFS, overwriting it if it already exists
+	///
+	/// Mostly useful for persisting an in-memory database, the snapshot can be opened like any other database file.
+	/// The snapshot is compressed and encrypted like the database is. Fails with `Error::InvalidArgument` if the path
+	/// leads to the database's own file.
+	pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+		let dest = self
+			.opts
+			.vfs
+			.open(path.as_ref(), OpenFlags::new().create(true))?;
+		self.flush_tables()?;
+		self.disk.copy_to(dest)
+	}
+}
+/// Path of the rollback journal of a database
+fn journal_path(path: &Path) -> PathBuf {
+	let mut journal = path.as_os_str().to_owned();
+	journal.push("-journal");
+	PathBuf::from(journal)
+}
+
+impl Drop for LilDbConnection {
+	fn drop(&mut self) {
+		// errors can't be reported from here, `sync` reports them
+		let _ = self.flush_tables();
 	}
 }
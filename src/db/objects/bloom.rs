@@ -0,0 +1,194 @@
+use std::f64::consts::LN_2;
+
+use crate::{
+	db::disk::{BloomBlockPageView, DiskManager, PageId, block_bits},
+	util::{hash_bytes, slice_to_array},
+	*,
+};
+
+/// Most hash functions a filter will use, regardless of the requested false positive rate
+const MAX_HASHES: u8 = 16;
+
+/// Persistent blocked Bloom filter, answering whether a key may have been added
+///
+/// Every key maps to a single block (one page) and all of its bits are set within that block, so a lookup reads at
+/// most one page. Bits can't be unset, so removed keys keep testing positive until the filter is cleared and rebuilt.
+///
+/// The filter is described by a header page listing its blocks, so it can be reopened from that page alone:
+/// ```txt
+/// |n_hashes|n_blocks|block1|block2|...
+/// 0        1        5
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+	header: PageId,
+	blocks: Vec<PageId>,
+	n_hashes: u8,
+}
+impl BloomFilter {
+	/// Allocates an empty filter sized for a number of keys at a target false positive rate
+	///
+	/// Fails with `Error::InvalidArgument` if the filter would need more blocks than its header page can list.
+	pub fn create(
+		disk: &mut DiskManager,
+		expected_keys: u64,
+		false_positive_rate: f64,
+	) -> Result<BloomFilter> {
+		if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+			return Err(Error::InvalidArgument(
+				"Bloom filter false positive rate must be between 0 and 1".to_string(),
+			));
+		}
+		let n = expected_keys.max(1) as f64;
+		let bits = (-n * false_positive_rate.ln() / (LN_2 * LN_2)).ceil();
+		let n_blocks = (bits / block_bits(disk.page_size()) as f64).ceil().max(1.0);
+		let n_hashes = ((bits / n) * LN_2).round().clamp(1.0, MAX_HASHES as f64) as u8;
+		if n_blocks > max_blocks(disk) as f64 {
+			return Err(Error::InvalidArgument(format!(
+				"Bloom filter for {expected_keys} keys needs more blocks than fit in its header page"
+			)));
+		}
+
+		let mut header = disk.allocate_page()?;
+		let blocks = (0..n_blocks as u32)
+			.map(|_| disk.allocate_page().map(|page| page.id))
+			.collect::<Result<Vec<_>>>()?;
+		header.data[0] = n_hashes;
+		header.data[1..5].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+		for (i, block) in blocks.iter().enumerate() {
+			header.data[(5 + i * 4)..(9 + i * 4)].copy_from_slice(&block.to_le_bytes());
+		}
+		disk.flush_page(&header)?;
+		Ok(BloomFilter {
+			header: header.id,
+			blocks,
+			n_hashes,
+		})
+	}
+
+	/// Opens an existing filter given its header page
+	pub fn open(disk: &mut DiskManager, header: PageId) -> Result<BloomFilter> {
+		let page = disk.read_page(header)?;
+		let n_hashes = page.data[0];
+		let n_blocks = u32::from_le_bytes(slice_to_array(&page.data[1..5])) as usize;
+		if n_hashes == 0 || n_blocks == 0 || n_blocks > max_blocks(disk) {
+			return Err(Error::Corruption(format!(
+				"Page {header} is not a Bloom filter header"
+			)));
+		}
+		let blocks = (0..n_blocks)
+			.map(|i| PageId::from_le_bytes(slice_to_array(&page.data[(5 + i * 4)..(9 + i * 4)])))
+			.collect();
+		Ok(BloomFilter {
+			header,
+			blocks,
+			n_hashes,
+		})
+	}
+
+	/// ID of the header page, to reopen the filter with
+	#[inline]
+	pub fn header(&self) -> PageId {
+		self.header
+	}
+
+	/// Adds a key to the filter
+	pub fn insert(&self, disk: &mut DiskManager, key: &[u8]) -> Result<()> {
+		let (block, bits) = self.probes(key, block_bits(disk.page_size()));
+		let mut page = disk.read_page(block)?;
+		let mut view = BloomBlockPageView::new(&mut page.data);
+		for bit in bits {
+			view.set(bit);
+		}
+		disk.flush_page(&page)
+	}
+
+	/// Checks if a key may have been added, `false` means it definitely wasn't
+	pub fn may_contain(&self, disk: &mut DiskManager, key: &[u8]) -> Result<bool> {
+		let (block, mut bits) = self.probes(key, block_bits(disk.page_size()));
+		let mut page = disk.read_page(block)?;
+		let view = BloomBlockPageView::new(&mut page.data);
+		Ok(bits.all(|bit| view.get(bit)))
+	}
+
+	/// Removes every key from the filter
+	pub fn clear(&self, disk: &mut DiskManager) -> Result<()> {
+		for id in self.blocks.iter() {
+			let mut page = disk.read_page(*id)?;
+			BloomBlockPageView::new(&mut page.data).clear();
+			disk.flush_page(&page)?;
+		}
+		Ok(())
+	}
+
+	/// Gets the block a key belongs to and the bits it sets in it, using double hashing
+	fn probes(&self, key: &[u8], block_bits: u32) -> (PageId, impl Iterator<Item = u32>) {
+		let hash = hash_bytes(key);
+		let block = self.blocks[(hash % self.blocks.len() as u64) as usize];
+		let h1 = (hash >> 32) as u32;
+		let h2 = hash_bytes(&hash.to_le_bytes()) as u32 | 1;
+		let bits = (0..self.n_hashes as u32)
+			.map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % block_bits);
+		(block, bits)
+	}
+}
+
+/// Most blocks a header page can list
+fn max_blocks(disk: &DiskManager) -> usize {
+	(disk.empty_page(0).data.len() - 5) / size_of::<PageId>()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn no_false_negatives() {
+		let mut disk = DiskManager::temp("bloom_no_false_negatives");
+		let n = 30_000u32;
+		let filter = BloomFilter::create(&mut disk, n as u64, 0.01).unwrap();
+		assert!(filter.blocks.len() > 1);
+		for i in 0..n {
+			filter.insert(&mut disk, &i.to_le_bytes()).unwrap();
+		}
+		for i in 0..n {
+			assert!(filter.may_contain(&mut disk, &i.to_le_bytes()).unwrap());
+		}
+
+		// allowing some slack over the target rate
+		let false_positives = (n..(2 * n))
+			.filter(|i| filter.may_contain(&mut disk, &i.to_le_bytes()).unwrap())
+			.count();
+		assert!(false_positives < (n / 50) as usize);
+
+		// the header page is enough to reopen it
+		let reopened = BloomFilter::open(&mut disk, filter.header()).unwrap();
+		assert_eq!(reopened, filter);
+		assert!(
+			reopened
+				.may_contain(&mut disk, &7u32.to_le_bytes())
+				.unwrap()
+		);
+
+		filter.clear(&mut disk).unwrap();
+		assert!(!filter.may_contain(&mut disk, &0u32.to_le_bytes()).unwrap());
+	}
+
+	#[test]
+	fn invalid() {
+		let mut disk = DiskManager::temp("bloom_invalid");
+		assert!(matches!(
+			BloomFilter::create(&mut disk, 100, 1.0),
+			Err(Error::InvalidArgument(_))
+		));
+		assert!(matches!(
+			BloomFilter::create(&mut disk, u32::MAX as u64, 0.01),
+			Err(Error::InvalidArgument(_))
+		));
+		let page = disk.allocate_page().unwrap();
+		assert!(matches!(
+			BloomFilter::open(&mut disk, page.id),
+			Err(Error::Corruption(_))
+		));
+	}
+}
@@ -0,0 +1,634 @@
+pub mod tokenize;
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use super::HashIndex;
+use crate::{
+	db::{
+		disk::{DiskManager, PageId, PostingsPageView, RecordId},
+		record::*,
+	},
+	util::{hash_bytes, slice_to_array},
+	*,
+};
+use tokenize::{
+	Analyzer, Lowercase, NGramTokenizer, PorterStemmer, StopWords, WhitespaceTokenizer,
+	WordTokenizer,
+};
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalization
+const B: f64 = 0.75;
+/// Bytes of a term's text held by each entry of the term text index
+const TERM_CHUNK_SIZE: usize = 16;
+
+/// How a full-text index splits text into terms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TokenizerKind {
+	/// Splits on whitespace, keeping punctuation attached to terms
+	Whitespace,
+	/// Splits on anything that isn't a letter or digit
+	#[default]
+	Words,
+	/// Every run of this many characters in each word, so queries can match parts of words
+	NGrams(u8),
+}
+
+/// How a full-text index reduces words to their stems, so different forms of a word match each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StemmerKind {
+	/// The Porter algorithm, for English
+	Porter,
+}
+
+/// Describes which string column of a table a full-text index is built from, and how it turns text into the terms it
+/// indexes, the same way for text and queries
+///
+/// ```
+/// use lildb::{StemmerKind, TextIndexDef, TokenizerKind};
+///
+/// let def = TextIndexDef::new("body")
+///     .tokenizer(TokenizerKind::Whitespace)
+///     .stop_words(["the", "a"])
+///     .stemmer(StemmerKind::Porter);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextIndexDef {
+	/// Table column holding the text, which has to be a string column
+	pub column: String,
+	pub tokenizer: TokenizerKind,
+	/// Lowercases terms, so matching ignores case
+	pub lowercase: bool,
+	/// Terms left out of the index and of queries, compared after lowercasing
+	pub stop_words: Vec<String>,
+	/// Stems terms after stop words are dropped, `None` to keep words as they are
+	pub stemmer: Option<StemmerKind>,
+}
+impl TextIndexDef {
+	/// Lowercased words of a column, without stop words
+	pub fn new<S: Into<String>>(column: S) -> TextIndexDef {
+		TextIndexDef {
+			column: column.into(),
+			tokenizer: TokenizerKind::default(),
+			lowercase: true,
+			stop_words: Vec::new(),
+			stemmer: None,
+		}
+	}
+
+	pub fn tokenizer(mut self, tokenizer: TokenizerKind) -> Self {
+		self.tokenizer = tokenizer;
+		self
+	}
+
+	pub fn lowercase(mut self, lowercase: bool) -> Self {
+		self.lowercase = lowercase;
+		self
+	}
+
+	pub fn stop_words<S: Into<String>>(mut self, words: impl IntoIterator<Item = S>) -> Self {
+		self.stop_words = words.into_iter().map(Into::into).collect();
+		self
+	}
+
+	pub fn stemmer(mut self, stemmer: StemmerKind) -> Self {
+		self.stemmer = Some(stemmer);
+		self
+	}
+
+	/// Checks that n-grams are at least a character long
+	pub(crate) fn validate(&self) -> Result<()> {
+		if self.tokenizer == TokenizerKind::NGrams(0) {
+			return Err(Error::InvalidArgument(
+				"N-grams need to be at least 1 character long".to_string(),
+			));
+		}
+		Ok(())
+	}
+
+	/// Builds the analyzer the definition describes
+	pub(crate) fn analyzer(&self) -> Analyzer {
+		let mut analyzer = match self.tokenizer {
+			TokenizerKind::Whitespace => Analyzer::new(WhitespaceTokenizer),
+			TokenizerKind::Words => Analyzer::new(WordTokenizer),
+			TokenizerKind::NGrams(n) => Analyzer::new(NGramTokenizer { n: n as usize }),
+		};
+		if self.lowercase {
+			analyzer = analyzer.filter(Lowercase);
+		}
+		if !self.stop_words.is_empty() {
+			analyzer = analyzer.filter(StopWords::new(self.stop_words.iter().cloned()));
+		}
+		match self.stemmer {
+			Some(StemmerKind::Porter) => analyzer.filter(PorterStemmer),
+			None => analyzer,
+		}
+	}
+}
+/// Inverted index over text, supporting keyword matching and BM25 ranking
+///
+/// Each distinct term gets an ID. The `terms` `HashIndex` maps the 64-bit hash of a term to the IDs of the terms with
+/// that hash, their lengths and the first page of their posting lists, and `term_text` holds each term's bytes in
+/// chunks keyed by `(id, chunk)`, so terms with colliding hashes are told apart by comparing their bytes. A term's
+/// posting list is a chain of pages linked through `Page::next`, with one entry per document holding the term's
+/// frequency in it. Document lengths are kept in another `HashIndex` keyed by record ID, and corpus totals live in a
+/// root page, which also points to the hash indexes:
+/// ```txt
+/// |n_docs|total_terms|n_terms|terms|term_text|doc_lengths|
+/// 0      4           12      16    20        24          28
+/// ```
+///
+/// Entries of `terms` and `term_text` belong to a term rather than a record, their record ID is the term's ID.
+pub struct FullTextIndex {
+	terms: HashIndex,
+	term_text: HashIndex,
+	doc_lengths: HashIndex,
+	root: PageId,
+	analyzer: Analyzer,
+	term_hash: fn(&[u8]) -> u64,
+}
+impl FullTextIndex {
+	/// Allocates the pages for a new, empty index
+	pub fn create(disk: &mut DiskManager, analyzer: Analyzer) -> Result<FullTextIndex> {
+		let terms = HashIndex::create(disk, hash_key_schema(), term_schema())?;
+		let term_text = HashIndex::create(disk, chunk_key_schema(), chunk_schema())?;
+		let doc_lengths = HashIndex::create(disk, doc_key_schema(), tf_schema())?;
+		let mut root = disk.allocate_page()?;
+		root.data[16..20].copy_from_slice(&terms.directory().to_le_bytes());
+		root.data[20..24].copy_from_slice(&term_text.directory().to_le_bytes());
+		root.data[24..28].copy_from_slice(&doc_lengths.directory().to_le_bytes());
+		disk.flush_page(&root)?;
+		Ok(FullTextIndex {
+			terms,
+			term_text,
+			doc_lengths,
+			root: root.id,
+			analyzer,
+			term_hash: hash_bytes,
+		})
+	}
+
+	/// Opens an existing index given its root page, `analyzer` must be the one it was created with
+	pub fn open(disk: &mut DiskManager, root: PageId, analyzer: Analyzer) -> Result<FullTextIndex> {
+		let page = disk.read_page(root)?;
+		let directory =
+			|offset: usize| PageId::from_le_bytes(slice_to_array(&page.data[offset..(offset + 4)]));
+		Ok(FullTextIndex {
+			terms: HashIndex::open(directory(16), hash_key_schema(), term_schema()),
+			term_text: HashIndex::open(directory(20), chunk_key_schema(), chunk_schema()),
+			doc_lengths: HashIndex::open(directory(24), doc_key_schema(), tf_schema()),
+			root,
+			analyzer,
+			term_hash: hash_bytes,
+		})
+	}
+
+	/// ID of the root page, to reopen the index with
+	#[inline]
+	pub fn root(&self) -> PageId {
+		self.root
+	}
+
+	/// Hashes terms with another function, to make them collide
+	#[cfg(test)]
+	fn with_term_hash(mut self, term_hash: fn(&[u8]) -> u64) -> Self {
+		self.term_hash = term_hash;
+		self
+	}
+
+	/// Adds a document's text to the index
+	pub fn insert(&self, disk: &mut DiskManager, text: &str, rid: RecordId) -> Result<()> {
+		let terms = self.analyzer.analyze(text);
+		for (term, tf) in term_frequencies(&terms) {
+			let head = match self.find_term(disk, term)? {
+				Some((_, head)) => head,
+				None => self.add_term(disk, term)?,
+			};
+			add_posting(disk, head, rid, tf)?;
+		}
+		self.doc_lengths.insert(
+			disk,
+			&doc_key(rid),
+			&Record::new().item(Value::U32(terms.len() as u32)),
+			rid,
+		)?;
+		self.update_stats(disk, 1, terms.len() as i64)
+	}
+
+	/// Removes a document from the index, `text` must be what it was inserted with
+	pub fn delete(&self, disk: &mut DiskManager, text: &str, rid: RecordId) -> Result<()> {
+		let terms = self.analyzer.analyze(text);
+		for term in term_frequencies(&terms).keys() {
+			if let Some((_, head)) = self.find_term(disk, term)? {
+				remove_posting(disk, head, rid)?;
+			}
+		}
+		if !self.doc_lengths.remove(disk, &doc_key(rid), rid)? {
+			return Err(Error::Corruption(
+				"Deleted document was missing from full text index".to_string(),
+			));
+		}
+		self.update_stats(disk, -1, -(terms.len() as i64))
+	}
+
+	/// Finds every document containing all the terms in a query
+	pub fn matches(&self, disk: &mut DiskManager, query: &str) -> Result<Vec<RecordId>> {
+		let terms = self.analyzer.analyze(query);
+		let Some((first, rest)) = terms.split_first() else {
+			return Ok(Vec::new());
+		};
+
+		let mut found = self.postings_of(disk, first)?;
+		found.sort_unstable();
+		for term in rest {
+			if found.is_empty() {
+				break;
+			}
+			let mut docs = self.postings_of(disk, term)?;
+			docs.sort_unstable();
+			found = intersect_sorted(&found, &docs);
+		}
+		Ok(found)
+	}
+
+	/// Scores every document containing any of the terms in a query with BM25, best matches first
+	pub fn rank(&self, disk: &mut DiskManager, query: &str) -> Result<Vec<(RecordId, f64)>> {
+		let (n_docs, total_terms) = self.read_stats(disk)?;
+		if n_docs == 0 {
+			return Ok(Vec::new());
+		}
+		let avg_len = total_terms as f64 / n_docs as f64;
+
+		let mut scores: HashMap<RecordId, f64> = HashMap::new();
+		for term in term_frequencies(&self.analyzer.analyze(query)).keys() {
+			let Some((_, head)) = self.find_term(disk, term)? else {
+				continue;
+			};
+			let postings = read_postings(disk, head)?;
+			let df = postings.len() as f64;
+			let idf = ((n_docs as f64 - df + 0.5) / (df + 0.5) + 1.0).ln();
+			for (rid, tf) in postings {
+				let tf = tf as f64;
+				let len = self
+					.doc_lengths
+					.get_with_payload(disk, &doc_key(rid))?
+					.first()
+					.map_or(0, |(len, _)| as_u32(len, 0)) as f64;
+				let score = idf * (tf * (K1 + 1.0)) / (tf + K1 * (1.0 - B + B * len / avg_len));
+				*scores.entry(rid).or_default() += score;
+			}
+		}
+
+		let mut ranked: Vec<(RecordId, f64)> = scores.into_iter().collect();
+		ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+		Ok(ranked)
+	}
+
+	/// Gets the documents a term appears in, in no particular order
+	fn postings_of(&self, disk: &mut DiskManager, term: &str) -> Result<Vec<RecordId>> {
+		match self.find_term(disk, term)? {
+			Some((_, head)) => Ok(read_postings(disk, head)?
+				.into_iter()
+				.map(|(rid, _)| rid)
+				.collect()),
+			None => Ok(Vec::new()),
+		}
+	}
+
+	/// Gets the ID of a term and the first page of its posting list, `None` if it was never indexed
+	fn find_term(&self, disk: &mut DiskManager, term: &str) -> Result<Option<(u32, PageId)>> {
+		let bytes = term.as_bytes();
+		for (entry, _) in self
+			.terms
+			.get_with_payload(disk, &hash_key((self.term_hash)(bytes)))?
+		{
+			let (id, len) = (as_u32(&entry, 0), as_u32(&entry, 1));
+			if len as usize == bytes.len() && self.term_matches(disk, id, bytes)? {
+				return Ok(Some((id, as_u32(&entry, 2))));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Compares the stored bytes of a term with `bytes`, which must be as long as the term
+	fn term_matches(&self, disk: &mut DiskManager, id: u32, bytes: &[u8]) -> Result<bool> {
+		for (i, expected) in bytes.chunks(TERM_CHUNK_SIZE).enumerate() {
+			let Some((chunk, _)) = self
+				.term_text
+				.get_with_payload(disk, &chunk_key(id, i))?
+				.into_iter()
+				.next()
+			else {
+				return Err(Error::Corruption(
+					"Text of an indexed term is missing".to_string(),
+				));
+			};
+			if chunk.to_bytes()[..expected.len()] != *expected {
+				return Ok(false);
+			}
+		}
+		Ok(true)
+	}
+
+	/// Gives a term that isn't indexed yet the next ID, storing its text, and returns the first page of its empty
+	/// posting list
+	fn add_term(&self, disk: &mut DiskManager, term: &str) -> Result<PageId> {
+		let head = disk.allocate_page()?.id;
+		let mut page = disk.read_page(self.root)?;
+		let id = u32::from_le_bytes(slice_to_array(&page.data[12..16]));
+		let rid = term_rid(id);
+
+		let bytes = term.as_bytes();
+		for (i, text) in bytes.chunks(TERM_CHUNK_SIZE).enumerate() {
+			let mut chunk = [0u8; TERM_CHUNK_SIZE];
+			chunk[..text.len()].copy_from_slice(text);
+			self.term_text.insert(
+				disk,
+				&chunk_key(id, i),
+				&Record::from_bytes(&chunk, &chunk_schema()),
+				rid,
+			)?;
+		}
+		self.terms.insert(
+			disk,
+			&hash_key((self.term_hash)(bytes)),
+			&Record::new()
+				.item(Value::U32(id))
+				.item(Value::U32(bytes.len() as u32))
+				.item(Value::U32(head)),
+			rid,
+		)?;
+
+		page.data[12..16].copy_from_slice(&(id + 1).to_le_bytes());
+		disk.flush_page(&page)?;
+		Ok(head)
+	}
+
+	/// Gets the number of documents and total number of terms indexed
+	fn read_stats(&self, disk: &mut DiskManager) -> Result<(u32, u64)> {
+		let page = disk.read_page(self.root)?;
+		Ok((
+			u32::from_le_bytes(slice_to_array(&page.data[0..4])),
+			u64::from_le_bytes(slice_to_array(&page.data[4..12])),
+		))
+	}
+
+	fn update_stats(&self, disk: &mut DiskManager, docs: i64, terms: i64) -> Result<()> {
+		let (n_docs, total_terms) = self.read_stats(disk)?;
+		let mut page = disk.read_page(self.root)?;
+		page.data[0..4].copy_from_slice(&((n_docs as i64 + docs) as u32).to_le_bytes());
+		page.data[4..12].copy_from_slice(&((total_terms as i64 + terms) as u64).to_le_bytes());
+		disk.flush_page(&page)
+	}
+}
+
+/// Adds a document to a posting list
+///
+/// Entries are added to the first page, or the one after it, where a new page is linked in once both are full. So
+/// adding reads at most two pages, at the cost of not reusing space freed further down the chain.
+fn add_posting(disk: &mut DiskManager, head: PageId, rid: RecordId, tf: u32) -> Result<()> {
+	let mut page = disk.read_page(head)?;
+	if PostingsPageView::new(&mut page.data).push(rid, tf) {
+		return disk.flush_page(&page);
+	}
+	if page.next != head {
+		let mut next = disk.read_page(page.next)?;
+		if PostingsPageView::new(&mut next.data).push(rid, tf) {
+			return disk.flush_page(&next);
+		}
+	}
+	let mut new = disk.allocate_page()?;
+	PostingsPageView::new(&mut new.data).push(rid, tf);
+	if page.next != head {
+		new.next = page.next;
+	}
+	page.next = new.id;
+	disk.flush_page(&new)?;
+	disk.flush_page(&page)
+}
+
+/// Removes a document from a posting list, returning `false` if it wasn't in it
+fn remove_posting(disk: &mut DiskManager, head: PageId, rid: RecordId) -> Result<bool> {
+	let mut id = head;
+	loop {
+		let mut page = disk.read_page(id)?;
+		if PostingsPageView::new(&mut page.data).remove(rid) {
+			disk.flush_page(&page)?;
+			return Ok(true);
+		}
+		if page.next == id {
+			return Ok(false);
+		}
+		id = page.next;
+	}
+}
+
+/// Reads every entry of a posting list, in no particular order
+fn read_postings(disk: &mut DiskManager, head: PageId) -> Result<Vec<(RecordId, u32)>> {
+	let mut postings = Vec::new();
+	let mut id = head;
+	loop {
+		let mut page = disk.read_page(id)?;
+		let view = PostingsPageView::new(&mut page.data);
+		postings.extend((0..view.len()).map(|i| view.get(i)));
+		if page.next == id {
+			return Ok(postings);
+		}
+		id = page.next;
+	}
+}
+
+fn term_frequencies(terms: &[String]) -> HashMap<&String, u32> {
+	let mut counts = HashMap::new();
+	for term in terms {
+		*counts.entry(term).or_default() += 1;
+	}
+	counts
+}
+
+/// Record IDs found in both sorted lists, merging them
+fn intersect_sorted(a: &[RecordId], b: &[RecordId]) -> Vec<RecordId> {
+	let mut found = Vec::new();
+	let (mut i, mut j) = (0, 0);
+	while i < a.len() && j < b.len() {
+		match a[i].cmp(&b[j]) {
+			Ordering::Less => i += 1,
+			Ordering::Greater => j += 1,
+			Ordering::Equal => {
+				found.push(a[i]);
+				i += 1;
+				j += 1;
+			}
+		}
+	}
+	found
+}
+
+/// Term hashes are split across two columns
+fn hash_key_schema() -> Schema {
+	Schema::new().with_n(ValueType::U32, 2)
+}
+
+fn hash_key(hash: u64) -> Record {
+	Record::new()
+		.item(Value::U32((hash >> 32) as u32))
+		.item(Value::U32(hash as u32))
+}
+
+/// A term's ID, length in bytes and the first page of its posting list
+fn term_schema() -> Schema {
+	Schema::new().with_n(ValueType::U32, 3)
+}
+
+fn term_rid(id: u32) -> RecordId {
+	RecordId::new(id, 0)
+}
+
+/// A term's ID and the index of a chunk of its text
+fn chunk_key_schema() -> Schema {
+	Schema::new().with_n(ValueType::U32, 2)
+}
+
+fn chunk_key(id: u32, chunk: usize) -> Record {
+	Record::new()
+		.item(Value::U32(id))
+		.item(Value::U32(chunk as u32))
+}
+
+/// Bytes of a term's text, zero padded
+fn chunk_schema() -> Schema {
+	Schema::new().with_n(ValueType::U32, TERM_CHUNK_SIZE / 4)
+}
+
+fn doc_key_schema() -> Schema {
+	Schema::new().with_n(ValueType::U32, 2)
+}
+
+fn doc_key(rid: RecordId) -> Record {
+	Record::new()
+		.item(Value::U32(rid.page_id))
+		.item(Value::U32(rid.slot as u32))
+}
+
+fn tf_schema() -> Schema {
+	Schema::new().with(ValueType::U32)
+}
+
+fn as_u32(rec: &Record, column: usize) -> u32 {
+	match rec.get(column) {
+		Some(Value::U32(n)) => *n,
+		_ => 0,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{tokenize::StopWords, *};
+
+	#[test]
+	fn matches_and_rank() {
+		let mut disk = DiskManager::temp("fulltext_matches_and_rank");
+		let index = FullTextIndex::create(
+			&mut disk,
+			Analyzer::default().filter(StopWords::new(["the"])),
+		)
+		.unwrap();
+		check_matches_and_rank(&mut disk, &index);
+	}
+
+	/// Terms with the same hash are told apart by their text
+	#[test]
+	fn colliding_terms() {
+		let mut disk = DiskManager::temp("fulltext_colliding_terms");
+		let index = FullTextIndex::create(
+			&mut disk,
+			Analyzer::default().filter(StopWords::new(["the"])),
+		)
+		.unwrap()
+		.with_term_hash(|_| 7);
+		check_matches_and_rank(&mut disk, &index);
+
+		// longer than a chunk, differing only in the last one
+		let long = "internationalization";
+		index.insert(&mut disk, long, RecordId::new(2, 0)).unwrap();
+		index
+			.insert(&mut disk, "internationalizatiom", RecordId::new(2, 1))
+			.unwrap();
+		assert_eq!(
+			index.matches(&mut disk, long).unwrap(),
+			vec![RecordId::new(2, 0)]
+		);
+		assert!(
+			index
+				.matches(&mut disk, "international")
+				.unwrap()
+				.is_empty()
+		);
+	}
+
+	/// Posting lists longer than a page, and reopening the index from its root
+	#[test]
+	fn long_posting_lists() {
+		let mut disk = DiskManager::temp("fulltext_long_posting_lists");
+		let index = FullTextIndex::create(&mut disk, Analyzer::default()).unwrap();
+		let n = 5_000u32;
+		for i in 0..n {
+			let text = if i % 2 == 0 { "common even" } else { "common" };
+			index.insert(&mut disk, text, RecordId::new(i, 0)).unwrap();
+		}
+		for i in (0..n).step_by(4) {
+			let text = if i % 2 == 0 { "common even" } else { "common" };
+			index.delete(&mut disk, text, RecordId::new(i, 0)).unwrap();
+		}
+
+		let index = FullTextIndex::open(&mut disk, index.root(), Analyzer::default()).unwrap();
+		let expected: Vec<RecordId> = (0..n)
+			.filter(|i| i % 4 != 0)
+			.map(|i| RecordId::new(i, 0))
+			.collect();
+		assert_eq!(index.matches(&mut disk, "common").unwrap(), expected);
+		assert_eq!(
+			index.matches(&mut disk, "even").unwrap().len(),
+			(n / 4) as usize
+		);
+		assert_eq!(
+			index.rank(&mut disk, "even").unwrap().len(),
+			(n / 4) as usize
+		);
+	}
+
+	fn check_matches_and_rank(disk: &mut DiskManager, index: &FullTextIndex) {
+		let docs = [
+			"The quick brown fox",
+			"The lazy brown dog",
+			"Fox news about a fox and another fox",
+		];
+		for (i, doc) in docs.iter().enumerate() {
+			index.insert(disk, doc, RecordId::new(1, i as u16)).unwrap();
+		}
+
+		let found = index.matches(disk, "BROWN fox").unwrap();
+		assert_eq!(found, vec![RecordId::new(1, 0)]);
+		assert_eq!(
+			index.matches(disk, "fox").unwrap(),
+			vec![RecordId::new(1, 0), RecordId::new(1, 2)]
+		);
+		assert!(index.matches(disk, "the").unwrap().is_empty());
+		assert!(index.matches(disk, "cat").unwrap().is_empty());
+		assert!(index.matches(disk, "fox cat").unwrap().is_empty());
+
+		// the doc repeating "fox" ranks first
+		let ranked = index.rank(disk, "fox").unwrap();
+		assert_eq!(ranked.len(), 2);
+		assert_eq!(ranked[0].0, RecordId::new(1, 2));
+		assert!(ranked[0].1 > ranked[1].1);
+
+		index.delete(disk, docs[2], RecordId::new(1, 2)).unwrap();
+		assert_eq!(
+			index.matches(disk, "fox").unwrap(),
+			vec![RecordId::new(1, 0)]
+		);
+		assert_eq!(index.read_stats(disk).unwrap(), (2, 6));
+	}
+}
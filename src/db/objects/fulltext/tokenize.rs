@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+
+/// Splits text into terms
+pub trait Tokenizer {
+	fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Splits on whitespace, keeping punctuation attached to terms
+pub struct WhitespaceTokenizer;
+impl Tokenizer for WhitespaceTokenizer {
+	fn tokenize(&self, text: &str) -> Vec<String> {
+		text.split_whitespace().map(String::from).collect()
+	}
+}
+
+/// Splits on anything that isn't a unicode letter or digit
+///
+/// This approximates word boundaries rather than implementing UAX #29: punctuation inside words such as `don't` or
+/// `3.14` splits them, and scripts written without spaces, such as Chinese or Thai, aren't split into words.
+pub struct WordTokenizer;
+impl Tokenizer for WordTokenizer {
+	fn tokenize(&self, text: &str) -> Vec<String> {
+		text.split(|c: char| !c.is_alphanumeric())
+			.filter(|w| !w.is_empty())
+			.map(String::from)
+			.collect()
+	}
+}
+
+/// Produces every `n` character long substring of each word, words shorter than `n` are kept whole
+pub struct NGramTokenizer {
+	pub n: usize,
+}
+impl Tokenizer for NGramTokenizer {
+	fn tokenize(&self, text: &str) -> Vec<String> {
+		let mut grams = Vec::new();
+		for word in WordTokenizer.tokenize(text) {
+			let chars: Vec<char> = word.chars().collect();
+			if chars.len() <= self.n {
+				grams.push(word);
+				continue;
+			}
+			grams.extend(chars.windows(self.n).map(|w| w.iter().collect::<String>()));
+		}
+		grams
+	}
+}
+
+/// Transforms terms after tokenizing, returning `None` drops the term
+///
+/// This is the hook for stemmers, and is implemented for any matching closure.
+pub trait TokenFilter {
+	fn filter(&self, term: String) -> Option<String>;
+}
+impl<F: Fn(String) -> Option<String>> TokenFilter for F {
+	fn filter(&self, term: String) -> Option<String> {
+		self(term)
+	}
+}
+
+pub struct Lowercase;
+impl TokenFilter for Lowercase {
+	fn filter(&self, term: String) -> Option<String> {
+		Some(term.to_lowercase())
+	}
+}
+
+/// Drops terms found in a list of stop words
+pub struct StopWords {
+	words: HashSet<String>,
+}
+impl StopWords {
+	pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(words: I) -> StopWords {
+		StopWords {
+			words: words.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+impl TokenFilter for StopWords {
+	fn filter(&self, term: String) -> Option<String> {
+		if self.words.contains(&term) {
+			None
+		} else {
+			Some(term)
+		}
+	}
+}
+
+/// Reduces English words to their stems with the Porter algorithm, so `connected`, `connecting` and `connections` all
+/// become `connect`
+///
+/// Expects lowercased terms, anything other than ASCII lowercase letters is kept as it is.
+pub struct PorterStemmer;
+impl TokenFilter for PorterStemmer {
+	fn filter(&self, term: String) -> Option<String> {
+		if term.len() <= 2 || !term.bytes().all(|b| b.is_ascii_lowercase()) {
+			return Some(term);
+		}
+		let mut word = Stemming {
+			b: term.into_bytes(),
+			j: 0,
+		};
+		word.step1ab();
+		if word.b.len() > 1 {
+			word.step1c();
+			word.step2();
+			word.step3();
+			word.step4();
+			word.step5();
+		}
+		Some(String::from_utf8(word.b).expect("Stems are ASCII"))
+	}
+}
+
+/// Suffixes replaced in step 2 of the Porter algorithm, the first one that matches is used
+const STEP2_SUFFIXES: [(&str, &str); 20] = [
+	("ational", "ate"),
+	("tional", "tion"),
+	("enci", "ence"),
+	("anci", "ance"),
+	("izer", "ize"),
+	("bli", "ble"),
+	("alli", "al"),
+	("entli", "ent"),
+	("eli", "e"),
+	("ousli", "ous"),
+	("ization", "ize"),
+	("ation", "ate"),
+	("ator", "ate"),
+	("alism", "al"),
+	("iveness", "ive"),
+	("fulness", "ful"),
+	("ousness", "ous"),
+	("aliti", "al"),
+	("iviti", "ive"),
+	("biliti", "ble"),
+];
+/// Suffixes replaced in step 3 of the Porter algorithm
+const STEP3_SUFFIXES: [(&str, &str); 7] = [
+	("icate", "ic"),
+	("ative", ""),
+	("alize", "al"),
+	("iciti", "ic"),
+	("ical", "ic"),
+	("ful", ""),
+	("ness", ""),
+];
+/// Suffixes removed in step 4 of the Porter algorithm, `ion` only after an `s` or `t`
+const STEP4_SUFFIXES: [&str; 19] = [
+	"al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ion", "ou",
+	"ism", "ate", "iti", "ous", "ive", "ize",
+];
+
+/// A word part way through the Porter algorithm, where `j` is the length of the stem the last matched suffix follows
+struct Stemming {
+	b: Vec<u8>,
+	j: usize,
+}
+impl Stemming {
+	/// Whether the letter at `i` is a consonant, `y` counting as one unless it follows a consonant
+	fn cons(&self, i: usize) -> bool {
+		match self.b[i] {
+			b'a' | b'e' | b'i' | b'o' | b'u' => false,
+			b'y' => i == 0 || !self.cons(i - 1),
+			_ => true,
+		}
+	}
+
+	/// Number of vowel-consonant sequences in the stem, `m` in the algorithm
+	fn m(&self) -> usize {
+		let mut n = 0;
+		let mut i = 0;
+		// leading consonants don't count
+		while i < self.j && self.cons(i) {
+			i += 1;
+		}
+		loop {
+			while i < self.j && !self.cons(i) {
+				i += 1;
+			}
+			if i >= self.j {
+				return n;
+			}
+			while i < self.j && self.cons(i) {
+				i += 1;
+			}
+			n += 1;
+		}
+	}
+
+	fn vowel_in_stem(&self) -> bool {
+		(0..self.j).any(|i| !self.cons(i))
+	}
+
+	/// Whether the letters at `i` and before it are the same consonant
+	fn double_cons(&self, i: usize) -> bool {
+		i >= 1 && self.b[i] == self.b[i - 1] && self.cons(i)
+	}
+
+	/// Whether the letters up to `i` end with consonant-vowel-consonant, the last not being `w`, `x` or `y`
+	fn cvc(&self, i: usize) -> bool {
+		i >= 2
+			&& self.cons(i)
+			&& !self.cons(i - 1)
+			&& self.cons(i - 2)
+			&& !matches!(self.b[i], b'w' | b'x' | b'y')
+	}
+
+	/// Checks for a suffix, setting the stem to what precedes it if found
+	fn ends(&mut self, suffix: &str) -> bool {
+		if !self.b.ends_with(suffix.as_bytes()) {
+			return false;
+		}
+		self.j = self.b.len() - suffix.len();
+		true
+	}
+
+	/// Replaces what follows the stem
+	fn set_to(&mut self, s: &str) {
+		self.b.truncate(self.j);
+		self.b.extend_from_slice(s.as_bytes());
+	}
+
+	/// Replaces the first suffix that matches, if the stem before it has a measure above 0
+	fn replace(&mut self, suffixes: &[(&str, &str)]) {
+		if let Some((_, to)) = suffixes.iter().find(|(from, _)| self.ends(from))
+			&& self.m() > 0
+		{
+			self.set_to(to);
+		}
+	}
+
+	/// Plurals and past participles
+	fn step1ab(&mut self) {
+		if self.b.ends_with(b"s") {
+			if self.ends("sses") {
+				self.b.truncate(self.b.len() - 2);
+			} else if self.ends("ies") {
+				self.set_to("i");
+			} else if self.b[self.b.len() - 2] != b's' {
+				self.b.pop();
+			}
+		}
+		if self.ends("eed") {
+			if self.m() > 0 {
+				self.b.pop();
+			}
+		} else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+			self.b.truncate(self.j);
+			let last = self.b.len() - 1;
+			if self.ends("at") || self.ends("bl") || self.ends("iz") {
+				self.b.push(b'e');
+			} else if self.double_cons(last) {
+				if !matches!(self.b[last], b'l' | b's' | b'z') {
+					self.b.pop();
+				}
+			} else if self.m() == 1 && self.cvc(last) {
+				self.b.push(b'e');
+			}
+		}
+	}
+
+	/// A final `y` becomes `i` if there is a vowel before it
+	fn step1c(&mut self) {
+		if self.ends("y") && self.vowel_in_stem() {
+			self.b[self.j] = b'i';
+		}
+	}
+
+	/// Double suffixes become single ones
+	fn step2(&mut self) {
+		self.replace(&STEP2_SUFFIXES);
+	}
+
+	fn step3(&mut self) {
+		self.replace(&STEP3_SUFFIXES);
+	}
+
+	/// Suffixes are removed from stems with a measure above 1
+	fn step4(&mut self) {
+		let found = STEP4_SUFFIXES.iter().any(|suffix| {
+			self.ends(suffix)
+				&& (*suffix != "ion" || (self.j >= 1 && matches!(self.b[self.j - 1], b's' | b't')))
+		});
+		if found && self.m() > 1 {
+			self.b.truncate(self.j);
+		}
+	}
+
+	/// Final `e`s and double `l`s are removed from longer stems
+	fn step5(&mut self) {
+		self.j = self.b.len();
+		if self.b.ends_with(b"e") {
+			let m = self.m();
+			if m > 1 || (m == 1 && !self.cvc(self.b.len() - 2)) {
+				self.b.pop();
+			}
+		}
+		self.j = self.b.len();
+		if self.b.ends_with(b"l") && self.double_cons(self.b.len() - 1) && self.m() > 1 {
+			self.b.pop();
+		}
+	}
+}
+
+/// Turns text into the terms that get indexed, a tokenizer followed by filters applied in order
+pub struct Analyzer {
+	tokenizer: Box<dyn Tokenizer>,
+	filters: Vec<Box<dyn TokenFilter>>,
+}
+impl Analyzer {
+	pub fn new<T: Tokenizer + 'static>(tokenizer: T) -> Analyzer {
+		Analyzer {
+			tokenizer: Box::new(tokenizer),
+			filters: Vec::new(),
+		}
+	}
+
+	pub fn filter<F: TokenFilter + 'static>(mut self, filter: F) -> Self {
+		self.filters.push(Box::new(filter));
+		self
+	}
+
+	pub fn analyze(&self, text: &str) -> Vec<String> {
+		self.tokenizer
+			.tokenize(text)
+			.into_iter()
+			.filter_map(|term| self.filters.iter().try_fold(term, |term, f| f.filter(term)))
+			.collect()
+	}
+}
+impl Default for Analyzer {
+	/// Lowercased words
+	fn default() -> Self {
+		Analyzer::new(WordTokenizer).filter(Lowercase)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tokenizers() {
+		assert_eq!(
+			WhitespaceTokenizer.tokenize(" hello,  wörld\tfoo-bar "),
+			vec!["hello,", "wörld", "foo-bar"]
+		);
+		assert_eq!(
+			WordTokenizer.tokenize(" hello,  wörld\tfoo-bar "),
+			vec!["hello", "wörld", "foo", "bar"]
+		);
+		assert_eq!(
+			NGramTokenizer { n: 3 }.tokenize("abcd ab"),
+			vec!["abc", "bcd", "ab"]
+		);
+	}
+
+	#[test]
+	fn filters() {
+		let analyzer = Analyzer::default()
+			.filter(StopWords::new(["the", "a"]))
+			.filter(|term: String| Some(term.trim_end_matches('s').to_string()));
+		assert_eq!(
+			analyzer.analyze("The Cats chased a MOUSE"),
+			vec!["cat", "chased", "mouse"]
+		);
+	}
+
+	#[test]
+	fn porter_stemmer() {
+		for (word, stem) in [
+			("caresses", "caress"),
+			("ponies", "poni"),
+			("cats", "cat"),
+			("feed", "feed"),
+			("agreed", "agre"),
+			("plastered", "plaster"),
+			("motoring", "motor"),
+			("sing", "sing"),
+			("conflated", "conflat"),
+			("sized", "size"),
+			("hopping", "hop"),
+			("falling", "fall"),
+			("filing", "file"),
+			("happy", "happi"),
+			("relational", "relat"),
+			("generalizations", "gener"),
+			("adoption", "adopt"),
+			("controlling", "control"),
+			("connections", "connect"),
+			("is", "is"),
+			("Running", "Running"),
+			("naïve", "naïve"),
+		] {
+			assert_eq!(
+				PorterStemmer.filter(word.to_string()).unwrap(),
+				stem,
+				"{word}"
+			);
+		}
+	}
+}
@@ -0,0 +1,566 @@
+use super::BloomFilter;
+use crate::{
+	db::{
+		disk::{
+			DiskManager, HashBucketPageView, HashDirectoryPageView, HashSlotsPageView, Page,
+			PageId, RecordId,
+		},
+		record::*,
+	},
+	util::hash_bytes,
+	*,
+};
+
+/// Persistent extendible hash index, mapping fixed length keys to record IDs
+///
+/// The index is rooted at a directory of `2^global_depth` bucket pointers, indexed by the low bits of the key's hash. The
+/// pointers are held by slot pages, which the directory's root page lists, so the directory spans more pages as it
+/// doubles. A full bucket is split in two (doubling the directory if needed) until the directory can no longer grow,
+/// after which further entries spill into a chain of overflow pages linked through `Page::next`.
+///
+/// Each entry may also carry a fixed length payload, stored inline so it can be read back without visiting the record.
+///
+/// An optional `BloomFilter` over the keys lets lookups for missing keys skip reading the buckets.
+///
+/// Only supports equality lookups, keys may be duplicated.
+pub struct HashIndex {
+	directory: PageId,
+	key_schema: Schema,
+	payload_schema: Schema,
+	bloom: Option<BloomFilter>,
+}
+impl HashIndex {
+	/// Allocates the pages for a new, empty index
+	pub fn create(
+		disk: &mut DiskManager,
+		key_schema: Schema,
+		payload_schema: Schema,
+	) -> Result<HashIndex> {
+		if key_schema.size().is_none() || payload_schema.size().is_none() {
+			return Err(Error::InvalidArgument(
+				"Hash index keys and payloads must have a fixed length schema".to_string(),
+			));
+		}
+
+		let mut bucket = disk.allocate_page()?;
+		HashBucketPageView::new(&mut bucket.data, &key_schema, &payload_schema)?.init(0);
+		disk.flush_page(&bucket)?;
+
+		let mut slots = disk.allocate_page()?;
+		HashSlotsPageView::new(&mut slots.data).set_bucket(0, bucket.id);
+		disk.flush_page(&slots)?;
+
+		let mut directory = disk.allocate_page()?;
+		HashDirectoryPageView::new(&mut directory.data).init(slots.id);
+		disk.flush_page(&directory)?;
+
+		Ok(HashIndex {
+			directory: directory.id,
+			key_schema,
+			payload_schema,
+			bloom: None,
+		})
+	}
+
+	/// Opens an existing index given its directory's root page
+	pub fn open(directory: PageId, key_schema: Schema, payload_schema: Schema) -> HashIndex {
+		HashIndex {
+			directory,
+			key_schema,
+			payload_schema,
+			bloom: None,
+		}
+	}
+
+	/// Reattaches a Bloom filter previously built for this index
+	pub fn with_bloom_filter(mut self, bloom: BloomFilter) -> Self {
+		self.bloom = Some(bloom);
+		self
+	}
+
+	/// ID of the page this index is rooted at
+	#[inline]
+	pub fn directory(&self) -> PageId {
+		self.directory
+	}
+
+	#[inline]
+	pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+		self.bloom.as_ref()
+	}
+
+	/// Creates a Bloom filter over the index's current keys, which will be kept up to date from then on
+	pub fn attach_bloom_filter(
+		&mut self,
+		disk: &mut DiskManager,
+		expected_keys: u64,
+		false_positive_rate: f64,
+	) -> Result<()> {
+		self.bloom = Some(BloomFilter::create(
+			disk,
+			expected_keys,
+			false_positive_rate,
+		)?);
+		self.rebuild_bloom_filter(disk)
+	}
+
+	/// Rebuilds the Bloom filter from scratch, dropping keys that have since been removed
+	pub fn rebuild_bloom_filter(&self, disk: &mut DiskManager) -> Result<()> {
+		let Some(bloom) = &self.bloom else {
+			return Ok(());
+		};
+		bloom.clear(disk)?;
+
+		let mut root = disk.read_page(self.directory)?;
+		let dir = HashDirectoryPageView::new(&mut root.data);
+		let mut buckets = Vec::with_capacity(dir.len());
+		for i in 0..dir.n_pages() {
+			let mut page = disk.read_page(dir.page(i))?;
+			let slots = HashSlotsPageView::new(&mut page.data);
+			let n_slots = dir.len().min(dir.slots_per_page());
+			buckets.extend((0..n_slots).map(|slot| slots.bucket(slot)));
+		}
+		buckets.sort();
+		buckets.dedup();
+
+		for bucket in buckets {
+			let mut page_id = bucket;
+			loop {
+				let mut page = disk.read_page(page_id)?;
+				let entries = HashBucketPageView::new(
+					&mut page.data,
+					&self.key_schema,
+					&self.payload_schema,
+				)?
+				.entries();
+				for (key, _, _) in entries {
+					bloom.insert(disk, &key.to_bytes())?;
+				}
+				if page.next == page.id {
+					break;
+				}
+				page_id = page.next;
+			}
+		}
+		Ok(())
+	}
+
+	/// Adds an entry to the index
+	pub fn insert(
+		&self,
+		disk: &mut DiskManager,
+		key: &Record,
+		payload: &Record,
+		rid: RecordId,
+	) -> Result<()> {
+		if !self.key_schema.validate(key) || !self.payload_schema.validate(payload) {
+			return Err(Error::TypeMismatch(
+				"Hash index entry does not match schema".to_string(),
+			));
+		}
+		let hash = hash_bytes(&key.to_bytes());
+
+		loop {
+			let (slot, bucket_id) = self.lookup(disk, hash)?;
+			let mut bucket = disk.read_page(bucket_id)?;
+			let mut view =
+				HashBucketPageView::new(&mut bucket.data, &self.key_schema, &self.payload_schema)?;
+			if view.insert(key, payload, rid) {
+				disk.flush_page(&bucket)?;
+				break;
+			}
+
+			let local_depth = view.local_depth();
+			if !self.split(disk, bucket, slot, local_depth)? {
+				self.insert_overflow(disk, bucket_id, key, payload, rid)?;
+				break;
+			}
+		}
+
+		if let Some(bloom) = &self.bloom {
+			bloom.insert(disk, &key.to_bytes())?;
+		}
+		Ok(())
+	}
+
+	/// Finds every record ID stored under a key
+	pub fn get(&self, disk: &mut DiskManager, key: &Record) -> Result<Vec<RecordId>> {
+		Ok(self
+			.get_with_payload(disk, key)?
+			.into_iter()
+			.map(|(_, rid)| rid)
+			.collect())
+	}
+
+	/// Finds the payload and record ID of every entry stored under a key
+	pub fn get_with_payload(
+		&self,
+		disk: &mut DiskManager,
+		key: &Record,
+	) -> Result<Vec<(Record, RecordId)>> {
+		if let Some(bloom) = &self.bloom
+			&& !bloom.may_contain(disk, &key.to_bytes())?
+		{
+			return Ok(Vec::new());
+		}
+
+		let mut found = Vec::new();
+		let mut page_id = self.bucket_for(disk, key)?;
+		loop {
+			let mut page = disk.read_page(page_id)?;
+			found.extend(
+				HashBucketPageView::new(&mut page.data, &self.key_schema, &self.payload_schema)?
+					.find(key),
+			);
+			if page.next == page.id {
+				return Ok(found);
+			}
+			page_id = page.next;
+		}
+	}
+
+	/// Removes an entry from the index, returning `false` if it was not present
+	pub fn remove(&self, disk: &mut DiskManager, key: &Record, rid: RecordId) -> Result<bool> {
+		let mut page_id = self.bucket_for(disk, key)?;
+		loop {
+			let mut page = disk.read_page(page_id)?;
+			if HashBucketPageView::new(&mut page.data, &self.key_schema, &self.payload_schema)?
+				.remove(key, rid)
+			{
+				disk.flush_page(&page)?;
+				return Ok(true);
+			}
+			if page.next == page.id {
+				return Ok(false);
+			}
+			page_id = page.next;
+		}
+	}
+
+	/// Gets the primary bucket page a key belongs in
+	fn bucket_for(&self, disk: &mut DiskManager, key: &Record) -> Result<PageId> {
+		let (_, bucket) = self.lookup(disk, hash_bytes(&key.to_bytes()))?;
+		Ok(bucket)
+	}
+
+	/// Finds the directory slot a hash maps to, and the bucket it points to
+	fn lookup(&self, disk: &mut DiskManager, hash: u64) -> Result<(usize, PageId)> {
+		let mut root = disk.read_page(self.directory)?;
+		let dir = HashDirectoryPageView::new(&mut root.data);
+		let slot = dir.slot_for(hash);
+		let per_page = dir.slots_per_page();
+		let mut page = disk.read_page(dir.page(slot / per_page))?;
+		Ok((
+			slot,
+			HashSlotsPageView::new(&mut page.data).bucket(slot % per_page),
+		))
+	}
+
+	/// Doubles the directory, copying its slot pages once it outgrows the first one
+	///
+	/// Returns `false` if the directory is already at its maximum depth. The root is left for the caller to write.
+	fn double(&self, disk: &mut DiskManager, root: &mut Page) -> Result<bool> {
+		let mut dir = HashDirectoryPageView::new(&mut root.data);
+		let depth = dir.global_depth();
+		if depth >= dir.max_global_depth() {
+			return Ok(false);
+		}
+		let len = dir.len();
+		if len * 2 <= dir.slots_per_page() {
+			let mut page = disk.read_page(dir.page(0))?;
+			HashSlotsPageView::new(&mut page.data).double(len);
+			disk.flush_page(&page)?;
+		} else {
+			// each new slot points to the same bucket as its mirror, so the new slot pages are copies of the old ones
+			let mut copies = Vec::with_capacity(dir.n_pages());
+			for i in 0..dir.n_pages() {
+				let page = disk.read_page(dir.page(i))?;
+				let mut copy = disk.reserve_page()?;
+				copy.data.copy_from_slice(&page.data);
+				copies.push(copy);
+			}
+			disk.flush_pages(&copies)?;
+			for copy in copies {
+				dir.push_page(copy.id);
+			}
+		}
+		dir.set_global_depth(depth + 1);
+		Ok(true)
+	}
+
+	/// Splits a full bucket in two, doubling the directory first if needed
+	///
+	/// Returns `false` if the bucket can't be split because the directory is at its maximum size
+	fn split(
+		&self,
+		disk: &mut DiskManager,
+		mut bucket: Page,
+		slot: usize,
+		local_depth: u8,
+	) -> Result<bool> {
+		let mut root = disk.read_page(self.directory)?;
+		let global_depth = HashDirectoryPageView::new(&mut root.data).global_depth();
+		if local_depth == global_depth && !self.double(disk, &mut root)? {
+			return Ok(false);
+		}
+		let dir = HashDirectoryPageView::new(&mut root.data);
+		let per_page = dir.slots_per_page();
+
+		// repoint the half of the slots sharing this bucket that have the new bit set, which are spread over the slot
+		// pages in order
+		let mut sibling = disk.allocate_page()?;
+		let mut slot_pages: Vec<Page> = Vec::new();
+		let stride = 1usize << local_depth;
+		let mut i = slot & (stride - 1);
+		while i < dir.len() {
+			if (i >> local_depth) & 1 == 1 {
+				let id = dir.page(i / per_page);
+				if slot_pages.last().is_none_or(|page| page.id != id) {
+					slot_pages.push(disk.read_page(id)?);
+				}
+				let page = slot_pages.last_mut().unwrap();
+				HashSlotsPageView::new(&mut page.data).set_bucket(i % per_page, sibling.id);
+			}
+			i += stride;
+		}
+
+		// redistribute entries
+		let mut view =
+			HashBucketPageView::new(&mut bucket.data, &self.key_schema, &self.payload_schema)?;
+		let entries = view.drain();
+		view.set_local_depth(local_depth + 1);
+		let mut sibling_view =
+			HashBucketPageView::new(&mut sibling.data, &self.key_schema, &self.payload_schema)?;
+		sibling_view.init(local_depth + 1);
+		for (key, payload, rid) in entries {
+			let hash = hash_bytes(&key.to_bytes());
+			if (hash >> local_depth) & 1 == 1 {
+				sibling_view.insert(&key, &payload, rid);
+			} else {
+				view.insert(&key, &payload, rid);
+			}
+		}
+
+		disk.flush_page(&bucket)?;
+		disk.flush_page(&sibling)?;
+		disk.flush_pages(&slot_pages)?;
+		disk.flush_page(&root)?;
+		Ok(true)
+	}
+
+	/// Inserts into the overflow chain of a bucket that can no longer be split, extending the chain if it is full
+	fn insert_overflow(
+		&self,
+		disk: &mut DiskManager,
+		bucket_id: PageId,
+		key: &Record,
+		payload: &Record,
+		rid: RecordId,
+	) -> Result<()> {
+		let mut page = disk.read_page(bucket_id)?;
+		loop {
+			if page.next == page.id {
+				let mut overflow = disk.allocate_page()?;
+				overflow.prev = page.id;
+				let mut view = HashBucketPageView::new(
+					&mut overflow.data,
+					&self.key_schema,
+					&self.payload_schema,
+				)?;
+				view.init(0);
+				view.insert(key, payload, rid);
+				page.next = overflow.id;
+				disk.flush_page(&overflow)?;
+				return disk.flush_page(&page);
+			}
+
+			page = disk.read_page(page.next)?;
+			if HashBucketPageView::new(&mut page.data, &self.key_schema, &self.payload_schema)?
+				.insert(key, payload, rid)
+			{
+				return disk.flush_page(&page);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn insert_get_remove() {
+		let mut disk = DiskManager::temp("hash_index_insert_get_remove");
+		let index = HashIndex::create(&mut disk, Schema::new().with(ValueType::U32), Schema::new())
+			.unwrap();
+
+		// enough entries to force several splits
+		let n = 5_000;
+		for i in 0..n {
+			let key = Record::new().item(Value::U32(i));
+			index
+				.insert(
+					&mut disk,
+					&key,
+					&Record::new(),
+					RecordId::new(i, (i % 7) as u16),
+				)
+				.unwrap();
+		}
+		for i in 0..n {
+			let key = Record::new().item(Value::U32(i));
+			assert_eq!(
+				index.get(&mut disk, &key).unwrap(),
+				vec![RecordId::new(i, (i % 7) as u16)]
+			);
+		}
+		assert!(
+			index
+				.get(&mut disk, &Record::new().item(Value::U32(n)))
+				.unwrap()
+				.is_empty()
+		);
+
+		// removing every other entry
+		for i in (0..n).step_by(2) {
+			let key = Record::new().item(Value::U32(i));
+			assert!(
+				index
+					.remove(&mut disk, &key, RecordId::new(i, (i % 7) as u16))
+					.unwrap()
+			);
+			assert!(
+				!index
+					.remove(&mut disk, &key, RecordId::new(i, (i % 7) as u16))
+					.unwrap()
+			);
+		}
+		for i in 0..n {
+			let key = Record::new().item(Value::U32(i));
+			assert_eq!(index.get(&mut disk, &key).unwrap().len(), (i % 2) as usize);
+		}
+	}
+
+	#[test]
+	fn directory_spans_pages() {
+		let mut disk = DiskManager::temp("hash_index_directory_spans_pages");
+		let key_schema = Schema::new().with_n(ValueType::U32, 64);
+		let index = HashIndex::create(&mut disk, key_schema.clone(), Schema::new()).unwrap();
+
+		// wide keys fill buckets quickly, so the directory outgrows its first slot page
+		let key = |i: u32| (0..64).fold(Record::new(), |rec, j| rec.item(Value::U32(i * 64 + j)));
+		let n = 20_000;
+		for i in 0..n {
+			index
+				.insert(&mut disk, &key(i), &Record::new(), RecordId::new(i, 0))
+				.unwrap();
+		}
+		let mut root = disk.read_page(index.directory()).unwrap();
+		assert!(HashDirectoryPageView::new(&mut root.data).n_pages() > 1);
+
+		let reopened = HashIndex::open(index.directory(), key_schema, Schema::new());
+		for i in 0..n {
+			assert_eq!(
+				reopened.get(&mut disk, &key(i)).unwrap(),
+				vec![RecordId::new(i, 0)]
+			);
+		}
+		assert!(reopened.get(&mut disk, &key(n)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn duplicate_keys_overflow() {
+		let mut disk = DiskManager::temp("hash_index_duplicate_keys_overflow");
+		let index = HashIndex::create(&mut disk, Schema::new().with(ValueType::I32), Schema::new())
+			.unwrap();
+
+		// identical keys can never be separated by a split, so they have to overflow
+		let key = Record::new().item(Value::I32(-1));
+		let n = 3_000;
+		for i in 0..n {
+			index
+				.insert(&mut disk, &key, &Record::new(), RecordId::new(i, 0))
+				.unwrap();
+		}
+		let mut found = index.get(&mut disk, &key).unwrap();
+		found.sort_by_key(|rid| rid.page_id);
+		assert_eq!(
+			found,
+			(0..n).map(|i| RecordId::new(i, 0)).collect::<Vec<_>>()
+		);
+
+		// reopening from the directory page
+		let reopened = HashIndex::open(
+			index.directory(),
+			Schema::new().with(ValueType::I32),
+			Schema::new(),
+		);
+		assert!(
+			reopened
+				.remove(&mut disk, &key, RecordId::new(n - 1, 0))
+				.unwrap()
+		);
+		assert_eq!(
+			reopened.get(&mut disk, &key).unwrap().len(),
+			(n - 1) as usize
+		);
+	}
+
+	#[test]
+	fn bloom_filter() {
+		let mut disk = DiskManager::temp("hash_index_bloom_filter");
+		let mut index =
+			HashIndex::create(&mut disk, Schema::new().with(ValueType::U32), Schema::new())
+				.unwrap();
+		let key = |i| Record::new().item(Value::U32(i));
+
+		// entries from before the filter was attached are still found
+		for i in 0..1_000 {
+			index
+				.insert(&mut disk, &key(i), &Record::new(), RecordId::new(i, 0))
+				.unwrap();
+		}
+		index.attach_bloom_filter(&mut disk, 2_000, 0.01).unwrap();
+		let bloom = index.bloom_filter().unwrap().clone();
+		for i in 1_000..2_000 {
+			index
+				.insert(&mut disk, &key(i), &Record::new(), RecordId::new(i, 0))
+				.unwrap();
+		}
+		for i in 0..2_000 {
+			assert_eq!(
+				index.get(&mut disk, &key(i)).unwrap(),
+				vec![RecordId::new(i, 0)]
+			);
+			assert!(bloom.may_contain(&mut disk, &key(i).to_bytes()).unwrap());
+		}
+		assert!(index.get(&mut disk, &key(5_000)).unwrap().is_empty());
+
+		// removed keys are only dropped from the filter on rebuild
+		for i in 0..1_000 {
+			index
+				.remove(&mut disk, &key(i), RecordId::new(i, 0))
+				.unwrap();
+		}
+		let stale = (0..1_000)
+			.filter(|i| bloom.may_contain(&mut disk, &key(*i).to_bytes()).unwrap())
+			.count();
+		assert_eq!(stale, 1_000);
+		index.rebuild_bloom_filter(&mut disk).unwrap();
+		let stale = (0..1_000)
+			.filter(|i| bloom.may_contain(&mut disk, &key(*i).to_bytes()).unwrap())
+			.count();
+		assert!(stale < 50);
+
+		let reopened = HashIndex::open(
+			index.directory(),
+			Schema::new().with(ValueType::U32),
+			Schema::new(),
+		)
+		.with_bloom_filter(BloomFilter::open(&mut disk, bloom.header()).unwrap());
+		assert_eq!(
+			reopened.get(&mut disk, &key(1_500)).unwrap(),
+			vec![RecordId::new(1_500, 0)]
+		);
+	}
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use super::StorageEngine;
+use crate::{
+	db::{
+		disk::{DiskManager, FixedLenPageView, Page, PageId, RecordId},
+		record::*,
+	},
+	*,
+};
+
+/// Pages read ahead at once while scanning
+const SCAN_PREFETCH_PAGES: u32 = 16;
+
+/// Unordered storage of fixed length records, in a chain of pages linked through `Page::next`
+///
+/// Record IDs are the page and slot a record lives in, and stay valid until it is deleted.
+pub struct HeapFile {
+	first: PageId,
+	schema: Schema,
+	/// Where inserts can go, found by walking the chain on the first insert after opening
+	space: Option<FreeSpace>,
+}
+/// Pages of a heap file that may have free slots, and the last page, which the chain is extended from
+struct FreeSpace {
+	pages: BTreeSet<PageId>,
+	last: PageId,
+}
+impl HeapFile {
+	/// Allocates the first page of a new, empty heap file
+	pub fn create(disk: &mut DiskManager, schema: Schema) -> Result<HeapFile> {
+		let mut page = disk.allocate_page()?;
+		FixedLenPageView::new(&mut page.data, &schema)?.init();
+		disk.flush_page(&page)?;
+		Ok(HeapFile {
+			first: page.id,
+			schema,
+			space: Some(FreeSpace {
+				pages: BTreeSet::from([page.id]),
+				last: page.id,
+			}),
+		})
+	}
+
+	/// Opens an existing heap file given its first page
+	pub fn open(first: PageId, schema: Schema) -> HeapFile {
+		HeapFile {
+			first,
+			schema,
+			space: None,
+		}
+	}
+
+	/// Walks the chain for pages with free slots
+	fn find_space(&self, disk: &mut DiskManager) -> Result<FreeSpace> {
+		let mut pages = BTreeSet::new();
+		let mut page = disk.read_page(self.first)?;
+		loop {
+			if !FixedLenPageView::new(&mut page.data, &self.schema)?.is_full() {
+				pages.insert(page.id);
+			}
+			if page.next == page.id {
+				return Ok(FreeSpace {
+					pages,
+					last: page.id,
+				});
+			}
+			page = disk.read_page(page.next)?;
+		}
+	}
+}
+impl StorageEngine for HeapFile {
+	fn root(&self) -> PageId {
+		self.first
+	}
+
+	fn insert(&mut self, disk: &mut DiskManager, rec: Record) -> Result<RecordId> {
+		if !self.schema.validate(&rec) {
+			return Err(Error::TypeMismatch(
+				"Record does not match table schema".to_string(),
+			));
+		}
+
+		if self.space.is_none() {
+			self.space = Some(self.find_space(disk)?);
+		}
+		let space = self.space.as_mut().unwrap();
+
+		// first page with a free slot
+		while let Some(id) = space.pages.first().copied() {
+			let mut page = disk.read_page(id)?;
+			let mut view = FixedLenPageView::new(&mut page.data, &self.schema)?;
+			if let Some(slot) = view.insert_record(&rec)? {
+				if view.is_full() {
+					space.pages.remove(&id);
+				}
+				disk.flush_page(&page)?;
+				return Ok(RecordId::new(page.id, slot));
+			}
+			space.pages.remove(&id);
+		}
+
+		// every page is full, so extend the chain
+		let mut page = disk.read_page(space.last)?;
+		let mut new_page = disk.allocate_page()?;
+		new_page.prev = page.id;
+		page.next = new_page.id;
+		let mut view = FixedLenPageView::new(&mut new_page.data, &self.schema)?;
+		view.init();
+		let Some(slot) = view.insert_record(&rec)? else {
+			return Err(Error::Internal(
+				"Failed to insert into empty page".to_string(),
+			));
+		};
+		disk.flush_page(&new_page)?;
+		disk.flush_page(&page)?;
+		space.last = new_page.id;
+		space.pages.insert(new_page.id);
+		Ok(RecordId::new(new_page.id, slot))
+	}
+
+	fn get(&self, disk: &mut DiskManager, rid: RecordId) -> Result<Option<Record>> {
+		let mut page = disk.read_page(rid.page_id)?;
+		Ok(FixedLenPageView::new(&mut page.data, &self.schema)?.read_record(rid.slot))
+	}
+
+	fn update(&mut self, disk: &mut DiskManager, rid: RecordId, rec: Record) -> Result<bool> {
+		if !self.schema.validate(&rec) {
+			return Err(Error::TypeMismatch(
+				"Record does not match table schema".to_string(),
+			));
+		}
+		let mut page = disk.read_page(rid.page_id)?;
+		if !FixedLenPageView::new(&mut page.data, &self.schema)?.update_record(rid.slot, &rec) {
+			return Ok(false);
+		}
+		disk.flush_page(&page)?;
+		Ok(true)
+	}
+
+	fn delete(&mut self, disk: &mut DiskManager, rid: RecordId) -> Result<bool> {
+		let mut page = disk.read_page(rid.page_id)?;
+		if !FixedLenPageView::new(&mut page.data, &self.schema)?.delete_record(rid.slot) {
+			return Ok(false);
+		}
+		disk.flush_page(&page)?;
+		if let Some(space) = &mut self.space {
+			space.pages.insert(rid.page_id);
+		}
+		Ok(true)
+	}
+
+	fn scan(&self, disk: &mut DiskManager) -> Result<Vec<(RecordId, Record)>> {
+		let mut found = Vec::new();
+		// pages are usually allocated back to back, so consecutive IDs are read ahead in one batch
+		let mut prefetched = VecDeque::new();
+		let mut id = self.first;
+		loop {
+			if prefetched.front().is_none_or(|p: &Page| p.id != id) {
+				let end = (id + SCAN_PREFETCH_PAGES).min(disk.n_pages());
+				let ids: Vec<PageId> = (id..end).collect();
+				prefetched = disk.read_pages(&ids)?.into();
+			}
+			let mut page = prefetched.pop_front().unwrap();
+
+			let view = FixedLenPageView::new(&mut page.data, &self.schema)?;
+			for slot in view.occupied_slots() {
+				if let Some(rec) = view.read_record(slot) {
+					found.push((RecordId::new(page.id, slot), rec));
+				}
+			}
+			if page.next == page.id {
+				return Ok(found);
+			}
+			id = page.next;
+		}
+	}
+
+	fn flush(&mut self, _disk: &mut DiskManager) -> Result<()> {
+		// every change is written through
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reuses_free_slots() {
+		let mut disk = DiskManager::temp("heapfile_reuses_free_slots");
+		let schema = Schema::new().with(ValueType::U32);
+		let mut heap = HeapFile::create(&mut disk, schema.clone()).unwrap();
+		let rids: Vec<RecordId> = (0..5_000)
+			.map(|i| {
+				heap.insert(&mut disk, Record::new().item(Value::U32(i)))
+					.unwrap()
+			})
+			.collect();
+		let n_pages = disk.n_pages();
+
+		// freed slots are filled before the chain grows, including after reopening
+		let rec = Record::new().item(Value::U32(0));
+		assert!(heap.delete(&mut disk, rids[10]).unwrap());
+		assert_eq!(heap.insert(&mut disk, rec.clone()).unwrap(), rids[10]);
+		assert!(heap.delete(&mut disk, rids[20]).unwrap());
+		let mut heap = HeapFile::open(heap.root(), schema);
+		assert_eq!(heap.insert(&mut disk, rec).unwrap(), rids[20]);
+		assert_eq!(disk.n_pages(), n_pages);
+	}
+}
@@ -0,0 +1,915 @@
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, BinaryHeap},
+};
+
+use super::StorageEngine;
+use crate::{
+	db::{
+		disk::{DiskManager, MemtableLogPageView, Page, PageId, RecordId, SortedRunPageView},
+		record::*,
+	},
+	util::slice_to_array,
+	*,
+};
+
+/// Entries buffered in memory before being flushed to a sorted run, by default
+const DEFAULT_MEMTABLE_LIMIT: usize = 1_024;
+/// Runs level 0 can hold before they are all merged into level 1
+const L0_MAX_RUNS: usize = 4;
+/// How many times more entries each level can hold than the one above it
+const LEVEL_RATIO: u64 = 10;
+/// Pages of a run read or written at once while merging or scanning
+const RUN_READ_BATCH: usize = 32;
+/// Size of a free extent in the manifest
+const EXTENT_SIZE: usize = 8;
+
+/// A sorted run of entries, spread over contiguous pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SortedRun {
+	first_page: PageId,
+	n_pages: u32,
+	n_entries: u32,
+}
+impl SortedRun {
+	const SIZE: usize = 12;
+}
+
+/// Contiguous pages no run uses anymore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Extent {
+	first_page: PageId,
+	n_pages: u32,
+}
+impl From<&SortedRun> for Extent {
+	fn from(run: &SortedRun) -> Extent {
+		Extent {
+			first_page: run.first_page,
+			n_pages: run.n_pages,
+		}
+	}
+}
+
+/// Log structured merge tree storage
+///
+/// Writes go to an in-memory memtable, which is flushed to a new sorted run in level 0 once it fills up. Level 0 runs
+/// may overlap, and once there are too many they are merged into level 1. Every deeper level holds a single run,
+/// merged into the next level once it outgrows its size limit. Deletes write tombstones, which are dropped when merged
+/// into the deepest level.
+///
+/// Pages of merged runs become free extents, which new runs are written to when one is large enough. An extent is
+/// only reused once a manifest no longer referring to its run has been written, so a crash never leaves the manifest
+/// pointing at overwritten pages.
+///
+/// Every write to the memtable is first appended to a log, a chain of pages replayed into the memtable when the tree is
+/// opened, so writes are as durable as they are in a heap file. The log is cleared once the memtable is flushed, by
+/// starting a new generation of it, and its pages are reused.
+///
+/// Records are keyed by an increasing row number, handed out as `RecordId`s. The levels are recorded in a manifest page
+/// with the layout:
+/// ```txt
+/// |next_key|log|n_levels|level0|level1|...|n_free|extent1|extent2|...
+/// 0        8   12       13
+/// ```
+/// Where `log` is the first page of the log, each level is `|n_runs|run1|run2|...|`, each run is
+/// `|first_page|n_pages|n_entries|` and each extent is `|first_page|n_pages|`. Extents that don't fit in the manifest
+/// are left out, leaking their pages.
+pub struct LsmTree {
+	manifest: PageId,
+	schema: Schema,
+	memtable: BTreeMap<u64, Option<Record>>,
+	memtable_limit: usize,
+	/// Level 0 runs are oldest first
+	levels: Vec<Vec<SortedRun>>,
+	next_key: u64,
+	/// Extents new runs can be written to
+	free: Vec<Extent>,
+	/// Extents of merged runs, which can't be reused until the manifest is written
+	freed: Vec<Extent>,
+	log: MemtableLog,
+}
+
+/// The pages memtable writes are logged to, in chain order, which are kept for later generations of the log
+struct MemtableLog {
+	pages: Vec<PageId>,
+	/// The page entries are appended to, as an index into `pages`, and its contents
+	tail: usize,
+	tail_page: Page,
+	/// Bumped each time the log is cleared, so entries left in pages from before are ignored
+	generation: u32,
+}
+impl LsmTree {
+	/// Allocates the manifest of a new, empty tree
+	pub fn create(disk: &mut DiskManager, schema: Schema) -> Result<LsmTree> {
+		if schema.size().is_none() {
+			return Err(Error::InvalidArgument(
+				"LSM tree records must have a fixed length schema".to_string(),
+			));
+		}
+		let manifest = disk.allocate_page()?.id;
+		let log = MemtableLog::create(disk, &schema)?;
+		let mut tree = LsmTree {
+			manifest,
+			schema,
+			memtable: BTreeMap::new(),
+			memtable_limit: DEFAULT_MEMTABLE_LIMIT,
+			levels: vec![Vec::new()],
+			next_key: 0,
+			free: Vec::new(),
+			freed: Vec::new(),
+			log,
+		};
+		tree.write_manifest(disk)?;
+		Ok(tree)
+	}
+
+	/// Opens an existing tree given its manifest page, replaying the log into the memtable
+	pub fn open(disk: &mut DiskManager, manifest: PageId, schema: Schema) -> Result<LsmTree> {
+		let page = disk.read_page(manifest)?;
+		let field =
+			|offset: usize| u32::from_le_bytes(slice_to_array(&page.data[offset..(offset + 4)]));
+		let mut next_key = u64::from_le_bytes(slice_to_array(&page.data[0..8]));
+		let log_head = field(8);
+		let n_levels = page.data[12] as usize;
+		let mut levels = Vec::with_capacity(n_levels);
+		let mut offset = 13;
+		for _ in 0..n_levels {
+			let n_runs = page.data[offset] as usize;
+			offset += 1;
+			let mut runs = Vec::with_capacity(n_runs);
+			for _ in 0..n_runs {
+				runs.push(SortedRun {
+					first_page: field(offset),
+					n_pages: field(offset + 4),
+					n_entries: field(offset + 8),
+				});
+				offset += SortedRun::SIZE;
+			}
+			levels.push(runs);
+		}
+
+		let n_free = u16::from_le_bytes(slice_to_array(&page.data[offset..(offset + 2)])) as usize;
+		offset += 2;
+		let free = (0..n_free)
+			.map(|i| {
+				let offset = offset + i * EXTENT_SIZE;
+				Extent {
+					first_page: field(offset),
+					n_pages: field(offset + 4),
+				}
+			})
+			.collect();
+
+		let mut memtable = BTreeMap::new();
+		let log = MemtableLog::replay(disk, log_head, &schema, &mut memtable, &mut next_key)?;
+		Ok(LsmTree {
+			manifest,
+			schema,
+			memtable,
+			memtable_limit: DEFAULT_MEMTABLE_LIMIT,
+			levels,
+			next_key,
+			free,
+			freed: Vec::new(),
+			log,
+		})
+	}
+
+	/// Sets how many entries the memtable holds before being flushed, to make tests flush often
+	#[cfg(test)]
+	fn with_memtable_limit(mut self, limit: usize) -> Self {
+		self.memtable_limit = limit.max(1);
+		self
+	}
+
+	/// Every run, newest first
+	fn runs(&self) -> impl Iterator<Item = &SortedRun> {
+		self.levels.iter().flat_map(|level| level.iter().rev())
+	}
+
+	/// Finds the newest entry for a key, `Some(None)` if its newest entry is a tombstone
+	fn find(&self, disk: &mut DiskManager, key: u64) -> Result<Option<Option<Record>>> {
+		if let Some(entry) = self.memtable.get(&key) {
+			return Ok(Some(entry.clone()));
+		}
+		for run in self.runs() {
+			if let Some(entry) = self.run_find(disk, run, key)? {
+				return Ok(Some(entry));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Binary searches a run for a key, first by page then within the page
+	fn run_find(
+		&self,
+		disk: &mut DiskManager,
+		run: &SortedRun,
+		key: u64,
+	) -> Result<Option<Option<Record>>> {
+		// last page starting at or before the key
+		let (mut lo, mut hi) = (0, run.n_pages);
+		while lo < hi {
+			let mid = lo + (hi - lo) / 2;
+			let mut page = disk.read_page(run.first_page + mid)?;
+			if SortedRunPageView::new(&mut page.data, &self.schema)?.key(0) <= key {
+				lo = mid + 1;
+			} else {
+				hi = mid;
+			}
+		}
+		if lo == 0 {
+			return Ok(None);
+		}
+		let mut page = disk.read_page(run.first_page + lo - 1)?;
+		Ok(SortedRunPageView::new(&mut page.data, &self.schema)?.find(key))
+	}
+
+	/// Writes sorted entries out to a new run, `None` if there were no entries
+	///
+	/// `next` gives the entries in order, at most `max_entries` of them. The run goes to the smallest free extent they
+	/// are sure to fit in, or to pages appended to the file.
+	fn write_run(
+		&mut self,
+		disk: &mut DiskManager,
+		max_entries: usize,
+		mut next: impl FnMut(&mut DiskManager) -> Result<Option<(u64, Option<Record>)>>,
+	) -> Result<Option<SortedRun>> {
+		let per_page =
+			SortedRunPageView::new(&mut disk.empty_page(0).data, &self.schema)?.capacity();
+		let extent = self.take_free(max_entries.div_ceil(per_page as usize) as u32);
+
+		let mut run: Option<SortedRun> = None;
+		let mut page: Option<Page> = None;
+		// full pages, written out a batch at a time
+		let mut full = Vec::new();
+		while let Some((key, rec)) = next(disk)? {
+			if let Some(p) = page.as_mut()
+				&& SortedRunPageView::new(&mut p.data, &self.schema)?.push(key, rec.as_ref())
+			{
+				run.as_mut().unwrap().n_entries += 1;
+				continue;
+			}
+
+			// current page is full, or this is the first entry
+			if let Some(p) = page.take() {
+				full.push(p);
+				if full.len() >= RUN_READ_BATCH {
+					disk.flush_pages(&full)?;
+					full.clear();
+				}
+			}
+			let n_pages = run.map_or(0, |run| run.n_pages);
+			let mut p = match extent {
+				Some(extent) if n_pages < extent.n_pages => {
+					disk.empty_page(extent.first_page + n_pages)
+				}
+				Some(_) => {
+					return Err(Error::Internal(
+						"LSM run outgrew the extent it was written to".to_string(),
+					));
+				}
+				// written with the rest of the batch
+				None => disk.reserve_page()?,
+			};
+			SortedRunPageView::new(&mut p.data, &self.schema)?.push(key, rec.as_ref());
+			match run.as_mut() {
+				Some(run) => {
+					// runs are found by their first page and length, so their pages have to be contiguous
+					if p.id != run.first_page + run.n_pages {
+						return Err(Error::Internal(
+							"Pages of an LSM run were not allocated contiguously".to_string(),
+						));
+					}
+					run.n_pages += 1;
+					run.n_entries += 1;
+				}
+				None => {
+					run = Some(SortedRun {
+						first_page: p.id,
+						n_pages: 1,
+						n_entries: 1,
+					})
+				}
+			}
+			page = Some(p);
+		}
+		full.extend(page);
+		disk.flush_pages(&full)?;
+
+		// the rest of the extent stays free
+		if let Some(extent) = extent {
+			let used = run.map_or(0, |run| run.n_pages);
+			self.release(Extent {
+				first_page: extent.first_page + used,
+				n_pages: extent.n_pages - used,
+			});
+		}
+		Ok(run)
+	}
+
+	/// Takes the smallest free extent of at least `n_pages` pages
+	fn take_free(&mut self, n_pages: u32) -> Option<Extent> {
+		let (i, _) = self
+			.free
+			.iter()
+			.enumerate()
+			.filter(|(_, extent)| extent.n_pages >= n_pages)
+			.min_by_key(|(_, extent)| extent.n_pages)?;
+		Some(self.free.swap_remove(i))
+	}
+
+	/// Makes an extent free to reuse
+	fn release(&mut self, extent: Extent) {
+		add_extent(&mut self.free, extent);
+	}
+
+	/// Writes the memtable out as a new level 0 run, then compacts
+	fn flush_memtable(&mut self, disk: &mut DiskManager) -> Result<()> {
+		let entries = std::mem::take(&mut self.memtable);
+		let max_entries = entries.len();
+		let mut entries = entries.into_iter();
+		if let Some(run) = self.write_run(disk, max_entries, |_| Ok(entries.next()))? {
+			self.levels[0].push(run);
+		}
+		self.compact(disk)?;
+		self.write_manifest(disk)?;
+		self.log.clear(disk, &self.schema)
+	}
+
+	/// Merges levels into the next until every level is within its limit
+	fn compact(&mut self, disk: &mut DiskManager) -> Result<()> {
+		if self.levels[0].len() > L0_MAX_RUNS {
+			self.merge_down(disk, 0)?;
+		}
+		let base = (self.memtable_limit * L0_MAX_RUNS) as u64;
+		let mut level = 1;
+		while level < self.levels.len() {
+			let size: u64 = self.levels[level]
+				.iter()
+				.map(|run| run.n_entries as u64)
+				.sum();
+			if size > base * LEVEL_RATIO.pow(level as u32 - 1) {
+				self.merge_down(disk, level)?;
+			}
+			level += 1;
+		}
+		Ok(())
+	}
+
+	/// Merges every run in a level with the run in the level below it
+	fn merge_down(&mut self, disk: &mut DiskManager, level: usize) -> Result<()> {
+		if self.levels.len() <= level + 1 {
+			self.levels.push(Vec::new());
+		}
+		let is_last = self.levels[(level + 2)..].iter().all(|l| l.is_empty());
+
+		// newest first, so the newest entry for each key wins
+		let runs: Vec<SortedRun> = self.levels[level]
+			.iter()
+			.rev()
+			.chain(self.levels[level + 1].iter())
+			.copied()
+			.collect();
+		let max_entries = runs.iter().map(|run| run.n_entries as usize).sum();
+		let mut merge = Merge::new(
+			disk,
+			self.schema.clone(),
+			runs.iter().map(|run| RunCursor::new(*run)).collect(),
+		)?;
+		let run = self.write_run(disk, max_entries, |disk| {
+			loop {
+				match merge.next(disk)? {
+					// nothing older is left for a tombstone to hide
+					Some((_, None)) if is_last => continue,
+					entry => return Ok(entry),
+				}
+			}
+		})?;
+
+		self.levels[level].clear();
+		self.levels[level + 1] = run.into_iter().collect();
+		self.freed.extend(runs.iter().map(Extent::from));
+		Ok(())
+	}
+
+	/// Writes the manifest, after which extents of merged runs can be reused
+	fn write_manifest(&mut self, disk: &mut DiskManager) -> Result<()> {
+		let mut page = disk.empty_page(self.manifest);
+		page.data[0..8].copy_from_slice(&self.next_key.to_le_bytes());
+		page.data[8..12].copy_from_slice(&self.log.pages[0].to_le_bytes());
+		page.data[12] = self.levels.len() as u8;
+		let mut offset = 13;
+		for level in self.levels.iter() {
+			page.data[offset] = level.len() as u8;
+			offset += 1;
+			for run in level {
+				page.data[offset..(offset + 4)].copy_from_slice(&run.first_page.to_le_bytes());
+				page.data[(offset + 4)..(offset + 8)].copy_from_slice(&run.n_pages.to_le_bytes());
+				page.data[(offset + 8)..(offset + 12)]
+					.copy_from_slice(&run.n_entries.to_le_bytes());
+				offset += SortedRun::SIZE;
+			}
+		}
+
+		// this manifest no longer refers to merged runs, so it can list them as free, the largest extents if not all of
+		// them fit
+		let mut free = self.free.clone();
+		for extent in self.freed.iter() {
+			add_extent(&mut free, *extent);
+		}
+		free.sort_by_key(|extent| Reverse(extent.n_pages));
+		free.truncate((page.data.len() - offset - 2) / EXTENT_SIZE);
+		page.data[offset..(offset + 2)].copy_from_slice(&(free.len() as u16).to_le_bytes());
+		offset += 2;
+		for extent in free {
+			page.data[offset..(offset + 4)].copy_from_slice(&extent.first_page.to_le_bytes());
+			page.data[(offset + 4)..(offset + 8)].copy_from_slice(&extent.n_pages.to_le_bytes());
+			offset += EXTENT_SIZE;
+		}
+		disk.flush_page(&page)?;
+
+		for extent in std::mem::take(&mut self.freed) {
+			self.release(extent);
+		}
+		Ok(())
+	}
+}
+
+impl MemtableLog {
+	/// Allocates the first page of an empty log
+	fn create(disk: &mut DiskManager, schema: &Schema) -> Result<MemtableLog> {
+		let mut page = disk.allocate_page()?;
+		MemtableLogPageView::new(&mut page.data, schema)?.reset(0);
+		disk.flush_page(&page)?;
+		Ok(MemtableLog {
+			pages: vec![page.id],
+			tail: 0,
+			tail_page: page,
+			generation: 0,
+		})
+	}
+
+	/// Reads the log starting at `head`, putting the entries of its current generation into the memtable in the order
+	/// they were written
+	fn replay(
+		disk: &mut DiskManager,
+		head: PageId,
+		schema: &Schema,
+		memtable: &mut BTreeMap<u64, Option<Record>>,
+		next_key: &mut u64,
+	) -> Result<MemtableLog> {
+		let mut tail_page = disk.read_page(head)?;
+		let generation = MemtableLogPageView::new(&mut tail_page.data, schema)?.generation();
+		let mut pages = vec![head];
+		let mut stale = None;
+		loop {
+			for (key, rec) in MemtableLogPageView::new(&mut tail_page.data, schema)?.entries() {
+				*next_key = (*next_key).max(key + 1);
+				memtable.insert(key, rec);
+			}
+			if tail_page.next == tail_page.id {
+				break;
+			}
+			let mut page = disk.read_page(tail_page.next)?;
+			if MemtableLogPageView::new(&mut page.data, schema)?.generation() != generation {
+				stale = Some(page);
+				break;
+			}
+			pages.push(page.id);
+			tail_page = page;
+		}
+
+		// pages left from earlier generations, to reuse
+		let tail = pages.len() - 1;
+		while let Some(page) = stale.take() {
+			pages.push(page.id);
+			if page.next != page.id {
+				stale = Some(disk.read_page(page.next)?);
+			}
+		}
+		Ok(MemtableLog {
+			pages,
+			tail,
+			tail_page,
+			generation,
+		})
+	}
+
+	/// Appends a memtable write, which has to be done before the write is made to the memtable
+	fn append(
+		&mut self,
+		disk: &mut DiskManager,
+		schema: &Schema,
+		key: u64,
+		rec: Option<&Record>,
+	) -> Result<()> {
+		let mut view = MemtableLogPageView::new(&mut self.tail_page.data, schema)?;
+		let len = view.len();
+		if view.push(key, rec) {
+			let res = disk.flush_page(&self.tail_page);
+			if res.is_err() {
+				// so the entry isn't written along with a later one
+				MemtableLogPageView::new(&mut self.tail_page.data, schema)?.truncate(len);
+			}
+			return res;
+		}
+
+		// the tail is full, so the entry starts the next page, reusing one from an earlier generation if there is one
+		let mut page = match self.pages.get(self.tail + 1) {
+			Some(&id) => {
+				let mut page = disk.empty_page(id);
+				page.next = self.pages.get(self.tail + 2).copied().unwrap_or(id);
+				page
+			}
+			None => disk.allocate_page()?,
+		};
+		let mut view = MemtableLogPageView::new(&mut page.data, schema)?;
+		view.reset(self.generation);
+		view.push(key, rec);
+		disk.flush_page(&page)?;
+		if self.tail + 1 == self.pages.len() {
+			// only linked once it holds the entry
+			self.tail_page.next = page.id;
+			if let Err(e) = disk.flush_page(&self.tail_page) {
+				self.tail_page.next = self.tail_page.id;
+				return Err(e);
+			}
+			self.pages.push(page.id);
+		}
+		self.tail += 1;
+		self.tail_page = page;
+		Ok(())
+	}
+
+	/// Starts a new generation, once the memtable has been flushed
+	///
+	/// If this fails, later writes are appended to the current generation, so the entries already flushed are replayed
+	/// along with them, which only writes the same records again.
+	fn clear(&mut self, disk: &mut DiskManager, schema: &Schema) -> Result<()> {
+		let generation = self.generation.wrapping_add(1);
+		let head = self.pages[0];
+		let mut page = disk.empty_page(head);
+		page.next = self.pages.get(1).copied().unwrap_or(head);
+		MemtableLogPageView::new(&mut page.data, schema)?.reset(generation);
+		disk.flush_page(&page)?;
+		self.generation = generation;
+		self.tail = 0;
+		self.tail_page = page;
+		Ok(())
+	}
+}
+
+/// Adds an extent to a list of free extents, merging it with the extents next to it
+fn add_extent(free: &mut Vec<Extent>, extent: Extent) {
+	if extent.n_pages == 0 {
+		return;
+	}
+	free.push(extent);
+	free.sort_by_key(|extent| extent.first_page);
+	free.dedup_by(|next, prev| {
+		if prev.first_page + prev.n_pages != next.first_page {
+			return false;
+		}
+		prev.n_pages += next.n_pages;
+		true
+	});
+}
+
+/// Reads the entries of a run in order, a batch of pages at a time
+struct RunCursor {
+	run: SortedRun,
+	/// Pages of the run read so far
+	read: u32,
+	entries: std::vec::IntoIter<(u64, Option<Record>)>,
+}
+impl RunCursor {
+	fn new(run: SortedRun) -> RunCursor {
+		RunCursor {
+			run,
+			read: 0,
+			entries: Vec::new().into_iter(),
+		}
+	}
+
+	/// A cursor over sorted entries already in memory
+	fn in_memory(entries: Vec<(u64, Option<Record>)>) -> RunCursor {
+		RunCursor {
+			run: SortedRun {
+				first_page: 0,
+				n_pages: 0,
+				n_entries: 0,
+			},
+			read: 0,
+			entries: entries.into_iter(),
+		}
+	}
+
+	fn next(
+		&mut self,
+		disk: &mut DiskManager,
+		schema: &Schema,
+	) -> Result<Option<(u64, Option<Record>)>> {
+		loop {
+			if let Some(entry) = self.entries.next() {
+				return Ok(Some(entry));
+			}
+			if self.read >= self.run.n_pages {
+				return Ok(None);
+			}
+			// runs are contiguous, so they are read in batches
+			let end = (self.read + RUN_READ_BATCH as u32).min(self.run.n_pages);
+			let ids: Vec<PageId> =
+				((self.run.first_page + self.read)..(self.run.first_page + end)).collect();
+			let mut entries = Vec::new();
+			for mut page in disk.read_pages(&ids)? {
+				entries.extend(SortedRunPageView::new(&mut page.data, schema)?.entries());
+			}
+			self.entries = entries.into_iter();
+			self.read = end;
+		}
+	}
+}
+
+/// Merges sorted runs into one sorted stream of entries, keeping only the newest entry for each key
+///
+/// Only a batch of pages of each run is held in memory at once.
+struct Merge {
+	schema: Schema,
+	/// Newest first
+	cursors: Vec<RunCursor>,
+	/// Key of the next entry of each cursor, with the cursor's index to prefer newer runs on equal keys
+	heads: BinaryHeap<Reverse<(u64, usize)>>,
+	/// Record of the next entry of each cursor
+	records: Vec<Option<Record>>,
+}
+impl Merge {
+	fn new(disk: &mut DiskManager, schema: Schema, cursors: Vec<RunCursor>) -> Result<Merge> {
+		let mut merge = Merge {
+			schema,
+			records: vec![None; cursors.len()],
+			cursors,
+			heads: BinaryHeap::new(),
+		};
+		for i in 0..merge.cursors.len() {
+			merge.advance(disk, i)?;
+		}
+		Ok(merge)
+	}
+
+	/// Moves a cursor on to its next entry
+	fn advance(&mut self, disk: &mut DiskManager, i: usize) -> Result<()> {
+		if let Some((key, rec)) = self.cursors[i].next(disk, &self.schema)? {
+			self.heads.push(Reverse((key, i)));
+			self.records[i] = rec;
+		}
+		Ok(())
+	}
+
+	fn next(&mut self, disk: &mut DiskManager) -> Result<Option<(u64, Option<Record>)>> {
+		let Some(Reverse((key, i))) = self.heads.pop() else {
+			return Ok(None);
+		};
+		let rec = self.records[i].take();
+		self.advance(disk, i)?;
+		// older entries for the same key are shadowed
+		while let Some(&Reverse((next_key, j))) = self.heads.peek()
+			&& next_key == key
+		{
+			self.heads.pop();
+			self.advance(disk, j)?;
+		}
+		Ok(Some((key, rec)))
+	}
+}
+
+impl StorageEngine for LsmTree {
+	fn root(&self) -> PageId {
+		self.manifest
+	}
+
+	fn insert(&mut self, disk: &mut DiskManager, rec: Record) -> Result<RecordId> {
+		if !self.schema.validate(&rec) {
+			return Err(Error::TypeMismatch(
+				"Record does not match table schema".to_string(),
+			));
+		}
+		let key = self.next_key;
+		self.log.append(disk, &self.schema, key, Some(&rec))?;
+		self.next_key += 1;
+		self.memtable.insert(key, Some(rec));
+		if self.memtable.len() >= self.memtable_limit {
+			self.flush_memtable(disk)?;
+		}
+		Ok(key_to_rid(key))
+	}
+
+	fn get(&self, disk: &mut DiskManager, rid: RecordId) -> Result<Option<Record>> {
+		Ok(self.find(disk, rid_to_key(rid))?.flatten())
+	}
+
+	fn update(&mut self, disk: &mut DiskManager, rid: RecordId, rec: Record) -> Result<bool> {
+		if !self.schema.validate(&rec) {
+			return Err(Error::TypeMismatch(
+				"Record does not match table schema".to_string(),
+			));
+		}
+		let key = rid_to_key(rid);
+		if self.find(disk, key)?.flatten().is_none() {
+			return Ok(false);
+		}
+		// shadows the old version like a delete does
+		self.log.append(disk, &self.schema, key, Some(&rec))?;
+		self.memtable.insert(key, Some(rec));
+		if self.memtable.len() >= self.memtable_limit {
+			self.flush_memtable(disk)?;
+		}
+		Ok(true)
+	}
+
+	fn delete(&mut self, disk: &mut DiskManager, rid: RecordId) -> Result<bool> {
+		let key = rid_to_key(rid);
+		if self.find(disk, key)?.flatten().is_none() {
+			return Ok(false);
+		}
+		self.log.append(disk, &self.schema, key, None)?;
+		self.memtable.insert(key, None);
+		if self.memtable.len() >= self.memtable_limit {
+			self.flush_memtable(disk)?;
+		}
+		Ok(true)
+	}
+
+	fn scan(&self, disk: &mut DiskManager) -> Result<Vec<(RecordId, Record)>> {
+		// the memtable is newer than any run
+		let mut cursors = vec![RunCursor::in_memory(
+			self.memtable
+				.iter()
+				.map(|(key, rec)| (*key, rec.clone()))
+				.collect(),
+		)];
+		cursors.extend(self.runs().map(|run| RunCursor::new(*run)));
+		let mut merge = Merge::new(disk, self.schema.clone(), cursors)?;
+
+		let mut records = Vec::new();
+		while let Some((key, rec)) = merge.next(disk)? {
+			if let Some(rec) = rec {
+				records.push((key_to_rid(key), rec));
+			}
+		}
+		Ok(records)
+	}
+
+	fn flush(&mut self, disk: &mut DiskManager) -> Result<()> {
+		if self.memtable.is_empty() {
+			return self.write_manifest(disk);
+		}
+		self.flush_memtable(disk)
+	}
+}
+
+/// Row keys are handed out as record IDs, split across the page and slot
+fn key_to_rid(key: u64) -> RecordId {
+	RecordId::new((key >> 16) as u32, key as u16)
+}
+
+fn rid_to_key(rid: RecordId) -> u64 {
+	((rid.page_id as u64) << 16) | rid.slot as u64
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compaction() {
+		let mut disk = DiskManager::temp("lsm_compaction");
+		let schema = Schema::new().with(ValueType::U32);
+		let mut tree = LsmTree::create(&mut disk, schema.clone())
+			.unwrap()
+			.with_memtable_limit(64);
+
+		let n = 3_000;
+		let rids: Vec<RecordId> = (0..n)
+			.map(|i| {
+				tree.insert(&mut disk, Record::new().item(Value::U32(i)))
+					.unwrap()
+			})
+			.collect();
+		for rid in rids.iter().filter(|rid| rid.slot % 2 == 0) {
+			assert!(tree.delete(&mut disk, *rid).unwrap());
+		}
+
+		// data has been pushed past level 1, and level 0 stays small
+		assert!(tree.levels.len() >= 3);
+		assert!(tree.levels[0].len() <= L0_MAX_RUNS);
+		assert!(tree.levels.iter().skip(1).all(|level| level.len() <= 1));
+
+		tree.flush(&mut disk).unwrap();
+		let tree = LsmTree::open(&mut disk, tree.root(), schema).unwrap();
+		for (i, rid) in rids.iter().enumerate() {
+			let expected = (i % 2 == 1).then(|| Record::new().item(Value::U32(i as u32)));
+			assert_eq!(tree.get(&mut disk, *rid).unwrap(), expected);
+		}
+		assert_eq!(tree.scan(&mut disk).unwrap().len(), (n / 2) as usize);
+	}
+
+	#[test]
+	fn logs_memtable_writes() {
+		let mut disk = DiskManager::temp("lsm_logs_memtable_writes");
+		let schema = Schema::new().with(ValueType::U32);
+		let rec = |i: u32| Record::new().item(Value::U32(i));
+		let mut tree = LsmTree::create(&mut disk, schema.clone()).unwrap();
+
+		// enough writes to fill a few log pages, without flushing the memtable
+		let rids: Vec<RecordId> = (0..1_000)
+			.map(|i| tree.insert(&mut disk, rec(i)).unwrap())
+			.collect();
+		assert!(tree.delete(&mut disk, rids[3]).unwrap());
+		assert!(tree.update(&mut disk, rids[4], rec(44)).unwrap());
+		assert!(tree.levels[0].is_empty() && tree.log.pages.len() > 1);
+
+		// dropped without flushing, as if the process had crashed
+		let mut tree = LsmTree::open(&mut disk, tree.root(), schema.clone()).unwrap();
+		assert_eq!(tree.get(&mut disk, rids[3]).unwrap(), None);
+		assert_eq!(tree.get(&mut disk, rids[4]).unwrap(), Some(rec(44)));
+		assert_eq!(tree.scan(&mut disk).unwrap().len(), 999);
+		let log_pages = tree.log.pages.clone();
+
+		// flushing clears the log, and later writes reuse its pages
+		for i in 1_000..1_100 {
+			tree.insert(&mut disk, rec(i)).unwrap();
+		}
+		assert_eq!(tree.levels[0].len(), 1);
+		let rid = tree.insert(&mut disk, rec(7)).unwrap();
+		let tree = LsmTree::open(&mut disk, tree.root(), schema).unwrap();
+		assert_eq!(tree.log.pages, log_pages);
+		assert_eq!(tree.memtable.len(), tree.next_key as usize - 1_024);
+		assert_eq!(tree.get(&mut disk, rid).unwrap(), Some(rec(7)));
+		assert_eq!(tree.scan(&mut disk).unwrap().len(), 1_100);
+	}
+
+	#[test]
+	fn reclaims_merged_runs() {
+		let mut disk = DiskManager::temp("lsm_reclaims_merged_runs");
+		let schema = Schema::new().with(ValueType::U32);
+		let mut tree = LsmTree::create(&mut disk, schema.clone())
+			.unwrap()
+			.with_memtable_limit(64);
+
+		// only the last 100 records are kept, so the data stays small however much is merged
+		let mut rids = Vec::new();
+		for i in 0..20_000 {
+			rids.push(
+				tree.insert(&mut disk, Record::new().item(Value::U32(i)))
+					.unwrap(),
+			);
+			if i >= 100 {
+				assert!(tree.delete(&mut disk, rids[i as usize - 100]).unwrap());
+			}
+		}
+		assert!(disk.n_pages() < 100, "{} pages", disk.n_pages());
+
+		// free extents survive reopening
+		tree.flush(&mut disk).unwrap();
+		let free = tree.free.clone();
+		assert!(!free.is_empty());
+		let tree = LsmTree::open(&mut disk, tree.root(), schema).unwrap();
+		assert_eq!(tree.free, free);
+		assert_eq!(
+			tree.scan(&mut disk).unwrap(),
+			rids[(rids.len() - 100)..]
+				.iter()
+				.zip(19_900..)
+				.map(|(rid, i)| (*rid, Record::new().item(Value::U32(i))))
+				.collect::<Vec<_>>()
+		);
+	}
+
+	#[test]
+	fn keeps_merged_runs_until_manifest_is_written() {
+		let mut disk = DiskManager::temp("lsm_keeps_merged_runs_until_manifest_is_written");
+		let schema = Schema::new().with(ValueType::U32);
+		let mut tree = LsmTree::create(&mut disk, schema)
+			.unwrap()
+			.with_memtable_limit(64);
+		for i in 0..64 * 2 {
+			tree.insert(&mut disk, Record::new().item(Value::U32(i)))
+				.unwrap();
+		}
+		tree.merge_down(&mut disk, 0).unwrap();
+		let freed = tree.freed.clone();
+		assert_eq!(freed.len(), 2);
+
+		// a manifest that fails to be written still refers to the merged runs
+		let mut disk = disk.with_read_only(true);
+		assert!(tree.write_manifest(&mut disk).is_err());
+		assert_eq!(tree.freed, freed);
+		assert!(tree.take_free(1).is_none());
+
+		let mut disk = disk.with_read_only(false);
+		tree.write_manifest(&mut disk).unwrap();
+		assert!(tree.freed.is_empty());
+		assert!(tree.take_free(2).is_some());
+	}
+}
@@ -1 +1,14 @@
+pub mod bloom;
+pub mod fulltext;
+pub mod hash_index;
 mod heapfile;
+pub mod lsm;
+pub mod rtree;
+pub mod secondary_index;
+pub mod storage;
+
+pub use bloom::BloomFilter;
+pub use hash_index::HashIndex;
+pub use heapfile::HeapFile;
+pub use lsm::LsmTree;
+pub use storage::StorageEngine;
@@ -0,0 +1,493 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{
+	db::{
+		disk::{DiskManager, PageId, RTreeEntry, RTreeNodePageView, RecordId, node_capacity},
+		geometry::{Point, Rect},
+	},
+	*,
+};
+
+/// Fewest entries a node other than the root can hold before it is dissolved
+fn min_entries(capacity: usize) -> usize {
+	capacity * 2 / 5
+}
+
+/// Persistent R-tree spatial index, mapping bounding rectangles to record IDs
+///
+/// Nodes are split with Guttman's quadratic split. The root always stays on the same page: when it splits its
+/// contents are moved to a new page and it becomes the parent of both halves. Underfull nodes left after a delete are
+/// dissolved and their records reinserted, their pages are not reused.
+pub struct RTree {
+	root: PageId,
+}
+impl RTree {
+	/// Allocates the root of a new, empty tree
+	pub fn create(disk: &mut DiskManager) -> Result<RTree> {
+		let root = disk.allocate_page()?.id;
+		write_node(disk, root, true, &[])?;
+		Ok(RTree { root })
+	}
+
+	/// Opens an existing tree given its root page
+	pub fn open(root: PageId) -> RTree {
+		RTree { root }
+	}
+
+	/// ID of the page this tree is rooted at
+	#[inline]
+	pub fn root(&self) -> PageId {
+		self.root
+	}
+
+	/// Adds an entry to the tree
+	pub fn insert(&self, disk: &mut DiskManager, rect: Rect, rid: RecordId) -> Result<()> {
+		let (_, split) = self.insert_into(disk, self.root, RTreeEntry { rect, ptr: rid })?;
+		let Some(sibling) = split else {
+			return Ok(());
+		};
+
+		// grow the tree, keeping the root in place
+		let (is_leaf, entries) = read_node(disk, self.root)?;
+		let left = disk.allocate_page()?.id;
+		write_node(disk, left, is_leaf, &entries)?;
+		write_node(
+			disk,
+			self.root,
+			false,
+			&[
+				RTreeEntry {
+					rect: bounding(&entries),
+					ptr: RecordId::new(left, 0),
+				},
+				sibling,
+			],
+		)
+	}
+
+	/// Removes an entry from the tree, returning `false` if it was not present
+	pub fn delete(&self, disk: &mut DiskManager, rect: Rect, rid: RecordId) -> Result<bool> {
+		let mut orphans = Vec::new();
+		if self
+			.delete_from(disk, self.root, &rect, rid, &mut orphans)?
+			.is_none()
+		{
+			return Ok(false);
+		}
+
+		// shrink the tree while the root has a single child
+		loop {
+			let (is_leaf, entries) = read_node(disk, self.root)?;
+			match entries.as_slice() {
+				[] if !is_leaf => write_node(disk, self.root, true, &[])?,
+				[only] if !is_leaf => {
+					let (child_is_leaf, child_entries) = read_node(disk, only.ptr.page_id)?;
+					write_node(disk, self.root, child_is_leaf, &child_entries)?;
+					continue;
+				}
+				_ => {}
+			}
+			break;
+		}
+
+		for orphan in orphans {
+			self.insert(disk, orphan.rect, orphan.ptr)?;
+		}
+		Ok(true)
+	}
+
+	/// Finds every entry lying entirely inside a window
+	pub fn within(&self, disk: &mut DiskManager, window: &Rect) -> Result<Vec<RecordId>> {
+		let mut found = Vec::new();
+		self.search(disk, self.root, window, &mut found, |w, r| w.contains(r))?;
+		Ok(found)
+	}
+
+	/// Finds every entry overlapping a window
+	pub fn intersecting(&self, disk: &mut DiskManager, window: &Rect) -> Result<Vec<RecordId>> {
+		let mut found = Vec::new();
+		self.search(disk, self.root, window, &mut found, |w, r| w.intersects(r))?;
+		Ok(found)
+	}
+
+	/// Finds the `k` entries closest to a point, closest first, along with their distances
+	pub fn nearest(
+		&self,
+		disk: &mut DiskManager,
+		point: &Point,
+		k: usize,
+	) -> Result<Vec<(RecordId, f64)>> {
+		// best first search, nodes are only expanded once they are closer than every record found so far
+		let mut found = Vec::new();
+		let mut queue = BinaryHeap::new();
+		queue.push(Candidate {
+			dist: 0.0,
+			item: CandidateItem::Node(self.root),
+		});
+		while found.len() < k
+			&& let Some(Candidate { dist, item }) = queue.pop()
+		{
+			match item {
+				CandidateItem::Record(rid) => found.push((rid, dist)),
+				CandidateItem::Node(id) => {
+					let (is_leaf, entries) = read_node(disk, id)?;
+					for entry in entries {
+						queue.push(Candidate {
+							dist: entry.rect.distance_to(point),
+							item: if is_leaf {
+								CandidateItem::Record(entry.ptr)
+							} else {
+								CandidateItem::Node(entry.ptr.page_id)
+							},
+						});
+					}
+				}
+			}
+		}
+		Ok(found)
+	}
+
+	/// Inserts an entry into the subtree rooted at a node
+	///
+	/// Returns the node's new bounding rectangle, and the entry for its new sibling if it had to be split
+	fn insert_into(
+		&self,
+		disk: &mut DiskManager,
+		node: PageId,
+		entry: RTreeEntry,
+	) -> Result<(Rect, Option<RTreeEntry>)> {
+		let (is_leaf, mut entries) = read_node(disk, node)?;
+		if is_leaf {
+			entries.push(entry);
+		} else {
+			let i = choose_subtree(&entries, &entry.rect);
+			let (rect, split) = self.insert_into(disk, entries[i].ptr.page_id, entry)?;
+			entries[i].rect = rect;
+			entries.extend(split);
+		}
+
+		let capacity = node_capacity(disk.page_size());
+		if entries.len() <= capacity {
+			write_node(disk, node, is_leaf, &entries)?;
+			return Ok((bounding(&entries), None));
+		}
+
+		let (a, b) = quadratic_split(entries, min_entries(capacity));
+		let sibling = disk.allocate_page()?.id;
+		write_node(disk, node, is_leaf, &a)?;
+		write_node(disk, sibling, is_leaf, &b)?;
+		Ok((
+			bounding(&a),
+			Some(RTreeEntry {
+				rect: bounding(&b),
+				ptr: RecordId::new(sibling, 0),
+			}),
+		))
+	}
+
+	/// Deletes an entry from the subtree rooted at a node, moving the leaf entries of any dissolved nodes to `orphans`
+	///
+	/// Returns `None` if the entry wasn't found, otherwise the node's new bounding rectangle and size
+	fn delete_from(
+		&self,
+		disk: &mut DiskManager,
+		node: PageId,
+		rect: &Rect,
+		rid: RecordId,
+		orphans: &mut Vec<RTreeEntry>,
+	) -> Result<Option<(Rect, usize)>> {
+		let (is_leaf, mut entries) = read_node(disk, node)?;
+		if is_leaf {
+			let Some(i) = entries.iter().position(|e| e.ptr == rid && e.rect == *rect) else {
+				return Ok(None);
+			};
+			entries.swap_remove(i);
+		} else {
+			let mut found = false;
+			for i in 0..entries.len() {
+				if !entries[i].rect.contains(rect) {
+					continue;
+				}
+				let child = entries[i].ptr.page_id;
+				let Some((child_rect, child_len)) =
+					self.delete_from(disk, child, rect, rid, orphans)?
+				else {
+					continue;
+				};
+				if child_len < min_entries(node_capacity(disk.page_size())) {
+					collect_leaves(disk, child, orphans)?;
+					entries.swap_remove(i);
+				} else {
+					entries[i].rect = child_rect;
+				}
+				found = true;
+				break;
+			}
+			if !found {
+				return Ok(None);
+			}
+		}
+
+		write_node(disk, node, is_leaf, &entries)?;
+		Ok(Some((bounding(&entries), entries.len())))
+	}
+
+	fn search(
+		&self,
+		disk: &mut DiskManager,
+		node: PageId,
+		window: &Rect,
+		found: &mut Vec<RecordId>,
+		matches: fn(&Rect, &Rect) -> bool,
+	) -> Result<()> {
+		let (is_leaf, entries) = read_node(disk, node)?;
+		for entry in entries {
+			if is_leaf {
+				if matches(window, &entry.rect) {
+					found.push(entry.ptr);
+				}
+			} else if window.intersects(&entry.rect) {
+				self.search(disk, entry.ptr.page_id, window, found, matches)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn read_node(disk: &mut DiskManager, id: PageId) -> Result<(bool, Vec<RTreeEntry>)> {
+	let mut page = disk.read_page(id)?;
+	let view = RTreeNodePageView::new(&mut page.data);
+	Ok((view.is_leaf(), view.entries()))
+}
+
+fn write_node(
+	disk: &mut DiskManager,
+	id: PageId,
+	is_leaf: bool,
+	entries: &[RTreeEntry],
+) -> Result<()> {
+	let mut page = disk.empty_page(id);
+	RTreeNodePageView::new(&mut page.data).write(is_leaf, entries);
+	disk.flush_page(&page)
+}
+
+/// Gathers every leaf entry in a subtree
+fn collect_leaves(disk: &mut DiskManager, node: PageId, out: &mut Vec<RTreeEntry>) -> Result<()> {
+	let (is_leaf, entries) = read_node(disk, node)?;
+	if is_leaf {
+		out.extend(entries);
+		return Ok(());
+	}
+	for entry in entries {
+		collect_leaves(disk, entry.ptr.page_id, out)?;
+	}
+	Ok(())
+}
+
+/// Smallest rectangle covering every entry, a point at the origin if there are none
+fn bounding(entries: &[RTreeEntry]) -> Rect {
+	entries
+		.iter()
+		.map(|e| e.rect)
+		.reduce(|a, b| a.union(&b))
+		.unwrap_or(Rect::point(Point::new(0.0, 0.0)))
+}
+
+/// Picks the child needing the least enlargement to cover a rectangle, breaking ties by smallest area
+fn choose_subtree(entries: &[RTreeEntry], rect: &Rect) -> usize {
+	let mut best = 0;
+	for i in 1..entries.len() {
+		let (cur, prev) = (&entries[i].rect, &entries[best].rect);
+		match cur.enlargement(rect).total_cmp(&prev.enlargement(rect)) {
+			Ordering::Less => best = i,
+			Ordering::Equal if cur.area() < prev.area() => best = i,
+			_ => {}
+		}
+	}
+	best
+}
+
+/// Guttman's quadratic split, divides entries into two groups of at least `min_entries` each
+fn quadratic_split(
+	mut entries: Vec<RTreeEntry>,
+	min_entries: usize,
+) -> (Vec<RTreeEntry>, Vec<RTreeEntry>) {
+	// seeds are the pair that would waste the most area if grouped together
+	let mut seeds = (0, 1);
+	let mut worst = f64::NEG_INFINITY;
+	for i in 0..entries.len() {
+		for j in (i + 1)..entries.len() {
+			let (a, b) = (&entries[i].rect, &entries[j].rect);
+			let waste = a.union(b).area() - a.area() - b.area();
+			if waste > worst {
+				worst = waste;
+				seeds = (i, j);
+			}
+		}
+	}
+	let seed_b = entries.swap_remove(seeds.1);
+	let seed_a = entries.swap_remove(seeds.0);
+	let (mut rect_a, mut rect_b) = (seed_a.rect, seed_b.rect);
+	let (mut a, mut b) = (vec![seed_a], vec![seed_b]);
+
+	while !entries.is_empty() {
+		// give everything left to a group that needs it to reach the minimum
+		if a.len() + entries.len() <= min_entries {
+			a.append(&mut entries);
+			break;
+		}
+		if b.len() + entries.len() <= min_entries {
+			b.append(&mut entries);
+			break;
+		}
+
+		// next is the entry with the strongest preference for one group
+		let (i, _) = entries
+			.iter()
+			.enumerate()
+			.map(|(i, e)| {
+				(
+					i,
+					(rect_a.enlargement(&e.rect) - rect_b.enlargement(&e.rect)).abs(),
+				)
+			})
+			.max_by(|x, y| x.1.total_cmp(&y.1))
+			.unwrap();
+		let entry = entries.swap_remove(i);
+		let (grow_a, grow_b) = (
+			rect_a.enlargement(&entry.rect),
+			rect_b.enlargement(&entry.rect),
+		);
+		let to_a = match grow_a.total_cmp(&grow_b) {
+			Ordering::Less => true,
+			Ordering::Greater => false,
+			Ordering::Equal => a.len() <= b.len(),
+		};
+		if to_a {
+			rect_a = rect_a.union(&entry.rect);
+			a.push(entry);
+		} else {
+			rect_b = rect_b.union(&entry.rect);
+			b.push(entry);
+		}
+	}
+	(a, b)
+}
+
+/// A node or record waiting to be visited by a nearest neighbour search, ordered closest first
+struct Candidate {
+	dist: f64,
+	item: CandidateItem,
+}
+enum CandidateItem {
+	Node(PageId),
+	Record(RecordId),
+}
+impl PartialEq for Candidate {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl Ord for Candidate {
+	fn cmp(&self, other: &Self) -> Ordering {
+		// reversed, since `BinaryHeap` is a max heap
+		other.dist.total_cmp(&self.dist)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A 50x50 grid of points, one unit apart
+	fn grid() -> Vec<(Rect, RecordId)> {
+		(0..2_500u32)
+			.map(|i| {
+				let p = Point::new((i % 50) as f64, (i / 50) as f64);
+				(Rect::point(p), RecordId::new(i, 0))
+			})
+			.collect()
+	}
+
+	#[test]
+	fn window_queries() {
+		let mut disk = DiskManager::temp("rtree_window_queries");
+		let tree = RTree::create(&mut disk).unwrap();
+		for (rect, rid) in grid() {
+			tree.insert(&mut disk, rect, rid).unwrap();
+		}
+
+		let window = Rect::new(Point::new(9.5, 19.5), Point::new(14.5, 22.5));
+		let mut found = tree.within(&mut disk, &window).unwrap();
+		found.sort_by_key(|rid| rid.page_id);
+		let expected: Vec<RecordId> = grid()
+			.into_iter()
+			.filter(|(rect, _)| window.contains(rect))
+			.map(|(_, rid)| rid)
+			.collect();
+		assert_eq!(expected.len(), 15);
+		assert_eq!(found, expected);
+
+		// a box touching a point intersects it
+		let boxed = Rect::new(Point::new(-1.0, -1.0), Point::new(0.0, 0.5));
+		tree.insert(&mut disk, boxed, RecordId::new(9_999, 0))
+			.unwrap();
+		let mut hits = tree
+			.intersecting(&mut disk, &Rect::point(Point::new(0.0, 0.0)))
+			.unwrap();
+		hits.sort_by_key(|rid| rid.page_id);
+		assert_eq!(hits, vec![RecordId::new(0, 0), RecordId::new(9_999, 0)]);
+	}
+
+	#[test]
+	fn nearest_neighbours() {
+		let mut disk = DiskManager::temp("rtree_nearest_neighbours");
+		let tree = RTree::create(&mut disk).unwrap();
+		for (rect, rid) in grid() {
+			tree.insert(&mut disk, rect, rid).unwrap();
+		}
+
+		let found = tree.nearest(&mut disk, &Point::new(20.1, 30.2), 3).unwrap();
+		let ids: Vec<u32> = found.iter().map(|(rid, _)| rid.page_id).collect();
+		// (20, 30), (20, 31), (21, 30)
+		assert_eq!(ids, vec![1_520, 1_570, 1_521]);
+		assert!(found.windows(2).all(|w| w[0].1 <= w[1].1));
+	}
+
+	#[test]
+	fn delete() {
+		let mut disk = DiskManager::temp("rtree_delete");
+		let tree = RTree::create(&mut disk).unwrap();
+		let points = grid();
+		for (rect, rid) in points.iter() {
+			tree.insert(&mut disk, *rect, *rid).unwrap();
+		}
+
+		// remove every point left of x = 40
+		for (rect, rid) in points.iter().filter(|(r, _)| r.min.x < 40.0) {
+			assert!(tree.delete(&mut disk, *rect, *rid).unwrap());
+			assert!(!tree.delete(&mut disk, *rect, *rid).unwrap());
+		}
+
+		let everything = Rect::new(Point::new(-1.0, -1.0), Point::new(100.0, 100.0));
+		let mut found = tree.within(&mut disk, &everything).unwrap();
+		found.sort_by_key(|rid| rid.page_id);
+		let expected: Vec<RecordId> = points
+			.iter()
+			.filter(|(r, _)| r.min.x >= 40.0)
+			.map(|(_, rid)| *rid)
+			.collect();
+		assert_eq!(found, expected);
+
+		for (rect, rid) in points.iter().filter(|(r, _)| r.min.x >= 40.0) {
+			assert!(tree.delete(&mut disk, *rect, *rid).unwrap());
+		}
+		assert!(tree.within(&mut disk, &everything).unwrap().is_empty());
+	}
+}
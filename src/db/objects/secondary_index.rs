@@ -0,0 +1,636 @@
+use super::{
+	BloomFilter, HashIndex,
+	fulltext::{FullTextIndex, TextIndexDef},
+	rtree::RTree,
+};
+use crate::{
+	db::{
+		catalog,
+		disk::{DiskManager, PageId, RecordId},
+		eval::{CompiledExpr, Scope},
+		geometry::{Point, Rect},
+		record::*,
+		table::TableDef,
+	},
+	query::Expr,
+	*,
+};
+
+/// The available index structures, to be chosen per index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexKind {
+	/// Extendible hash index, which only finds exact keys
+	#[default]
+	Hash,
+	/// R-tree over a single point or rect column, which finds records within a rect or nearest to a point
+	///
+	/// Can't include columns or keep a Bloom filter.
+	Spatial,
+}
+
+/// Describes which columns of a table a secondary index is built from, and how it is stored
+///
+/// ```
+/// use lildb::{IndexDef, query::{BinaryOp, Expr, Value}};
+///
+/// // (group, id) -> score, only for active users
+/// let def = IndexDef::new(["group", "id"])
+///     .include(["score"])
+///     .filter(Expr::binary(BinaryOp::Eq, Expr::ident("active"), Value::Integer(1).into()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDef {
+	pub kind: IndexKind,
+	/// Table columns making up the key, in order
+	pub key_columns: Vec<String>,
+	/// Table columns stored alongside each entry, so lookups needing only these don't have to read the table
+	pub include_columns: Vec<String>,
+	/// Only records this condition is true for are indexed, making this a partial index
+	pub predicate: Option<Expr>,
+	pub bloom_filter: Option<BloomFilterDef>,
+}
+impl IndexDef {
+	/// Defines a full, non-covering index over some key columns
+	pub fn new<S: Into<String>>(key_columns: impl IntoIterator<Item = S>) -> IndexDef {
+		IndexDef {
+			kind: IndexKind::default(),
+			key_columns: key_columns.into_iter().map(Into::into).collect(),
+			include_columns: Vec::new(),
+			predicate: None,
+			bloom_filter: None,
+		}
+	}
+
+	pub fn kind(mut self, kind: IndexKind) -> Self {
+		self.kind = kind;
+		self
+	}
+
+	pub fn include<S: Into<String>>(mut self, columns: impl IntoIterator<Item = S>) -> Self {
+		self.include_columns = columns.into_iter().map(Into::into).collect();
+		self
+	}
+
+	/// Sets the condition records need to meet to be indexed, such as one parsed with `lql::parse_expr`
+	pub fn filter(mut self, predicate: Expr) -> Self {
+		self.predicate = Some(predicate);
+		self
+	}
+
+	/// Keeps a Bloom filter over the index's keys, sized for a number of keys at a target false positive rate
+	pub fn bloom_filter(mut self, expected_keys: u64, false_positive_rate: f64) -> Self {
+		self.bloom_filter = Some(BloomFilterDef {
+			expected_keys,
+			false_positive_rate,
+		});
+		self
+	}
+}
+
+/// Sizing of a Bloom filter over an index's keys, which lets lookups of missing keys skip reading the index
+///
+/// Deleted keys stay in the filter until it is rebuilt by `LilDbConnection::vacuum`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomFilterDef {
+	pub expected_keys: u64,
+	pub false_positive_rate: f64,
+}
+
+/// An index over the records of a table, which whoever changes the table keeps in sync through `add` and `remove`,
+/// checking `covers` for each record before changing the table
+///
+/// Keys may span several columns, included columns are stored as the entry payload, and records the definition's
+/// predicate isn't true for are left out entirely. Full-text indexes are kept the same way, keyed by the text of a
+/// single string column.
+pub struct SecondaryIndex {
+	key_columns: Vec<usize>,
+	include_columns: Vec<usize>,
+	predicate: Option<CompiledExpr>,
+	index: Structure,
+}
+
+/// The structure an index is stored in, depending on its kind
+enum Structure {
+	Hash(HashIndex),
+	Spatial(RTree),
+	Text(Box<FullTextIndex>),
+}
+impl SecondaryIndex {
+	/// Creates a new, empty index over a table
+	pub fn create(
+		disk: &mut DiskManager,
+		table: &str,
+		table_def: &TableDef,
+		def: IndexDef,
+	) -> Result<SecondaryIndex> {
+		let (key_columns, include_columns, predicate) = resolve(table, table_def, &def)?;
+		let schema = table_def.schema();
+		let index = match def.kind {
+			IndexKind::Hash => {
+				let mut index = HashIndex::create(
+					disk,
+					schema.project(&key_columns),
+					schema.project(&include_columns),
+				)?;
+				if let Some(bloom) = def.bloom_filter {
+					index.attach_bloom_filter(
+						disk,
+						bloom.expected_keys,
+						bloom.false_positive_rate,
+					)?;
+				}
+				Structure::Hash(index)
+			}
+			IndexKind::Spatial => Structure::Spatial(RTree::create(disk)?),
+		};
+		Ok(SecondaryIndex {
+			key_columns,
+			include_columns,
+			predicate,
+			index,
+		})
+	}
+
+	/// Opens an existing index given the page it is rooted at, and the header page of its Bloom filter if it has one
+	pub fn open(
+		disk: &mut DiskManager,
+		root: PageId,
+		bloom: Option<PageId>,
+		table: &str,
+		table_def: &TableDef,
+		def: IndexDef,
+	) -> Result<SecondaryIndex> {
+		let (key_columns, include_columns, predicate) = resolve(table, table_def, &def)?;
+		let schema = table_def.schema();
+		let index = match def.kind {
+			IndexKind::Hash => {
+				let mut index = HashIndex::open(
+					root,
+					schema.project(&key_columns),
+					schema.project(&include_columns),
+				);
+				if let Some(header) = bloom {
+					index = index.with_bloom_filter(BloomFilter::open(disk, header)?);
+				}
+				Structure::Hash(index)
+			}
+			IndexKind::Spatial => Structure::Spatial(RTree::open(root)),
+		};
+		Ok(SecondaryIndex {
+			key_columns,
+			include_columns,
+			predicate,
+			index,
+		})
+	}
+
+	/// Creates a new, empty full-text index over a string column of a table
+	pub fn create_text(
+		disk: &mut DiskManager,
+		table: &str,
+		table_def: &TableDef,
+		def: &TextIndexDef,
+	) -> Result<SecondaryIndex> {
+		let column = resolve_text(table, table_def, def)?;
+		Ok(SecondaryIndex::text(
+			column,
+			Box::new(FullTextIndex::create(disk, def.analyzer())?),
+		))
+	}
+
+	/// Opens an existing full-text index given the page it is rooted at
+	pub fn open_text(
+		disk: &mut DiskManager,
+		root: PageId,
+		table: &str,
+		table_def: &TableDef,
+		def: &TextIndexDef,
+	) -> Result<SecondaryIndex> {
+		let column = resolve_text(table, table_def, def)?;
+		Ok(SecondaryIndex::text(
+			column,
+			Box::new(FullTextIndex::open(disk, root, def.analyzer())?),
+		))
+	}
+
+	fn text(column: usize, index: Box<FullTextIndex>) -> SecondaryIndex {
+		SecondaryIndex {
+			key_columns: vec![column],
+			include_columns: Vec::new(),
+			predicate: None,
+			index: Structure::Text(index),
+		}
+	}
+
+	/// ID of the page the index is rooted at, to reopen it with
+	#[inline]
+	pub fn root(&self) -> PageId {
+		match &self.index {
+			Structure::Hash(index) => index.directory(),
+			Structure::Spatial(tree) => tree.root(),
+			Structure::Text(index) => index.root(),
+		}
+	}
+
+	/// Header page of the index's Bloom filter, to reopen it with
+	#[inline]
+	pub fn bloom_filter(&self) -> Option<PageId> {
+		match &self.index {
+			Structure::Hash(index) => index.bloom_filter().map(BloomFilter::header),
+			Structure::Spatial(_) | Structure::Text(_) => None,
+		}
+	}
+
+	/// Rebuilds the Bloom filter from the keys left in the index, see `HashIndex::rebuild_bloom_filter`
+	pub fn rebuild_bloom_filter(&self, disk: &mut DiskManager) -> Result<()> {
+		match &self.index {
+			Structure::Hash(index) => index.rebuild_bloom_filter(disk),
+			Structure::Spatial(_) | Structure::Text(_) => Ok(()),
+		}
+	}
+
+	/// Finds the IDs of every indexed record with a matching key
+	pub fn get(&self, disk: &mut DiskManager, key: &Record) -> Result<Vec<RecordId>> {
+		self.hash()?.get(disk, key)
+	}
+
+	/// Finds every indexed record with a matching key, returning its included columns without touching the table
+	pub fn get_covered(
+		&self,
+		disk: &mut DiskManager,
+		key: &Record,
+	) -> Result<Vec<(Record, RecordId)>> {
+		self.hash()?.get_with_payload(disk, key)
+	}
+
+	/// Finds the IDs of every indexed record whose point or rect lies inside a window
+	pub fn within(&self, disk: &mut DiskManager, window: &Rect) -> Result<Vec<RecordId>> {
+		self.spatial()?.within(disk, window)
+	}
+
+	/// Finds the IDs of every indexed record whose point or rect overlaps a window
+	pub fn intersecting(&self, disk: &mut DiskManager, window: &Rect) -> Result<Vec<RecordId>> {
+		self.spatial()?.intersecting(disk, window)
+	}
+
+	/// Finds the IDs of the `k` indexed records closest to a point, closest first
+	pub fn nearest(
+		&self,
+		disk: &mut DiskManager,
+		point: &Point,
+		k: usize,
+	) -> Result<Vec<RecordId>> {
+		Ok(self
+			.spatial()?
+			.nearest(disk, point, k)?
+			.into_iter()
+			.map(|(rid, _)| rid)
+			.collect())
+	}
+
+	/// Finds the IDs of every indexed record whose text contains all the terms of a query, in no particular order
+	pub fn matches(&self, disk: &mut DiskManager, query: &str) -> Result<Vec<RecordId>> {
+		self.full_text()?.matches(disk, query)
+	}
+
+	/// Scores the indexed records whose text contains any term of a query with BM25, best matches first
+	pub fn rank(&self, disk: &mut DiskManager, query: &str) -> Result<Vec<(RecordId, f64)>> {
+		self.full_text()?.rank(disk, query)
+	}
+
+	/// Maintains the index after a record was inserted into the table
+	pub fn insert(&self, disk: &mut DiskManager, rec: &Record, rid: RecordId) -> Result<()> {
+		if !self.covers(rec)? {
+			return Ok(());
+		}
+		self.add(disk, rec, rid)
+	}
+
+	/// Adds a record's entry, for a record `covers` was already checked to be true for
+	pub fn add(&self, disk: &mut DiskManager, rec: &Record, rid: RecordId) -> Result<()> {
+		match &self.index {
+			Structure::Hash(index) => index.insert(
+				disk,
+				&rec.project(&self.key_columns),
+				&rec.project(&self.include_columns),
+				rid,
+			),
+			Structure::Spatial(tree) => tree.insert(disk, self.bounds(rec), rid),
+			Structure::Text(index) => index.insert(disk, self.text_of(rec), rid),
+		}
+	}
+
+	/// Removes a record's entry after it was deleted from the table, for a record `covers` was checked to be true for
+	pub fn remove(&self, disk: &mut DiskManager, rec: &Record, rid: RecordId) -> Result<()> {
+		let removed = match &self.index {
+			Structure::Hash(index) => index.remove(disk, &rec.project(&self.key_columns), rid)?,
+			Structure::Spatial(tree) => tree.delete(disk, self.bounds(rec), rid)?,
+			Structure::Text(index) => {
+				index.delete(disk, self.text_of(rec), rid)?;
+				true
+			}
+		};
+		if !removed {
+			return Err(Error::Corruption(
+				"Deleted record was missing from index".to_string(),
+			));
+		}
+		Ok(())
+	}
+
+	fn hash(&self) -> Result<&HashIndex> {
+		match &self.index {
+			Structure::Hash(index) => Ok(index),
+			Structure::Spatial(_) => Err(Error::InvalidArgument(
+				"A spatial index finds records by location, not by key".to_string(),
+			)),
+			Structure::Text(_) => Err(Error::InvalidArgument(
+				"A full-text index finds records by their text, not by key".to_string(),
+			)),
+		}
+	}
+
+	fn spatial(&self) -> Result<&RTree> {
+		match &self.index {
+			Structure::Spatial(tree) => Ok(tree),
+			Structure::Hash(_) | Structure::Text(_) => Err(Error::InvalidArgument(
+				"Only a spatial index finds records by location".to_string(),
+			)),
+		}
+	}
+
+	fn full_text(&self) -> Result<&FullTextIndex> {
+		match &self.index {
+			Structure::Text(index) => Ok(index),
+			Structure::Hash(_) | Structure::Spatial(_) => Err(Error::InvalidArgument(
+				"Only a full-text index finds records by their text".to_string(),
+			)),
+		}
+	}
+
+	/// Text a record is indexed by in a full-text index
+	fn text_of<'r>(&self, rec: &'r Record) -> &'r str {
+		match rec.get(self.key_columns[0]) {
+			Some(Value::String(s)) => s,
+			// checked when the index was resolved
+			_ => unreachable!(),
+		}
+	}
+
+	/// Rect a record is indexed by in a spatial index, a point being a zero sized rect
+	fn bounds(&self, rec: &Record) -> Rect {
+		match rec.get(self.key_columns[0]) {
+			Some(Value::Point(p)) => Rect::point(*p),
+			Some(Value::Rect(r)) => *r,
+			// checked when the index was resolved
+			_ => unreachable!(),
+		}
+	}
+
+	/// Checks if a table record belongs in the index, which fails if evaluating the predicate does
+	pub fn covers(&self, rec: &Record) -> Result<bool> {
+		match &self.predicate {
+			Some(predicate) => predicate.is_true(rec),
+			None => Ok(true),
+		}
+	}
+}
+
+/// Finds the positions of an index's columns in the table, and compiles its predicate
+fn resolve(
+	table: &str,
+	table_def: &TableDef,
+	def: &IndexDef,
+) -> Result<(Vec<usize>, Vec<usize>, Option<CompiledExpr>)> {
+	if def.key_columns.is_empty() {
+		return Err(Error::InvalidArgument(
+			"An index needs at least one key column".to_string(),
+		));
+	}
+	let positions = |columns: &[String]| {
+		columns
+			.iter()
+			.map(|name| {
+				table_def.column_index(name).ok_or_else(|| {
+					Error::NotFound(format!("column \"{name}\" of table \"{table}\""))
+				})
+			})
+			.collect::<Result<Vec<_>>>()
+	};
+	let predicate = match &def.predicate {
+		Some(expr) => {
+			let compiled = CompiledExpr::condition(expr, &Scope::table(table, table_def))?;
+			catalog::check_predicate(expr)?;
+			Some(compiled)
+		}
+		None => None,
+	};
+	let key_columns = positions(&def.key_columns)?;
+	if def.kind == IndexKind::Spatial {
+		let is_spatial =
+			|i: usize| matches!(table_def.columns()[i].1, ValueType::Point | ValueType::Rect);
+		if key_columns.len() != 1 || !is_spatial(key_columns[0]) {
+			return Err(Error::InvalidArgument(
+				"A spatial index needs a single point or rect key column".to_string(),
+			));
+		}
+		if !def.include_columns.is_empty() || def.bloom_filter.is_some() {
+			return Err(Error::InvalidArgument(
+				"A spatial index can't include columns or keep a Bloom filter".to_string(),
+			));
+		}
+	}
+	Ok((key_columns, positions(&def.include_columns)?, predicate))
+}
+
+/// Finds the position of a full-text index's column in the table, which has to hold strings
+fn resolve_text(table: &str, table_def: &TableDef, def: &TextIndexDef) -> Result<usize> {
+	def.validate()?;
+	let Some(column) = table_def.column_index(&def.column) else {
+		return Err(Error::NotFound(format!(
+			"column \"{}\" of table \"{table}\"",
+			def.column
+		)));
+	};
+	if !matches!(table_def.columns()[column].1, ValueType::String(_)) {
+		return Err(Error::TypeMismatch(format!(
+			"A full-text index needs a string column, \"{}\" isn't one",
+			def.column
+		)));
+	}
+	Ok(column)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::query::{self, BinaryOp};
+
+	fn users() -> TableDef {
+		TableDef::new()
+			.column("id", ValueType::U32)
+			.column("group", ValueType::U32)
+			.column("score", ValueType::I32)
+			.column("active", ValueType::U32)
+	}
+
+	fn user(id: u32, group: u32, score: i32, active: u32) -> Record {
+		Record::new()
+			.item(Value::U32(id))
+			.item(Value::U32(group))
+			.item(Value::I32(score))
+			.item(Value::U32(active))
+	}
+
+	#[test]
+	fn composite_covering_partial() {
+		let mut disk = DiskManager::temp("secondary_index_composite_covering_partial");
+
+		// (group, id) -> score, only for active users
+		let def = IndexDef::new(["group", "id"])
+			.kind(IndexKind::Hash)
+			.include(["score"])
+			.filter(Expr::binary(
+				BinaryOp::Eq,
+				Expr::ident("active"),
+				query::Value::Integer(1).into(),
+			));
+		let index = SecondaryIndex::create(&mut disk, "Users", &users(), def).unwrap();
+
+		let users = [user(1, 10, -5, 1), user(2, 10, 7, 0), user(3, 20, 9, 1)];
+		for (i, u) in users.iter().enumerate() {
+			index
+				.insert(&mut disk, u, RecordId::new(100, i as u16))
+				.unwrap();
+		}
+
+		let key = |group, id| Record::new().item(Value::U32(group)).item(Value::U32(id));
+		assert_eq!(
+			index.get_covered(&mut disk, &key(10, 1)).unwrap(),
+			vec![(Record::new().item(Value::I32(-5)), RecordId::new(100, 0))]
+		);
+		// inactive, so never indexed
+		assert!(index.get(&mut disk, &key(10, 2)).unwrap().is_empty());
+
+		// deactivating drops it from the index
+		assert!(!index.covers(&user(3, 20, 9, 0)).unwrap());
+		index
+			.remove(&mut disk, &users[2], RecordId::new(100, 2))
+			.unwrap();
+		assert!(index.get(&mut disk, &key(20, 3)).unwrap().is_empty());
+
+		// activating adds it
+		index
+			.insert(&mut disk, &user(2, 10, 8, 1), RecordId::new(100, 1))
+			.unwrap();
+		assert_eq!(
+			index.get_covered(&mut disk, &key(10, 2)).unwrap(),
+			vec![(Record::new().item(Value::I32(8)), RecordId::new(100, 1))]
+		);
+
+		index
+			.remove(&mut disk, &users[0], RecordId::new(100, 0))
+			.unwrap();
+		assert!(index.get(&mut disk, &key(10, 1)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn bloom_filter() {
+		let mut disk = DiskManager::temp("secondary_index_bloom_filter");
+		let def = IndexDef::new(["id"]).bloom_filter(1_000, 0.01);
+		let index = SecondaryIndex::create(&mut disk, "Users", &users(), def.clone()).unwrap();
+		let header = index.bloom_filter().unwrap();
+		let bytes = |id| Record::new().item(Value::U32(id)).to_bytes();
+		for id in 0..100 {
+			let rid = RecordId::new(100, id as u16);
+			index.insert(&mut disk, &user(id, 0, 0, 0), rid).unwrap();
+		}
+		index
+			.remove(&mut disk, &user(7, 0, 0, 0), RecordId::new(100, 7))
+			.unwrap();
+
+		// reopened from the header page alone, and rebuilding drops deleted keys
+		let index = SecondaryIndex::open(
+			&mut disk,
+			index.root(),
+			Some(header),
+			"Users",
+			&users(),
+			def,
+		)
+		.unwrap();
+		let bloom = index.hash().unwrap().bloom_filter().unwrap().clone();
+		assert!(bloom.may_contain(&mut disk, &bytes(7)).unwrap());
+		index.rebuild_bloom_filter(&mut disk).unwrap();
+		assert!(!bloom.may_contain(&mut disk, &bytes(7)).unwrap());
+		assert!(bloom.may_contain(&mut disk, &bytes(8)).unwrap());
+	}
+
+	#[test]
+	fn spatial() {
+		let mut disk = DiskManager::temp("secondary_index_spatial");
+		let shops = TableDef::new()
+			.column("id", ValueType::U32)
+			.column("location", ValueType::Point);
+		let def = IndexDef::new(["location"]).kind(IndexKind::Spatial);
+		let index = SecondaryIndex::create(&mut disk, "Shops", &shops, def.clone()).unwrap();
+		let shop = |id: u32| {
+			Record::new()
+				.item(Value::U32(id))
+				.item(Value::Point(Point::new(id as f64, 0.0)))
+		};
+		for id in 0..10 {
+			index
+				.insert(&mut disk, &shop(id), RecordId::new(100, id as u16))
+				.unwrap();
+		}
+		index
+			.remove(&mut disk, &shop(3), RecordId::new(100, 3))
+			.unwrap();
+
+		let index =
+			SecondaryIndex::open(&mut disk, index.root(), None, "Shops", &shops, def).unwrap();
+		let mut found = index
+			.within(
+				&mut disk,
+				&Rect::new(Point::new(1.5, -1.0), Point::new(4.5, 1.0)),
+			)
+			.unwrap();
+		found.sort();
+		assert_eq!(found, vec![RecordId::new(100, 2), RecordId::new(100, 4)]);
+		assert_eq!(
+			index.nearest(&mut disk, &Point::new(2.9, 0.0), 2).unwrap(),
+			vec![RecordId::new(100, 2), RecordId::new(100, 4)]
+		);
+		assert!(matches!(
+			index.get(&mut disk, &Record::new().item(Value::U32(1))),
+			Err(Error::InvalidArgument(_))
+		));
+	}
+
+	#[test]
+	fn invalid_def() {
+		let mut disk = DiskManager::temp("secondary_index_invalid_def");
+		let mut create = |def| SecondaryIndex::create(&mut disk, "Users", &users(), def);
+		assert!(matches!(
+			create(IndexDef::new(["name"])),
+			Err(Error::NotFound(_))
+		));
+		assert!(matches!(
+			create(IndexDef::new(Vec::<String>::new())),
+			Err(Error::InvalidArgument(_))
+		));
+		assert!(matches!(
+			create(IndexDef::new(["id"]).filter(Expr::ident("score"))),
+			Err(Error::TypeMismatch(_))
+		));
+		assert!(matches!(
+			create(IndexDef::new(["id"]).bloom_filter(100, 0.0)),
+			Err(Error::InvalidArgument(_))
+		));
+		assert!(matches!(
+			create(IndexDef::new(["id"]).kind(IndexKind::Spatial)),
+			Err(Error::InvalidArgument(_))
+		));
+	}
+}
@@ -0,0 +1,125 @@
+use super::{HeapFile, LsmTree};
+use crate::{
+	db::{
+		disk::{DiskManager, PageId, RecordId},
+		record::*,
+	},
+	*,
+};
+
+/// How a table's records are stored
+///
+/// Indexes refer to records by the `RecordId` their engine hands out, which engines without pages treat as an opaque
+/// row number.
+pub trait StorageEngine {
+	/// ID of the page the storage is rooted at, to reopen it with
+	fn root(&self) -> PageId;
+
+	/// Stores a record, returning its new ID
+	fn insert(&mut self, disk: &mut DiskManager, rec: Record) -> Result<RecordId>;
+
+	/// Reads a record, `None` if there is no record with this ID
+	fn get(&self, disk: &mut DiskManager, rid: RecordId) -> Result<Option<Record>>;
+
+	/// Replaces a record, which keeps its ID, returning `false` if there was no record with this ID
+	fn update(&mut self, disk: &mut DiskManager, rid: RecordId, rec: Record) -> Result<bool>;
+
+	/// Removes a record, returning `false` if there was no record with this ID
+	fn delete(&mut self, disk: &mut DiskManager, rid: RecordId) -> Result<bool>;
+
+	/// Reads every record
+	fn scan(&self, disk: &mut DiskManager) -> Result<Vec<(RecordId, Record)>>;
+
+	/// Writes out anything held in memory
+	fn flush(&mut self, disk: &mut DiskManager) -> Result<()>;
+}
+
+/// The available storage engines, to be chosen per table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageKind {
+	/// Unordered pages of fixed length records, updated in place
+	#[default]
+	Heap,
+	/// Log structured merge tree, buffering logged writes in memory and merging them into sorted runs
+	Lsm,
+}
+impl StorageKind {
+	/// Creates empty storage of this kind
+	pub fn create(self, disk: &mut DiskManager, schema: Schema) -> Result<Box<dyn StorageEngine>> {
+		Ok(match self {
+			StorageKind::Heap => Box::new(HeapFile::create(disk, schema)?),
+			StorageKind::Lsm => Box::new(LsmTree::create(disk, schema)?),
+		})
+	}
+
+	/// Opens existing storage of this kind given its root page
+	pub fn open(
+		self,
+		disk: &mut DiskManager,
+		root: PageId,
+		schema: Schema,
+	) -> Result<Box<dyn StorageEngine>> {
+		Ok(match self {
+			StorageKind::Heap => Box::new(HeapFile::open(root, schema)),
+			StorageKind::Lsm => Box::new(LsmTree::open(disk, root, schema)?),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn row(i: u32) -> Record {
+		Record::new()
+			.item(Value::U32(i))
+			.item(Value::I32(-(i as i32)))
+	}
+
+	/// Runs the same operations against every engine
+	#[test]
+	fn engines_agree() {
+		for kind in [StorageKind::Heap, StorageKind::Lsm] {
+			let mut disk = DiskManager::temp(&format!("storage_engines_agree_{kind:?}"));
+			let schema = Schema::new().with(ValueType::U32).with(ValueType::I32);
+			let mut storage = kind.create(&mut disk, schema.clone()).unwrap();
+
+			let n = 5_000;
+			let rids: Vec<RecordId> = (0..n)
+				.map(|i| storage.insert(&mut disk, row(i)).unwrap())
+				.collect();
+			for (i, rid) in rids.iter().enumerate() {
+				assert_eq!(storage.get(&mut disk, *rid).unwrap(), Some(row(i as u32)));
+			}
+
+			for rid in rids.iter().step_by(3) {
+				assert!(storage.delete(&mut disk, *rid).unwrap());
+				assert!(!storage.delete(&mut disk, *rid).unwrap());
+				assert_eq!(storage.get(&mut disk, *rid).unwrap(), None);
+				assert!(!storage.update(&mut disk, *rid, row(0)).unwrap());
+			}
+
+			// updates keep the record ID
+			let updated = row(n + 1);
+			assert!(storage.update(&mut disk, rids[1], updated.clone()).unwrap());
+			assert_eq!(storage.get(&mut disk, rids[1]).unwrap(), Some(updated));
+
+			// reopening sees the same records
+			storage.flush(&mut disk).unwrap();
+			let storage = kind.open(&mut disk, storage.root(), schema).unwrap();
+			let mut scanned: Vec<u32> = storage
+				.scan(&mut disk)
+				.unwrap()
+				.into_iter()
+				.map(|(_, rec)| match rec.get(0) {
+					Some(Value::U32(i)) => *i,
+					_ => panic!("Unexpected record {rec:?}"),
+				})
+				.collect();
+			scanned.sort();
+			let mut expected: Vec<u32> = (2..n).filter(|i| i % 3 != 0).collect();
+			expected.push(n + 1);
+			assert_eq!(scanned, expected);
+		}
+	}
+}
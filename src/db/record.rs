@@ -1,8 +1,10 @@
 use std::iter;
 
+use super::geometry::{Point, Rect};
 use crate::util::slice_to_array;
 
-#[derive(Debug, PartialEq, Eq)]
+/// A row of values, one per column
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Record {
 	items: Vec<Value>,
 }
@@ -16,18 +18,48 @@ impl Record {
 		self
 	}
 
-	pub fn to_bytes(self) -> Vec<u8> {
+	/// Gets the value in a column
+	#[inline]
+	pub fn get(&self, column: usize) -> Option<&Value> {
+		self.items.get(column)
+	}
+
+	/// Builds a new record out of a subset of this record's columns, in the order given
+	///
+	/// Panics if any column is out of bounds
+	pub fn project(&self, columns: &[usize]) -> Record {
+		Record {
+			items: columns.iter().map(|c| self.items[*c].clone()).collect(),
+		}
+	}
+
+	/// Values of the record, in column order
+	#[inline]
+	pub fn items(&self) -> &[Value] {
+		&self.items
+	}
+
+	/// Bytes of the record's values one after the other, which are only as long as its strings need
+	pub(crate) fn to_bytes(&self) -> Vec<u8> {
 		let mut bytes = Vec::new();
-		for item in self.items.into_iter() {
-			bytes.extend_from_slice(&item.to_bytes());
+		for item in self.items.iter() {
+			item.write_to(&mut bytes);
 		}
 		bytes
 	}
 
+	/// Bytes of the record zero padded to `size`, the fixed size its schema gives, so equal records have equal slots
+	pub(crate) fn to_padded_bytes(&self, size: usize) -> Vec<u8> {
+		let mut bytes = self.to_bytes();
+		debug_assert!(bytes.len() <= size);
+		bytes.resize(size, 0);
+		bytes
+	}
+
 	/// Generates a record from bytes given a matching schema
 	///
-	/// Assumes bytes contains the right amount of bytes
-	pub fn from_bytes(bytes: &[u8], schema: &Schema) -> Record {
+	/// Assumes bytes contains the right amount of bytes, ignoring any padding after the values
+	pub(crate) fn from_bytes(bytes: &[u8], schema: &Schema) -> Record {
 		let mut rec = Record::new();
 		let mut cur: usize = 0;
 		for ty in schema.items.iter() {
@@ -44,15 +76,28 @@ impl Record {
 						&bytes[(cur - size_of::<i32>())..cur],
 					)))
 				}
+				ValueType::Point => {
+					cur += Point::SIZE;
+					Value::Point(Point::from_bytes(&bytes[(cur - Point::SIZE)..cur]))
+				}
+				ValueType::Rect => {
+					cur += Rect::SIZE;
+					Value::Rect(Rect::from_bytes(&bytes[(cur - Rect::SIZE)..cur]))
+				}
+				ValueType::String(_) => {
+					let len = u16::from_le_bytes(slice_to_array(&bytes[cur..(cur + 2)])) as usize;
+					cur += 2 + len;
+					Value::String(String::from_utf8_lossy(&bytes[(cur - len)..cur]).into_owned())
+				}
 			};
 			rec = rec.item(val);
 		}
-		debug_assert_eq!(cur, bytes.len());
+		debug_assert!(cur <= bytes.len());
 		rec
 	}
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Schema {
 	items: Vec<ValueType>,
 	size: Option<u16>,
@@ -67,37 +112,35 @@ impl Schema {
 
 	pub fn with(mut self, ty: ValueType) -> Self {
 		self.items.push(ty);
-		self.size = if let Some(new_size) = ty.size() {
-			self.size.map(|size| size + new_size)
-		} else {
-			None
-		};
+		self.recalculate_size();
 		self
 	}
 
 	pub fn with_n(mut self, ty: ValueType, n: usize) -> Self {
 		self.items.extend(iter::repeat_n(ty, n));
-		self.size = if let Some(new_size) = ty.size() {
-			self.size.map(|size| size + new_size * (n as u16))
-		} else {
-			None
-		};
+		self.recalculate_size();
 		self
 	}
 
-	/// Update size with the current items
+	/// Number of columns
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.items.len()
+	}
+
+	/// Builds a new schema out of a subset of this schema's columns, in the order given
+	///
+	/// Panics if any column is out of bounds
+	pub fn project(&self, columns: &[usize]) -> Schema {
+		Schema::from(columns.iter().map(|c| self.items[*c]).collect::<Vec<_>>())
+	}
+
+	/// Update size with the current items, which isn't fixed if it doesn't fit in a `u16`
 	fn recalculate_size(&mut self) {
-		let mut total_size = 0;
-		for item in self.items.iter() {
-			match item.size() {
-				Some(size) => total_size += size,
-				None => {
-					self.size = None;
-					return;
-				}
-			}
-		}
-		self.size = Some(total_size);
+		self.size = self
+			.items
+			.iter()
+			.try_fold(0u16, |total, item| total.checked_add(item.size()?));
 	}
 
 	/// Returns `None` if schema does not have a fixed size
@@ -111,8 +154,7 @@ impl Schema {
 		if rec.items.len() != self.items.len() {
 			return false;
 		}
-		return iter::zip(self.items.iter(), rec.items.iter())
-			.all(|(expected, actual)| actual.ty() == *expected);
+		iter::zip(self.items.iter(), rec.items.iter()).all(|(ty, value)| ty.holds(value))
 	}
 }
 impl From<Vec<ValueType>> for Schema {
@@ -126,44 +168,76 @@ impl From<Vec<ValueType>> for Schema {
 	}
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ValueType {
 	U32,
 	I32,
+	Point,
+	Rect,
+	/// UTF-8 text of up to this many bytes, which always takes up room for the longest
+	String(u16),
 }
 impl ValueType {
 	/// Returns `None` if value type is variable size
 	pub const fn size(&self) -> Option<u16> {
 		match self {
 			ValueType::U32 | ValueType::I32 => Some(4),
+			ValueType::Point => Some(Point::SIZE as u16),
+			ValueType::Rect => Some(Rect::SIZE as u16),
+			ValueType::String(max_len) => max_len.checked_add(2),
+		}
+	}
+
+	/// Whether a value can be stored in a column of this type
+	pub fn holds(&self, value: &Value) -> bool {
+		match (self, value) {
+			(ValueType::U32, Value::U32(_))
+			| (ValueType::I32, Value::I32(_))
+			| (ValueType::Point, Value::Point(_))
+			| (ValueType::Rect, Value::Rect(_)) => true,
+			(ValueType::String(max_len), Value::String(s)) => s.len() <= *max_len as usize,
+			_ => false,
 		}
 	}
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Value {
 	U32(u32),
 	I32(i32),
+	Point(Point),
+	Rect(Rect),
+	String(String),
 }
 impl Value {
-	pub const fn size(&self) -> u16 {
+	/// Bytes the value takes up in a record, before padding
+	pub fn size(&self) -> u16 {
 		match self {
 			Value::U32(_) | Value::I32(_) => 4,
+			Value::Point(_) => Point::SIZE as u16,
+			Value::Rect(_) => Rect::SIZE as u16,
+			Value::String(s) => 2 + s.len() as u16,
 		}
 	}
 
-	pub const fn ty(&self) -> ValueType {
-		match self {
-			Value::U32(_) => ValueType::U32,
-			Value::I32(_) => ValueType::I32,
-		}
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(self.size() as usize);
+		self.write_to(&mut bytes);
+		bytes
 	}
 
-	pub fn to_bytes(self) -> Vec<u8> {
+	/// Appends the value's bytes, a string being its length followed by its UTF-8
+	fn write_to(&self, bytes: &mut Vec<u8>) {
 		use Value::*;
 		match self {
-			U32(n) => Vec::from(n.to_le_bytes()),
-			I32(n) => Vec::from(n.to_le_bytes()),
+			U32(n) => bytes.extend_from_slice(&n.to_le_bytes()),
+			I32(n) => bytes.extend_from_slice(&n.to_le_bytes()),
+			Point(p) => bytes.extend_from_slice(&p.to_bytes()),
+			Rect(r) => bytes.extend_from_slice(&r.to_bytes()),
+			String(s) => {
+				bytes.extend_from_slice(&(s.len() as u16).to_le_bytes());
+				bytes.extend_from_slice(s.as_bytes());
+			}
 		}
 	}
 }
@@ -0,0 +1,344 @@
+use super::{
+	disk::{DiskManager, RecordId},
+	geometry::{Point, Rect},
+	objects::{StorageEngine, secondary_index::SecondaryIndex, storage::StorageKind},
+	record::{Record, Schema, ValueType},
+};
+use crate::*;
+
+/// Columns of a table, and how its records are stored
+///
+/// ```
+/// use lildb::{TableDef, ValueType};
+///
+/// let users = TableDef::new()
+///     .column("id", ValueType::U32)
+///     .column("name", ValueType::String(32))
+///     .column("score", ValueType::I32);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TableDef {
+	columns: Vec<(String, ValueType)>,
+	storage: StorageKind,
+	/// `None` to use the database's compression
+	compression: Option<Compression>,
+}
+impl TableDef {
+	/// A table without columns, which need to be added before it can be created
+	pub fn new() -> TableDef {
+		TableDef::default()
+	}
+
+	/// Adds a column after the ones added before it
+	pub fn column<S: Into<String>>(mut self, name: S, ty: ValueType) -> Self {
+		self.columns.push((name.into(), ty));
+		self
+	}
+
+	/// Names and types of the columns, in the order records hold their values
+	#[inline]
+	pub fn columns(&self) -> &[(String, ValueType)] {
+		&self.columns
+	}
+
+	/// Sets how the table's records are stored, a heap file by default
+	pub fn storage(mut self, kind: StorageKind) -> Self {
+		self.storage = kind;
+		self
+	}
+
+	#[inline]
+	pub(crate) fn storage_kind(&self) -> StorageKind {
+		self.storage
+	}
+
+	/// Compresses the pages of the table and its indexes, the database's compression by default
+	///
+	/// Pages record how they are compressed, so tables compressed differently can share a database.
+	pub fn compression(mut self, compression: Compression) -> Self {
+		self.compression = Some(compression);
+		self
+	}
+
+	#[inline]
+	pub(crate) fn compression_override(&self) -> Option<Compression> {
+		self.compression
+	}
+
+	/// Position of a column in the table's records
+	pub(crate) fn column_index(&self, name: &str) -> Option<usize> {
+		self.columns.iter().position(|(column, _)| column == name)
+	}
+
+	/// Schema of the table's records
+	pub(crate) fn schema(&self) -> Schema {
+		Schema::from(self.columns.iter().map(|(_, ty)| *ty).collect::<Vec<_>>())
+	}
+
+	/// Checks that the table has columns, that their names are unique, and that its records fit in pages of a size
+	///
+	/// Records may take up at most a quarter of a page, leaving room for index entries made of their columns.
+	pub(crate) fn validate(&self, page_size: usize) -> Result<()> {
+		if self.columns.is_empty() {
+			return Err(Error::InvalidArgument(
+				"A table needs at least one column".to_string(),
+			));
+		}
+		match self.schema().size() {
+			Some(size) if size as usize <= page_size / 4 => {}
+			_ => {
+				return Err(Error::InvalidArgument(format!(
+					"Records take up more than a quarter of a {page_size} byte page"
+				)));
+			}
+		}
+		for (i, (name, _)) in self.columns.iter().enumerate() {
+			if self.columns[..i].iter().any(|(other, _)| other == name) {
+				return Err(Error::InvalidArgument(format!(
+					"Column \"{name}\" is defined twice"
+				)));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// A table's storage and indexes, kept open by its connection
+pub(crate) struct OpenTable {
+	pub name: String,
+	pub def: TableDef,
+	pub storage: Box<dyn StorageEngine>,
+	pub indexes: Vec<(String, SecondaryIndex)>,
+}
+impl OpenTable {
+	/// Writes out anything the table's storage holds in memory
+	pub fn flush(&mut self, disk: &mut DiskManager) -> Result<()> {
+		disk.with_compression(self.def.compression, |disk| self.storage.flush(disk))
+	}
+
+	/// Which of the table's indexes a record belongs in
+	///
+	/// Evaluated before a write touches the table's storage, so a predicate that fails leaves both the table and its
+	/// indexes as they were.
+	fn covering(&self, rec: &Record) -> Result<Vec<bool>> {
+		if !self.def.schema().validate(rec) {
+			return Err(Error::TypeMismatch(
+				"Record does not match table schema".to_string(),
+			));
+		}
+		self.indexes
+			.iter()
+			.map(|(_, index)| index.covers(rec))
+			.collect()
+	}
+
+	/// Adds a record to the indexes `covered` marks, taking it back out of those it was added to if one fails
+	fn add_to_indexes(
+		&self,
+		disk: &mut DiskManager,
+		covered: &[bool],
+		rec: &Record,
+		rid: RecordId,
+	) -> Result<()> {
+		for (i, (_, index)) in self.indexes.iter().enumerate().take(covered.len()) {
+			if covered[i]
+				&& let Err(e) = index.add(disk, rec, rid)
+			{
+				self.remove_from_indexes(disk, &covered[..i], rec, rid)?;
+				return Err(e);
+			}
+		}
+		Ok(())
+	}
+
+	/// Removes a record from the indexes `covered` marks, adding it back to those it was removed from if one fails
+	fn remove_from_indexes(
+		&self,
+		disk: &mut DiskManager,
+		covered: &[bool],
+		rec: &Record,
+		rid: RecordId,
+	) -> Result<()> {
+		for (i, (_, index)) in self.indexes.iter().enumerate().take(covered.len()) {
+			if covered[i]
+				&& let Err(e) = index.remove(disk, rec, rid)
+			{
+				self.add_to_indexes(disk, &covered[..i], rec, rid)?;
+				return Err(e);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn index(&self, name: &str) -> Result<&SecondaryIndex> {
+		self.indexes
+			.iter()
+			.find(|(index, _)| index == name)
+			.map(|(_, index)| index)
+			.ok_or_else(|| Error::NotFound(format!("index \"{name}\" on table \"{}\"", self.name)))
+	}
+}
+
+/// A table of a database, borrowed from the connection it was opened with
+///
+/// Every write keeps the table's indexes up to date, and writes pages with the table's compression. A write that fails
+/// part way is undone, leaving the table and its indexes as they were.
+pub struct Table<'a> {
+	disk: &'a mut DiskManager,
+	table: &'a mut OpenTable,
+}
+impl<'a> Table<'a> {
+	pub(crate) fn new(disk: &'a mut DiskManager, table: &'a mut OpenTable) -> Table<'a> {
+		Table { disk, table }
+	}
+
+	#[inline]
+	pub fn name(&self) -> &str {
+		&self.table.name
+	}
+
+	#[inline]
+	pub fn def(&self) -> &TableDef {
+		&self.table.def
+	}
+
+	/// Adds a record, returning its ID
+	///
+	/// Fails with `Error::TypeMismatch` if the record doesn't match the table's columns.
+	pub fn insert(&mut self, rec: Record) -> Result<RecordId> {
+		let table = &mut *self.table;
+		let covered = table.covering(&rec)?;
+		self.disk.with_compression(table.def.compression, |disk| {
+			let rid = table.storage.insert(disk, rec.clone())?;
+			if let Err(e) = table.add_to_indexes(disk, &covered, &rec, rid) {
+				table.storage.delete(disk, rid)?;
+				return Err(e);
+			}
+			Ok(rid)
+		})
+	}
+
+	/// Reads a record, `None` if there is no record with this ID
+	pub fn get(&mut self, rid: RecordId) -> Result<Option<Record>> {
+		self.table.storage.get(self.disk, rid)
+	}
+
+	/// Replaces a record, which keeps its ID, returning `false` if there is no record with this ID
+	pub fn update(&mut self, rid: RecordId, rec: Record) -> Result<bool> {
+		let table = &mut *self.table;
+		self.disk.with_compression(table.def.compression, |disk| {
+			let Some(old) = table.storage.get(disk, rid)? else {
+				return Ok(false);
+			};
+			let was_covered = table.covering(&old)?;
+			let covered = table.covering(&rec)?;
+			table.storage.update(disk, rid, rec.clone())?;
+			if let Err(e) = table.remove_from_indexes(disk, &was_covered, &old, rid) {
+				table.storage.update(disk, rid, old)?;
+				return Err(e);
+			}
+			if let Err(e) = table.add_to_indexes(disk, &covered, &rec, rid) {
+				table.add_to_indexes(disk, &was_covered, &old, rid)?;
+				table.storage.update(disk, rid, old)?;
+				return Err(e);
+			}
+			Ok(true)
+		})
+	}
+
+	/// Removes a record, returning `false` if there is no record with this ID
+	pub fn delete(&mut self, rid: RecordId) -> Result<bool> {
+		let table = &mut *self.table;
+		self.disk.with_compression(table.def.compression, |disk| {
+			let Some(old) = table.storage.get(disk, rid)? else {
+				return Ok(false);
+			};
+			// the indexes go first, as a deleted record can't be put back under the same ID
+			let covered = table.covering(&old)?;
+			table.remove_from_indexes(disk, &covered, &old, rid)?;
+			if let Err(e) = table.storage.delete(disk, rid) {
+				table.add_to_indexes(disk, &covered, &old, rid)?;
+				return Err(e);
+			}
+			Ok(true)
+		})
+	}
+
+	/// Reads every record, in no particular order
+	pub fn scan(&mut self) -> Result<Vec<(RecordId, Record)>> {
+		self.table.storage.scan(self.disk)
+	}
+
+	/// Finds the records whose key columns in an index equal `key`, which holds a value for each key column in order
+	pub fn find(&mut self, index: &str, key: &Record) -> Result<Vec<(RecordId, Record)>> {
+		let rids = self.table.index(index)?.get(self.disk, key)?;
+		self.read_found(index, rids)
+	}
+
+	/// Finds the records whose point or rect lies inside `window`, through a spatial index
+	pub fn within(&mut self, index: &str, window: Rect) -> Result<Vec<(RecordId, Record)>> {
+		let rids = self.table.index(index)?.within(self.disk, &window)?;
+		self.read_found(index, rids)
+	}
+
+	/// Finds the records whose point or rect overlaps `window`, through a spatial index
+	pub fn intersecting(&mut self, index: &str, window: Rect) -> Result<Vec<(RecordId, Record)>> {
+		let rids = self.table.index(index)?.intersecting(self.disk, &window)?;
+		self.read_found(index, rids)
+	}
+
+	/// Finds the `k` records closest to `point`, closest first, through a spatial index
+	pub fn nearest(
+		&mut self,
+		index: &str,
+		point: Point,
+		k: usize,
+	) -> Result<Vec<(RecordId, Record)>> {
+		let rids = self.table.index(index)?.nearest(self.disk, &point, k)?;
+		self.read_found(index, rids)
+	}
+
+	/// Finds the records whose text contains every term of `query`, in no particular order, through a full-text index
+	pub fn matches(&mut self, index: &str, query: &str) -> Result<Vec<(RecordId, Record)>> {
+		let rids = self.table.index(index)?.matches(self.disk, query)?;
+		self.read_found(index, rids)
+	}
+
+	/// Scores the records whose text contains any term of `query` with BM25, best matches first, through a full-text
+	/// index
+	pub fn rank(&mut self, index: &str, query: &str) -> Result<Vec<(RecordId, Record, f64)>> {
+		let scored = self.table.index(index)?.rank(self.disk, query)?;
+		let (rids, scores): (Vec<_>, Vec<_>) = scored.into_iter().unzip();
+		Ok(self
+			.read_found(index, rids)?
+			.into_iter()
+			.zip(scores)
+			.map(|((rid, rec), score)| (rid, rec, score))
+			.collect())
+	}
+
+	/// Like `find`, but only reads the columns the index includes, without reading the records themselves
+	pub fn find_covered(&mut self, index: &str, key: &Record) -> Result<Vec<(RecordId, Record)>> {
+		Ok(self
+			.table
+			.index(index)?
+			.get_covered(self.disk, key)?
+			.into_iter()
+			.map(|(rec, rid)| (rid, rec))
+			.collect())
+	}
+
+	/// Reads the records an index found
+	fn read_found(&mut self, index: &str, rids: Vec<RecordId>) -> Result<Vec<(RecordId, Record)>> {
+		let mut found = Vec::with_capacity(rids.len());
+		for rid in rids {
+			let Some(rec) = self.table.storage.get(self.disk, rid)? else {
+				return Err(Error::Corruption(format!(
+					"Index \"{index}\" refers to a missing record"
+				)));
+			};
+			found.push((rid, rec));
+		}
+		Ok(found)
+	}
+}
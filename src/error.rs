@@ -1,17 +1,185 @@
-use std::io;
+use std::{fmt, io, ops::Range};
 
+/// Location of a span of source text, lines and columns counting from 0
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SourceLocation {
+	pub line: u32,
+	pub start_col: u32,
+	pub end_col: u32,
+}
+impl SourceLocation {
+	pub const fn new(line: u32, cols: Range<u32>) -> SourceLocation {
+		SourceLocation {
+			line,
+			start_col: cols.start,
+			end_col: cols.end,
+		}
+	}
+}
+impl fmt::Display for SourceLocation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "line {}, col {}", self.line, self.start_col)
+	}
+}
+
+/// Every error the database can produce
+///
+/// Each variant has a numeric [`Error::code`] that is stable across versions, for mapping errors onto other error
+/// schemes (such as HTTP statuses) without matching on messages.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
 	/// File IO errors, wrapping around a `std::io::Error`
 	Io(io::Error),
+	/// Something that was looked up does not exist
+	NotFound(String),
+	/// Something that was created already exists
+	AlreadyExists(String),
+	/// A write would break a constraint, such as a unique key
+	ConstraintViolation(String),
+	/// A value or record does not have the type or schema expected of it
+	TypeMismatch(String),
+	/// The database file is damaged, or isn't a database file
+	Corruption(String),
+	/// Timed out waiting for a lock held by another connection
+	Busy,
+	/// Tried to write to a database opened read only
+	ReadOnly,
+	/// A transaction conflicted with a concurrent one, and should be retried
+	TransactionConflict,
+	/// Query text could not be parsed
+	Parse {
+		message: String,
+		location: SourceLocation,
+	},
+	/// An argument or option was invalid, such as contradicting options when opening a database
+	InvalidArgument(String),
 	/// Internal error, ideally should never thrown
 	Internal(String),
+	/// Another error, with a description of what was being done when it happened
+	Context { context: String, source: Box<Error> },
+}
+impl Error {
+	pub fn parse<S: Into<String>>(message: S, location: SourceLocation) -> Error {
+		Error::Parse {
+			message: message.into(),
+			location,
+		}
+	}
+
+	/// Wraps the error with a description of what was being done when it happened
+	pub fn context<S: Into<String>>(self, context: S) -> Error {
+		Error::Context {
+			context: context.into(),
+			source: Box::new(self),
+		}
+	}
+
+	/// Stable numeric code identifying the kind of error
+	///
+	/// | Code | Variant |
+	/// |------|---------|
+	/// | 1    | `Io` |
+	/// | 2    | `NotFound` |
+	/// | 3    | `AlreadyExists` |
+	/// | 4    | `ConstraintViolation` |
+	/// | 5    | `TypeMismatch` |
+	/// | 6    | `Corruption` |
+	/// | 7    | `Busy` |
+	/// | 8    | `ReadOnly` |
+	/// | 9    | `TransactionConflict` |
+	/// | 10   | `Parse` |
+	/// | 11   | `InvalidArgument` |
+	/// | 99   | `Internal` |
+	///
+	/// `Context` has the code of the error it wraps. Codes are never reused or renumbered.
+	pub fn code(&self) -> u16 {
+		match self {
+			Error::Io(_) => 1,
+			Error::NotFound(_) => 2,
+			Error::AlreadyExists(_) => 3,
+			Error::ConstraintViolation(_) => 4,
+			Error::TypeMismatch(_) => 5,
+			Error::Corruption(_) => 6,
+			Error::Busy => 7,
+			Error::ReadOnly => 8,
+			Error::TransactionConflict => 9,
+			Error::Parse { .. } => 10,
+			Error::InvalidArgument(_) => 11,
+			Error::Internal(_) => 99,
+			Error::Context { source, .. } => source.code(),
+		}
+	}
+
+	/// The innermost error, skipping through any `Context`s
+	pub fn root(&self) -> &Error {
+		match self {
+			Error::Context { source, .. } => source.root(),
+			e => e,
+		}
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Error::Io(e) => write!(f, "I/O error: {e}"),
+			Error::NotFound(what) => write!(f, "Not found: {what}"),
+			Error::AlreadyExists(what) => write!(f, "Already exists: {what}"),
+			Error::ConstraintViolation(msg) => write!(f, "Constraint violation: {msg}"),
+			Error::TypeMismatch(msg) => write!(f, "Type mismatch: {msg}"),
+			Error::Corruption(msg) => write!(f, "Database is corrupt: {msg}"),
+			Error::Busy => write!(f, "Timed out waiting for a lock held by another connection"),
+			Error::ReadOnly => write!(f, "Database is read only"),
+			Error::TransactionConflict => {
+				write!(f, "Transaction conflicted with a concurrent transaction")
+			}
+			Error::Parse { message, location } => write!(f, "{message} ({location})"),
+			Error::InvalidArgument(msg) => write!(f, "Invalid argument: {msg}"),
+			Error::Internal(msg) => write!(f, "Internal error: {msg}"),
+			Error::Context { context, .. } => write!(f, "{context}"),
+		}
+	}
+}
+
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Io(e) => Some(e),
+			Error::Context { source, .. } => Some(source.as_ref()),
+			_ => None,
+		}
+	}
 }
 
 impl From<io::Error> for Error {
 	fn from(e: io::Error) -> Self {
-		return Self::Io(e);
+		Self::Io(e)
 	}
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+	use std::error::Error as _;
+
+	use super::*;
+
+	#[test]
+	fn context_chain() {
+		let e =
+			Error::from(io::Error::from(io::ErrorKind::PermissionDenied)).context("Opening db.ldb");
+		assert_eq!(e.code(), 1);
+		assert_eq!(e.to_string(), "Opening db.ldb");
+
+		let source = e.source().unwrap();
+		assert!(source.to_string().starts_with("I/O error"));
+		assert!(source.source().unwrap().is::<io::Error>());
+		assert!(matches!(e.root(), Error::Io(_)));
+
+		let e = Error::parse("Expected \";\"", SourceLocation::new(2, 4..5));
+		assert_eq!(e.to_string(), "Expected \";\" (line 2, col 4)");
+		assert_eq!(e.code(), 10);
+	}
+}
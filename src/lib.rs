@@ -1,35 +1,262 @@
-#![allow(dead_code)]
 mod db;
 mod error;
 pub mod query;
 mod util;
+pub mod vfs;
 
-use db::LilDbConnection;
-use error::{Error, Result};
+use std::{sync::Arc, time::Duration};
 
-/// Internal page size, in bytes
-const PAGE_SIZE: usize = 8_192;
+pub use db::{
+	BloomFilterDef, IndexDef, IndexKind, LilDbConnection, Point, QueryResult, Record, RecordId,
+	Rect, StemmerKind, StorageKind, Table, TableDef, TextIndexDef, TokenizerKind, Value, ValueType,
+};
+pub use error::{Error, Result, SourceLocation};
+
+/// Page size of new databases unless set otherwise, in bytes
+pub const DEFAULT_PAGE_SIZE: usize = 8_192;
+/// Smallest supported page size, in bytes
+pub const MIN_PAGE_SIZE: usize = 4_096;
+/// Largest supported page size, in bytes
+pub const MAX_PAGE_SIZE: usize = 65_536;
+
+/// Bytes a query may hold in memory before spilling, unless set otherwise
+pub const DEFAULT_QUERY_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Path that opens a private, in-memory database instead of a file
+pub const MEMORY_PATH: &str = ":memory:";
 
 /// Open a new connection to the database at the path specified with default options
 pub fn open<P: Into<std::path::PathBuf>>(db: P) -> Result<LilDbConnection> {
 	LilDbOpts::default().open(db)
 }
 
+/// When writes are synced to durable storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Synchronous {
+	/// Never sync, leaving it to the OS. Fastest, but an OS crash or power loss can corrupt the database
+	Off,
+	/// Sync at commit points, such as when the database is explicitly synced
+	#[default]
+	Normal,
+	/// Sync after every page write
+	Full,
+}
+
+/// How pages are compressed on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+	/// Store pages as they are
+	#[default]
+	None,
+	/// Fast LZ4-style compression
+	Fast,
+	/// Searches harder for repeated bytes than `Fast`, for a better ratio at the cost of slower writes. Reads are just
+	/// as fast
+	High,
+}
+
+/// Totals over the pages stored in a database, before and after compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+	pub pages: u64,
+	/// Bytes of the pages themselves
+	pub page_bytes: u64,
+	/// Bytes stored on disk for those pages
+	pub stored_bytes: u64,
+}
+impl CompressionStats {
+	/// How many times smaller pages are on disk, 1.0 if there are no pages
+	pub fn ratio(&self) -> f64 {
+		if self.stored_bytes == 0 {
+			return 1.0;
+		}
+		self.page_bytes as f64 / self.stored_bytes as f64
+	}
+}
+
 /// Optional options to specify when opening a connection to a DB
-#[derive(Clone, Copy)]
+///
+/// ```no_run
+/// let db = lildb::LilDbOpts::new()
+///     .read_only(true)
+///     .busy_timeout(std::time::Duration::from_secs(5))
+///     .open("replica.ldb");
+/// ```
+#[derive(Clone)]
 pub struct LilDbOpts {
 	/// Create the database if it does not exist
 	create: bool,
+	/// Fail if the database already exists
+	create_new: bool,
+	/// Open without write access
+	read_only: bool,
+	/// File system the database is stored on
+	vfs: Arc<dyn vfs::Vfs>,
+	/// Keep the database in memory, ignoring the path it is opened with
+	in_memory: bool,
+	/// Read pages through a memory mapping of the file
+	mmap: bool,
+	/// Page size to create the database with
+	page_size: usize,
+	synchronous: Synchronous,
+	/// Pages kept cached in memory
+	cache_size: usize,
+	/// How long to wait for locks held by other connections, forever if `None`
+	busy_timeout: Option<Duration>,
+	/// Page compression to create the database with
+	compression: Compression,
+	/// Key to encrypt pages with
+	encryption_key: Option<[u8; 32]>,
+	/// Bytes a query may hold in memory before spilling to temporary files
+	query_memory: usize,
 }
 
 impl LilDbOpts {
+	/// Default options, same as `LilDbOpts::default()`
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Options for a database that lives only in memory, same as opening [`MEMORY_PATH`]
+	pub fn in_memory() -> Self {
+		Self {
+			in_memory: true,
+			..Self::default()
+		}
+	}
+
 	pub fn open<P: Into<std::path::PathBuf>>(&self, db: P) -> Result<LilDbConnection> {
-		Ok(LilDbConnection::open_db(db.into(), *self)?)
+		LilDbConnection::open_db(db.into(), self.clone())
+	}
+
+	/// Creates the database if it does not exist, on by default
+	pub fn create(mut self, create: bool) -> Self {
+		self.create = create;
+		self
+	}
+
+	/// Fails to open if the database already exists, off by default
+	pub fn create_new(mut self, create_new: bool) -> Self {
+		self.create_new = create_new;
+		self
+	}
+
+	/// Opens the database without write access, off by default
+	///
+	/// Any write fails with `Error::ReadOnly`, and the database is never created.
+	pub fn read_only(mut self, read_only: bool) -> Self {
+		self.read_only = read_only;
+		self
+	}
+
+	/// Stores the database on a different file system, the OS's by default
+	pub fn vfs(mut self, vfs: Arc<dyn vfs::Vfs>) -> Self {
+		self.vfs = vfs;
+		self
+	}
+
+	/// Reads pages through a memory mapping of the database file instead of read syscalls, off by default
+	///
+	/// Falls back to regular reads on file systems that can't be mapped. Works best for read-mostly workloads.
+	pub fn mmap(mut self, enabled: bool) -> Self {
+		self.mmap = enabled;
+		self
+	}
+
+	/// Page size used when creating a new database, a power of two from [`MIN_PAGE_SIZE`] to [`MAX_PAGE_SIZE`]
+	///
+	/// The page size is recorded in the database file, so existing databases keep the page size they were created
+	/// with, regardless of this option.
+	pub fn page_size(mut self, page_size: usize) -> Self {
+		self.page_size = page_size;
+		self
+	}
+
+	/// When writes are synced to durable storage, [`Synchronous::Normal`] by default
+	pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+		self.synchronous = synchronous;
+		self
+	}
+
+	/// Number of pages to keep cached in memory, 0 (no cache) by default
+	///
+	/// The cache is private to the connection, so it should only be enabled when no other connection writes to the
+	/// database.
+	pub fn cache_size(mut self, pages: usize) -> Self {
+		self.cache_size = pages;
+		self
+	}
+
+	/// How long to wait for file locks held by other connections before failing with `Error::Busy`
+	///
+	/// Waits indefinitely by default, or when given `None`.
+	pub fn busy_timeout(mut self, timeout: impl Into<Option<Duration>>) -> Self {
+		self.busy_timeout = timeout.into();
+		self
+	}
+
+	/// Compresses pages when creating a new database, [`Compression::None`] by default
+	///
+	/// Each page is compressed on its own, and the space it saves is freed by punching holes in the file, on file
+	/// systems that support it. Like the page size, compression is recorded in the database file, so existing databases
+	/// keep the compression they were created with. Tables can choose their own with [`TableDef::compression`].
+	pub fn compression(mut self, compression: Compression) -> Self {
+		self.compression = compression;
+		self
+	}
+
+	/// Encrypts every page (but the first, which only holds the file header) with ChaCha20-Poly1305, unencrypted by
+	/// default
+	///
+	/// A new database is created encrypted with this key, and an existing one has to be opened with the key it was
+	/// encrypted with, otherwise opening fails with `Error::InvalidArgument`. Pages that fail authentication, because
+	/// they were tampered with, are reported as `Error::Corruption`.
+	///
+	/// Needs the `encryption` feature, without it opening any database with a key fails with
+	/// `Error::InvalidArgument`.
+	pub fn encryption_key(mut self, key: [u8; 32]) -> Self {
+		self.encryption_key = Some(key);
+		self
+	}
+
+	/// Bytes of memory a query may use for grouping and sorting before it spills to temporary files next to the
+	/// database, 64 MiB by default
+	///
+	/// Temporary files are created in the configured VFS, named after the database, and removed once the query is done
+	/// with them. Queries on an in-memory database spill to memory instead.
+	pub fn query_memory(mut self, bytes: usize) -> Self {
+		self.query_memory = bytes;
+		self
+	}
+
+	/// Checks that the options don't contradict each other
+	fn validate(&self) -> Result<()> {
+		if self.read_only && (self.create_new || self.in_memory) {
+			return Err(Error::InvalidArgument(
+				"A read only database can't be created, so it can't be new or in memory"
+					.to_string(),
+			));
+		}
+		Ok(())
 	}
 }
 
 impl Default for LilDbOpts {
 	fn default() -> Self {
-		Self { create: true }
+		Self {
+			create: true,
+			create_new: false,
+			read_only: false,
+			vfs: Arc::new(vfs::OsVfs),
+			in_memory: false,
+			mmap: false,
+			page_size: DEFAULT_PAGE_SIZE,
+			synchronous: Synchronous::default(),
+			cache_size: 0,
+			busy_timeout: None,
+			compression: Compression::default(),
+			encryption_key: None,
+			query_memory: DEFAULT_QUERY_MEMORY,
+		}
 	}
 }
@@ -0,0 +1,293 @@
+use std::ops::RangeInclusive;
+
+use super::{Query, Value};
+
+/// An expression, such as a filter condition or a computed column
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+	/// A literal, or a bare word naming a column
+	Value(Value),
+	/// A column of a particular object, `object.column`, to tell apart columns of joined objects
+	Column {
+		object: String,
+		name: String,
+	},
+	Unary {
+		op: UnaryOp,
+		expr: Box<Expr>,
+	},
+	Binary {
+		op: BinaryOp,
+		left: Box<Expr>,
+		right: Box<Expr>,
+	},
+	/// `expr in (list...)`, or `expr not in (list...)` when negated
+	In {
+		expr: Box<Expr>,
+		list: Vec<Expr>,
+		negated: bool,
+	},
+	/// `expr between low and high`, inclusive of both ends
+	Between {
+		expr: Box<Expr>,
+		low: Box<Expr>,
+		high: Box<Expr>,
+		negated: bool,
+	},
+	/// `expr in query`, or `expr not in query` when negated, where the query selects one column
+	InQuery {
+		expr: Box<Expr>,
+		query: Box<Query>,
+		negated: bool,
+	},
+	/// A query used as a value, which must produce one record with one column
+	///
+	/// Qualified columns of objects outside the query refer to the record being evaluated, making it correlated.
+	Subquery(Box<Query>),
+	/// `exists(query)`, true if the query produces any records
+	Exists(Box<Query>),
+	/// `expr is null`, or `expr is not null` when negated
+	IsNull {
+		expr: Box<Expr>,
+		negated: bool,
+	},
+	/// A record literal, `{column: value, ...}`
+	Record(Vec<(String, Expr)>),
+	/// A list literal, `[a, b, ...]`
+	List(Vec<Expr>),
+	/// An aggregate function, reducing the values of the expressions over a group of records to one
+	Aggregate {
+		function: AggregateFunction,
+		args: Vec<Expr>,
+	},
+	/// A window function, computed for each record from the records in its window, such as
+	/// `rank() over(partition_by(dept), order_by(salary desc))`
+	Window {
+		function: WindowFunction,
+		args: Vec<Expr>,
+		window: Window,
+	},
+	/// A selected column named with `expr as name`
+	Alias {
+		expr: Box<Expr>,
+		name: String,
+	},
+	/// A key to sort by, from `expr [asc | desc] [nulls first | nulls last]`
+	///
+	/// Nulls sort after every other value unless put first, so by default they come last in ascending order and first
+	/// in descending order.
+	SortKey {
+		expr: Box<Expr>,
+		descending: bool,
+		nulls_first: bool,
+	},
+	/// A call to a scalar function, such as `lower(name)`
+	Call {
+		name: String,
+		args: Vec<Expr>,
+	},
+}
+impl Expr {
+	pub fn unary(op: UnaryOp, expr: Expr) -> Expr {
+		Expr::Unary {
+			op,
+			expr: Box::new(expr),
+		}
+	}
+
+	pub fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+		Expr::Binary {
+			op,
+			left: Box::new(left),
+			right: Box::new(right),
+		}
+	}
+
+	/// A bare word naming a column
+	pub fn ident<S: Into<String>>(name: S) -> Expr {
+		Expr::Value(Value::Identifier(name.into()))
+	}
+}
+impl From<Value> for Expr {
+	fn from(value: Value) -> Self {
+		Expr::Value(value)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+	/// `count()` counts records, `count(expr)` counts values that aren't null
+	Count,
+	/// `count_distinct(expr)`, counts distinct values that aren't null
+	CountDistinct,
+	Sum,
+	Avg,
+	Min,
+	Max,
+	/// `string_agg(expr, separator)`, concatenates strings with a separator between them
+	StringAgg,
+}
+impl AggregateFunction {
+	pub const ALL: &[AggregateFunction] = &[
+		AggregateFunction::Count,
+		AggregateFunction::CountDistinct,
+		AggregateFunction::Sum,
+		AggregateFunction::Avg,
+		AggregateFunction::Min,
+		AggregateFunction::Max,
+		AggregateFunction::StringAgg,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			AggregateFunction::Count => "count",
+			AggregateFunction::CountDistinct => "count_distinct",
+			AggregateFunction::Sum => "sum",
+			AggregateFunction::Avg => "avg",
+			AggregateFunction::Min => "min",
+			AggregateFunction::Max => "max",
+			AggregateFunction::StringAgg => "string_agg",
+		}
+	}
+
+	/// Find aggregate function by name
+	pub fn find(name: &str) -> Option<AggregateFunction> {
+		Self::ALL.iter().copied().find(|f| f.name() == name)
+	}
+
+	/// How many arguments the function takes
+	pub fn arg_count(self) -> RangeInclusive<usize> {
+		match self {
+			AggregateFunction::Count => 0..=1,
+			AggregateFunction::StringAgg => 2..=2,
+			_ => 1..=1,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunction {
+	/// Position of the record in its partition, counting from 1
+	RowNumber,
+	/// Position of the first record in the partition with the same order keys, so ties leave gaps
+	Rank,
+	/// Number of distinct order keys in the partition up to the record, so ties don't leave gaps
+	DenseRank,
+	/// `lag(expr, offset = 1, default = null)`, value of a record `offset` records before
+	Lag,
+	/// `lead(expr, offset = 1, default = null)`, value of a record `offset` records after
+	Lead,
+	FirstValue,
+	LastValue,
+	/// An aggregate over the records of the frame, such as a running total
+	Aggregate(AggregateFunction),
+}
+impl WindowFunction {
+	const RANKING: &[WindowFunction] = &[
+		WindowFunction::RowNumber,
+		WindowFunction::Rank,
+		WindowFunction::DenseRank,
+		WindowFunction::Lag,
+		WindowFunction::Lead,
+		WindowFunction::FirstValue,
+		WindowFunction::LastValue,
+	];
+
+	pub fn name(self) -> &'static str {
+		match self {
+			WindowFunction::RowNumber => "row_number",
+			WindowFunction::Rank => "rank",
+			WindowFunction::DenseRank => "dense_rank",
+			WindowFunction::Lag => "lag",
+			WindowFunction::Lead => "lead",
+			WindowFunction::FirstValue => "first_value",
+			WindowFunction::LastValue => "last_value",
+			WindowFunction::Aggregate(f) => f.name(),
+		}
+	}
+
+	/// Find window function by name, which includes the aggregate functions
+	pub fn find(name: &str) -> Option<WindowFunction> {
+		Self::RANKING
+			.iter()
+			.copied()
+			.find(|f| f.name() == name)
+			.or_else(|| AggregateFunction::find(name).map(WindowFunction::Aggregate))
+	}
+
+	/// How many arguments the function takes
+	pub fn arg_count(self) -> RangeInclusive<usize> {
+		match self {
+			WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => 0..=0,
+			WindowFunction::Lag | WindowFunction::Lead => 1..=3,
+			WindowFunction::FirstValue | WindowFunction::LastValue => 1..=1,
+			WindowFunction::Aggregate(f) => f.arg_count(),
+		}
+	}
+}
+
+/// The records a window function is computed from
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Window {
+	/// Records are split into partitions with equal values of these, all records are one partition if empty
+	pub partition_by: Vec<Expr>,
+	/// `Expr::SortKey`s ordering each partition
+	pub order_by: Vec<Expr>,
+	/// Defaults to the start of the partition up to the record and its peers when ordered, otherwise the whole
+	/// partition
+	pub frame: Option<WindowFrame>,
+}
+
+/// The records of a partition around the current one that a window function sees
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowFrame {
+	pub units: FrameUnits,
+	pub start: FrameBound,
+	pub end: FrameBound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnits {
+	/// Bounds are counted in records
+	Rows,
+	/// Bounds are differences in the value of the order key, so records with equal keys are in or out together
+	Range,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBound {
+	UnboundedPreceding,
+	Preceding(Box<Expr>),
+	CurrentRow,
+	Following(Box<Expr>),
+	UnboundedFollowing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+	/// `-`
+	Neg,
+	/// `not`
+	Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Rem,
+	Eq,
+	NotEq,
+	Lt,
+	LtEq,
+	Gt,
+	GtEq,
+	And,
+	Or,
+	/// `like`, matching a pattern where `%` is any run of chars and `_` any one char
+	Like,
+	/// `not like`
+	NotLike,
+}
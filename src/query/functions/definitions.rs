@@ -20,3 +20,218 @@ pub const deleteFunction: FunctionDef = FunctionDef {
 	object_type: Type::Object,
 	return_type: Type::Object,
 };
+
+/// Inserts `{...}` or a list of records `[{...}, ...]`
+pub const insertFunction: FunctionDef = FunctionDef {
+	name: "insert",
+	positional_args: &[Type::RecordLiteral],
+	object_type: Type::Object,
+	return_type: Type::None,
+};
+
+/// Inserts records, replacing any existing records with the same primary key
+pub const upsertFunction: FunctionDef = FunctionDef {
+	name: "upsert",
+	positional_args: &[Type::RecordLiteral],
+	object_type: Type::Object,
+	return_type: Type::None,
+};
+
+/// Reads the record with a primary key
+pub const getFunction: FunctionDef = FunctionDef {
+	name: "get",
+	positional_args: &[Type::Value],
+	object_type: Type::Object,
+	return_type: Type::Records,
+};
+
+/// Sets columns of the records to the values of `{column: expr, ...}`, which can refer to the record's columns
+pub const updateFunction: FunctionDef = FunctionDef {
+	name: "update",
+	positional_args: &[Type::RecordLiteral],
+	object_type: Type::Records,
+	return_type: Type::None,
+};
+
+/// Deletes the records, where `delete` deletes the whole object
+pub const deleteRecordsFunction: FunctionDef = FunctionDef {
+	name: "delete_records",
+	positional_args: &[],
+	object_type: Type::Records,
+	return_type: Type::None,
+};
+
+/// Keeps the records a condition is true for
+pub const whereFunction: FunctionDef = FunctionDef {
+	name: "where",
+	positional_args: &[Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Projects records onto columns, such as `select(name, age + 1 as next_age)`
+pub const selectFunction: FunctionDef = FunctionDef {
+	name: "select",
+	positional_args: &[Type::Columns],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Sorts records, such as `order_by(age desc nulls last, name)`
+pub const orderByFunction: FunctionDef = FunctionDef {
+	name: "order_by",
+	positional_args: &[Type::SortKeys],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Keeps at most a number of records
+pub const limitFunction: FunctionDef = FunctionDef {
+	name: "limit",
+	positional_args: &[Type::IntegerLiteral],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Skips a number of records
+pub const offsetFunction: FunctionDef = FunctionDef {
+	name: "offset",
+	positional_args: &[Type::IntegerLiteral],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Groups records with equal values of the keys, such as `group_by(country, year(created) as year)`
+pub const groupByFunction: FunctionDef = FunctionDef {
+	name: "group_by",
+	positional_args: &[Type::GroupKeys],
+	object_type: Type::Records,
+	return_type: Type::Groups,
+};
+
+/// Keeps the groups a condition is true for, such as `having(count() > 10)`
+///
+/// Called on records that weren't grouped, all of them are one group, see `Type::accepts`.
+pub const havingFunction: FunctionDef = FunctionDef {
+	name: "having",
+	positional_args: &[Type::GroupCondition],
+	object_type: Type::Groups,
+	return_type: Type::Groups,
+};
+
+/// Reduces each group to a record, such as `aggregate(country, count() as users)`
+///
+/// Called on records that weren't grouped, all of them are one group, see `Type::accepts`.
+pub const aggregateFunction: FunctionDef = FunctionDef {
+	name: "aggregate",
+	positional_args: &[Type::Aggregates],
+	object_type: Type::Groups,
+	return_type: Type::Records,
+};
+
+/// Pairs up records with the records of another object that a condition is true for, such as
+/// `join(Orders, Users.id = Orders.user_id)`
+///
+/// The object can be named with `as`, to join an object with itself.
+pub const joinFunction: FunctionDef = FunctionDef {
+	name: "join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Like `join`, also keeping records without a match, paired with nulls
+pub const leftJoinFunction: FunctionDef = FunctionDef {
+	name: "left_join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Like `join`, also keeping records of the other object without a match, paired with nulls
+pub const rightJoinFunction: FunctionDef = FunctionDef {
+	name: "right_join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Like `join`, also keeping records of both sides without a match, paired with nulls
+pub const fullJoinFunction: FunctionDef = FunctionDef {
+	name: "full_join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Pairs up every record with every record of another object
+pub const crossJoinFunction: FunctionDef = FunctionDef {
+	name: "cross_join",
+	positional_args: &[Type::Object],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Keeps the records that have a match in another object, without pairing them up
+pub const semiJoinFunction: FunctionDef = FunctionDef {
+	name: "semi_join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Keeps the records that have no match in another object
+pub const antiJoinFunction: FunctionDef = FunctionDef {
+	name: "anti_join",
+	positional_args: &[Type::Object, Type::Condition],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Adds the records of another query, dropping duplicate records
+pub const unionFunction: FunctionDef = FunctionDef {
+	name: "union",
+	positional_args: &[Type::Query],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Adds the records of another query, keeping duplicate records
+pub const unionAllFunction: FunctionDef = FunctionDef {
+	name: "union_all",
+	positional_args: &[Type::Query],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Keeps the records whose text contains every term of a full-text query, such as `matches("brown fox")`
+pub const matchesFunction: FunctionDef = FunctionDef {
+	name: "matches",
+	positional_args: &[Type::StringLiteral],
+	object_type: Type::Records,
+	return_type: Type::TextMatches,
+};
+
+/// Sorts the records found by `matches` by how relevant they are to its query, most relevant first
+pub const rankFunction: FunctionDef = FunctionDef {
+	name: "rank",
+	positional_args: &[],
+	object_type: Type::TextMatches,
+	return_type: Type::Records,
+};
+
+/// Keeps the records whose point or rect lies inside a rect, such as `within(rect(0, 0, 10, 5))`
+pub const withinFunction: FunctionDef = FunctionDef {
+	name: "within",
+	positional_args: &[Type::Rect],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
+
+/// Keeps the given number of records closest to a point, nearest first, such as `nearest(point(1, 2), 10)`
+pub const nearestFunction: FunctionDef = FunctionDef {
+	name: "nearest",
+	positional_args: &[Type::Point, Type::IntegerLiteral],
+	object_type: Type::Records,
+	return_type: Type::Records,
+};
@@ -1,7 +1,7 @@
 #![allow(non_upper_case_globals)]
 mod definitions;
 
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::RangeInclusive};
 
 use super::Type;
 
@@ -10,6 +10,7 @@ pub use definitions::*;
 #[derive(Debug, PartialEq, Eq)]
 pub struct FunctionDef {
 	pub name: &'static str,
+	/// Types of the arguments, where the last type repeats if it is one that can, see `Type::repeats`
 	pub positional_args: &'static [Type],
 	/// Type that this function is called on
 	pub object_type: Type,
@@ -17,14 +18,50 @@ pub struct FunctionDef {
 	pub return_type: Type,
 }
 
-pub const FUNCTIONS: &[FunctionDef] = &[createFunction, ensureExistsFunction, deleteFunction];
+impl FunctionDef {
+	/// Number of arguments the function takes
+	pub fn arg_count(&self) -> RangeInclusive<usize> {
+		let count = self.positional_args.len();
+		match self.positional_args.last() {
+			Some(ty) if ty.repeats() => count..=usize::MAX,
+			_ => count..=count,
+		}
+	}
+}
+
+pub const FUNCTIONS: &[FunctionDef] = &[
+	createFunction,
+	ensureExistsFunction,
+	deleteFunction,
+	insertFunction,
+	upsertFunction,
+	getFunction,
+	updateFunction,
+	deleteRecordsFunction,
+	whereFunction,
+	selectFunction,
+	orderByFunction,
+	limitFunction,
+	offsetFunction,
+	groupByFunction,
+	havingFunction,
+	aggregateFunction,
+	joinFunction,
+	leftJoinFunction,
+	rightJoinFunction,
+	fullJoinFunction,
+	crossJoinFunction,
+	semiJoinFunction,
+	antiJoinFunction,
+	unionFunction,
+	unionAllFunction,
+	matchesFunction,
+	rankFunction,
+	withinFunction,
+	nearestFunction,
+];
 
 /// Find function by name
 pub fn find_function(name: &String) -> Option<&'static FunctionDef> {
-	for f in FUNCTIONS.iter() {
-		if f.name == name {
-			return Some(f);
-		}
-	}
-	None
+	FUNCTIONS.iter().find(|f| f.name == name)
 }
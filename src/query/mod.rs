@@ -1,34 +1,73 @@
+mod expr;
 pub mod functions;
 mod types;
 
+use crate::{Point, Rect};
 use functions::FunctionDef;
 
+pub use expr::{
+	AggregateFunction, BinaryOp, Expr, FrameBound, FrameUnits, UnaryOp, Window, WindowFrame,
+	WindowFunction,
+};
 pub use types::Type;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Query {
 	object_name: String,
 	function: Option<FunctionCall>,
+	/// Named queries that the query can read from like objects, from `let name = ...;` statements before it
+	ctes: Vec<Cte>,
 }
 impl Query {
 	pub fn new<S: Into<String>>(object_name: S, function: Option<FunctionCall>) -> Query {
 		Query {
 			object_name: object_name.into(),
 			function,
+			ctes: Vec::new(),
 		}
 	}
+
+	/// Adds a named query that this query can read from, after any added before
+	pub fn with_cte(mut self, cte: Cte) -> Self {
+		self.ctes.push(cte);
+		self
+	}
+
+	pub fn ctes(&self) -> &[Cte] {
+		&self.ctes
+	}
+
+	pub fn object_name(&self) -> &str {
+		&self.object_name
+	}
+
+	pub fn function(&self) -> Option<&FunctionCall> {
+		self.function.as_ref()
+	}
+}
+
+/// A common table expression, a query named with `let name = ...;` that later queries can read from like an object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cte {
+	pub name: String,
+	/// Whether the query can read from itself, with `let recursive`, to walk hierarchies
+	///
+	/// Such a query is a starting query `union` or `union_all` a query reading from the CTE, which is repeated on the
+	/// records the last repetition added until it adds none.
+	pub recursive: bool,
+	pub query: Query,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FunctionCall {
 	function: &'static FunctionDef,
-	args: Vec<Value>,
+	args: Vec<Expr>,
 	chained: Option<Box<FunctionCall>>,
 }
 impl FunctionCall {
 	pub fn new(
 		function: &'static FunctionDef,
-		args: Vec<Value>,
+		args: Vec<Expr>,
 		chained: Option<FunctionCall>,
 	) -> FunctionCall {
 		FunctionCall {
@@ -37,51 +76,34 @@ impl FunctionCall {
 			chained: chained.map(Box::new),
 		}
 	}
+
+	pub fn function(&self) -> &'static FunctionDef {
+		self.function
+	}
+
+	pub fn args(&self) -> &[Expr] {
+		&self.args
+	}
+
+	/// Function called on the result of this one
+	pub fn chained(&self) -> Option<&FunctionCall> {
+		self.chained.as_deref()
+	}
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// A value given to a function
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
+	/// A bare word, such as the name of a column or object
+	Identifier(String),
 	String(String),
+	Bytes(Vec<u8>),
+	Integer(i64),
+	Float(f64),
+	Bool(bool),
+	Null,
+	/// `point(x, y)`
+	Point(Point),
+	/// `rect(x1, y1, x2, y2)`, from the coordinates of any two opposite corners
+	Rect(Rect),
 }
-
-/*
-db
-	.table("users")
-	.where_(gt(col("age"), 18))
-	.and(eq(col("active"), true))
-	.select(&["name", "email"])
-	.order_by("age" desc)
-	.limit(10)
-
-tokens:
-word period word lparen word rparen
-
-AST:
-limit: R
-	sort: R
-		select: R
-			where: R
-				read: R
-					table: T
-						database: D
-
-Parse tree:
-function
--name: limit
--args: [10]
--on:
-	function
-	-name: sort
-	-args: "age", desc
-	-on:
-		table
-
-OR
-
-
-
-limit(sort(select(where(read(table(database))))))
-R     R    R      R     R    T     D
-
-db
-*/
@@ -6,6 +6,59 @@ pub enum Type {
 	Object,
 	/// Data read from a table/index
 	Records,
+	/// Records matched by a full-text query, which can be ordered by how relevant they are to it
+	TextMatches,
 
 	StringLiteral,
+	IntegerLiteral,
+	/// Any single value, such as a primary key
+	Value,
+	/// An expression evaluated against each record, which keeps the record when it is true
+	Condition,
+	/// Expressions evaluated against each record, which can use window functions and be named with `as`
+	Columns,
+	/// Expressions to group records by, which can be named with `as`
+	GroupKeys,
+	/// Expressions to sort records by, in order of priority
+	SortKeys,
+	/// Records grouped by key
+	Groups,
+	/// Expressions evaluated against each group, which can use aggregate functions and be named with `as`
+	Aggregates,
+	/// An expression evaluated against each group, which can use aggregate functions and keeps the group when it is
+	/// true
+	GroupCondition,
+	/// A query used as a value, such as `Users.where(vip)`
+	Query,
+	/// A record literal mapping column names to values, or a list of them where many records are taken
+	RecordLiteral,
+	/// A point on a plane, `point(x, y)`
+	Point,
+	/// An axis aligned rectangle, `rect(x1, y1, x2, y2)`
+	Rect,
+}
+
+impl Type {
+	/// Whether a function whose last argument is of this type takes any number of them
+	pub fn repeats(&self) -> bool {
+		matches!(
+			self,
+			Type::Columns | Type::GroupKeys | Type::SortKeys | Type::Aggregates
+		)
+	}
+
+	/// Whether `found` can be used where this type is expected
+	///
+	/// An object or text matches are read as their records, and records that weren't grouped are one group.
+	pub fn accepts(&self, found: &Type) -> bool {
+		self == found
+			|| matches!(
+				(self, found),
+				(Type::Records, Type::Object | Type::TextMatches)
+					| (
+						Type::Groups,
+						Type::Records | Type::Object | Type::TextMatches
+					)
+			)
+	}
 }
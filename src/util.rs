@@ -4,6 +4,70 @@
 pub fn slice_to_array<const N: usize>(bytes: &[u8]) -> [u8; N] {
 	debug_assert_eq!(N, bytes.len());
 	let mut buf = [0u8; N];
-	buf.copy_from_slice(&bytes);
+	buf.copy_from_slice(bytes);
 	buf
 }
+
+/// Hashes bytes with 64-bit FNV-1a
+///
+/// Unlike `std::hash`, the output is guaranteed to be stable, so it is safe to persist
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+	const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+	const PRIME: u64 = 0x100000001b3;
+	let mut hash = OFFSET_BASIS;
+	for b in bytes {
+		hash ^= *b as u64;
+		hash = hash.wrapping_mul(PRIME);
+	}
+	hash
+}
+
+/// A file under `test_artifacts` for unit tests that need a real file descriptor, deleted when dropped, even if the
+/// test panics
+#[cfg(test)]
+pub struct TestFile(std::path::PathBuf);
+#[cfg(test)]
+impl TestFile {
+	/// Use `test_file!()`, which names the file after the calling test's module and line, so tests never share one
+	pub fn new(name: &str) -> TestFile {
+		let dir = std::path::Path::new("./test_artifacts/unit");
+		std::fs::create_dir_all(dir).unwrap();
+		let path = dir.join(name);
+		let _ = std::fs::remove_file(&path);
+		TestFile(path)
+	}
+}
+#[cfg(test)]
+impl std::ops::Deref for TestFile {
+	type Target = std::path::Path;
+
+	fn deref(&self) -> &std::path::Path {
+		&self.0
+	}
+}
+#[cfg(test)]
+impl AsRef<std::path::Path> for TestFile {
+	fn as_ref(&self) -> &std::path::Path {
+		&self.0
+	}
+}
+#[cfg(test)]
+impl Drop for TestFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+/// A [`TestFile`] unique to the calling test
+#[cfg(test)]
+macro_rules! test_file {
+	() => {
+		$crate::util::TestFile::new(&format!(
+			"{}_{}",
+			module_path!().replace("::", "_"),
+			line!()
+		))
+	};
+}
+#[cfg(test)]
+pub(crate) use test_file;
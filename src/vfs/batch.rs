@@ -0,0 +1,140 @@
+use std::{
+	num::NonZeroUsize,
+	panic::{self, AssertUnwindSafe},
+	sync::{Arc, Mutex, OnceLock, mpsc},
+	thread,
+};
+
+/// Batches at least this large are spread over worker threads
+const MIN_PARALLEL_BATCH: usize = 4;
+/// Upper bound on worker threads
+const MAX_WORKERS: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Worker threads shared by every batch, started on first use and kept for the life of the process
+struct Pool {
+	jobs: mpsc::Sender<Job>,
+	workers: usize,
+}
+
+fn pool() -> &'static Pool {
+	static POOL: OnceLock<Pool> = OnceLock::new();
+	POOL.get_or_init(|| {
+		let workers = thread::available_parallelism()
+			.map_or(1, NonZeroUsize::get)
+			.min(MAX_WORKERS);
+		let (jobs, queue) = mpsc::channel::<Job>();
+		let queue = Arc::new(Mutex::new(queue));
+		// the calling thread works on a batch too, so one less worker is needed
+		let spawned = (1..workers)
+			.filter(|i| {
+				let queue = queue.clone();
+				thread::Builder::new()
+					.name(format!("lildb-io-{i}"))
+					.spawn(move || {
+						loop {
+							let job = queue.lock().unwrap().recv();
+							match job {
+								Ok(job) => job(),
+								Err(_) => return,
+							}
+						}
+					})
+					.is_ok()
+			})
+			.count();
+		Pool {
+			jobs,
+			workers: spawned + 1,
+		}
+	})
+}
+
+/// Runs an operation on every item, spread across a pool of worker threads and the calling thread, returning the
+/// results in the order of the items
+///
+/// Fallback for platforms without a native batched I/O interface: each worker still does one syscall per item, but
+/// the syscalls overlap instead of waiting on each other. Jobs outlive the call if a worker is slow to pick them up, so
+/// items and the operation are owned rather than borrowed.
+pub fn parallel<T, R>(items: Vec<T>, op: impl Fn(T) -> R + Send + Sync + 'static) -> Vec<R>
+where
+	T: Send + 'static,
+	R: Send + 'static,
+{
+	let pool = pool();
+	if items.len() < MIN_PARALLEL_BATCH || pool.workers == 1 {
+		return items.into_iter().map(op).collect();
+	}
+
+	let chunk_size = items.len().div_ceil(pool.workers);
+	let mut items = items.into_iter();
+	let own: Vec<T> = items.by_ref().take(chunk_size).collect();
+	let op = Arc::new(op);
+	let (done, results) = mpsc::channel();
+	let mut sent = 0;
+	while items.len() > 0 {
+		let chunk: Vec<T> = items.by_ref().take(chunk_size).collect();
+		sent += 1;
+		let (op, done, i) = (op.clone(), done.clone(), sent);
+		let job: Job = Box::new(move || {
+			let res =
+				panic::catch_unwind(AssertUnwindSafe(|| chunk.into_iter().map(&*op).collect()));
+			let _ = done.send((i, res));
+		});
+		if let Err(mpsc::SendError(job)) = pool.jobs.send(job) {
+			job();
+		}
+	}
+	drop(done);
+
+	// the calling thread's chunk comes first
+	let own_res = panic::catch_unwind(AssertUnwindSafe(|| own.into_iter().map(&*op).collect()));
+	let mut chunk_results: Vec<Vec<R>> = (0..=sent).map(|_| Vec::new()).collect();
+	let mut panicked = None;
+	for (i, job_res) in [(0, own_res)].into_iter().chain(results.iter().take(sent)) {
+		match job_res {
+			Ok(res) => chunk_results[i] = res,
+			Err(payload) => panicked = Some(payload),
+		}
+	}
+	if let Some(payload) = panicked {
+		panic::resume_unwind(payload);
+	}
+	chunk_results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashSet, io};
+
+	use super::*;
+
+	#[test]
+	fn every_item_once() {
+		let items: Vec<u32> = (0..100).collect();
+		let doubled = parallel(items, |i| i * 2);
+		assert!(doubled.iter().enumerate().all(|(i, &x)| x == 2 * i as u32));
+
+		let res: io::Result<Vec<()>> = parallel((0..100).collect(), |i| match i {
+			50 => Err(io::Error::other("failed")),
+			_ => Ok(()),
+		})
+		.into_iter()
+		.collect();
+		assert!(res.is_err());
+	}
+
+	#[test]
+	fn reuses_workers() {
+		let threads = Arc::new(Mutex::new(HashSet::new()));
+		for _ in 0..20 {
+			let threads = threads.clone();
+			parallel(vec![0u8; 64], move |_| {
+				threads.lock().unwrap().insert(thread::current().id());
+			});
+		}
+		// the pool's workers and this thread, rather than new threads for every batch
+		assert!(threads.lock().unwrap().len() <= pool().workers);
+	}
+}
@@ -0,0 +1,224 @@
+use std::{
+	io,
+	path::Path,
+	sync::{
+		Arc,
+		atomic::{AtomicBool, AtomicU64, Ordering},
+	},
+};
+
+use super::*;
+
+/// Never fail
+const DISARMED: u64 = u64::MAX;
+
+/// Wraps another VFS, failing operations on demand
+///
+/// Faults are scheduled through the shared [`FaultInjector`], and apply to every file opened through this VFS.
+pub struct FaultyVfs<V: Vfs> {
+	inner: V,
+	faults: Arc<FaultInjector>,
+}
+impl<V: Vfs> FaultyVfs<V> {
+	pub fn new(inner: V) -> FaultyVfs<V> {
+		FaultyVfs {
+			inner,
+			faults: Arc::new(FaultInjector::default()),
+		}
+	}
+
+	/// Handle used to schedule faults, which can be kept after the VFS is moved into the database options
+	pub fn faults(&self) -> Arc<FaultInjector> {
+		self.faults.clone()
+	}
+}
+impl<V: Vfs> Vfs for FaultyVfs<V> {
+	fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn VfsFile>> {
+		self.faults.trip(&self.faults.opens)?;
+		let inner = self.inner.open(path, flags)?;
+		Ok(Box::new(FaultyFile {
+			inner,
+			faults: self.faults.clone(),
+		}))
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		self.faults.trip(&self.faults.writes)?;
+		self.inner.remove(path)
+	}
+}
+
+/// Schedules injected faults
+///
+/// Each kind of operation has a countdown: after that many more successful calls, every following call fails until
+/// [`FaultInjector::heal`] is called, or only the first one if [`FaultInjector::fail_once`] was called.
+#[derive(Debug)]
+pub struct FaultInjector {
+	opens: AtomicU64,
+	reads: AtomicU64,
+	writes: AtomicU64,
+	syncs: AtomicU64,
+	once: AtomicBool,
+}
+impl FaultInjector {
+	pub fn fail_opens_after(&self, n: u64) {
+		self.opens.store(n, Ordering::SeqCst);
+	}
+
+	pub fn fail_reads_after(&self, n: u64) {
+		self.reads.store(n, Ordering::SeqCst);
+	}
+
+	pub fn fail_writes_after(&self, n: u64) {
+		self.writes.store(n, Ordering::SeqCst);
+	}
+
+	pub fn fail_syncs_after(&self, n: u64) {
+		self.syncs.store(n, Ordering::SeqCst);
+	}
+
+	/// Heals after the next injected fault, so only a single operation fails
+	pub fn fail_once(&self) {
+		self.once.store(true, Ordering::SeqCst);
+	}
+
+	/// Cancels every scheduled fault
+	pub fn heal(&self) {
+		for counter in [&self.opens, &self.reads, &self.writes, &self.syncs] {
+			counter.store(DISARMED, Ordering::SeqCst);
+		}
+		self.once.store(false, Ordering::SeqCst);
+	}
+
+	/// Counts down one operation, failing if the countdown has run out
+	fn trip(&self, counter: &AtomicU64) -> io::Result<()> {
+		let res = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match n {
+			DISARMED | 0 => None,
+			n => Some(n - 1),
+		});
+		match res {
+			Err(0) => {
+				if self.once.load(Ordering::SeqCst) {
+					self.heal();
+				}
+				Err(io::Error::other("Injected fault"))
+			}
+			_ => Ok(()),
+		}
+	}
+}
+impl Default for FaultInjector {
+	fn default() -> Self {
+		Self {
+			opens: AtomicU64::new(DISARMED),
+			reads: AtomicU64::new(DISARMED),
+			writes: AtomicU64::new(DISARMED),
+			syncs: AtomicU64::new(DISARMED),
+			once: AtomicBool::new(false),
+		}
+	}
+}
+
+struct FaultyFile {
+	inner: Box<dyn VfsFile>,
+	faults: Arc<FaultInjector>,
+}
+impl VfsFile for FaultyFile {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		self.faults.trip(&self.faults.reads)?;
+		self.inner.read_at(buf, offset)
+	}
+
+	fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+		self.faults.trip(&self.faults.writes)?;
+		self.inner.write_at(buf, offset)
+	}
+
+	fn sync(&self) -> io::Result<()> {
+		self.faults.trip(&self.faults.syncs)?;
+		self.inner.sync()
+	}
+
+	fn len(&self) -> io::Result<u64> {
+		self.inner.len()
+	}
+
+	fn set_len(&self, len: u64) -> io::Result<()> {
+		self.faults.trip(&self.faults.writes)?;
+		self.inner.set_len(len)
+	}
+
+	fn lock(&self, kind: LockKind) -> io::Result<()> {
+		self.inner.lock(kind)
+	}
+
+	fn try_lock(&self, kind: LockKind) -> io::Result<bool> {
+		self.inner.try_lock(kind)
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		self.inner.unlock()
+	}
+
+	fn read_batch(&self, ops: &mut [ReadOp]) -> io::Result<()> {
+		for _ in 0..ops.len() {
+			self.faults.trip(&self.faults.reads)?;
+		}
+		self.inner.read_batch(ops)
+	}
+
+	fn write_batch(&self, ops: &[WriteOp]) -> io::Result<()> {
+		for _ in 0..ops.len() {
+			self.faults.trip(&self.faults.writes)?;
+		}
+		self.inner.write_batch(ops)
+	}
+
+	fn punch_hole(&self, offset: u64, len: u64) -> io::Result<()> {
+		self.faults.trip(&self.faults.writes)?;
+		self.inner.punch_hole(offset, len)
+	}
+
+	fn id(&self) -> io::Result<Option<FileId>> {
+		self.inner.id()
+	}
+
+	unsafe fn map(&self, len: u64) -> io::Result<Option<Mmap>> {
+		// SAFETY: passed on to the caller
+		unsafe { self.inner.map(len) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vfs::MemoryVfs;
+
+	#[test]
+	fn countdown() {
+		let vfs = FaultyVfs::new(MemoryVfs::new());
+		let faults = vfs.faults();
+		let f = vfs
+			.open(Path::new("a"), OpenFlags::new().create(true))
+			.unwrap();
+
+		faults.fail_writes_after(2);
+		f.write_at(b"a", 0).unwrap();
+		f.write_at(b"b", 1).unwrap();
+		assert!(f.write_at(b"c", 2).is_err());
+		assert!(f.write_at(b"c", 2).is_err());
+
+		let mut buf = [0u8; 2];
+		f.read_at(&mut buf, 0).unwrap();
+		assert_eq!(&buf, b"ab");
+
+		faults.heal();
+		f.write_at(b"c", 2).unwrap();
+		assert_eq!(f.len().unwrap(), 3);
+
+		faults.fail_reads_after(0);
+		faults.fail_once();
+		assert!(f.read_at(&mut buf, 0).is_err());
+		f.read_at(&mut buf, 0).unwrap();
+	}
+}
@@ -0,0 +1,254 @@
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, Condvar, Mutex, RwLock},
+};
+
+use super::*;
+
+/// A file system that only exists in memory
+///
+/// Clones share the same files, which live until the last clone and every open handle is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryVfs {
+	files: Arc<Mutex<HashMap<PathBuf, Arc<MemoryFileData>>>>,
+}
+impl MemoryVfs {
+	pub fn new() -> MemoryVfs {
+		MemoryVfs::default()
+	}
+
+	/// Checks if a file exists
+	pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+		self.files.lock().unwrap().contains_key(path.as_ref())
+	}
+
+	/// Copies out the contents of a file
+	pub fn contents<P: AsRef<Path>>(&self, path: P) -> Option<Vec<u8>> {
+		let files = self.files.lock().unwrap();
+		let file = files.get(path.as_ref())?;
+		Some(file.bytes.read().unwrap().clone())
+	}
+}
+impl Vfs for MemoryVfs {
+	fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn VfsFile>> {
+		let mut files = self.files.lock().unwrap();
+		let data = match files.get(path) {
+			Some(_) if flags.create_new => {
+				return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+			}
+			Some(data) => data.clone(),
+			None if (flags.create || flags.create_new) && !flags.read_only => {
+				let data = Arc::new(MemoryFileData::default());
+				files.insert(path.to_path_buf(), data.clone());
+				data
+			}
+			None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+		};
+		Ok(Box::new(MemoryFile {
+			data,
+			held: Mutex::new(None),
+			read_only: flags.read_only,
+		}))
+	}
+
+	/// Open handles keep the contents of a removed file until they are dropped
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		match self.files.lock().unwrap().remove(path) {
+			Some(_) => Ok(()),
+			None => Err(io::Error::from(io::ErrorKind::NotFound)),
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct MemoryFileData {
+	bytes: RwLock<Vec<u8>>,
+	lock: Mutex<LockState>,
+	lock_released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct LockState {
+	shared: usize,
+	exclusive: bool,
+}
+
+struct MemoryFile {
+	data: Arc<MemoryFileData>,
+	/// Lock held by this handle
+	held: Mutex<Option<LockKind>>,
+	read_only: bool,
+}
+impl MemoryFile {
+	fn check_writable(&self) -> io::Result<()> {
+		if self.read_only {
+			return Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"File was opened read only",
+			));
+		}
+		Ok(())
+	}
+
+	/// Takes the lock if it is free
+	fn acquire(&self, state: &mut LockState, kind: LockKind) -> bool {
+		let available = match kind {
+			LockKind::Shared => !state.exclusive,
+			LockKind::Exclusive => !state.exclusive && state.shared == 0,
+		};
+		if available {
+			match kind {
+				LockKind::Shared => state.shared += 1,
+				LockKind::Exclusive => state.exclusive = true,
+			}
+		}
+		available
+	}
+}
+impl VfsFile for MemoryFile {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		let bytes = self.data.bytes.read().unwrap();
+		let start = offset as usize;
+		let Some(src) = bytes.get(start..(start + buf.len())) else {
+			return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+		};
+		buf.copy_from_slice(src);
+		Ok(())
+	}
+
+	fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+		self.check_writable()?;
+		let mut bytes = self.data.bytes.write().unwrap();
+		let start = offset as usize;
+		if bytes.len() < start + buf.len() {
+			bytes.resize(start + buf.len(), 0);
+		}
+		bytes[start..(start + buf.len())].copy_from_slice(buf);
+		Ok(())
+	}
+
+	fn sync(&self) -> io::Result<()> {
+		Ok(())
+	}
+
+	fn len(&self) -> io::Result<u64> {
+		Ok(self.data.bytes.read().unwrap().len() as u64)
+	}
+
+	fn set_len(&self, len: u64) -> io::Result<()> {
+		self.check_writable()?;
+		self.data.bytes.write().unwrap().resize(len as usize, 0);
+		Ok(())
+	}
+
+	fn id(&self) -> io::Result<Option<FileId>> {
+		// handles to the same file share its data
+		Ok(Some(FileId(0, Arc::as_ptr(&self.data) as u64)))
+	}
+
+	fn lock(&self, kind: LockKind) -> io::Result<()> {
+		let mut held = self.held.lock().unwrap();
+		if held.is_some() {
+			return Err(io::Error::other("File is already locked by this handle"));
+		}
+
+		let mut state = self.data.lock.lock().unwrap();
+		while !self.acquire(&mut state, kind) {
+			state = self.data.lock_released.wait(state).unwrap();
+		}
+		*held = Some(kind);
+		Ok(())
+	}
+
+	fn try_lock(&self, kind: LockKind) -> io::Result<bool> {
+		let mut held = self.held.lock().unwrap();
+		if held.is_some() {
+			return Err(io::Error::other("File is already locked by this handle"));
+		}
+
+		let mut state = self.data.lock.lock().unwrap();
+		if !self.acquire(&mut state, kind) {
+			return Ok(false);
+		}
+		*held = Some(kind);
+		Ok(true)
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		let mut held = self.held.lock().unwrap();
+		let mut state = self.data.lock.lock().unwrap();
+		match held.take() {
+			Some(LockKind::Shared) => state.shared -= 1,
+			Some(LockKind::Exclusive) => state.exclusive = false,
+			None => return Ok(()),
+		}
+		self.data.lock_released.notify_all();
+		Ok(())
+	}
+}
+impl Drop for MemoryFile {
+	fn drop(&mut self) {
+		let _ = self.unlock();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{thread, time::Duration};
+
+	use super::*;
+
+	#[test]
+	fn read_write() {
+		let vfs = MemoryVfs::new();
+		let path = Path::new("a.ldb");
+		assert!(vfs.open(path, OpenFlags::default()).is_err());
+
+		let f = vfs.open(path, OpenFlags::new().create(true)).unwrap();
+		f.write_at(b"world", 6).unwrap();
+		f.write_at(b"hello ", 0).unwrap();
+		assert_eq!(f.len().unwrap(), 11);
+
+		// other handles see the same contents
+		let g = vfs.open(path, OpenFlags::default()).unwrap();
+		let mut buf = [0u8; 5];
+		g.read_at(&mut buf, 6).unwrap();
+		assert_eq!(&buf, b"world");
+		assert!(g.read_at(&mut buf, 8).is_err());
+
+		g.set_len(5).unwrap();
+		assert_eq!(vfs.contents(path).unwrap(), b"hello");
+
+		// removing leaves open handles working
+		vfs.remove(path).unwrap();
+		assert!(!vfs.exists(path));
+		assert!(vfs.remove(path).is_err());
+		f.read_at(&mut buf, 0).unwrap();
+		assert_eq!(&buf, b"hello");
+	}
+
+	#[test]
+	fn exclusive_lock_blocks() {
+		let vfs = MemoryVfs::new();
+		let path = Path::new("a.ldb");
+		let f = vfs.open(path, OpenFlags::new().create(true)).unwrap();
+		let g = vfs.open(path, OpenFlags::default()).unwrap();
+
+		f.lock(LockKind::Shared).unwrap();
+		g.lock(LockKind::Shared).unwrap();
+		g.unlock().unwrap();
+
+		let waiter = thread::spawn(move || {
+			g.lock(LockKind::Exclusive).unwrap();
+			g.write_at(b"x", 0).unwrap();
+			g.unlock().unwrap();
+		});
+		thread::sleep(Duration::from_millis(50));
+		assert_eq!(f.len().unwrap(), 0);
+		f.unlock().unwrap();
+		waiter.join().unwrap();
+		assert_eq!(f.len().unwrap(), 1);
+	}
+}
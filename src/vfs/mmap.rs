@@ -0,0 +1,39 @@
+use std::io;
+
+/// A read-only, shared memory mapping of the start of a file
+///
+/// Writes made through the file are visible through the mapping. The mapping does not follow the file's length, so
+/// callers have to remap once they need to read past [`Mmap::len`], or once the file shrinks.
+pub struct Mmap {
+	map: memmap2::Mmap,
+}
+impl Mmap {
+	/// Maps the first `len` bytes of a file, `len` must be greater than 0
+	///
+	/// # Safety
+	///
+	/// For as long as the mapping lives, the file must not be truncated below `len`, which makes reading the mapping
+	/// fault, and must not be written to (by this process or any other) while a slice from [`Mmap::as_slice`] is
+	/// borrowed, which would change memory behind a shared reference.
+	#[cfg(unix)]
+	pub unsafe fn map<F: std::os::fd::AsRawFd>(f: &F, len: usize) -> io::Result<Mmap> {
+		// SAFETY: passed on to the caller
+		let map = unsafe { memmap2::MmapOptions::new().len(len).map(f.as_raw_fd()) }?;
+		Ok(Mmap { map })
+	}
+
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.map.len()
+	}
+
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+
+	#[inline]
+	pub fn as_slice(&self) -> &[u8] {
+		&self.map
+	}
+}
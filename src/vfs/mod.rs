@@ -0,0 +1,159 @@
+//!
+//! Virtual file system abstraction, so the database can be stored on something other than the OS's file system.
+//!
+//! A `Vfs` opens `VfsFile`s, which the database reads and writes at byte offsets. [`OsVfs`] is the real file system,
+//! [`MemoryVfs`] keeps files in memory, and [`FaultyVfs`] wraps another VFS to inject errors.
+//!
+mod batch;
+mod faulty;
+mod memory;
+mod mmap;
+mod os;
+#[cfg(target_os = "linux")]
+mod uring;
+
+use std::{io, path::Path};
+
+pub use faulty::{FaultInjector, FaultyVfs};
+pub use memory::MemoryVfs;
+pub use mmap::Mmap;
+pub use os::OsVfs;
+
+/// A file system
+pub trait Vfs: Send + Sync {
+	/// Opens the file at a path for reading and writing
+	fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn VfsFile>>;
+
+	/// Deletes the file at a path, such as a temporary file the database is done with
+	fn remove(&self, path: &Path) -> io::Result<()>;
+}
+
+/// An open file
+///
+/// Every method takes `&self`, implementations are expected to synchronize internally.
+pub trait VfsFile: Send + Sync {
+	/// Reads exactly enough bytes to fill the buffer, starting at an offset
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+	/// Writes the whole buffer starting at an offset, growing the file if needed
+	fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+
+	/// Makes sure every write so far is durable
+	fn sync(&self) -> io::Result<()>;
+
+	/// Length of the file, in bytes
+	fn len(&self) -> io::Result<u64>;
+
+	fn is_empty(&self) -> io::Result<bool> {
+		Ok(self.len()? == 0)
+	}
+
+	/// Truncates or zero extends the file
+	fn set_len(&self, len: u64) -> io::Result<()>;
+
+	/// Blocks until the lock is acquired
+	fn lock(&self, kind: LockKind) -> io::Result<()>;
+
+	/// Acquires the lock if it is free, returning `false` instead of blocking if it isn't
+	fn try_lock(&self, kind: LockKind) -> io::Result<bool>;
+
+	/// Releases the lock held by this handle
+	fn unlock(&self) -> io::Result<()>;
+
+	/// Performs many reads at once, filling every buffer
+	///
+	/// Implementations may run the reads concurrently and in any order.
+	fn read_batch(&self, ops: &mut [ReadOp]) -> io::Result<()> {
+		for op in ops {
+			self.read_at(op.buf, op.offset)?;
+		}
+		Ok(())
+	}
+
+	/// Performs many writes at once, writing every buffer in full
+	///
+	/// Implementations may run the writes concurrently and in any order, so they should not overlap.
+	fn write_batch(&self, ops: &[WriteOp]) -> io::Result<()> {
+		for op in ops {
+			self.write_at(op.buf, op.offset)?;
+		}
+		Ok(())
+	}
+
+	/// Frees the storage behind a range of the file, which must only hold zeroes, without changing the file's length
+	///
+	/// Only a hint, so the default does nothing.
+	fn punch_hole(&self, _offset: u64, _len: u64) -> io::Result<()> {
+		Ok(())
+	}
+
+	/// Identifies the file behind this handle, the same for every handle to that file, `None` if it can't be told
+	///
+	/// Lets a database tell that two paths lead to the same file, so the default never can.
+	fn id(&self) -> io::Result<Option<FileId>> {
+		Ok(None)
+	}
+
+	/// Maps the first `len` bytes of the file into memory, `None` if this file can't be mapped
+	///
+	/// # Safety
+	///
+	/// The caller must uphold the contract of [`Mmap::map`] for as long as the mapping lives.
+	unsafe fn map(&self, _len: u64) -> io::Result<Option<Mmap>> {
+		Ok(None)
+	}
+}
+
+/// Identity of a file, such as its device and inode numbers, see [`VfsFile::id`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(pub u64, pub u64);
+
+/// One read of a batch, see [`VfsFile::read_batch`]
+pub struct ReadOp<'a> {
+	pub offset: u64,
+	pub buf: &'a mut [u8],
+}
+
+/// One write of a batch, see [`VfsFile::write_batch`]
+pub struct WriteOp<'a> {
+	pub offset: u64,
+	pub buf: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+	/// Any number of handles may hold a shared lock at once, as long as none hold an exclusive one
+	Shared,
+	Exclusive,
+}
+
+/// How a file should be opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpenFlags {
+	/// Create the file if it does not exist
+	pub create: bool,
+	/// Fail if the file already exists
+	pub create_new: bool,
+	/// Open the file without write access, writes through the handle fail
+	pub read_only: bool,
+}
+impl OpenFlags {
+	pub fn new() -> OpenFlags {
+		OpenFlags::default()
+	}
+
+	pub fn create(mut self, create: bool) -> Self {
+		self.create = create;
+		self
+	}
+
+	pub fn create_new(mut self, create_new: bool) -> Self {
+		self.create_new = create_new;
+		self
+	}
+
+	pub fn read_only(mut self, read_only: bool) -> Self {
+		self.read_only = read_only;
+		self
+	}
+}
@@ -0,0 +1,353 @@
+use std::{
+	fs::{File, OpenOptions, TryLockError},
+	io,
+	path::Path,
+	sync::Arc,
+};
+
+#[cfg(target_os = "linux")]
+use super::uring::{Op, Uring};
+use super::*;
+
+/// The operating system's file system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsVfs;
+impl Vfs for OsVfs {
+	fn open(&self, path: &Path, flags: OpenFlags) -> io::Result<Box<dyn VfsFile>> {
+		let f = OpenOptions::new()
+			.read(true)
+			.write(!flags.read_only)
+			.create(flags.create && !flags.read_only)
+			.create_new(flags.create_new)
+			.open(path)?;
+		Ok(Box::new(OsFile {
+			f: Arc::new(f),
+			#[cfg(target_os = "linux")]
+			ring: std::sync::OnceLock::new(),
+		}))
+	}
+
+	fn remove(&self, path: &Path) -> io::Result<()> {
+		std::fs::remove_file(path)
+	}
+}
+
+struct OsFile {
+	/// Shared with the jobs of batches running on the thread pool
+	f: Arc<File>,
+	/// Set up on the first batch, `None` if io_uring isn't available, or once submitting to it failed
+	#[cfg(target_os = "linux")]
+	ring: std::sync::OnceLock<std::sync::Mutex<Option<Uring>>>,
+}
+#[cfg(target_os = "linux")]
+impl OsFile {
+	/// Submits a batch through io_uring, `None` if it isn't available
+	///
+	/// Transfers that come back short are finished with regular syscalls. If submitting fails, the ring is dropped and
+	/// later batches run on the thread pool instead.
+	fn submit(&self, ops: &[Op]) -> Option<io::Result<()>> {
+		use std::os::fd::AsRawFd;
+
+		let mut ring = self
+			.ring
+			.get_or_init(|| std::sync::Mutex::new(Uring::new().ok()))
+			.lock()
+			.unwrap();
+		debug_assert!(ops.iter().all(|op| op.fd == self.f.as_raw_fd()));
+		// SAFETY: every op points into a buffer borrowed by the caller for the whole call, and the ring is dropped if
+		// this fails
+		let results = match unsafe { ring.as_mut()?.submit(ops) } {
+			Ok(results) => results,
+			Err(e) => {
+				*ring = None;
+				return Some(Err(e));
+			}
+		};
+		drop(ring);
+		for (op, res) in ops.iter().zip(results) {
+			let done = match res {
+				Ok(done) => done,
+				Err(e) => return Some(Err(e)),
+			};
+			if done == op.len as usize {
+				continue;
+			}
+			let (rest, offset) = (op.len as usize - done, op.offset + done as u64);
+			// SAFETY: same buffer as the op
+			let res = unsafe {
+				if op.write {
+					write_all_at(
+						&self.f,
+						std::slice::from_raw_parts(op.buf.add(done), rest),
+						offset,
+					)
+				} else {
+					read_exact_at(
+						&self.f,
+						std::slice::from_raw_parts_mut(op.buf.add(done), rest),
+						offset,
+					)
+				}
+			};
+			if let Err(e) = res {
+				return Some(Err(e));
+			}
+		}
+		Some(Ok(()))
+	}
+}
+impl VfsFile for OsFile {
+	fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+		read_exact_at(&self.f, buf, offset)
+	}
+
+	fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+		write_all_at(&self.f, buf, offset)
+	}
+
+	fn sync(&self) -> io::Result<()> {
+		self.f.sync_data()
+	}
+
+	fn len(&self) -> io::Result<u64> {
+		Ok(self.f.metadata()?.len())
+	}
+
+	fn set_len(&self, len: u64) -> io::Result<()> {
+		self.f.set_len(len)
+	}
+
+	fn lock(&self, kind: LockKind) -> io::Result<()> {
+		match kind {
+			LockKind::Shared => self.f.lock_shared(),
+			LockKind::Exclusive => self.f.lock(),
+		}
+	}
+
+	fn try_lock(&self, kind: LockKind) -> io::Result<bool> {
+		let res = match kind {
+			LockKind::Shared => self.f.try_lock_shared(),
+			LockKind::Exclusive => self.f.try_lock(),
+		};
+		match res {
+			Ok(()) => Ok(true),
+			Err(TryLockError::WouldBlock) => Ok(false),
+			Err(TryLockError::Error(e)) => Err(e),
+		}
+	}
+
+	fn unlock(&self) -> io::Result<()> {
+		self.f.unlock()
+	}
+
+	fn read_batch(&self, ops: &mut [ReadOp]) -> io::Result<()> {
+		#[cfg(target_os = "linux")]
+		{
+			use std::os::fd::AsRawFd;
+
+			let ring_ops: Vec<Op> = ops
+				.iter_mut()
+				.map(|op| Op {
+					write: false,
+					fd: self.f.as_raw_fd(),
+					offset: op.offset,
+					buf: op.buf.as_mut_ptr(),
+					len: op.buf.len() as u32,
+				})
+				.collect();
+			if let Some(res) = self.submit(&ring_ops) {
+				return res;
+			}
+		}
+		// the pool's jobs can't borrow, so they read into buffers of their own
+		let f = self.f.clone();
+		let reads: Vec<(u64, usize)> = ops.iter().map(|op| (op.offset, op.buf.len())).collect();
+		let results = batch::parallel(reads, move |(offset, len)| {
+			let mut buf = vec![0u8; len];
+			read_exact_at(&f, &mut buf, offset).map(|_| buf)
+		});
+		for (op, res) in ops.iter_mut().zip(results) {
+			op.buf.copy_from_slice(&res?);
+		}
+		Ok(())
+	}
+
+	fn write_batch(&self, ops: &[WriteOp]) -> io::Result<()> {
+		#[cfg(target_os = "linux")]
+		{
+			use std::os::fd::AsRawFd;
+
+			let ring_ops: Vec<Op> = ops
+				.iter()
+				.map(|op| Op {
+					write: true,
+					fd: self.f.as_raw_fd(),
+					offset: op.offset,
+					// only ever read from, for writes
+					buf: op.buf.as_ptr() as *mut u8,
+					len: op.buf.len() as u32,
+				})
+				.collect();
+			if let Some(res) = self.submit(&ring_ops) {
+				return res;
+			}
+		}
+		let f = self.f.clone();
+		let writes: Vec<(u64, Vec<u8>)> =
+			ops.iter().map(|op| (op.offset, op.buf.to_vec())).collect();
+		batch::parallel(writes, move |(offset, buf)| write_all_at(&f, &buf, offset))
+			.into_iter()
+			.collect()
+	}
+
+	#[cfg(target_os = "linux")]
+	fn punch_hole(&self, offset: u64, len: u64) -> io::Result<()> {
+		use std::os::fd::AsRawFd;
+
+		// SAFETY: only the file's own storage is affected
+		let res = unsafe {
+			libc::fallocate64(
+				self.f.as_raw_fd(),
+				libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+				offset as libc::off64_t,
+				len as libc::off64_t,
+			)
+		};
+		if res != 0 {
+			let e = io::Error::last_os_error();
+			// not every file system can punch holes, and it's only a hint
+			if e.kind() != io::ErrorKind::Unsupported {
+				return Err(e);
+			}
+		}
+		Ok(())
+	}
+
+	#[cfg(unix)]
+	fn id(&self) -> io::Result<Option<FileId>> {
+		use std::os::unix::fs::MetadataExt;
+
+		let metadata = self.f.metadata()?;
+		Ok(Some(FileId(metadata.dev(), metadata.ino())))
+	}
+
+	#[cfg(unix)]
+	unsafe fn map(&self, len: u64) -> io::Result<Option<Mmap>> {
+		// SAFETY: passed on to the caller
+		match unsafe { Mmap::map(&*self.f, len as usize) } {
+			Ok(map) => Ok(Some(map)),
+			Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+#[cfg(unix)]
+fn read_exact_at(f: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+	std::os::unix::fs::FileExt::read_exact_at(f, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_all_at(f: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+	std::os::unix::fs::FileExt::write_all_at(f, buf, offset)
+}
+
+/// Reads until `buf` is full, as `seek_read` may read less than asked for
+#[cfg(windows)]
+fn read_exact_at(f: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+	use std::os::windows::fs::FileExt;
+
+	while !buf.is_empty() {
+		match f.seek_read(buf, offset) {
+			Ok(0) => {
+				return Err(io::Error::new(
+					io::ErrorKind::UnexpectedEof,
+					"failed to fill whole buffer",
+				));
+			}
+			Ok(n) => {
+				buf = &mut buf[n..];
+				offset += n as u64;
+			}
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(())
+}
+
+/// Writes all of `buf`, as `seek_write` may write less than asked for
+#[cfg(windows)]
+fn write_all_at(f: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+	use std::os::windows::fs::FileExt;
+
+	while !buf.is_empty() {
+		match f.seek_write(buf, offset) {
+			Ok(0) => {
+				return Err(io::Error::new(
+					io::ErrorKind::WriteZero,
+					"failed to write whole buffer",
+				));
+			}
+			Ok(n) => {
+				buf = &buf[n..];
+				offset += n as u64;
+			}
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn batches_without_uring() {
+		use crate::vfs::uring::FAIL_SETUP;
+
+		FAIL_SETUP.set(true);
+		let path = crate::util::test_file!();
+		let f = OsFile {
+			f: Arc::new(
+				OpenOptions::new()
+					.read(true)
+					.write(true)
+					.create(true)
+					.truncate(true)
+					.open(&path)
+					.unwrap(),
+			),
+			ring: std::sync::OnceLock::new(),
+		};
+
+		let written: Vec<Vec<u8>> = (0..32u8).map(|i| vec![i; 16]).collect();
+		let writes: Vec<WriteOp> = written
+			.iter()
+			.enumerate()
+			.map(|(i, buf)| WriteOp {
+				offset: i as u64 * 16,
+				buf,
+			})
+			.collect();
+		f.write_batch(&writes).unwrap();
+		let mut read = vec![vec![0u8; 16]; 32];
+		let mut reads: Vec<ReadOp> = read
+			.iter_mut()
+			.enumerate()
+			.map(|(i, buf)| ReadOp {
+				offset: i as u64 * 16,
+				buf,
+			})
+			.collect();
+		f.read_batch(&mut reads).unwrap();
+		assert_eq!(read, written);
+
+		// the thread pool ran both batches
+		assert!(f.ring.get().unwrap().lock().unwrap().is_none());
+		FAIL_SETUP.set(false);
+	}
+}
@@ -0,0 +1,261 @@
+//!
+//! Submission of positioned reads and writes through io_uring, in batches.
+//!
+use std::{io, os::fd::RawFd};
+
+use io_uring::{EnterFlags, IoUring, opcode, types::Fd};
+
+/// Submission queue size, batches larger than this are split up
+const QUEUE_DEPTH: u32 = 64;
+
+/// One positioned read or write, pointing at a caller owned buffer
+pub struct Op {
+	pub write: bool,
+	pub fd: RawFd,
+	pub offset: u64,
+	pub buf: *mut u8,
+	pub len: u32,
+}
+
+#[cfg(test)]
+thread_local! {
+	/// Makes setting up a ring fail on this thread, to test what happens without io_uring
+	pub static FAIL_SETUP: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+pub struct Uring {
+	ring: IoUring,
+	/// Fails the `io_uring_enter` call after this many, once
+	#[cfg(test)]
+	fail_enter_after: Option<u32>,
+}
+impl Uring {
+	/// Sets up a ring, failing if the kernel does not support (or allow) io_uring
+	pub fn new() -> io::Result<Uring> {
+		#[cfg(test)]
+		if FAIL_SETUP.get() {
+			return Err(io::Error::from(io::ErrorKind::Unsupported));
+		}
+		Ok(Uring {
+			ring: IoUring::new(QUEUE_DEPTH)?,
+			#[cfg(test)]
+			fail_enter_after: None,
+		})
+	}
+
+	/// Submits queued entries and waits for at least one completion, returning how many entries were submitted
+	fn enter(&mut self, to_submit: u32) -> io::Result<u32> {
+		#[cfg(test)]
+		if let Some(n) = self.fail_enter_after.as_mut() {
+			if *n == 0 {
+				self.fail_enter_after = None;
+				return Err(io::Error::other("Injected io_uring_enter failure"));
+			}
+			*n -= 1;
+		}
+		// SAFETY: no argument is passed, the caller vouches for the buffers of queued entries
+		let submitted = unsafe {
+			self.ring.submitter().enter::<libc::sigset_t>(
+				to_submit,
+				1,
+				EnterFlags::GETEVENTS.bits(),
+				None,
+			)
+		}?;
+		Ok(submitted as u32)
+	}
+
+	/// Submits every operation and waits for them to complete, returning the result of each (bytes transferred)
+	///
+	/// If submitting fails, operations that weren't submitted yet are left queued, and the error is only returned once
+	/// every operation that was submitted has completed, so none of them touch their buffer after this returns.
+	///
+	/// # Safety
+	///
+	/// Every buffer must be valid for `len` bytes until this returns, and not be aliased by another op. After an error
+	/// the ring must be dropped instead of used again, as it would submit the operations left queued.
+	pub unsafe fn submit(&mut self, ops: &[Op]) -> io::Result<Vec<io::Result<usize>>> {
+		let mut results = Vec::with_capacity(ops.len());
+		for chunk in ops.chunks(self.ring.params().sq_entries() as usize) {
+			results.resize_with(results.len() + chunk.len(), || Ok(0));
+			let base = results.len() - chunk.len();
+
+			// queue the chunk
+			let mut sq = self.ring.submission();
+			for (i, op) in chunk.iter().enumerate() {
+				let entry = if op.write {
+					opcode::Write::new(Fd(op.fd), op.buf, op.len)
+						.offset(op.offset)
+						.build()
+				} else {
+					opcode::Read::new(Fd(op.fd), op.buf, op.len)
+						.offset(op.offset)
+						.build()
+				};
+				// SAFETY: the caller vouches for the buffer, and chunks fit in the queue, which starts out empty
+				unsafe { sq.push(&entry.user_data((base + i) as u64)) }
+					.map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+			}
+			drop(sq);
+
+			// submit and wait for every completion
+			let n = chunk.len() as u32;
+			let mut submitted = 0;
+			let mut completed = 0;
+			let mut error = None;
+			while completed < n {
+				// after an error nothing more is submitted, but whatever is in flight still has to be waited for
+				let to_submit = match error {
+					None => n - submitted,
+					Some(_) if completed == submitted => break,
+					Some(_) => 0,
+				};
+				match self.enter(to_submit) {
+					Ok(n) => submitted += n,
+					Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+					// for a ring we own, errors while only waiting are transient, so keep waiting
+					Err(e) => {
+						if error.is_none() {
+							error = Some(e);
+						}
+					}
+				}
+
+				for cqe in self.ring.completion() {
+					results[cqe.user_data() as usize] = if cqe.result() < 0 {
+						Err(io::Error::from_raw_os_error(-cqe.result()))
+					} else {
+						Ok(cqe.result() as usize)
+					};
+					completed += 1;
+				}
+			}
+			if let Some(e) = error {
+				return Err(e);
+			}
+		}
+		Ok(results)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::fd::AsRawFd;
+
+	use super::*;
+
+	/// Sets up a ring, `None` where io_uring isn't available, as containers and CI runners often disable it
+	fn ring() -> Option<Uring> {
+		match Uring::new() {
+			Ok(ring) => Some(ring),
+			Err(e) => {
+				eprintln!("skipping, as io_uring isn't available: {e}");
+				None
+			}
+		}
+	}
+
+	#[test]
+	fn read_write() {
+		let Some(mut ring) = ring() else {
+			return;
+		};
+		let path = crate::util::test_file!();
+		let f = std::fs::File::options()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(&path)
+			.unwrap();
+
+		let mut bufs: Vec<Vec<u8>> = (0..200u8).map(|i| vec![i; 16]).collect();
+		let ops: Vec<Op> = bufs
+			.iter_mut()
+			.enumerate()
+			.map(|(i, buf)| Op {
+				write: true,
+				fd: f.as_raw_fd(),
+				offset: i as u64 * 16,
+				buf: buf.as_mut_ptr(),
+				len: 16,
+			})
+			.collect();
+		let results = unsafe { ring.submit(&ops) }.unwrap();
+		assert!(results.into_iter().all(|r| r.unwrap() == 16));
+
+		let mut read = vec![0u8; 32];
+		let op = Op {
+			write: false,
+			fd: f.as_raw_fd(),
+			offset: 16 * 198,
+			buf: read.as_mut_ptr(),
+			len: 48,
+		};
+		let results = unsafe { ring.submit(&[op]) }.unwrap();
+		// short read at the end of the file
+		assert_eq!(results[0].as_ref().unwrap(), &32);
+		assert_eq!(read[..16], [198; 16]);
+		assert_eq!(read[16..], [199; 16]);
+	}
+
+	#[test]
+	fn failed_enter() {
+		let Some(mut ring) = ring() else {
+			return;
+		};
+		let path = crate::util::test_file!();
+		let f = std::fs::File::options()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(&path)
+			.unwrap();
+		let write = |buf: &mut [u8], offset| Op {
+			write: true,
+			fd: f.as_raw_fd(),
+			offset,
+			buf: buf.as_mut_ptr(),
+			len: buf.len() as u32,
+		};
+
+		// nothing was submitted, and the write left queued is never submitted once the ring is dropped
+		ring.fail_enter_after = Some(0);
+		let mut dropped = vec![1u8; 16];
+		assert!(unsafe { ring.submit(&[write(&mut dropped, 0)]) }.is_err());
+		drop(ring);
+		drop(dropped);
+
+		// the pipe read can't complete until something is written to the pipe, so it is still in flight when
+		// waiting fails, and has to finish before the error is returned
+		let mut ring = Uring::new().unwrap();
+		let (reader, mut writer) = std::io::pipe().unwrap();
+		let mut written = vec![2u8; 16];
+		let mut piped = vec![0u8; 4];
+		let ops = [
+			write(&mut written, 16),
+			Op {
+				write: false,
+				fd: reader.as_raw_fd(),
+				offset: u64::MAX,
+				buf: piped.as_mut_ptr(),
+				len: 4,
+			},
+		];
+		let feeder = std::thread::spawn(move || {
+			std::thread::sleep(std::time::Duration::from_millis(50));
+			std::io::Write::write_all(&mut writer, b"pipe").unwrap();
+		});
+		ring.fail_enter_after = Some(1);
+		assert!(unsafe { ring.submit(&ops) }.is_err());
+		assert_eq!(piped, b"pipe");
+		feeder.join().unwrap();
+		drop(ring);
+
+		let mut read = vec![0u8; 32];
+		std::os::unix::fs::FileExt::read_exact_at(&f, &mut read, 0).unwrap();
+		assert_eq!(read[..16], [0; 16]);
+		assert_eq!(read[16..], [2; 16]);
+	}
+}
@@ -1,31 +1,42 @@
 #[cfg(test)]
 mod tests;
 
-use std::{fmt, iter::Peekable, ops::Range, str::Chars};
+use std::{fmt, iter::Peekable, str::Chars};
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct SourceLocation {
-	pub line: u32,
-	pub start_col: u32,
-	pub end_col: u32,
-}
-impl SourceLocation {
-	pub const fn new(line: u32, cols: Range<u32>) -> SourceLocation {
-		SourceLocation {
-			line,
-			start_col: cols.start,
-			end_col: cols.end,
-		}
-	}
-}
+use lildb::{Error, Result};
 
-#[derive(Debug, PartialEq, Eq)]
+pub use lildb::SourceLocation;
+
+#[derive(Debug, PartialEq)]
 pub struct Token {
 	pub ty: TokenType,
 	pub loc: SourceLocation,
+	/// Whitespace and comments before the token, only kept if the tokens were made with `keep_trivia()`
+	pub trivia: Vec<Trivia>,
+	/// The token as written, before escapes are replaced or numbers parsed. Only kept with `keep_trivia()` too
+	pub text: String,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// Source text that isn't part of any token, kept so that source can be reproduced with its comments and layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+	pub kind: TriviaKind,
+	/// The text, including comment markers
+	pub text: String,
+	/// Trivia spanning lines is located by its first char
+	pub loc: SourceLocation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+	Whitespace,
+	/// `-- ...` up to the end of the line, not including the newline
+	LineComment,
+	/// `/* ... */`, which can be nested
+	BlockComment,
+}
+
+#[derive(Debug, PartialEq)]
 pub enum TokenType {
 	Word(String),
 	OpenParen,
@@ -33,6 +44,60 @@ pub enum TokenType {
 	Period,
 	Comma,
 	Semicolon,
+	OpenBrace,
+	CloseBrace,
+	OpenBracket,
+	CloseBracket,
+	Colon,
+
+	// operators
+	Plus,
+	Minus,
+	Star,
+	Slash,
+	Percent,
+	/// `=`
+	Eq,
+	/// `!=` or `<>`
+	NotEq,
+	Lt,
+	LtEq,
+	Gt,
+	GtEq,
+
+	// keywords
+	Let,
+	And,
+	Or,
+	Not,
+	In,
+	Between,
+	Is,
+	Like,
+
+	// literals
+	/// A quoted string, with escape sequences replaced
+	String(String),
+	/// A byte string, written `b"..."`
+	Bytes(Vec<u8>),
+	Integer(i64),
+	Float(f64),
+	Bool(bool),
+	Null,
+}
+impl TokenType {
+	/// Whether the token is a literal value
+	pub fn is_literal(&self) -> bool {
+		matches!(
+			self,
+			TokenType::String(_)
+				| TokenType::Bytes(_)
+				| TokenType::Integer(_)
+				| TokenType::Float(_)
+				| TokenType::Bool(_)
+				| TokenType::Null
+		)
+	}
 }
 impl fmt::Display for TokenType {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -43,54 +108,411 @@ impl fmt::Display for TokenType {
 			TokenType::Period => write!(f, "."),
 			TokenType::Comma => write!(f, ","),
 			TokenType::Semicolon => write!(f, ";"),
+			TokenType::OpenBrace => write!(f, "{{"),
+			TokenType::CloseBrace => write!(f, "}}"),
+			TokenType::OpenBracket => write!(f, "["),
+			TokenType::CloseBracket => write!(f, "]"),
+			TokenType::Colon => write!(f, ":"),
+			TokenType::Plus => write!(f, "+"),
+			TokenType::Minus => write!(f, "-"),
+			TokenType::Star => write!(f, "*"),
+			TokenType::Slash => write!(f, "/"),
+			TokenType::Percent => write!(f, "%"),
+			TokenType::Eq => write!(f, "="),
+			TokenType::NotEq => write!(f, "!="),
+			TokenType::Lt => write!(f, "<"),
+			TokenType::LtEq => write!(f, "<="),
+			TokenType::Gt => write!(f, ">"),
+			TokenType::GtEq => write!(f, ">="),
+			TokenType::Let => write!(f, "let"),
+			TokenType::And => write!(f, "and"),
+			TokenType::Or => write!(f, "or"),
+			TokenType::Not => write!(f, "not"),
+			TokenType::In => write!(f, "in"),
+			TokenType::Between => write!(f, "between"),
+			TokenType::Is => write!(f, "is"),
+			TokenType::Like => write!(f, "like"),
+			TokenType::String(s) => write!(f, "{s:?}"),
+			TokenType::Bytes(b) => write!(f, "b\"{}\"", b.escape_ascii()),
+			TokenType::Integer(n) => write!(f, "{n}"),
+			TokenType::Float(n) => write!(f, "{n:?}"),
+			TokenType::Bool(b) => write!(f, "{b}"),
+			TokenType::Null => write!(f, "null"),
 		}
 	}
 }
 
 /// Iterator that produces tokens from a `Chars` iterator, is also peekable
+///
+/// Input that can't be lexed (such as an unterminated string) ends the tokens early, the error can then be taken with
+/// `take_error()`.
 pub struct Tokens<'input> {
 	chars: Peekable<Chars<'input>>,
 	/// Token that was peeked
 	peeked: Option<Token>,
+	/// Error that ended the tokens
+	error: Option<Error>,
+	/// Whether to attach trivia to tokens
+	keep_trivia: bool,
+	/// Trivia since the last token
+	trivia: Vec<Trivia>,
+	/// Chars consumed for the token being lexed, if trivia is kept
+	text: String,
+	/// Whether the last token lexed can end an operand, in which case a following `-` is subtraction rather than the
+	/// sign of a number
+	after_operand: bool,
 	/// Location of last token outputted, for error reporting
-	pub last_loc: SourceLocation,
-	pub line: u32,
-	pub col: u32,
+	pub(crate) last_loc: SourceLocation,
+	line: u32,
+	col: u32,
 }
 impl<'input> Tokens<'input> {
 	pub fn new(input: Chars<'input>) -> Tokens<'input> {
 		Tokens {
 			chars: input.peekable(),
 			peeked: None,
+			error: None,
+			keep_trivia: false,
+			trivia: Vec::new(),
+			text: String::new(),
+			after_operand: false,
 			last_loc: SourceLocation::new(0, 0..0),
 			line: 0,
 			col: 0,
 		}
 	}
 
+	/// Attach the whitespace and comments before each token to it, as `Token::trivia`, and the token's own source text
+	/// as `Token::text`
+	pub fn keep_trivia(mut self) -> Self {
+		self.keep_trivia = true;
+		self
+	}
+
+	/// Whitespace and comments after the last token, once all tokens have been taken
+	pub fn trailing_trivia(&self) -> &[Trivia] {
+		&self.trivia
+	}
+
 	/// Get a reference to the next token up in the iterator
 	pub fn peek(&mut self) -> Option<&Token> {
 		if self.peeked.is_none() {
 			self.peeked = self.next();
 		}
-		return self.peeked.as_ref();
+		self.peeked.as_ref()
 	}
 
 	/// Consumes token and errors if it does not have the expected type
-	pub fn expect(&mut self, ty: TokenType) -> Result<(), String> {
+	pub fn expect(&mut self, ty: TokenType) -> Result<()> {
 		if let Some(tok) = self.next() {
 			if tok.ty == ty {
 				Ok(())
 			} else {
-				Err(format!(
-					"Expected \"{}\", found {} (line {}, col {})",
-					ty, tok.ty, tok.loc.line, tok.loc.start_col
+				Err(Error::parse(
+					format!("Expected \"{}\", found {}", ty, tok.ty),
+					tok.loc,
 				))
 			}
 		} else {
-			Err(format!("Expected \"{}\", found EOF", ty))
+			Err(Error::parse(
+				format!("Expected \"{}\", found EOF", ty),
+				self.last_loc,
+			))
+		}
+	}
+
+	/// Consumes the next token if it has the given type
+	pub fn consume_if(&mut self, ty: TokenType) -> bool {
+		let matched = self.peek().is_some_and(|tok| tok.ty == ty);
+		if matched {
+			self.next();
+		}
+		matched
+	}
+
+	/// Consumes the next token if it is a particular word, for words that are only special in some places
+	pub fn consume_word(&mut self, word: &str) -> bool {
+		let matched = self
+			.peek()
+			.is_some_and(|tok| matches!(&tok.ty, TokenType::Word(w) if w == word));
+		if matched {
+			self.next();
+		}
+		matched
+	}
+
+	/// Takes the error that ended the tokens early, if there was one
+	pub fn take_error(&mut self) -> Option<Error> {
+		self.error.take()
+	}
+
+	/// Consumes a char, keeping track of the line and column
+	fn bump(&mut self) -> Option<char> {
+		let c = self.chars.next()?;
+		if self.keep_trivia {
+			self.text.push(c);
+		}
+		if c == '\n' {
+			self.line += 1;
+			self.col = 0;
+		} else {
+			self.col += 1;
+		}
+		Some(c)
+	}
+
+	/// Consumes the next char if it is `expected`
+	fn bump_if(&mut self, expected: char) -> bool {
+		let matched = self.chars.peek() == Some(&expected);
+		if matched {
+			self.bump();
+		}
+		matched
+	}
+
+	/// The char after the next one
+	fn peek_second(&self) -> Option<char> {
+		let mut chars = self.chars.clone();
+		chars.next();
+		chars.next()
+	}
+
+	/// Ends the tokens with an error
+	fn fail<T>(&mut self, message: &str, loc: SourceLocation) -> Option<T> {
+		self.error = Some(Error::parse(message, loc));
+		None
+	}
+
+	/// Location of the char that was just consumed
+	fn char_loc(&self) -> SourceLocation {
+		SourceLocation::new(self.line, (self.col - 1)..self.col)
+	}
+
+	/// Consumes whitespace and comments, keeping them as trivia if asked to
+	///
+	/// Returns `None` if a block comment is never closed.
+	fn lex_trivia(&mut self) -> Option<()> {
+		loop {
+			let line = self.line;
+			let start_col = self.col;
+			let mut text = String::new();
+			let kind = match self.chars.peek().copied() {
+				Some(c) if c.is_whitespace() => {
+					while let Some(&c) = self.chars.peek()
+						&& c.is_whitespace()
+					{
+						text.push(c);
+						self.bump();
+					}
+					TriviaKind::Whitespace
+				}
+				Some('-') if self.peek_second() == Some('-') => {
+					while let Some(&c) = self.chars.peek()
+						&& c != '\n'
+					{
+						text.push(c);
+						self.bump();
+					}
+					TriviaKind::LineComment
+				}
+				Some('/') if self.peek_second() == Some('*') => {
+					let start = SourceLocation::new(line, start_col..(start_col + 2));
+					let mut depth = 0;
+					loop {
+						let Some(c) = self.bump() else {
+							return self.fail("Unterminated block comment", start);
+						};
+						text.push(c);
+						let next = self.chars.peek().copied();
+						if c == '/' && next == Some('*') {
+							text.push(self.bump().unwrap());
+							depth += 1;
+						} else if c == '*' && next == Some('/') {
+							text.push(self.bump().unwrap());
+							depth -= 1;
+							if depth == 0 {
+								break;
+							}
+						}
+					}
+					TriviaKind::BlockComment
+				}
+				_ => return Some(()),
+			};
+
+			if self.keep_trivia {
+				let end_col = if self.line == line {
+					self.col
+				} else {
+					start_col + 1
+				};
+				self.trivia.push(Trivia {
+					kind,
+					text,
+					loc: SourceLocation::new(line, start_col..end_col),
+				});
+			}
+		}
+	}
+
+	/// Lexes a number, once its first char (a digit or a minus sign) has been consumed
+	fn lex_number(&mut self, first: char, start: SourceLocation) -> Option<TokenType> {
+		let mut text = String::from(first);
+		if first == '-' {
+			// always followed by a digit
+			text.push(self.bump().unwrap());
+		}
+		let radix = match self.chars.peek() {
+			Some('x' | 'X') if text.ends_with('0') => 16,
+			Some('b' | 'B') if text.ends_with('0') => 2,
+			_ => 10,
+		};
+		if radix != 10 {
+			// drop the 0 of the prefix
+			self.bump();
+			text.pop();
+		}
+
+		let mut is_float = false;
+		let mut has_exponent = false;
+		while let Some(&c) = self.chars.peek() {
+			match c {
+				'_' => {}
+				c if c.is_digit(radix) => text.push(c),
+				'.' if radix == 10
+					&& !is_float && self.peek_second().is_some_and(|c| c.is_ascii_digit()) =>
+				{
+					is_float = true;
+					text.push(c);
+				}
+				'e' | 'E' if radix == 10 && !has_exponent => {
+					let mut ahead = self.chars.clone();
+					ahead.next();
+					let sign = ahead.next_if(|c| *c == '+' || *c == '-');
+					if !ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+						break;
+					}
+					self.bump();
+					text.push('e');
+					if let Some(sign) = sign {
+						self.bump();
+						text.push(sign);
+					}
+					is_float = true;
+					has_exponent = true;
+					continue;
+				}
+				_ => break,
+			}
+			self.bump();
+		}
+
+		let loc = SourceLocation::new(start.line, start.start_col..self.col);
+		if self.chars.peek().is_some_and(|c| c.is_alphanumeric()) {
+			return self.fail("Invalid number literal", loc);
+		}
+		if is_float {
+			// only digits, a point and an exponent were kept, so this always parses
+			let n: f64 = text.parse().unwrap();
+			if !n.is_finite() {
+				return self.fail("Float literal out of range", loc);
+			}
+			return Some(TokenType::Float(n));
+		}
+		match i64::from_str_radix(&text, radix) {
+			Ok(n) => Some(TokenType::Integer(n)),
+			Err(_) if text.trim_start_matches('-').is_empty() => {
+				self.fail("Invalid number literal", loc)
+			}
+			Err(_) => self.fail("Integer literal out of range", loc),
+		}
+	}
+
+	/// Lexes a string or byte string, once its opening quote has been consumed
+	fn lex_string(&mut self, is_bytes: bool, start: SourceLocation) -> Option<TokenType> {
+		let mut buf: Vec<u8> = Vec::new();
+		loop {
+			let Some(c) = self.bump() else {
+				return self.fail("Unterminated string literal", start);
+			};
+			match c {
+				'"' => break,
+				'\\' => {
+					let escape_loc = self.char_loc();
+					let escaped = match self.bump() {
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some('0') => '\0',
+						Some('\\') => '\\',
+						Some('"') => '"',
+						Some('\'') => '\'',
+						Some('x') => {
+							let digits: String =
+								[self.bump(), self.bump()].into_iter().flatten().collect();
+							match u8::from_str_radix(&digits, 16) {
+								Ok(b) if digits.len() == 2 && (is_bytes || b.is_ascii()) => {
+									buf.push(b);
+									continue;
+								}
+								_ => {
+									let message = if is_bytes {
+										"Invalid escape, expected \\x and two hex digits"
+									} else {
+										"Invalid escape, expected \\x and two hex digits up to 7F"
+									};
+									return self.fail(message, escape_loc);
+								}
+							}
+						}
+						Some('u') if !is_bytes => match self.lex_unicode_escape() {
+							Some(c) => c,
+							None => {
+								return self.fail(
+									"Invalid unicode escape, expected \\u{...} with up to 6 hex digits",
+									escape_loc,
+								);
+							}
+						},
+						_ => return self.fail("Unknown escape sequence", escape_loc),
+					};
+					let mut utf8 = [0u8; 4];
+					buf.extend_from_slice(escaped.encode_utf8(&mut utf8).as_bytes());
+				}
+				c if is_bytes && !c.is_ascii() => {
+					let loc = self.char_loc();
+					return self.fail("Byte strings can only contain ASCII characters", loc);
+				}
+				c => {
+					let mut utf8 = [0u8; 4];
+					buf.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+				}
+			}
+		}
+
+		if is_bytes {
+			Some(TokenType::Bytes(buf))
+		} else {
+			// only whole chars and ASCII bytes were added
+			Some(TokenType::String(String::from_utf8(buf).unwrap()))
 		}
 	}
+
+	/// Lexes the `{...}` of a unicode escape
+	fn lex_unicode_escape(&mut self) -> Option<char> {
+		if self.bump()? != '{' {
+			return None;
+		}
+		let mut digits = String::new();
+		loop {
+			match self.bump()? {
+				'}' => break,
+				c if c.is_ascii_hexdigit() && digits.len() < 6 => digits.push(c),
+				_ => return None,
+			}
+		}
+		char::from_u32(u32::from_str_radix(&digits, 16).ok()?)
+	}
 }
 impl<'input> Iterator for Tokens<'input> {
 	type Item = Token;
@@ -101,71 +523,96 @@ impl<'input> Iterator for Tokens<'input> {
 			self.last_loc = tok.loc;
 			return Some(tok);
 		}
+		if self.error.is_some() {
+			return None;
+		}
 
-		let c = match self.chars.next() {
-			Some(c) => c,
-			None => return None,
-		};
-		self.col += 1;
+		self.lex_trivia()?;
+		self.text.clear();
+
+		let line = self.line;
+		let c = self.bump()?;
+		let start = self.char_loc();
+		let ty = match c {
+			// single char tokens
+			'(' => TokenType::OpenParen,
+			')' => TokenType::CloseParen,
+			'.' => TokenType::Period,
+			',' => TokenType::Comma,
+			';' => TokenType::Semicolon,
+			'{' => TokenType::OpenBrace,
+			'}' => TokenType::CloseBrace,
+			'[' => TokenType::OpenBracket,
+			']' => TokenType::CloseBracket,
+			':' => TokenType::Colon,
+			'+' => TokenType::Plus,
+			'*' => TokenType::Star,
+			'/' => TokenType::Slash,
+			'%' => TokenType::Percent,
+			'=' => TokenType::Eq,
+			'!' if self.bump_if('=') => TokenType::NotEq,
+			'<' if self.bump_if('=') => TokenType::LtEq,
+			'<' if self.bump_if('>') => TokenType::NotEq,
+			'<' => TokenType::Lt,
+			'>' if self.bump_if('=') => TokenType::GtEq,
+			'>' => TokenType::Gt,
 
-		// skip all whitespace
-		if c.is_whitespace() {
-			if c == '\n' {
-				self.line += 1;
-				self.col = 0;
+			'"' => self.lex_string(false, start)?,
+			'b' if self.chars.peek() == Some(&'"') => {
+				self.bump();
+				self.lex_string(true, start)?
 			}
-			while let Some(next) = self.chars.peek()
-				&& next.is_whitespace()
-			{
-				if *next == '\n' {
-					self.line += 1;
-					self.col = 0;
-				} else {
-					self.col += 1;
-				}
-				self.chars.next();
+			'0'..='9' => self.lex_number(c, start)?,
+			'-' if !self.after_operand && self.chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+				self.lex_number(c, start)?
 			}
-			return self.next();
-		}
+			'-' => TokenType::Minus,
 
-		// single char tokens
-		let mut single_char_token = |ty: TokenType| -> Token {
-			let t = Token {
-				ty,
-				loc: SourceLocation::new(self.line, (self.col - 1)..self.col),
-			};
-			self.last_loc = t.loc;
-			t
+			// word token
+			c if c.is_alphanumeric() || c == '_' => {
+				let mut word = String::from(c);
+				while let Some(&next) = self.chars.peek()
+					&& (next.is_alphanumeric() || next == '_')
+				{
+					word.push(next);
+					self.bump();
+				}
+				match word.as_str() {
+					"true" => TokenType::Bool(true),
+					"false" => TokenType::Bool(false),
+					"null" => TokenType::Null,
+					"let" => TokenType::Let,
+					"and" => TokenType::And,
+					"or" => TokenType::Or,
+					"not" => TokenType::Not,
+					"in" => TokenType::In,
+					"between" => TokenType::Between,
+					"is" => TokenType::Is,
+					"like" => TokenType::Like,
+					_ => TokenType::Word(word),
+				}
+			}
+			_ => return self.fail("Unexpected character", start),
 		};
-		match c {
-			'(' => return Some(single_char_token(TokenType::OpenParen)),
-			')' => return Some(single_char_token(TokenType::CloseParen)),
-			'.' => return Some(single_char_token(TokenType::Period)),
-			',' => return Some(single_char_token(TokenType::Comma)),
-			';' => return Some(single_char_token(TokenType::Semicolon)),
-			_ => {}
-		}
-
-		// word token
-		let start_col = self.col - 1;
-		let mut word = String::from(c);
-		while let Some(next) = self.chars.peek()
-			&& *next != '('
-			&& *next != ')'
-			&& *next != '.'
-			&& *next != ','
-			&& *next != ';'
-			&& !next.is_whitespace()
-		{
-			word.push(self.chars.next().unwrap());
-			self.col += 1;
-		}
+		self.after_operand = ty.is_literal()
+			|| matches!(
+				ty,
+				TokenType::Word(_) | TokenType::CloseParen | TokenType::CloseBracket
+			);
 
+		// tokens spanning lines (multi-line strings) are located by their first char
+		let end_col = if self.line == line {
+			self.col
+		} else {
+			start.end_col
+		};
 		let t = Token {
-			ty: TokenType::Word(word),
-			loc: SourceLocation::new(self.line, start_col..self.col),
+			ty,
+			loc: SourceLocation::new(line, start.start_col..end_col),
+			trivia: std::mem::take(&mut self.trivia),
+			text: std::mem::take(&mut self.text),
 		};
 		self.last_loc = t.loc;
-		return Some(t);
+		Some(t)
 	}
 }
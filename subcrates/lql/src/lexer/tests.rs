@@ -1,3 +1,5 @@
+use lildb::Error;
+
 use super::*;
 
 #[test]
@@ -5,8 +7,8 @@ use super::*;
 fn success1() {
 	let mut t = Tokens::new("hello world".chars());
 	
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("hello".to_string()), loc: SourceLocation::new(0, 0..5) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("world".to_string()), loc: SourceLocation::new(0, 6..11) }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("hello".to_string()), loc: SourceLocation::new(0, 0..5), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("world".to_string()), loc: SourceLocation::new(0, 6..11), trivia: Vec::new(), text: String::new() }));
 	assert_eq!(t.next(), None);
 }
 
@@ -15,17 +17,17 @@ fn success1() {
 fn success2() {
 	let mut t = Tokens::new("db.table(\"Users\").read();\n".chars());
 
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("db".to_string()), loc: SourceLocation::new(0, 0..2) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(0, 2..3) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("table".to_string()), loc: SourceLocation::new(0, 3..8) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(0, 8..9) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("\"Users\"".to_string()), loc: SourceLocation::new(0, 9..16) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(0, 16..17) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(0, 17..18) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("read".to_string()), loc: SourceLocation::new(0, 18..22) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(0, 22..23) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(0, 23..24) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Semicolon, loc: SourceLocation::new(0, 24..25) }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("db".to_string()), loc: SourceLocation::new(0, 0..2), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(0, 2..3), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("table".to_string()), loc: SourceLocation::new(0, 3..8), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(0, 8..9), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::String("Users".to_string()), loc: SourceLocation::new(0, 9..16), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(0, 16..17), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(0, 17..18), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("read".to_string()), loc: SourceLocation::new(0, 18..22), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(0, 22..23), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(0, 23..24), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Semicolon, loc: SourceLocation::new(0, 24..25), trivia: Vec::new(), text: String::new() }));
 	assert_eq!(t.next(), None);
 }
 
@@ -34,18 +36,18 @@ fn success2() {
 fn success3() {
 	let mut t = Tokens::new("abc\n    .efg(\"arg1\", 123)\n    .hij()\n".chars());
 
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("abc".to_string()), loc: SourceLocation::new(0, 0..3) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(1, 4..5) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("efg".to_string()), loc: SourceLocation::new(1, 5..8) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(1, 8..9) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("\"arg1\"".to_string()), loc: SourceLocation::new(1, 9..15) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Comma, loc: SourceLocation::new(1, 15..16) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("123".to_string()), loc: SourceLocation::new(1, 17..20) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(1, 20..21) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(2, 4..5) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("hij".to_string()), loc: SourceLocation::new(2, 5..8) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(2, 8..9) }));
-	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(2, 9..10) }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("abc".to_string()), loc: SourceLocation::new(0, 0..3), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(1, 4..5), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("efg".to_string()), loc: SourceLocation::new(1, 5..8), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(1, 8..9), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::String("arg1".to_string()), loc: SourceLocation::new(1, 9..15), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Comma, loc: SourceLocation::new(1, 15..16), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Integer(123), loc: SourceLocation::new(1, 17..20), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(1, 20..21), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Period, loc: SourceLocation::new(2, 4..5), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::Word("hij".to_string()), loc: SourceLocation::new(2, 5..8), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::OpenParen, loc: SourceLocation::new(2, 8..9), trivia: Vec::new(), text: String::new() }));
+	assert_eq!(t.next(), Some(Token { ty: TokenType::CloseParen, loc: SourceLocation::new(2, 9..10), trivia: Vec::new(), text: String::new() }));
 	assert_eq!(t.next(), None);
 }
 
@@ -62,3 +64,236 @@ fn empty() {
 	let mut t = Tokens::new("".chars());
 	assert_eq!(t.next(), None);
 }
+
+/// Lexes input that should be a single token
+fn single(input: &str) -> TokenType {
+	let mut t = Tokens::new(input.chars());
+	let tok = t
+		.next()
+		.unwrap_or_else(|| panic!("{input}: {:?}", t.take_error()));
+	assert_eq!(t.next(), None);
+	tok.ty
+}
+
+/// Lexes input that should fail, returning the error's location
+fn lex_error(input: &str) -> SourceLocation {
+	let mut t = Tokens::new(input.chars());
+	while t.next().is_some() {}
+	match t.take_error() {
+		Some(Error::Parse { location, .. }) => location,
+		e => panic!("{input}: expected parse error, got {e:?}"),
+	}
+}
+
+#[test]
+fn numbers() {
+	assert_eq!(single("0"), TokenType::Integer(0));
+	assert_eq!(single("-42"), TokenType::Integer(-42));
+	assert_eq!(single("1_000_000"), TokenType::Integer(1_000_000));
+	assert_eq!(single("0xff"), TokenType::Integer(255));
+	assert_eq!(single("-0x10"), TokenType::Integer(-16));
+	assert_eq!(single("0b101"), TokenType::Integer(5));
+	assert_eq!(single("9223372036854775807"), TokenType::Integer(i64::MAX));
+	assert_eq!(single("-9223372036854775808"), TokenType::Integer(i64::MIN));
+	assert_eq!(single("1.5"), TokenType::Float(1.5));
+	assert_eq!(single("-0.25"), TokenType::Float(-0.25));
+	assert_eq!(single("1e3"), TokenType::Float(1000.0));
+	assert_eq!(single("2.5E-1"), TokenType::Float(0.25));
+
+	// a period not followed by a digit chains a function
+	let mut t = Tokens::new("10.max".chars());
+	assert_eq!(t.next().unwrap().ty, TokenType::Integer(10));
+	assert_eq!(t.next().unwrap().ty, TokenType::Period);
+
+	assert_eq!(
+		lex_error("9223372036854775808"),
+		SourceLocation::new(0, 0..19)
+	);
+	assert_eq!(lex_error("(12abc)"), SourceLocation::new(0, 1..3));
+	assert_eq!(lex_error("0x"), SourceLocation::new(0, 0..2));
+	assert_eq!(lex_error("(10e400)"), SourceLocation::new(0, 1..7));
+	assert_eq!(lex_error("-1.5e309"), SourceLocation::new(0, 0..8));
+}
+
+#[test]
+fn keywords() {
+	assert_eq!(single("true"), TokenType::Bool(true));
+	assert_eq!(single("false"), TokenType::Bool(false));
+	assert_eq!(single("null"), TokenType::Null);
+	assert_eq!(single("nullable"), TokenType::Word("nullable".to_string()));
+}
+
+#[test]
+fn strings() {
+	assert_eq!(single(r#""""#), TokenType::String(String::new()));
+	assert_eq!(
+		single(r#""a \"quote\"\n\t\\ \x41""#),
+		TokenType::String("a \"quote\"\n\t\\ A".to_string())
+	);
+	assert_eq!(
+		single(r#""caf\u{e9} \u{1F600}""#),
+		TokenType::String("café 😀".to_string())
+	);
+	assert_eq!(
+		single("\"two\nlines\""),
+		TokenType::String("two\nlines".to_string())
+	);
+	assert_eq!(
+		single(r#"b"\x00\xffab\n""#),
+		TokenType::Bytes(vec![0x00, 0xff, b'a', b'b', b'\n'])
+	);
+	// just a word starting with b
+	assert_eq!(single("bob"), TokenType::Word("bob".to_string()));
+
+	assert_eq!(lex_error(r#"x("abc)"#), SourceLocation::new(0, 2..3));
+	assert_eq!(lex_error(r#""a\qb""#), SourceLocation::new(0, 2..3));
+	assert_eq!(lex_error(r#""\u{110000}""#), SourceLocation::new(0, 1..2));
+	assert_eq!(lex_error(r#""\u{}""#), SourceLocation::new(0, 1..2));
+	assert_eq!(lex_error(r#""\xff""#), SourceLocation::new(0, 1..2));
+	assert_eq!(lex_error(r#"b"é""#), SourceLocation::new(0, 2..3));
+}
+
+#[test]
+fn operators() {
+	let types: Vec<TokenType> = Tokens::new("a<=b<>c!=d>=-1-2 and not x".chars())
+		.map(|tok| tok.ty)
+		.collect();
+	let word = |s: &str| TokenType::Word(s.to_string());
+	assert_eq!(
+		types,
+		vec![
+			word("a"),
+			TokenType::LtEq,
+			word("b"),
+			TokenType::NotEq,
+			word("c"),
+			TokenType::NotEq,
+			word("d"),
+			TokenType::GtEq,
+			TokenType::Integer(-1),
+			TokenType::Minus,
+			TokenType::Integer(2),
+			TokenType::And,
+			TokenType::Not,
+			word("x"),
+		]
+	);
+
+	// a minus after a closing bracket subtracts too
+	let types: Vec<TokenType> = Tokens::new("{a: [1]-1}".chars())
+		.map(|tok| tok.ty)
+		.collect();
+	assert_eq!(
+		types,
+		vec![
+			TokenType::OpenBrace,
+			word("a"),
+			TokenType::Colon,
+			TokenType::OpenBracket,
+			TokenType::Integer(1),
+			TokenType::CloseBracket,
+			TokenType::Minus,
+			TokenType::Integer(1),
+			TokenType::CloseBrace,
+		]
+	);
+
+	assert_eq!(lex_error("a ! b"), SourceLocation::new(0, 2..3));
+	assert_eq!(lex_error("a # b"), SourceLocation::new(0, 2..3));
+}
+
+#[test]
+fn comments() {
+	let types: Vec<TokenType> =
+		Tokens::new("a -- line\n/* block /* nested */ still */ b--1\nc/**/d".chars())
+			.map(|tok| tok.ty)
+			.collect();
+	let word = |s: &str| TokenType::Word(s.to_string());
+	assert_eq!(types, vec![word("a"), word("b"), word("c"), word("d")]);
+
+	assert_eq!(single("1 -- trailing"), TokenType::Integer(1));
+	assert_eq!(
+		lex_error("a /* never /* closed */"),
+		SourceLocation::new(0, 2..4)
+	);
+	assert_eq!(lex_error("a\n  /*/"), SourceLocation::new(1, 2..4));
+}
+
+#[test]
+fn trivia() {
+	let mut t = Tokens::new("a /* x */\n  -- y\nb -- z".chars()).keep_trivia();
+
+	let a = t.next().unwrap();
+	assert!(a.trivia.is_empty());
+
+	let b = t.next().unwrap();
+	assert_eq!(b.ty, TokenType::Word("b".to_string()));
+	let trivia: Vec<(TriviaKind, &str, SourceLocation)> = b
+		.trivia
+		.iter()
+		.map(|t| (t.kind, t.text.as_str(), t.loc))
+		.collect();
+	assert_eq!(
+		trivia,
+		vec![
+			(TriviaKind::Whitespace, " ", SourceLocation::new(0, 1..2)),
+			(
+				TriviaKind::BlockComment,
+				"/* x */",
+				SourceLocation::new(0, 2..9)
+			),
+			(
+				TriviaKind::Whitespace,
+				"\n  ",
+				SourceLocation::new(0, 9..10)
+			),
+			(
+				TriviaKind::LineComment,
+				"-- y",
+				SourceLocation::new(1, 2..6)
+			),
+			(TriviaKind::Whitespace, "\n", SourceLocation::new(1, 6..7)),
+		]
+	);
+
+	assert_eq!(t.next(), None);
+	let trailing: Vec<&str> = t
+		.trailing_trivia()
+		.iter()
+		.map(|t| t.text.as_str())
+		.collect();
+	assert_eq!(trailing, vec![" ", "-- z"]);
+
+	// without keep_trivia nothing is attached
+	let mut t = Tokens::new("/* x */ a".chars());
+	let a = t.next().unwrap();
+	assert!(a.trivia.is_empty() && a.text.is_empty());
+	assert!(t.trailing_trivia().is_empty());
+}
+
+#[test]
+fn round_trip() {
+	let inputs = [
+		"",
+		"  -- only a comment",
+		"let recent = Users.where(created >= 0x7F_FF) /* keep /* nested */ */;\nrecent.select(name);\n",
+		"Users.matches(a<>b and c<=-1.5e+3 or d != -x - 1_000)\n\t.limit(10); -- trailing",
+		"Logs.insert({ msg: \"tab\\t quote\\\" \\u{1F600}\\x41\", raw: b\"\\x00\\xff\" })",
+		"Notes.insert({ body: \"spans\nlines\", ok: true, gone: null })",
+	];
+	for input in inputs {
+		let mut t = Tokens::new(input.chars()).keep_trivia();
+		let mut printed = String::new();
+		for tok in t.by_ref() {
+			for trivia in &tok.trivia {
+				printed.push_str(&trivia.text);
+			}
+			printed.push_str(&tok.text);
+		}
+		assert!(t.take_error().is_none(), "{input}");
+		for trivia in t.trailing_trivia() {
+			printed.push_str(&trivia.text);
+		}
+		assert_eq!(printed, input);
+	}
+}
@@ -1,17 +1,76 @@
-mod lexer;
+pub mod lexer;
 mod parser;
 
-use lildb::query;
+use lildb::{Error, Result, query};
 
 use lexer::Tokens;
-use parser::{tree::ParseTreeNode, try_parse_query};
+use parser::{tree::ParseTreeNode, try_parse_expr, try_parse_query};
 
-/// Parse a string into a `Query`
-pub fn parse(input: String) -> Result<query::Query, String> {
+/// Parse a string holding one query into a `Query`
+pub fn parse(input: String) -> Result<query::Query> {
 	let mut tokens = Tokens::new(input.chars());
-	let Some(parsed) = try_parse_query(&mut tokens)? else {
-		return Err("Input did not contain a query".to_string());
+	let query = parse_query(&mut tokens)?;
+	if let Some(tok) = tokens.peek() {
+		return Err(Error::parse(
+			format!("Expected end of input, found {}", tok.ty),
+			tok.loc,
+		));
+	}
+	if let Some(e) = tokens.take_error() {
+		return Err(e);
+	}
+	Ok(query)
+}
+
+/// Parse a string holding any number of queries, such as a migration, into `Query`s in the order they appear
+pub fn parse_script(input: String) -> Result<Vec<query::Query>> {
+	let mut tokens = Tokens::new(input.chars());
+	let mut queries = Vec::new();
+	while tokens.peek().is_some() {
+		queries.push(parse_query(&mut tokens)?);
+	}
+	if let Some(e) = tokens.take_error() {
+		return Err(e);
+	}
+	Ok(queries)
+}
+
+/// Parse a string holding one expression, such as the condition of a partial index, into an `Expr`
+pub fn parse_expr(input: String) -> Result<query::Expr> {
+	let mut tokens = Tokens::new(input.chars());
+	let parsed = try_parse_expr(&mut tokens);
+	if let Some(e) = tokens.take_error() {
+		return Err(e);
+	}
+	let Some(parsed) = parsed? else {
+		return Err(Error::parse(
+			"Input did not contain an expression",
+			tokens.last_loc,
+		));
+	};
+	if let Some(tok) = tokens.peek() {
+		return Err(Error::parse(
+			format!("Expected end of input, found {}", tok.ty),
+			tok.loc,
+		));
+	}
+	if let Some(e) = tokens.take_error() {
+		return Err(e);
+	}
+	parsed.validate()
+}
+
+fn parse_query(tokens: &mut Tokens) -> Result<query::Query> {
+	let parsed = try_parse_query(tokens);
+	// input that couldn't be lexed cuts the tokens short, which is the real reason parsing failed
+	if let Some(e) = tokens.take_error() {
+		return Err(e);
+	}
+	let Some(parsed) = parsed? else {
+		return Err(Error::parse(
+			"Input did not contain a query",
+			tokens.last_loc,
+		));
 	};
-	let q = parsed.validate();
-	q
+	parsed.validate()
 }
@@ -0,0 +1,480 @@
+//!
+//! Pratt parser for expressions. Each operator has a binding power on its left and right, and an operator only takes
+//! an operand away from its neighbour if it binds tighter. From loosest to tightest:
+//!
+//! | Operators | Associativity |
+//! |-----------|---------------|
+//! | `or` | left |
+//! | `and` | left |
+//! | `not` (prefix) | |
+//! | `= != <> < <= > >=`, `like`, `in`, `between`, `is null` | left |
+//! | `+ -` | left |
+//! | `* / %` | left |
+//! | `-` (prefix) | |
+//!
+use lildb::{
+	Error,
+	query::{BinaryOp, FrameUnits, UnaryOp},
+};
+
+use super::*;
+
+const OR: u8 = 1;
+const AND: u8 = 3;
+const NOT: u8 = 5;
+const COMPARISON: u8 = 7;
+const ADDITIVE: u8 = 9;
+const MULTIPLICATIVE: u8 = 11;
+const NEGATE: u8 = 13;
+
+pub fn try_parse_expr(tokens: &mut Tokens) -> ParseOutcome<ParseTreeExpr> {
+	try_parse_expr_bp(tokens, 0)
+}
+
+/// Parses an argument of a function, which is an expression that may be followed by `as name`, or by `asc`/`desc` and
+/// `nulls first`/`nulls last`
+///
+/// These words are only special after an argument, so they can still be used as identifiers.
+pub fn try_parse_argument(tokens: &mut Tokens) -> ParseOutcome<ParseTreeExpr> {
+	let Some(expr) = try_parse_expr(tokens)? else {
+		return Ok(None);
+	};
+
+	if tokens.consume_word("as") {
+		let loc = tokens.last_loc;
+		let Some(Token {
+			ty: TokenType::Word(name),
+			..
+		}) = tokens.next()
+		else {
+			return Err(Error::parse(
+				"Expected a name after \"as\"",
+				tokens.last_loc,
+			));
+		};
+		return Ok(Some(ParseTreeExpr::Alias {
+			expr: Box::new(expr),
+			name,
+			loc,
+		}));
+	}
+
+	let mut loc = None;
+	let mut descending = None;
+	if tokens.consume_word("asc") {
+		descending = Some(false);
+		loc = Some(tokens.last_loc);
+	} else if tokens.consume_word("desc") {
+		descending = Some(true);
+		loc = Some(tokens.last_loc);
+	}
+	let mut nulls_first = None;
+	if tokens.consume_word("nulls") {
+		loc.get_or_insert(tokens.last_loc);
+		if tokens.consume_word("first") {
+			nulls_first = Some(true);
+		} else if tokens.consume_word("last") {
+			nulls_first = Some(false);
+		} else {
+			return Err(Error::parse(
+				"Expected \"first\" or \"last\" after \"nulls\"",
+				tokens.last_loc,
+			));
+		}
+	}
+
+	match loc {
+		Some(loc) => Ok(Some(ParseTreeExpr::SortKey {
+			expr: Box::new(expr),
+			descending,
+			nulls_first,
+			loc,
+		})),
+		None => Ok(Some(expr)),
+	}
+}
+
+/// Parses an expression made of operators that bind at least as tightly as `min_bp`
+fn try_parse_expr_bp(tokens: &mut Tokens, min_bp: u8) -> ParseOutcome<ParseTreeExpr> {
+	let Some(mut left) = try_parse_prefix(tokens)? else {
+		return Ok(None);
+	};
+
+	while let Some(Token { ty, .. }) = tokens.peek() {
+		let Some(left_bp) = infix_binding_power(ty) else {
+			break;
+		};
+		if left_bp < min_bp {
+			break;
+		}
+
+		let op = tokens.next().unwrap().ty;
+		left = match op {
+			TokenType::Is => {
+				let negated = tokens.consume_if(TokenType::Not);
+				tokens.expect(TokenType::Null)?;
+				ParseTreeExpr::IsNull {
+					expr: Box::new(left),
+					negated,
+				}
+			}
+			TokenType::Not => match tokens.next().map(|tok| tok.ty) {
+				Some(TokenType::In) => parse_in(tokens, left, true)?,
+				Some(TokenType::Between) => parse_between(tokens, left, true)?,
+				Some(TokenType::Like) => binary(tokens, BinaryOp::NotLike, left, left_bp + 1)?,
+				_ => {
+					return Err(Error::parse(
+						"Expected \"in\", \"between\" or \"like\" after \"not\"",
+						tokens.last_loc,
+					));
+				}
+			},
+			TokenType::In => parse_in(tokens, left, false)?,
+			TokenType::Between => parse_between(tokens, left, false)?,
+			op => {
+				let op = match op {
+					TokenType::Or => BinaryOp::Or,
+					TokenType::And => BinaryOp::And,
+					TokenType::Eq => BinaryOp::Eq,
+					TokenType::NotEq => BinaryOp::NotEq,
+					TokenType::Lt => BinaryOp::Lt,
+					TokenType::LtEq => BinaryOp::LtEq,
+					TokenType::Gt => BinaryOp::Gt,
+					TokenType::GtEq => BinaryOp::GtEq,
+					TokenType::Like => BinaryOp::Like,
+					TokenType::Plus => BinaryOp::Add,
+					TokenType::Minus => BinaryOp::Sub,
+					TokenType::Star => BinaryOp::Mul,
+					TokenType::Slash => BinaryOp::Div,
+					TokenType::Percent => BinaryOp::Rem,
+					_ => unreachable!(),
+				};
+				// all binary operators are left associative
+				binary(tokens, op, left, left_bp + 1)?
+			}
+		};
+	}
+
+	Ok(Some(left))
+}
+
+/// Left binding power of a token following an operand, `None` if it isn't an operator
+fn infix_binding_power(ty: &TokenType) -> Option<u8> {
+	let bp = match ty {
+		TokenType::Or => OR,
+		TokenType::And => AND,
+		TokenType::Eq
+		| TokenType::NotEq
+		| TokenType::Lt
+		| TokenType::LtEq
+		| TokenType::Gt
+		| TokenType::GtEq
+		| TokenType::Like
+		| TokenType::In
+		| TokenType::Between
+		| TokenType::Is
+		| TokenType::Not => COMPARISON,
+		TokenType::Plus | TokenType::Minus => ADDITIVE,
+		TokenType::Star | TokenType::Slash | TokenType::Percent => MULTIPLICATIVE,
+		_ => return None,
+	};
+	Some(bp)
+}
+
+/// Parses a prefix operator, a parenthesized expression, a function call, a column or a value
+fn try_parse_prefix(tokens: &mut Tokens) -> ParseOutcome<ParseTreeExpr> {
+	let Some(Token { ty, .. }) = tokens.peek() else {
+		return Ok(None);
+	};
+	match ty {
+		TokenType::Minus => {
+			tokens.next();
+			let expr = expect_expr(tokens, NEGATE)?;
+			Ok(Some(ParseTreeExpr::Unary {
+				op: UnaryOp::Neg,
+				expr: Box::new(expr),
+			}))
+		}
+		TokenType::Not => {
+			tokens.next();
+			let expr = expect_expr(tokens, NOT)?;
+			Ok(Some(ParseTreeExpr::Unary {
+				op: UnaryOp::Not,
+				expr: Box::new(expr),
+			}))
+		}
+		TokenType::OpenParen => {
+			tokens.next();
+			let expr = expect_expr(tokens, 0)?;
+			tokens.expect(TokenType::CloseParen)?;
+			Ok(Some(expr))
+		}
+		TokenType::OpenBracket => {
+			tokens.next();
+			let items = parse_list_rest(tokens, TokenType::CloseBracket)?;
+			Ok(Some(ParseTreeExpr::List(items)))
+		}
+		TokenType::OpenBrace => {
+			tokens.next();
+			Ok(Some(ParseTreeExpr::Record(parse_record_rest(tokens)?)))
+		}
+		_ => {
+			let Some(value) = try_parse_value(tokens)? else {
+				return Ok(None);
+			};
+			let loc = tokens.last_loc;
+			let ParseTreeValue::Identifier(name) = value else {
+				return Ok(Some(ParseTreeExpr::Value(value)));
+			};
+			if tokens.consume_if(TokenType::Period) {
+				let Some(Token {
+					ty: TokenType::Word(member),
+					loc,
+					..
+				}) = tokens.next()
+				else {
+					return Err(Error::parse(
+						"Expected column or function name",
+						tokens.last_loc,
+					));
+				};
+				// a function called on the object makes it a query
+				if let Some(Token {
+					ty: TokenType::OpenParen,
+					..
+				}) = tokens.peek()
+				{
+					let function = parse_function_call_rest(tokens, member, loc)?;
+					return Ok(Some(ParseTreeExpr::Subquery(Box::new(ParseTreeQuery {
+						object: name,
+						function: Some(function),
+						ctes: Vec::new(),
+					}))));
+				}
+				return Ok(Some(ParseTreeExpr::Column {
+					object: name,
+					name: member,
+				}));
+			}
+			if !tokens.consume_if(TokenType::OpenParen) {
+				return Ok(Some(ParseTreeExpr::Value(ParseTreeValue::Identifier(name))));
+			}
+			let args = parse_list_rest(tokens, TokenType::CloseParen)?;
+			let window = if tokens.consume_word("over") {
+				Some(parse_window(tokens)?)
+			} else {
+				None
+			};
+			Ok(Some(ParseTreeExpr::Call {
+				name,
+				loc,
+				args,
+				window,
+			}))
+		}
+	}
+}
+
+/// Parses the right operand of a binary operator
+fn binary(
+	tokens: &mut Tokens,
+	op: BinaryOp,
+	left: ParseTreeExpr,
+	right_bp: u8,
+) -> lildb::Result<ParseTreeExpr> {
+	let right = expect_expr(tokens, right_bp)?;
+	Ok(ParseTreeExpr::Binary {
+		op,
+		left: Box::new(left),
+		right: Box::new(right),
+	})
+}
+
+/// Parses the `(list...)` or query after `in`
+fn parse_in(
+	tokens: &mut Tokens,
+	expr: ParseTreeExpr,
+	negated: bool,
+) -> lildb::Result<ParseTreeExpr> {
+	if !tokens.consume_if(TokenType::OpenParen) {
+		// peeking moves the location to the operand
+		tokens.peek();
+		let loc = tokens.last_loc;
+		let Some(ParseTreeExpr::Subquery(query)) = try_parse_prefix(tokens)? else {
+			return Err(Error::parse("Expected a list or a query after \"in\"", loc));
+		};
+		return Ok(ParseTreeExpr::InQuery {
+			expr: Box::new(expr),
+			query,
+			negated,
+		});
+	}
+	let list = parse_list_rest(tokens, TokenType::CloseParen)?;
+	Ok(ParseTreeExpr::In {
+		expr: Box::new(expr),
+		list,
+		negated,
+	})
+}
+
+/// Parses the `low and high` after `between`, the bounds can't contain comparisons or boolean operators so the `and`
+/// isn't mistaken for a boolean one
+fn parse_between(
+	tokens: &mut Tokens,
+	expr: ParseTreeExpr,
+	negated: bool,
+) -> lildb::Result<ParseTreeExpr> {
+	let low = expect_expr(tokens, ADDITIVE)?;
+	tokens.expect(TokenType::And)?;
+	let high = expect_expr(tokens, ADDITIVE)?;
+	Ok(ParseTreeExpr::Between {
+		expr: Box::new(expr),
+		low: Box::new(low),
+		high: Box::new(high),
+		negated,
+	})
+}
+
+/// Parses a comma separated list of expressions and the closing bracket, once the opening one has been consumed
+fn parse_list_rest(
+	tokens: &mut Tokens,
+	close: TokenType,
+) -> lildb::Result<Box<ParseTreeFunctionArgs>> {
+	let Some(list) = try_parse_function_args(tokens)? else {
+		return Err(Error::parse(
+			"Expected a list of expressions",
+			tokens.last_loc,
+		));
+	};
+	tokens.expect(close)?;
+	Ok(Box::new(list))
+}
+
+/// Parses the `name: value, ...}` of a record literal, once the opening brace has been consumed
+fn parse_record_rest(tokens: &mut Tokens) -> lildb::Result<Vec<ParseTreeRecordField>> {
+	let mut fields = Vec::new();
+	if tokens.consume_if(TokenType::CloseBrace) {
+		return Ok(fields);
+	}
+	loop {
+		let (name, loc) = match tokens.next() {
+			Some(Token {
+				ty: TokenType::Word(name) | TokenType::String(name),
+				loc,
+				..
+			}) => (name, loc),
+			_ => return Err(Error::parse("Expected field name", tokens.last_loc)),
+		};
+		tokens.expect(TokenType::Colon)?;
+		let value = expect_expr(tokens, 0)?;
+		fields.push(ParseTreeRecordField { name, loc, value });
+
+		if !tokens.consume_if(TokenType::Comma) {
+			tokens.expect(TokenType::CloseBrace)?;
+			return Ok(fields);
+		}
+	}
+}
+
+/// Parses the `(partition_by(...), order_by(...), rows(start, end))` after `over`, where each part is optional
+fn parse_window(tokens: &mut Tokens) -> lildb::Result<ParseTreeWindow> {
+	tokens.expect(TokenType::OpenParen)?;
+	let mut window = ParseTreeWindow::default();
+	if tokens.consume_if(TokenType::CloseParen) {
+		return Ok(window);
+	}
+	loop {
+		let Some(Token {
+			ty: TokenType::Word(part),
+			loc,
+			..
+		}) = tokens.next()
+		else {
+			return Err(Error::parse(
+				"Expected partition_by, order_by, rows or range",
+				tokens.last_loc,
+			));
+		};
+		match part.as_str() {
+			"partition_by" if window.partition_by.is_none() => {
+				tokens.expect(TokenType::OpenParen)?;
+				window.partition_by = Some(parse_list_rest(tokens, TokenType::CloseParen)?);
+			}
+			"order_by" if window.order_by.is_none() => {
+				tokens.expect(TokenType::OpenParen)?;
+				window.order_by = Some(parse_list_rest(tokens, TokenType::CloseParen)?);
+			}
+			"rows" | "range" if window.frame.is_none() => {
+				let units = if part == "rows" {
+					FrameUnits::Rows
+				} else {
+					FrameUnits::Range
+				};
+				tokens.expect(TokenType::OpenParen)?;
+				let start = parse_frame_bound(tokens)?;
+				tokens.expect(TokenType::Comma)?;
+				let end = parse_frame_bound(tokens)?;
+				tokens.expect(TokenType::CloseParen)?;
+				window.frame = Some(ParseTreeWindowFrame {
+					units,
+					start,
+					end,
+					loc,
+				});
+			}
+			_ => {
+				return Err(Error::parse(
+					"Expected partition_by, order_by, rows or range, each at most once",
+					loc,
+				));
+			}
+		}
+
+		if !tokens.consume_if(TokenType::Comma) {
+			tokens.expect(TokenType::CloseParen)?;
+			return Ok(window);
+		}
+	}
+}
+
+/// Parses `unbounded preceding`, `<expr> preceding`, `current row`, `<expr> following` or `unbounded following`
+fn parse_frame_bound(tokens: &mut Tokens) -> lildb::Result<ParseTreeFrameBound> {
+	if tokens.consume_word("current") {
+		if !tokens.consume_word("row") {
+			return Err(Error::parse(
+				"Expected \"row\" after \"current\"",
+				tokens.last_loc,
+			));
+		}
+		return Ok(ParseTreeFrameBound::CurrentRow);
+	}
+
+	let offset = if tokens.consume_word("unbounded") {
+		None
+	} else {
+		Some(Box::new(expect_expr(tokens, 0)?))
+	};
+	let preceding = if tokens.consume_word("preceding") {
+		true
+	} else if tokens.consume_word("following") {
+		false
+	} else {
+		return Err(Error::parse(
+			"Expected \"preceding\" or \"following\"",
+			tokens.last_loc,
+		));
+	};
+	Ok(match (offset, preceding) {
+		(None, true) => ParseTreeFrameBound::UnboundedPreceding,
+		(Some(offset), true) => ParseTreeFrameBound::Preceding(offset),
+		(None, false) => ParseTreeFrameBound::UnboundedFollowing,
+		(Some(offset), false) => ParseTreeFrameBound::Following(offset),
+	})
+}
+
+fn expect_expr(tokens: &mut Tokens, min_bp: u8) -> lildb::Result<ParseTreeExpr> {
+	match try_parse_expr_bp(tokens, min_bp)? {
+		Some(expr) => Ok(expr),
+		None => Err(Error::parse("Expected an expression", tokens.last_loc)),
+	}
+}
@@ -7,35 +7,99 @@
 //!
 //! See the readme for grammar definition.
 //!
+mod expr;
 pub mod tree;
 
+use lildb::{Error, SourceLocation};
+
 use crate::lexer::{Token, TokenType, Tokens};
 
+use expr::try_parse_argument;
+pub use expr::try_parse_expr;
 use tree::*;
 
 /// Describes the output of a parsing function in a recursive descent parser
 ///
 /// The `Result` value represents whether or not the input is invalid, hence a failure to parse, while the successful
 /// `Option` value represents whether or not the tokens match this grammar rule
-type ParseOutcome<T> = Result<Option<T>, String>;
+type ParseOutcome<T> = lildb::Result<Option<T>>;
 
 pub fn try_parse_query(tokens: &mut Tokens) -> ParseOutcome<ParseTreeQuery> {
+	if tokens.peek().is_none() {
+		return Ok(None);
+	}
+
+	let mut ctes = Vec::new();
+	while let Some(cte) = try_parse_cte(tokens)? {
+		ctes.push(cte);
+	}
+
 	let Some(Token {
 		ty: TokenType::Word(object),
 		..
 	}) = tokens.next()
 	else {
-		return Err(format!(
-			"Expected object (line {}, col {})",
-			tokens.last_loc.line, tokens.last_loc.start_col
-		));
+		return Err(Error::parse("Expected object", tokens.last_loc));
 	};
+	let mut query = parse_pipeline(tokens, object)?;
+	query.ctes = ctes;
 
-	let function = try_parse_function_call(tokens)?;
+	tokens.expect(TokenType::Semicolon)?;
 
+	Ok(Some(query))
+}
+
+/// Parses a `let [recursive] name = pipeline;` statement
+fn try_parse_cte(tokens: &mut Tokens) -> ParseOutcome<ParseTreeCte> {
+	if !tokens.consume_if(TokenType::Let) {
+		return Ok(None);
+	}
+
+	let mut recursive = false;
+	let (mut name, mut loc) = expect_word(tokens, "Expected a name after \"let\"")?;
+	if name == "recursive"
+		&& let Some(Token {
+			ty: TokenType::Word(_),
+			..
+		}) = tokens.peek()
+	{
+		recursive = true;
+		(name, loc) = expect_word(tokens, "Expected a name")?;
+	}
+	tokens.expect(TokenType::Eq)?;
+
+	let (object, _) = expect_word(tokens, "Expected object")?;
+	let query = parse_pipeline(tokens, object)?;
 	tokens.expect(TokenType::Semicolon)?;
 
-	return Ok(Some(ParseTreeQuery { object, function }));
+	Ok(Some(ParseTreeCte {
+		name,
+		loc,
+		recursive,
+		query,
+	}))
+}
+
+/// Parses the functions called on an object, once the object's name has been consumed
+fn parse_pipeline(tokens: &mut Tokens, object: String) -> lildb::Result<ParseTreeQuery> {
+	let function = try_parse_function_call(tokens)?;
+	Ok(ParseTreeQuery {
+		object,
+		function,
+		ctes: Vec::new(),
+	})
+}
+
+/// Consumes a word, or errors with `message`
+fn expect_word(tokens: &mut Tokens, message: &str) -> lildb::Result<(String, SourceLocation)> {
+	match tokens.next() {
+		Some(Token {
+			ty: TokenType::Word(word),
+			loc,
+			..
+		}) => Ok((word, loc)),
+		_ => Err(Error::parse(message, tokens.last_loc)),
+	}
 }
 
 fn try_parse_function_call(tokens: &mut Tokens) -> ParseOutcome<ParseTreeFunctionCall> {
@@ -49,64 +113,70 @@ fn try_parse_function_call(tokens: &mut Tokens) -> ParseOutcome<ParseTreeFunctio
 		return Ok(Some(ParseTreeFunctionCall::NoFunction));
 	}
 
-	let name = if let Some(Token {
+	let (name, loc) = if let Some(Token {
 		ty: TokenType::Word(_),
 		..
 	}) = &tokens.peek()
 	{
-		let TokenType::Word(s) = tokens.next().unwrap().ty else {
+		let Token {
+			ty: TokenType::Word(s),
+			loc,
+			..
+		} = tokens.next().unwrap()
+		else {
 			unreachable!();
 		};
-		s
+		(s, loc)
 	} else {
-		return Err(format!(
-			"Expected function name (line {}, col {})",
-			tokens.last_loc.line, tokens.last_loc.start_col
-		));
+		return Err(Error::parse("Expected function name", tokens.last_loc));
 	};
 
+	Ok(Some(parse_function_call_rest(tokens, name, loc)?))
+}
+
+/// Parses the arguments of a function and the functions chained after it, once its name has been consumed
+fn parse_function_call_rest(
+	tokens: &mut Tokens,
+	name: String,
+	loc: SourceLocation,
+) -> lildb::Result<ParseTreeFunctionCall> {
 	tokens.expect(TokenType::OpenParen)?;
 	let Some(args) = try_parse_function_args(tokens)? else {
-		return Err(format!(
-			"Expected function arguments (line {}, col {})",
-			tokens.last_loc.line, tokens.last_loc.start_col
-		));
+		return Err(Error::parse("Expected function arguments", tokens.last_loc));
 	};
 	tokens.expect(TokenType::CloseParen)?;
 
 	let Some(chained_function) = try_parse_function_call(tokens)? else {
-		return Err(format!(
-			"Expected function or null (line {}, col {})",
-			tokens.last_loc.line, tokens.last_loc.start_col
-		));
+		return Err(Error::parse("Expected function or null", tokens.last_loc));
 	};
 
-	Ok(Some(ParseTreeFunctionCall::Function {
+	Ok(ParseTreeFunctionCall::Function {
 		name,
+		loc,
 		args: Box::new(args),
 		chained: Box::new(chained_function),
-	}))
+	})
 }
 
 fn try_parse_function_args(tokens: &mut Tokens) -> ParseOutcome<ParseTreeFunctionArgs> {
 	if tokens.peek().is_some() {
-		let Some(value) = try_parse_value(tokens)? else {
+		let Some(value) = try_parse_argument(tokens)? else {
 			return Ok(Some(ParseTreeFunctionArgs::NoArgs));
 		};
 		let Some(more_args) = try_parse_more_function_args(tokens)? else {
-			return Err(format!(
-				"Expected continued argument list or end of arguments (line {}, col {})",
-				tokens.last_loc.line, tokens.last_loc.start_col
+			return Err(Error::parse(
+				"Expected continued argument list or end of arguments",
+				tokens.last_loc,
 			));
 		};
 
-		return Ok(Some(ParseTreeFunctionArgs::Args {
+		Ok(Some(ParseTreeFunctionArgs::Args {
 			value,
 			more: Box::new(more_args),
-		}));
+		}))
 	} else {
 		// e = no args
-		return Ok(Some(ParseTreeFunctionArgs::NoArgs));
+		Ok(Some(ParseTreeFunctionArgs::NoArgs))
 	}
 }
 
@@ -117,40 +187,43 @@ fn try_parse_more_function_args(tokens: &mut Tokens) -> ParseOutcome<ParseTreeMo
 	}) = tokens.peek()
 	{
 		tokens.next();
-		let Some(value) = try_parse_value(tokens)? else {
-			return Err(format!(
-				"Expected a value (line {}, col {})",
-				tokens.last_loc.line, tokens.last_loc.start_col
-			));
+		let Some(value) = try_parse_argument(tokens)? else {
+			return Err(Error::parse("Expected an expression", tokens.last_loc));
 		};
 		let Some(more_args) = try_parse_more_function_args(tokens)? else {
-			return Err(format!(
-				"Expected continued argument list or end of arguments (line {}, col {})",
-				tokens.last_loc.line, tokens.last_loc.start_col
+			return Err(Error::parse(
+				"Expected continued argument list or end of arguments",
+				tokens.last_loc,
 			));
 		};
 
-		return Ok(Some(ParseTreeMoreFunctionArgs::MoreArgs {
+		Ok(Some(ParseTreeMoreFunctionArgs::MoreArgs {
 			value,
 			more: Box::new(more_args),
-		}));
+		}))
 	} else {
 		// e = no args
-		return Ok(Some(ParseTreeMoreFunctionArgs::NoMoreArgs));
+		Ok(Some(ParseTreeMoreFunctionArgs::NoMoreArgs))
 	}
 }
 
 fn try_parse_value(tokens: &mut Tokens) -> ParseOutcome<ParseTreeValue> {
-	if let Some(Token {
-		ty: TokenType::Word(_),
-		..
-	}) = tokens.peek()
-	{
-		let TokenType::Word(s) = tokens.next().unwrap().ty else {
-			unreachable!();
-		};
-		Ok(Some(ParseTreeValue::String(s)))
-	} else {
-		Ok(None)
+	let Some(Token { ty, .. }) = tokens.peek() else {
+		return Ok(None);
+	};
+	if !ty.is_literal() && !matches!(ty, TokenType::Word(_)) {
+		return Ok(None);
 	}
+
+	let value = match tokens.next().unwrap().ty {
+		TokenType::Word(s) => ParseTreeValue::Identifier(s),
+		TokenType::String(s) => ParseTreeValue::String(s),
+		TokenType::Bytes(b) => ParseTreeValue::Bytes(b),
+		TokenType::Integer(n) => ParseTreeValue::Integer(n),
+		TokenType::Float(n) => ParseTreeValue::Float(n),
+		TokenType::Bool(b) => ParseTreeValue::Bool(b),
+		TokenType::Null => ParseTreeValue::Null,
+		_ => unreachable!(),
+	};
+	Ok(Some(value))
 }
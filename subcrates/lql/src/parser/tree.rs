@@ -1,27 +1,61 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::RangeInclusive};
 
-use lildb::query;
+use lildb::{
+	Error, Point, Rect, Result, SourceLocation,
+	query::{self, BinaryOp, FrameUnits, Type, UnaryOp},
+};
 
 /// A trait every parse tree node must implement. `validate()` validates the semantics of the parse tree, and consumes
 /// self, producing a value helpful for creating a full query
 pub trait ParseTreeNode: Debug {
 	type Product;
-	fn validate(self) -> Result<Self::Product, String>;
+	fn validate(self) -> Result<Self::Product>;
 }
 
 #[derive(Debug)]
 pub struct ParseTreeQuery {
 	pub object: String,
 	pub function: Option<ParseTreeFunctionCall>,
+	pub ctes: Vec<ParseTreeCte>,
 }
 impl ParseTreeNode for ParseTreeQuery {
 	type Product = query::Query;
-	fn validate(self) -> Result<Self::Product, String> {
+	fn validate(self) -> Result<Self::Product> {
 		let function = match self.function {
 			Some(f) => f.validate()?,
 			None => None,
 		};
-		return Ok(query::Query::new(self.object, function));
+		let mut query = query::Query::new(self.object, function);
+		for cte in self.ctes {
+			if query.ctes().iter().any(|c| c.name == cte.name) {
+				return Err(Error::parse(
+					format!("\"{}\" is already defined", cte.name),
+					cte.loc,
+				));
+			}
+			query = query.with_cte(cte.validate()?);
+		}
+		Ok(query)
+	}
+}
+
+/// A `let [recursive] name = query;` statement
+#[derive(Debug)]
+pub struct ParseTreeCte {
+	pub name: String,
+	/// Location of the name
+	pub loc: SourceLocation,
+	pub recursive: bool,
+	pub query: ParseTreeQuery,
+}
+impl ParseTreeNode for ParseTreeCte {
+	type Product = query::Cte;
+	fn validate(self) -> Result<Self::Product> {
+		Ok(query::Cte {
+			name: self.name,
+			recursive: self.recursive,
+			query: self.query.validate()?,
+		})
 	}
 }
 
@@ -29,53 +63,126 @@ impl ParseTreeNode for ParseTreeQuery {
 pub enum ParseTreeFunctionCall {
 	Function {
 		name: String,
+		/// Location of the function's name
+		loc: SourceLocation,
 		args: Box<ParseTreeFunctionArgs>,
 		chained: Box<ParseTreeFunctionCall>,
 	},
 	NoFunction,
 }
-impl ParseTreeNode for ParseTreeFunctionCall {
-	type Product = Option<query::FunctionCall>;
-	fn validate(self) -> Result<Self::Product, String> {
+impl ParseTreeFunctionCall {
+	/// Validates the function and the ones chained to it, where the first is called on a value of type `on`
+	pub fn validate_on(self, on: &Type) -> Result<Option<query::FunctionCall>> {
 		use ParseTreeFunctionCall::*;
 		match self {
 			Function {
 				name,
+				loc,
 				args,
 				chained,
 			} => {
-				if let Some(f) = query::functions::find_function(&name) {
-					Ok(Some(query::FunctionCall::new(
-						f,
-						args.validate()?,
-						chained.validate()?,
-					)))
-				} else {
-					Err(format!("Unrecognized function: \"{}\"", name))
+				let Some(f) = query::functions::find_function(&name) else {
+					return Err(Error::parse(
+						format!("Unrecognized function: \"{}\"", name),
+						loc,
+					));
+				};
+				if !f.object_type.accepts(on) {
+					return Err(Error::parse(
+						format!("{}() can't be called on {}", f.name, describe_type(on)),
+						loc,
+					));
 				}
+				let args = args.into_vec();
+				check_arg_count(f.name, f.arg_count(), args.len(), loc)?;
+				let args = args
+					.into_iter()
+					.enumerate()
+					.map(|(i, arg)| {
+						// the last type repeats for functions taking any number of arguments
+						let ty = f.positional_args.get(i).or(f.positional_args.last());
+						let expected = match ty {
+							Some(Type::Object) if !arg.is_object_name() => "an object name",
+							Some(Type::Query) if !arg.is_query() => "a query",
+							Some(Type::RecordLiteral) if !arg.is_record_literal() => {
+								"a record or a list of records"
+							}
+							Some(Type::IntegerLiteral) if !arg.is_count() => {
+								"a non-negative integer"
+							}
+							Some(Type::StringLiteral) if !arg.is_string() => "a string",
+							Some(Type::Point) if !arg.is_call("point") => "a point",
+							Some(Type::Rect) if !arg.is_call("rect") => "a rect",
+							_ => return arg.validate_arg(ty),
+						};
+						Err(Error::parse(
+							format!("{}() expects {expected} as argument {}", f.name, i + 1),
+							loc,
+						))
+					})
+					.collect::<Result<Vec<_>>>()?;
+				Ok(Some(query::FunctionCall::new(
+					f,
+					args,
+					chained.validate_on(&f.return_type)?,
+				)))
 			}
 			NoFunction => Ok(None),
 		}
 	}
 }
+impl ParseTreeNode for ParseTreeFunctionCall {
+	type Product = Option<query::FunctionCall>;
+	fn validate(self) -> Result<Self::Product> {
+		// a query starts with the object it is called on
+		self.validate_on(&Type::Object)
+	}
+}
+
+/// How a value of a type is called in errors
+fn describe_type(ty: &Type) -> &'static str {
+	match ty {
+		Type::None => "nothing, as the previous function doesn't return anything",
+		Type::Object => "an object",
+		Type::Records => "records",
+		Type::Groups => "groups",
+		Type::TextMatches => "text matches",
+		_ => "this",
+	}
+}
 
 #[derive(Debug)]
 pub enum ParseTreeFunctionArgs {
 	Args {
-		value: ParseTreeValue,
+		value: ParseTreeExpr,
 		more: Box<ParseTreeMoreFunctionArgs>,
 	},
 	NoArgs,
 }
+impl ParseTreeFunctionArgs {
+	/// The arguments, without validating them
+	pub fn into_vec(self) -> Vec<ParseTreeExpr> {
+		let mut args = Vec::new();
+		let ParseTreeFunctionArgs::Args { value, mut more } = self else {
+			return args;
+		};
+		args.push(value);
+		while let ParseTreeMoreFunctionArgs::MoreArgs { value, more: next } = *more {
+			args.push(value);
+			more = next;
+		}
+		args
+	}
+}
 impl ParseTreeNode for ParseTreeFunctionArgs {
-	type Product = Vec<query::Value>;
-	fn validate(self) -> Result<Self::Product, String> {
+	type Product = Vec<query::Expr>;
+	fn validate(self) -> Result<Self::Product> {
 		use ParseTreeFunctionArgs::*;
 		match self {
 			Args { value, more } => {
-				let mut following_args = more.validate()?;
-				following_args.push(value.validate()?);
-				Ok(following_args)
+				let mut args = vec![value.validate()?];
+				args.extend(more.validate()?);
+				Ok(args)
 			}
 			NoArgs => Ok(Vec::new()),
 		}
@@ -85,36 +192,539 @@ impl ParseTreeNode for ParseTreeFunctionArgs {
 #[derive(Debug)]
 pub enum ParseTreeMoreFunctionArgs {
 	MoreArgs {
-		value: ParseTreeValue,
+		value: ParseTreeExpr,
 		more: Box<ParseTreeMoreFunctionArgs>,
 	},
 	NoMoreArgs,
 }
 impl ParseTreeNode for ParseTreeMoreFunctionArgs {
-	type Product = Vec<query::Value>;
-	fn validate(self) -> Result<Self::Product, String> {
+	type Product = Vec<query::Expr>;
+	fn validate(self) -> Result<Self::Product> {
 		use ParseTreeMoreFunctionArgs::*;
 		match self {
 			MoreArgs { value, more } => {
-				let mut following_args = more.validate()?;
-				following_args.push(value.validate()?);
-				Ok(following_args)
+				let mut args = vec![value.validate()?];
+				args.extend(more.validate()?);
+				Ok(args)
 			}
 			NoMoreArgs => Ok(Vec::new()),
 		}
 	}
 }
 
+#[derive(Debug)]
+pub enum ParseTreeExpr {
+	Value(ParseTreeValue),
+	Column {
+		object: String,
+		name: String,
+	},
+	Unary {
+		op: UnaryOp,
+		expr: Box<ParseTreeExpr>,
+	},
+	Binary {
+		op: BinaryOp,
+		left: Box<ParseTreeExpr>,
+		right: Box<ParseTreeExpr>,
+	},
+	In {
+		expr: Box<ParseTreeExpr>,
+		list: Box<ParseTreeFunctionArgs>,
+		negated: bool,
+	},
+	Between {
+		expr: Box<ParseTreeExpr>,
+		low: Box<ParseTreeExpr>,
+		high: Box<ParseTreeExpr>,
+		negated: bool,
+	},
+	IsNull {
+		expr: Box<ParseTreeExpr>,
+		negated: bool,
+	},
+	InQuery {
+		expr: Box<ParseTreeExpr>,
+		query: Box<ParseTreeQuery>,
+		negated: bool,
+	},
+	Subquery(Box<ParseTreeQuery>),
+	Record(Vec<ParseTreeRecordField>),
+	List(Box<ParseTreeFunctionArgs>),
+	Call {
+		name: String,
+		/// Location of the function's name
+		loc: SourceLocation,
+		args: Box<ParseTreeFunctionArgs>,
+		/// Window given with `over`
+		window: Option<ParseTreeWindow>,
+	},
+	Alias {
+		expr: Box<ParseTreeExpr>,
+		name: String,
+		/// Location of the `as`
+		loc: SourceLocation,
+	},
+	SortKey {
+		expr: Box<ParseTreeExpr>,
+		descending: Option<bool>,
+		nulls_first: Option<bool>,
+		/// Location of the first word of the sort order
+		loc: SourceLocation,
+	},
+}
+/// Where an expression is evaluated, deciding what it can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExprScope {
+	/// Evaluated against each record
+	Record,
+	/// Evaluated against each record as it is selected, so it can use window functions
+	Columns,
+	/// Evaluated against each group of records, so it can use aggregate functions
+	Group,
+}
+
+impl ParseTreeExpr {
+	/// Whether the expression is a query
+	pub fn is_query(&self) -> bool {
+		matches!(self, ParseTreeExpr::Subquery(_))
+	}
+
+	/// Whether the expression is a non-negative integer literal, such as the number of records to keep
+	pub fn is_count(&self) -> bool {
+		matches!(self, ParseTreeExpr::Value(ParseTreeValue::Integer(n)) if *n >= 0)
+	}
+
+	/// Whether the expression is a string literal
+	pub fn is_string(&self) -> bool {
+		matches!(self, ParseTreeExpr::Value(ParseTreeValue::String(_)))
+	}
+
+	/// Whether the expression calls a function with this name, without a window
+	pub fn is_call(&self, function: &str) -> bool {
+		matches!(self, ParseTreeExpr::Call { name, window: None, .. } if name == function)
+	}
+
+	/// Whether the expression is a record literal, or a list of them
+	pub fn is_record_literal(&self) -> bool {
+		match self {
+			ParseTreeExpr::Record(_) => true,
+			ParseTreeExpr::List(list) => {
+				let ParseTreeFunctionArgs::Args { value, more } = &**list else {
+					return false;
+				};
+				let mut more = &**more;
+				let mut all = matches!(value, ParseTreeExpr::Record(_));
+				while let ParseTreeMoreFunctionArgs::MoreArgs { value, more: next } = more {
+					all &= matches!(value, ParseTreeExpr::Record(_));
+					more = next;
+				}
+				all
+			}
+			_ => false,
+		}
+	}
+
+	/// Whether the expression names an object, possibly with `as`
+	pub fn is_object_name(&self) -> bool {
+		match self {
+			ParseTreeExpr::Value(ParseTreeValue::Identifier(_)) => true,
+			ParseTreeExpr::Alias { expr, .. } => {
+				matches!(**expr, ParseTreeExpr::Value(ParseTreeValue::Identifier(_)))
+			}
+			_ => false,
+		}
+	}
+
+	/// Validates an argument of a function, where it is expected to be of type `ty`
+	///
+	/// Only arguments can be named or given a sort order, and only where the function expects it.
+	pub fn validate_arg(self, ty: Option<&Type>) -> Result<query::Expr> {
+		let scope = match ty {
+			Some(Type::Columns) => ExprScope::Columns,
+			Some(Type::Aggregates | Type::GroupCondition) => ExprScope::Group,
+			_ => ExprScope::Record,
+		};
+		match (self, ty) {
+			(
+				ParseTreeExpr::Alias { expr, name, .. },
+				Some(Type::Columns | Type::GroupKeys | Type::Aggregates | Type::Object),
+			) => Ok(query::Expr::Alias {
+				expr: Box::new(expr.validate_in(scope)?),
+				name,
+			}),
+			(
+				ParseTreeExpr::SortKey {
+					expr,
+					descending,
+					nulls_first,
+					..
+				},
+				Some(Type::SortKeys),
+			) => {
+				let descending = descending.unwrap_or(false);
+				Ok(query::Expr::SortKey {
+					expr: Box::new(expr.validate_in(scope)?),
+					descending,
+					// nulls are larger than any value unless asked otherwise
+					nulls_first: nulls_first.unwrap_or(descending),
+				})
+			}
+			(expr, Some(Type::SortKeys)) => Ok(query::Expr::SortKey {
+				expr: Box::new(expr.validate_in(scope)?),
+				descending: false,
+				nulls_first: false,
+			}),
+			(expr, _) => expr.validate_in(scope),
+		}
+	}
+
+	/// Validates the expression, where it is evaluated in `scope`
+	pub fn validate_in(self, scope: ExprScope) -> Result<query::Expr> {
+		use ParseTreeExpr::*;
+		let validate = |expr: Box<ParseTreeExpr>| expr.validate_in(scope).map(Box::new);
+		let validate_list = |list: Box<ParseTreeFunctionArgs>| {
+			list.into_vec()
+				.into_iter()
+				.map(|expr| expr.validate_in(scope))
+				.collect::<Result<Vec<_>>>()
+		};
+		match self {
+			Value(value) => Ok(query::Expr::Value(value.validate()?)),
+			Column { object, name } => Ok(query::Expr::Column { object, name }),
+			Unary { op, expr } => Ok(query::Expr::Unary {
+				op,
+				expr: validate(expr)?,
+			}),
+			Binary { op, left, right } => Ok(query::Expr::Binary {
+				op,
+				left: validate(left)?,
+				right: validate(right)?,
+			}),
+			In {
+				expr,
+				list,
+				negated,
+			} => Ok(query::Expr::In {
+				expr: validate(expr)?,
+				list: validate_list(list)?,
+				negated,
+			}),
+			Between {
+				expr,
+				low,
+				high,
+				negated,
+			} => Ok(query::Expr::Between {
+				expr: validate(expr)?,
+				low: validate(low)?,
+				high: validate(high)?,
+				negated,
+			}),
+			IsNull { expr, negated } => Ok(query::Expr::IsNull {
+				expr: validate(expr)?,
+				negated,
+			}),
+			InQuery {
+				expr,
+				query,
+				negated,
+			} => Ok(query::Expr::InQuery {
+				expr: validate(expr)?,
+				query: Box::new(query.validate()?),
+				negated,
+			}),
+			Subquery(query) => Ok(query::Expr::Subquery(Box::new(query.validate()?))),
+			Record(fields) => {
+				let mut record: Vec<(std::string::String, query::Expr)> = Vec::new();
+				for field in fields {
+					if record.iter().any(|(name, _)| *name == field.name) {
+						return Err(Error::parse(
+							format!("Duplicate field: \"{}\"", field.name),
+							field.loc,
+						));
+					}
+					record.push((field.name, field.value.validate_in(scope)?));
+				}
+				Ok(query::Expr::Record(record))
+			}
+			List(items) => Ok(query::Expr::List(validate_list(items)?)),
+			Call {
+				name,
+				loc,
+				args,
+				window: Some(window),
+			} => {
+				if scope != ExprScope::Columns {
+					return Err(Error::parse(
+						"Window functions can only be used in select()",
+						loc,
+					));
+				}
+				let Some(function) = query::WindowFunction::find(&name) else {
+					return Err(Error::parse(
+						format!("\"{name}\" is not a window function"),
+						loc,
+					));
+				};
+				// the arguments are evaluated against each record of the window
+				let args = args
+					.into_vec()
+					.into_iter()
+					.map(|expr| expr.validate_in(ExprScope::Record))
+					.collect::<Result<Vec<_>>>()?;
+				check_arg_count(&name, function.arg_count(), args.len(), loc)?;
+				Ok(query::Expr::Window {
+					function,
+					args,
+					window: window.validate()?,
+				})
+			}
+			Call {
+				name, loc, args, ..
+			} if name == "point" || name == "rect" => Ok(query::Expr::Value(geometry_literal(
+				&name,
+				loc,
+				args.into_vec(),
+			)?)),
+			Call {
+				name, loc, args, ..
+			} if name == "exists" => {
+				let mut args = args.into_vec();
+				let (Some(ParseTreeExpr::Subquery(query)), None) = (args.pop(), args.pop()) else {
+					return Err(Error::parse(
+						"exists() takes a query, such as exists(Orders.where(paid))",
+						loc,
+					));
+				};
+				Ok(query::Expr::Exists(Box::new(query.validate()?)))
+			}
+			Call {
+				name, loc, args, ..
+			} => {
+				if let Some(function) = query::WindowFunction::find(&name)
+					&& !matches!(function, query::WindowFunction::Aggregate(_))
+				{
+					return Err(Error::parse(
+						format!(
+							"Window function \"{name}\" needs a window, such as {name}() over(order_by(id))"
+						),
+						loc,
+					));
+				}
+				let Some(function) = query::AggregateFunction::find(&name) else {
+					return Ok(query::Expr::Call {
+						name,
+						args: validate_list(args)?,
+					});
+				};
+				if scope != ExprScope::Group {
+					return Err(Error::parse(
+						format!(
+							"Aggregate function \"{name}\" can only be used in aggregate() and having(), and not inside another aggregate"
+						),
+						loc,
+					));
+				}
+				// the values being aggregated are those of each record
+				let args = args
+					.into_vec()
+					.into_iter()
+					.map(|expr| expr.validate_in(ExprScope::Record))
+					.collect::<Result<Vec<_>>>()?;
+				check_arg_count(&name, function.arg_count(), args.len(), loc)?;
+				Ok(query::Expr::Aggregate { function, args })
+			}
+			Alias { loc, .. } => Err(Error::parse(
+				"Only columns given to select, group_by or aggregate, and joined objects, can be named",
+				loc,
+			)),
+			SortKey { loc, .. } => Err(Error::parse(
+				"Only keys given to order_by can have a sort order",
+				loc,
+			)),
+		}
+	}
+}
+impl ParseTreeNode for ParseTreeExpr {
+	type Product = query::Expr;
+	fn validate(self) -> Result<Self::Product> {
+		self.validate_in(ExprScope::Record)
+	}
+}
+
+/// Validates `point(x, y)` or `rect(x1, y1, x2, y2)`, whose arguments have to be number literals
+fn geometry_literal(
+	name: &str,
+	loc: SourceLocation,
+	args: Vec<ParseTreeExpr>,
+) -> Result<query::Value> {
+	let coords = args
+		.into_iter()
+		.map(|arg| match arg {
+			ParseTreeExpr::Value(ParseTreeValue::Integer(n)) => Some(n as f64),
+			ParseTreeExpr::Value(ParseTreeValue::Float(n)) => Some(n),
+			_ => None,
+		})
+		.collect::<Option<Vec<f64>>>();
+	match (name, coords.as_deref()) {
+		("point", Some(&[x, y])) => Ok(query::Value::Point(Point::new(x, y))),
+		("rect", Some(&[x1, y1, x2, y2])) => Ok(query::Value::Rect(Rect::new(
+			Point::new(x1, y1),
+			Point::new(x2, y2),
+		))),
+		("point", _) => Err(Error::parse(
+			"point() takes 2 numbers, such as point(1.5, -2)",
+			loc,
+		)),
+		_ => Err(Error::parse(
+			"rect() takes the 4 coordinates of two opposite corners, such as rect(0, 0, 10, 5)",
+			loc,
+		)),
+	}
+}
+
+/// Errors if a function given `found` arguments doesn't take that many
+fn check_arg_count(
+	name: &str,
+	count: RangeInclusive<usize>,
+	found: usize,
+	loc: SourceLocation,
+) -> Result<()> {
+	if count.contains(&found) {
+		return Ok(());
+	}
+	let expected = match (count.start(), count.end()) {
+		(1, 1) => "1 argument".to_string(),
+		(start, end) if start == end => format!("{start} arguments"),
+		(1, &usize::MAX) => "at least 1 argument".to_string(),
+		(start, &usize::MAX) => format!("at least {start} arguments"),
+		(start, end) => format!("{start} to {end} arguments"),
+	};
+	Err(Error::parse(
+		format!("{name}() takes {expected}, found {found}"),
+		loc,
+	))
+}
+
+/// The `over(...)` of a window function
+#[derive(Debug, Default)]
+pub struct ParseTreeWindow {
+	pub partition_by: Option<Box<ParseTreeFunctionArgs>>,
+	pub order_by: Option<Box<ParseTreeFunctionArgs>>,
+	pub frame: Option<ParseTreeWindowFrame>,
+}
+impl ParseTreeNode for ParseTreeWindow {
+	type Product = query::Window;
+	fn validate(self) -> Result<Self::Product> {
+		let partition_by = match self.partition_by {
+			Some(keys) => keys.validate()?,
+			None => Vec::new(),
+		};
+		let order_by = match self.order_by {
+			Some(keys) => keys
+				.into_vec()
+				.into_iter()
+				.map(|key| key.validate_arg(Some(&Type::SortKeys)))
+				.collect::<Result<Vec<_>>>()?,
+			None => Vec::new(),
+		};
+		let frame = match self.frame {
+			Some(frame) => Some(frame.validate()?),
+			None => None,
+		};
+		Ok(query::Window {
+			partition_by,
+			order_by,
+			frame,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub struct ParseTreeWindowFrame {
+	pub units: FrameUnits,
+	pub start: ParseTreeFrameBound,
+	pub end: ParseTreeFrameBound,
+	/// Location of the `rows` or `range`
+	pub loc: SourceLocation,
+}
+impl ParseTreeNode for ParseTreeWindowFrame {
+	type Product = query::WindowFrame;
+	fn validate(self) -> Result<Self::Product> {
+		if matches!(self.start, ParseTreeFrameBound::UnboundedFollowing) {
+			return Err(Error::parse(
+				"A frame can't start at \"unbounded following\"",
+				self.loc,
+			));
+		}
+		if matches!(self.end, ParseTreeFrameBound::UnboundedPreceding) {
+			return Err(Error::parse(
+				"A frame can't end at \"unbounded preceding\"",
+				self.loc,
+			));
+		}
+		Ok(query::WindowFrame {
+			units: self.units,
+			start: self.start.validate()?,
+			end: self.end.validate()?,
+		})
+	}
+}
+
+#[derive(Debug)]
+pub enum ParseTreeFrameBound {
+	UnboundedPreceding,
+	Preceding(Box<ParseTreeExpr>),
+	CurrentRow,
+	Following(Box<ParseTreeExpr>),
+	UnboundedFollowing,
+}
+impl ParseTreeNode for ParseTreeFrameBound {
+	type Product = query::FrameBound;
+	fn validate(self) -> Result<Self::Product> {
+		use ParseTreeFrameBound::*;
+		match self {
+			UnboundedPreceding => Ok(query::FrameBound::UnboundedPreceding),
+			Preceding(offset) => Ok(query::FrameBound::Preceding(Box::new(offset.validate()?))),
+			CurrentRow => Ok(query::FrameBound::CurrentRow),
+			Following(offset) => Ok(query::FrameBound::Following(Box::new(offset.validate()?))),
+			UnboundedFollowing => Ok(query::FrameBound::UnboundedFollowing),
+		}
+	}
+}
+
+/// A `name: value` field of a record literal
+#[derive(Debug)]
+pub struct ParseTreeRecordField {
+	pub name: String,
+	/// Location of the field's name
+	pub loc: SourceLocation,
+	pub value: ParseTreeExpr,
+}
+
 #[derive(Debug)]
 pub enum ParseTreeValue {
+	Identifier(String),
 	String(String),
+	Bytes(Vec<u8>),
+	Integer(i64),
+	Float(f64),
+	Bool(bool),
+	Null,
 }
 impl ParseTreeNode for ParseTreeValue {
 	type Product = query::Value;
-	fn validate(self) -> Result<Self::Product, String> {
+	fn validate(self) -> Result<Self::Product> {
 		use ParseTreeValue::*;
 		match self {
+			Identifier(s) => Ok(query::Value::Identifier(s)),
 			String(s) => Ok(query::Value::String(s)),
+			Bytes(b) => Ok(query::Value::Bytes(b)),
+			Integer(n) => Ok(query::Value::Integer(n)),
+			Float(n) => Ok(query::Value::Float(n)),
+			Bool(b) => Ok(query::Value::Bool(b)),
+			Null => Ok(query::Value::Null),
 		}
 	}
 }
@@ -1,5 +1,9 @@
-use lildb::query::{self, FunctionCall, Query, functions};
-use lql::parse;
+use lildb::query::{
+	self, AggregateFunction, BinaryOp, Cte, Expr, FrameBound, FrameUnits, FunctionCall, Query,
+	UnaryOp, Value, Window, WindowFrame, WindowFunction, functions,
+};
+use lildb::{Error, SourceLocation};
+use lql::{parse, parse_expr, parse_script};
 
 #[test]
 fn test_empty() {
@@ -34,7 +38,46 @@ fn simple_function() {
 #[test]
 fn unregonized_function() {
 	let input = "Users.DOESNOTEXIST();";
-	assert!(parse(input.to_string()).is_err());
+	let Err(Error::Parse { location, .. }) = parse(input.to_string()) else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(location, SourceLocation::new(0, 6..18));
+}
+
+#[test]
+fn error_location() {
+	let input = "Users\n\t.create()\n\t.delete() x";
+	let err = parse(input.to_string()).unwrap_err();
+	assert_eq!(err.code(), 10);
+	assert_eq!(
+		err.to_string(),
+		"Expected \";\", found \"x\" (line 2, col 11)"
+	);
+}
+
+#[test]
+fn empty_input() {
+	for input in ["", " \n\t", "-- nothing here\n"] {
+		let err = parse(input.to_string()).unwrap_err();
+		assert!(
+			err.to_string().starts_with("Input did not contain a query"),
+			"{input:?}: {err}"
+		);
+	}
+}
+
+#[test]
+fn literal_out_of_range() {
+	let err = parse("Users.where(10e400);".to_string()).unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"Float literal out of range (line 0, col 12)"
+	);
+	let err = parse("Users.where(9223372036854775808);".to_string()).unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"Integer literal out of range (line 0, col 12)"
+	);
 }
 
 #[test]
@@ -57,3 +100,1526 @@ fn chained_functions() {
 		)
 	);
 }
+
+#[test]
+fn typed_args() {
+	let input = r#"Users.select(-1.5e2, 0x10, "a\tb", b"\x01", true, null, name);"#;
+	let parsed = parse(input.to_string()).unwrap();
+	assert_eq!(
+		parsed,
+		Query::new(
+			"Users",
+			Some(FunctionCall::new(
+				&functions::selectFunction,
+				vec![
+					Value::Float(-150.0).into(),
+					Value::Integer(16).into(),
+					Value::String("a\tb".to_string()).into(),
+					Value::Bytes(vec![1]).into(),
+					Value::Bool(true).into(),
+					Value::Null.into(),
+					Value::Identifier("name".to_string()).into(),
+				],
+				None
+			))
+		)
+	);
+}
+
+#[test]
+fn lex_error() {
+	let input = "Users.matches(\"unterminated);";
+	let Err(Error::Parse { message, location }) = parse(input.to_string()) else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(message, "Unterminated string literal");
+	assert_eq!(location, SourceLocation::new(0, 14..15));
+}
+
+/// Parses a single expression, as the argument of a function
+fn expr(input: &str) -> Expr {
+	let parsed = parse(format!("Users.where({input});")).unwrap();
+	parsed.function().unwrap().args()[0].clone()
+}
+
+fn ident(name: &str) -> Expr {
+	Expr::ident(name)
+}
+
+fn int(n: i64) -> Expr {
+	Value::Integer(n).into()
+}
+
+#[test]
+fn precedence() {
+	use BinaryOp::*;
+
+	assert_eq!(
+		expr("1 + 2 * 3"),
+		Expr::binary(Add, int(1), Expr::binary(Mul, int(2), int(3)))
+	);
+	assert_eq!(
+		expr("(1 + 2) * 3"),
+		Expr::binary(Mul, Expr::binary(Add, int(1), int(2)), int(3))
+	);
+	// left associative
+	assert_eq!(
+		expr("10 - 2 - 3"),
+		Expr::binary(Sub, Expr::binary(Sub, int(10), int(2)), int(3))
+	);
+	assert_eq!(
+		expr("a = 1 or b > 2 and not c"),
+		Expr::binary(
+			Or,
+			Expr::binary(Eq, ident("a"), int(1)),
+			Expr::binary(
+				And,
+				Expr::binary(Gt, ident("b"), int(2)),
+				Expr::unary(UnaryOp::Not, ident("c"))
+			)
+		)
+	);
+	assert_eq!(
+		expr("not a <> b"),
+		Expr::unary(UnaryOp::Not, Expr::binary(NotEq, ident("a"), ident("b")))
+	);
+	// a minus after an operand subtracts, anywhere else it's part of the number
+	assert_eq!(
+		expr("a -1 * -2"),
+		Expr::binary(Sub, ident("a"), Expr::binary(Mul, int(1), int(-2)))
+	);
+	assert_eq!(
+		expr("-(a % 2)"),
+		Expr::unary(UnaryOp::Neg, Expr::binary(Rem, ident("a"), int(2)))
+	);
+}
+
+#[test]
+fn predicates() {
+	assert_eq!(
+		expr("age between 18 and 65 and active"),
+		Expr::binary(
+			BinaryOp::And,
+			Expr::Between {
+				expr: Box::new(ident("age")),
+				low: Box::new(int(18)),
+				high: Box::new(int(65)),
+				negated: false,
+			},
+			ident("active")
+		)
+	);
+	assert_eq!(
+		expr("id not in (1, 2 + 3)"),
+		Expr::In {
+			expr: Box::new(ident("id")),
+			list: vec![int(1), Expr::binary(BinaryOp::Add, int(2), int(3))],
+			negated: true,
+		}
+	);
+	assert_eq!(
+		expr("email is not null"),
+		Expr::IsNull {
+			expr: Box::new(ident("email")),
+			negated: true,
+		}
+	);
+	assert_eq!(
+		expr(r#"name not like "a%""#),
+		Expr::binary(
+			BinaryOp::NotLike,
+			ident("name"),
+			Value::String("a%".to_string()).into()
+		)
+	);
+	assert_eq!(
+		expr("lower(trim(name)) = coalesce(nick, \"x\")"),
+		Expr::binary(
+			BinaryOp::Eq,
+			Expr::Call {
+				name: "lower".to_string(),
+				args: vec![Expr::Call {
+					name: "trim".to_string(),
+					args: vec![ident("name")],
+				}],
+			},
+			Expr::Call {
+				name: "coalesce".to_string(),
+				args: vec![ident("nick"), Value::String("x".to_string()).into()],
+			}
+		)
+	);
+}
+
+#[test]
+fn expression_errors() {
+	let location = |input: &str| match parse(format!("Users.where({input});")) {
+		Err(Error::Parse { location, .. }) => location,
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	// "Users.where(" is 12 chars
+	assert_eq!(location("1 +"), SourceLocation::new(0, 15..16));
+	assert_eq!(location("a not 1"), SourceLocation::new(0, 18..19));
+	assert_eq!(location("a is 1"), SourceLocation::new(0, 17..18));
+	assert_eq!(location("a between 1 or 2"), SourceLocation::new(0, 24..26));
+	assert_eq!(location("(a"), SourceLocation::new(0, 15..16));
+	assert_eq!(location("a @ b"), SourceLocation::new(0, 14..15));
+}
+
+#[test]
+fn standalone_expression() {
+	assert_eq!(
+		parse_expr("active = 1 and score > -5".to_string()).unwrap(),
+		Expr::binary(
+			BinaryOp::And,
+			Expr::binary(BinaryOp::Eq, ident("active"), int(1)),
+			Expr::binary(BinaryOp::Gt, ident("score"), int(-5)),
+		)
+	);
+	let err = parse_expr("active = 1;".to_string()).unwrap_err();
+	assert_eq!(
+		err.to_string(),
+		"Expected end of input, found ; (line 0, col 10)"
+	);
+	assert!(parse_expr(String::new()).is_err());
+}
+
+#[test]
+fn partial_index_condition() {
+	use lildb::{IndexDef, LilDbOpts, Record, TableDef, Value as V, ValueType};
+
+	let mut db = LilDbOpts::in_memory().open("partial_index.ldb").unwrap();
+	let def = TableDef::new()
+		.column("id", ValueType::U32)
+		.column("score", ValueType::I32);
+	db.create_table("Users", def).unwrap();
+	let condition = parse_expr("Users.score between -5 and 5 and id % 2 = 0".to_string()).unwrap();
+	db.create_index("Even", "Users", IndexDef::new(["score"]).filter(condition))
+		.unwrap();
+
+	let mut users = db.table("Users").unwrap();
+	for id in 0..6 {
+		let rec = Record::new()
+			.item(V::U32(id))
+			.item(V::I32(id as i32 * 2 - 6));
+		users.insert(rec).unwrap();
+	}
+	let score = |n| Record::new().item(V::I32(n));
+	assert_eq!(users.find("Even", &score(-2)).unwrap().len(), 1);
+	assert!(users.find("Even", &score(-4)).unwrap().is_empty());
+	assert!(users.find("Even", &score(6)).unwrap().is_empty());
+
+	// the condition has to be true or false
+	let err = db
+		.create_index(
+			"Bad",
+			"Users",
+			IndexDef::new(["id"]).filter(parse_expr("id + 1".to_string()).unwrap()),
+		)
+		.unwrap_err();
+	assert!(matches!(err, Error::TypeMismatch(_)), "{err}");
+}
+
+fn string(s: &str) -> Expr {
+	Value::String(s.to_string()).into()
+}
+
+#[test]
+fn record_operations() {
+	let user = |name: &str, age: i64| {
+		Expr::Record(vec![
+			("name".to_string(), string(name)),
+			("age".to_string(), int(age)),
+		])
+	};
+
+	let parsed = parse(r#"Users.insert({name: "a", age: 3});"#.to_string()).unwrap();
+	let insert = parsed.function().unwrap();
+	assert_eq!(insert.function(), &functions::insertFunction);
+	assert_eq!(insert.args(), [user("a", 3)]);
+
+	let parsed =
+		parse(r#"Users.upsert([{name: "a", age: 3}, {"name": "b", age: 4}]);"#.to_string())
+			.unwrap();
+	let upsert = parsed.function().unwrap();
+	assert_eq!(upsert.function(), &functions::upsertFunction);
+	assert_eq!(
+		upsert.args(),
+		[Expr::List(vec![user("a", 3), user("b", 4)])]
+	);
+
+	let parsed = parse("Users.update({age: age + 1, active: false});".to_string()).unwrap();
+	assert_eq!(
+		parsed.function().unwrap().args(),
+		[Expr::Record(vec![
+			(
+				"age".to_string(),
+				Expr::binary(BinaryOp::Add, ident("age"), int(1))
+			),
+			("active".to_string(), Value::Bool(false).into()),
+		])]
+	);
+
+	let parsed = parse("Users.get(7).delete_records();".to_string()).unwrap();
+	let get = parsed.function().unwrap();
+	assert_eq!(get.function(), &functions::getFunction);
+	assert_eq!(
+		get.chained().unwrap().function(),
+		&functions::deleteRecordsFunction
+	);
+
+	assert_eq!(expr("{}"), Expr::Record(Vec::new()));
+	assert_eq!(expr("[]"), Expr::List(Vec::new()));
+}
+
+#[test]
+fn record_errors() {
+	let Err(Error::Parse { message, location }) =
+		parse("Users.insert({a: 1, b: 2, a: 3});".to_string())
+	else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(message, "Duplicate field: \"a\"");
+	assert_eq!(location, SourceLocation::new(0, 26..27));
+
+	let Err(Error::Parse { location, .. }) = parse("Users.insert({a 1});".to_string()) else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(location, SourceLocation::new(0, 16..17));
+}
+
+#[test]
+fn function_signature_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Users.insert(5);"),
+		(
+			"insert() expects a record or a list of records as argument 1".to_string(),
+			SourceLocation::new(0, 6..12)
+		)
+	);
+	assert_eq!(
+		error("Users.insert([{a: 1}, 2]);").0,
+		"insert() expects a record or a list of records as argument 1"
+	);
+	assert_eq!(
+		error("Users.insert();").0,
+		"insert() takes 1 argument, found 0"
+	);
+	assert_eq!(
+		error("Users.update(1, 2, 3);").0,
+		"update() takes 1 argument, found 3"
+	);
+	assert_eq!(
+		error("Users.create(x);").0,
+		"create() takes 0 arguments, found 1"
+	);
+	assert_eq!(
+		error("Users.join(Orders);").0,
+		"join() takes 2 arguments, found 1"
+	);
+	assert_eq!(
+		error("Users.select();").0,
+		"select() takes at least 1 argument, found 0"
+	);
+
+	// each function has to be called on what the one before it returns
+	assert_eq!(
+		error("Users.create().where(a);"),
+		(
+			"where() can't be called on nothing, as the previous function doesn't return anything"
+				.to_string(),
+			SourceLocation::new(0, 15..20)
+		)
+	);
+	assert_eq!(
+		error("Users.where(a).insert({a: 1});").0,
+		"insert() can't be called on records"
+	);
+	assert_eq!(
+		error("Users.group_by(a).where(a);").0,
+		"where() can't be called on groups"
+	);
+	assert_eq!(
+		error("Users.select(a).ensure_exists();").0,
+		"ensure_exists() can't be called on records"
+	);
+	assert!(parse("Users.ensure_exists().insert([{a: 1}, {a: 2}]);".to_string()).is_ok());
+	assert!(parse("Users.where(a).update({a: 1});".to_string()).is_ok());
+}
+
+#[test]
+fn read_pipeline() {
+	let parsed = parse(
+		"Users
+			.where(age >= 18 and active)
+			.select(name, age + 1 as next_age)
+			.order_by(age desc, name asc nulls first, id)
+			.limit(10)
+			.offset(20);"
+			.to_string(),
+	)
+	.unwrap();
+
+	let filter = parsed.function().unwrap();
+	assert_eq!(filter.function(), &functions::whereFunction);
+	assert_eq!(
+		filter.args(),
+		[Expr::binary(
+			BinaryOp::And,
+			Expr::binary(BinaryOp::GtEq, ident("age"), int(18)),
+			ident("active")
+		)]
+	);
+
+	let select = filter.chained().unwrap();
+	assert_eq!(select.function(), &functions::selectFunction);
+	assert_eq!(
+		select.args(),
+		[
+			ident("name"),
+			Expr::Alias {
+				expr: Box::new(Expr::binary(BinaryOp::Add, ident("age"), int(1))),
+				name: "next_age".to_string(),
+			}
+		]
+	);
+
+	let sort_key = |name: &str, descending: bool, nulls_first: bool| Expr::SortKey {
+		expr: Box::new(ident(name)),
+		descending,
+		nulls_first,
+	};
+	let order_by = select.chained().unwrap();
+	assert_eq!(order_by.function(), &functions::orderByFunction);
+	assert_eq!(
+		order_by.args(),
+		[
+			sort_key("age", true, true),
+			sort_key("name", false, true),
+			sort_key("id", false, false),
+		]
+	);
+
+	let limit = order_by.chained().unwrap();
+	assert_eq!(limit.function(), &functions::limitFunction);
+	assert_eq!(limit.args(), [int(10)]);
+	let offset = limit.chained().unwrap();
+	assert_eq!(offset.function(), &functions::offsetFunction);
+	assert_eq!(offset.args(), [int(20)]);
+	assert_eq!(offset.chained(), None);
+
+	// only special after an argument
+	assert_eq!(
+		expr("first = last"),
+		Expr::binary(BinaryOp::Eq, ident("first"), ident("last"))
+	);
+}
+
+#[test]
+fn text_search() {
+	let parsed = parse(r#"Articles.matches("brown fox").rank().limit(10);"#.to_string()).unwrap();
+	let matches = parsed.function().unwrap();
+	assert_eq!(matches.function(), &functions::matchesFunction);
+	assert_eq!(matches.args(), [string("brown fox")]);
+	let order = matches.chained().unwrap();
+	assert_eq!(order.function(), &functions::rankFunction);
+	assert!(order.args().is_empty());
+	assert_eq!(
+		order.chained().unwrap().function(),
+		&functions::limitFunction
+	);
+	// matches can be read as records
+	assert!(parse(r#"Articles.matches("fox").where(id > 1).select(id);"#.to_string()).is_ok());
+	// the rank() window function lives in select(), apart from the one following matches
+	assert!(
+		parse(
+			r#"Articles.matches("fox").rank().select(id, rank() over(order_by(id)) as position);"#
+				.to_string()
+		)
+		.is_ok()
+	);
+
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, .. }) => message,
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Articles.matches(fox);"),
+		"matches() expects a string as argument 1"
+	);
+	assert_eq!(
+		error(r#"Articles.matches("a" + "b");"#),
+		"matches() expects a string as argument 1"
+	);
+	assert_eq!(
+		error("Articles.rank();"),
+		"rank() can't be called on an object"
+	);
+	assert_eq!(
+		error(r#"Articles.matches("fox").limit(1).rank();"#),
+		"rank() can't be called on records"
+	);
+}
+
+#[test]
+fn spatial_search() {
+	use lildb::{Point, Rect};
+
+	let parsed =
+		parse("Shops.within(rect(10, 5, 0, -5.5)).nearest(point(1.5, -2), 3);".to_string())
+			.unwrap();
+	let within = parsed.function().unwrap();
+	assert_eq!(within.function(), &functions::withinFunction);
+	assert_eq!(
+		within.args(),
+		[Value::Rect(Rect::new(Point::new(0.0, -5.5), Point::new(10.0, 5.0))).into()]
+	);
+	let nearest = within.chained().unwrap();
+	assert_eq!(nearest.function(), &functions::nearestFunction);
+	assert_eq!(
+		nearest.args(),
+		[Value::Point(Point::new(1.5, -2.0)).into(), int(3)]
+	);
+	// literals like any other
+	assert_eq!(
+		expr("location = point(0, 0)"),
+		Expr::binary(
+			BinaryOp::Eq,
+			ident("location"),
+			Value::Point(Point::new(0.0, 0.0)).into()
+		)
+	);
+
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, .. }) => message,
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Shops.within(area);"),
+		"within() expects a rect as argument 1"
+	);
+	assert_eq!(
+		error("Shops.within(point(0, 0));"),
+		"within() expects a rect as argument 1"
+	);
+	assert_eq!(
+		error("Shops.nearest(rect(0, 0, 1, 1), 3);"),
+		"nearest() expects a point as argument 1"
+	);
+	assert_eq!(
+		error("Shops.nearest(point(x, 0), 3);"),
+		"point() takes 2 numbers, such as point(1.5, -2)"
+	);
+	assert_eq!(
+		error("Shops.where(area = rect(0, 0, 1));"),
+		"rect() takes the 4 coordinates of two opposite corners, such as rect(0, 0, 10, 5)"
+	);
+}
+
+#[test]
+fn argument_modifier_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Users.where(a as b);"),
+		(
+			"Only columns given to select, group_by or aggregate, and joined objects, can be named"
+				.to_string(),
+			SourceLocation::new(0, 14..16)
+		)
+	);
+	assert_eq!(
+		error("Users.select(a desc);"),
+		(
+			"Only keys given to order_by can have a sort order".to_string(),
+			SourceLocation::new(0, 15..19)
+		)
+	);
+	assert_eq!(
+		error("Users.order_by(a nulls);").1,
+		SourceLocation::new(0, 22..23)
+	);
+	assert_eq!(
+		error("Users.select(a as 1);").1,
+		SourceLocation::new(0, 18..19)
+	);
+	// modifiers don't reach into lists
+	assert_eq!(
+		error("Users.select(a in (b as c));").1,
+		SourceLocation::new(0, 21..23)
+	);
+}
+
+#[test]
+fn limit_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error(r#"Users.limit("10");"#),
+		(
+			"limit() expects a non-negative integer as argument 1".to_string(),
+			SourceLocation::new(0, 6..11)
+		)
+	);
+	assert_eq!(
+		error("Users.limit(-5);").0,
+		"limit() expects a non-negative integer as argument 1"
+	);
+	assert_eq!(
+		error("Users.limit(a + b);").0,
+		"limit() expects a non-negative integer as argument 1"
+	);
+	assert_eq!(
+		error("Users.offset(-1);").0,
+		"offset() expects a non-negative integer as argument 1"
+	);
+	assert_eq!(
+		error("Users.limit(1, 2);").0,
+		"limit() takes 1 argument, found 2"
+	);
+	assert_eq!(
+		error("Users.nearest(point(0, 0), 1.5);").0,
+		"nearest() expects a non-negative integer as argument 2"
+	);
+	assert!(parse("Users.limit(0).offset(0x10);".to_string()).is_ok());
+}
+
+#[test]
+fn aggregation() {
+	let parsed = parse(
+		r#"Users
+			.group_by(country)
+			.having(count() > 10 and avg(age) < 40)
+			.aggregate(country, count_distinct(city) as cities, string_agg(name, ", "));"#
+			.to_string(),
+	)
+	.unwrap();
+
+	let aggregate =
+		|function: AggregateFunction, args: Vec<Expr>| Expr::Aggregate { function, args };
+
+	let group_by = parsed.function().unwrap();
+	assert_eq!(group_by.function(), &functions::groupByFunction);
+	assert_eq!(group_by.args(), [ident("country")]);
+
+	let having = group_by.chained().unwrap();
+	assert_eq!(having.function(), &functions::havingFunction);
+	assert_eq!(
+		having.args(),
+		[Expr::binary(
+			BinaryOp::And,
+			Expr::binary(
+				BinaryOp::Gt,
+				aggregate(AggregateFunction::Count, Vec::new()),
+				int(10)
+			),
+			Expr::binary(
+				BinaryOp::Lt,
+				aggregate(AggregateFunction::Avg, vec![ident("age")]),
+				int(40)
+			)
+		)]
+	);
+
+	let columns = having.chained().unwrap();
+	assert_eq!(columns.function(), &functions::aggregateFunction);
+	assert_eq!(
+		columns.args(),
+		[
+			ident("country"),
+			Expr::Alias {
+				expr: Box::new(aggregate(
+					AggregateFunction::CountDistinct,
+					vec![ident("city")]
+				)),
+				name: "cities".to_string(),
+			},
+			aggregate(
+				AggregateFunction::StringAgg,
+				vec![ident("name"), string(", ")]
+			),
+		]
+	);
+
+	// aggregates can be used inside other expressions, and other functions are left alone
+	let parsed = parse("Orders.aggregate(round(sum(price * qty) / 100));".to_string()).unwrap();
+	assert_eq!(
+		parsed.function().unwrap().args(),
+		[Expr::Call {
+			name: "round".to_string(),
+			args: vec![Expr::binary(
+				BinaryOp::Div,
+				aggregate(
+					AggregateFunction::Sum,
+					vec![Expr::binary(BinaryOp::Mul, ident("price"), ident("qty"))]
+				),
+				int(100)
+			)],
+		}]
+	);
+}
+
+#[test]
+fn aggregation_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	let (message, location) = error("Users.where(count() > 1);");
+	assert!(message.starts_with("Aggregate function \"count\" can only be used in"));
+	assert_eq!(location, SourceLocation::new(0, 12..17));
+	// nested
+	assert_eq!(
+		error("Users.aggregate(max(min(a)));").1,
+		SourceLocation::new(0, 20..23)
+	);
+	assert_eq!(
+		error("Users.aggregate(sum(a, b));"),
+		(
+			"sum() takes 1 argument, found 2".to_string(),
+			SourceLocation::new(0, 16..19)
+		)
+	);
+	assert_eq!(
+		error("Users.aggregate(count(a, b));").0,
+		"count() takes 0 to 1 arguments, found 2"
+	);
+	assert_eq!(
+		error("Users.aggregate(string_agg(a));").0,
+		"string_agg() takes 2 arguments, found 1"
+	);
+
+	// records that weren't grouped are one group, but groups aren't records
+	assert!(parse("Users.where(a).having(count() > 1).aggregate(count());".to_string()).is_ok());
+	assert_eq!(
+		error("Users.group_by(a).order_by(a);").0,
+		"order_by() can't be called on groups"
+	);
+	assert_eq!(
+		error("Users.delete_records().aggregate(count());"),
+		(
+			"aggregate() can't be called on nothing, as the previous function doesn't return anything"
+				.to_string(),
+			SourceLocation::new(0, 23..32)
+		)
+	);
+}
+
+fn column(object: &str, name: &str) -> Expr {
+	Expr::Column {
+		object: object.to_string(),
+		name: name.to_string(),
+	}
+}
+
+#[test]
+fn joins() {
+	let parsed = parse(
+		"Users
+			.left_join(Orders, Users.id = Orders.user_id)
+			.join(Users as managers, managers.id = Users.manager_id)
+			.cross_join(Regions)
+			.anti_join(Bans, Bans.user_id = Users.id);"
+			.to_string(),
+	)
+	.unwrap();
+
+	let left_join = parsed.function().unwrap();
+	assert_eq!(left_join.function(), &functions::leftJoinFunction);
+	assert_eq!(
+		left_join.args(),
+		[
+			ident("Orders"),
+			Expr::binary(
+				BinaryOp::Eq,
+				column("Users", "id"),
+				column("Orders", "user_id")
+			)
+		]
+	);
+
+	let join = left_join.chained().unwrap();
+	assert_eq!(join.function(), &functions::joinFunction);
+	assert_eq!(
+		join.args()[0],
+		Expr::Alias {
+			expr: Box::new(ident("Users")),
+			name: "managers".to_string(),
+		}
+	);
+
+	let cross_join = join.chained().unwrap();
+	assert_eq!(cross_join.function(), &functions::crossJoinFunction);
+	assert_eq!(cross_join.args(), [ident("Regions")]);
+	assert_eq!(
+		cross_join.chained().unwrap().function(),
+		&functions::antiJoinFunction
+	);
+}
+
+#[test]
+fn join_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Users.join(1 + 2, a = b);"),
+		(
+			"join() expects an object name as argument 1".to_string(),
+			SourceLocation::new(0, 6..10)
+		)
+	);
+	assert_eq!(error("Users.where(a.1);").1, SourceLocation::new(0, 14..15));
+}
+
+#[test]
+fn window_functions() {
+	let parsed = parse(
+		"Employees.select(
+			name,
+			rank() over(partition_by(dept), order_by(salary desc)) as salary_rank,
+			sum(amount) over(order_by(day), rows(unbounded preceding, current row)),
+			lag(amount, 1, 0) over(range(1 preceding, 2 following)),
+			row_number() over()
+		);"
+		.to_string(),
+	)
+	.unwrap();
+	let args = parsed.function().unwrap().args();
+
+	assert_eq!(
+		args[1],
+		Expr::Alias {
+			expr: Box::new(Expr::Window {
+				function: WindowFunction::Rank,
+				args: Vec::new(),
+				window: Window {
+					partition_by: vec![ident("dept")],
+					order_by: vec![Expr::SortKey {
+						expr: Box::new(ident("salary")),
+						descending: true,
+						nulls_first: true,
+					}],
+					frame: None,
+				},
+			}),
+			name: "salary_rank".to_string(),
+		}
+	);
+	assert_eq!(
+		args[2],
+		Expr::Window {
+			function: WindowFunction::Aggregate(AggregateFunction::Sum),
+			args: vec![ident("amount")],
+			window: Window {
+				partition_by: Vec::new(),
+				order_by: vec![Expr::SortKey {
+					expr: Box::new(ident("day")),
+					descending: false,
+					nulls_first: false,
+				}],
+				frame: Some(WindowFrame {
+					units: FrameUnits::Rows,
+					start: FrameBound::UnboundedPreceding,
+					end: FrameBound::CurrentRow,
+				}),
+			},
+		}
+	);
+	assert_eq!(
+		args[3],
+		Expr::Window {
+			function: WindowFunction::Lag,
+			args: vec![ident("amount"), int(1), int(0)],
+			window: Window {
+				frame: Some(WindowFrame {
+					units: FrameUnits::Range,
+					start: FrameBound::Preceding(Box::new(int(1))),
+					end: FrameBound::Following(Box::new(int(2))),
+				}),
+				..Window::default()
+			},
+		}
+	);
+	assert_eq!(
+		args[4],
+		Expr::Window {
+			function: WindowFunction::RowNumber,
+			args: Vec::new(),
+			window: Window::default(),
+		}
+	);
+}
+
+#[test]
+fn window_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Users.where(rank() over() = 1);"),
+		(
+			"Window functions can only be used in select()".to_string(),
+			SourceLocation::new(0, 12..16)
+		)
+	);
+	assert_eq!(
+		error("Users.select(row_number());").1,
+		SourceLocation::new(0, 13..23)
+	);
+	assert_eq!(
+		error("Users.select(lower(a) over());").0,
+		"\"lower\" is not a window function"
+	);
+	assert_eq!(
+		error("Users.select(lead() over());").0,
+		"lead() takes 1 to 3 arguments, found 0"
+	);
+	assert_eq!(
+		error("Users.select(rank() over(order_by(a), order_by(b)));").1,
+		SourceLocation::new(0, 38..46)
+	);
+	assert_eq!(
+		error("Users.select(sum(a) over(rows(current row, unbounded preceding)));").1,
+		SourceLocation::new(0, 25..29)
+	);
+	assert_eq!(
+		error("Users.select(sum(a) over(rows(1, current row)));").1,
+		SourceLocation::new(0, 31..32)
+	);
+}
+
+#[test]
+fn subqueries() {
+	let vips = parse("Users.where(vip).select(id);".to_string()).unwrap();
+
+	assert_eq!(
+		expr("user_id in Users.where(vip).select(id)"),
+		Expr::InQuery {
+			expr: Box::new(ident("user_id")),
+			query: Box::new(vips.clone()),
+			negated: false,
+		}
+	);
+	assert_eq!(
+		expr("not exists(Users.where(vip).select(id))"),
+		Expr::unary(UnaryOp::Not, Expr::Exists(Box::new(vips)))
+	);
+
+	// correlated, referring to the record of the outer query
+	let parsed = parse(
+		"Users.select(name, Orders.where(Orders.user_id = Users.id).aggregate(count()) as orders);"
+			.to_string(),
+	)
+	.unwrap();
+	let Expr::Alias { expr, .. } = &parsed.function().unwrap().args()[1] else {
+		panic!("Expected an alias");
+	};
+	let Expr::Subquery(query) = &**expr else {
+		panic!("Expected a subquery");
+	};
+	assert_eq!(query.object_name(), "Orders");
+	assert_eq!(
+		query.function().unwrap().args(),
+		[Expr::binary(
+			BinaryOp::Eq,
+			column("Orders", "user_id"),
+			column("Users", "id")
+		)]
+	);
+}
+
+#[test]
+fn ctes() {
+	let parsed = parse(
+		"let active = Users.where(active);
+		let recursive reports = Employees.where(id = 1)
+			.union_all(reports.join(Employees, Employees.manager_id = reports.id).select(Employees.id));
+		active.join(reports, active.id = reports.id);"
+			.to_string(),
+	)
+	.unwrap();
+
+	assert_eq!(parsed.object_name(), "active");
+	assert_eq!(
+		parsed.function().unwrap().function(),
+		&functions::joinFunction
+	);
+
+	let [active, reports] = parsed.ctes() else {
+		panic!("Expected 2 CTEs");
+	};
+	assert_eq!(
+		active,
+		&Cte {
+			name: "active".to_string(),
+			recursive: false,
+			query: parse("Users.where(active);".to_string()).unwrap(),
+		}
+	);
+	assert_eq!(reports.name, "reports");
+	assert!(reports.recursive);
+	let union = reports.query.function().unwrap().chained().unwrap();
+	assert_eq!(union.function(), &functions::unionAllFunction);
+	let Expr::Subquery(recursion) = &union.args()[0] else {
+		panic!("Expected a subquery");
+	};
+	assert_eq!(recursion.object_name(), "reports");
+
+	// recursive is only special before a name
+	let parsed = parse("let recursive = Users.limit(1); recursive;".to_string()).unwrap();
+	assert_eq!(parsed.ctes()[0].name, "recursive");
+	assert!(!parsed.ctes()[0].recursive);
+}
+
+#[test]
+fn subquery_errors() {
+	let error = |input: &str| match parse(input.to_string()) {
+		Err(Error::Parse { message, location }) => (message, location),
+		other => panic!("{input}: expected a parse error, got {other:?}"),
+	};
+	assert_eq!(
+		error("Users.where(exists(1));"),
+		(
+			"exists() takes a query, such as exists(Orders.where(paid))".to_string(),
+			SourceLocation::new(0, 12..18)
+		)
+	);
+	assert_eq!(
+		error("Users.where(a in b);").1,
+		SourceLocation::new(0, 17..18)
+	);
+	assert_eq!(
+		error("Users.union(Orders);").0,
+		"union() expects a query as argument 1"
+	);
+	assert_eq!(
+		error("let a = Users.limit(1); let a = Users.limit(2); a;"),
+		(
+			"\"a\" is already defined".to_string(),
+			SourceLocation::new(0, 28..29)
+		)
+	);
+	assert_eq!(error("let = Users;").1, SourceLocation::new(0, 4..5));
+}
+
+#[test]
+fn scripts() {
+	let queries = parse_script(
+		"Users.create();
+		let adults = Users.where(age >= 18);
+		adults.select(name);
+
+		Users.delete();\n\n"
+			.to_string(),
+	)
+	.unwrap();
+	assert_eq!(queries.len(), 3);
+	assert_eq!(
+		queries[0].function().unwrap().function(),
+		&functions::createFunction
+	);
+	assert_eq!(queries[1].ctes()[0].name, "adults");
+	assert_eq!(
+		queries[2].function().unwrap().function(),
+		&functions::deleteFunction
+	);
+
+	assert_eq!(parse_script(" \n\t".to_string()).unwrap(), Vec::new());
+	assert_eq!(
+		parse_script("-- setup\nUsers.create(); /* done /* really */ */\n-- end".to_string())
+			.unwrap(),
+		vec![Query::new(
+			"Users",
+			Some(FunctionCall::new(
+				&functions::createFunction,
+				Vec::new(),
+				None
+			))
+		)]
+	);
+
+	let Err(Error::Parse { location, .. }) =
+		parse_script("Users.create();\nUsers.nope();".to_string())
+	else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(location, SourceLocation::new(1, 6..10));
+	let Err(Error::Parse { location, .. }) =
+		parse_script("Users.create();\nUsers.delete()".to_string())
+	else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(location, SourceLocation::new(1, 13..14));
+
+	// parse takes exactly one query
+	let Err(Error::Parse { message, location }) =
+		parse("Users.create(); Users.delete();".to_string())
+	else {
+		panic!("Expected a parse error");
+	};
+	assert_eq!(message, "Expected end of input, found \"Users\"");
+	assert_eq!(location, SourceLocation::new(0, 16..21));
+	assert!(parse("Users.create();\n\n".to_string()).is_ok());
+}
+
+fn execute(db: &mut lildb::LilDbConnection, input: &str) -> lildb::Result<lildb::QueryResult> {
+	db.execute(&parse(input.to_string())?)
+}
+
+fn people_db(opts: lildb::LilDbOpts) -> lildb::LilDbConnection {
+	use lildb::{TableDef, ValueType};
+
+	let mut db = opts.open("people.ldb").unwrap();
+	let def = TableDef::new()
+		.column("id", ValueType::U32)
+		.column("name", ValueType::String(16))
+		.column("country", ValueType::String(2))
+		.column("age", ValueType::I32);
+	db.create_table("People", def).unwrap();
+	let people: Vec<String> = (0..40)
+		.map(|i| {
+			format!(
+				"{{id: {i}, name: \"person {i}\", country: \"{}\", age: {}}}",
+				["nz", "fr", "jp"][i % 3],
+				20 + i % 7
+			)
+		})
+		.collect();
+	let inserted = execute(&mut db, &format!("People.insert([{}]);", people.join(", "))).unwrap();
+	assert_eq!(inserted.changed, 40);
+	db
+}
+
+#[test]
+fn executed_queries() {
+	let mut db = people_db(lildb::LilDbOpts::in_memory());
+
+	let found = execute(
+		&mut db,
+		"People.where(age >= 25 and name like \"%1%\").order_by(age desc, id).limit(3).select(name, age * 2 as double);",
+	)
+	.unwrap();
+	assert_eq!(found.columns, ["name", "double"]);
+	let named = |name: &str, n: i64| vec![Value::String(name.to_string()), Value::Integer(n)];
+	assert_eq!(
+		found.rows,
+		[
+			named("person 13", 52),
+			named("person 12", 50),
+			named("person 19", 50)
+		]
+	);
+	let skipped = execute(&mut db, "People.order_by(id).offset(38).select(id);").unwrap();
+	assert_eq!(skipped.rows, [[Value::Integer(38)], [Value::Integer(39)]]);
+
+	let updated = execute(
+		&mut db,
+		"People.where(country = \"jp\").update({age: age + 100, country: \"fr\"});",
+	)
+	.unwrap();
+	assert_eq!(updated.changed, 13);
+	let deleted = execute(&mut db, "People.where(age > 100).delete_records();").unwrap();
+	assert_eq!(deleted.changed, 13);
+	let counted = execute(&mut db, "People.aggregate(count(), max(age));").unwrap();
+	assert_eq!(counted.columns, ["count", "max"]);
+	assert_eq!(counted.rows, [[Value::Integer(27), Value::Integer(26)]]);
+
+	let named = execute(
+		&mut db,
+		"let old = People.where(age > 23).select(id); old.where(old.id < 5).union(People.where(id < 2).select(id));",
+	)
+	.unwrap();
+	assert_eq!(
+		named.rows,
+		[
+			[Value::Integer(4)],
+			[Value::Integer(0)],
+			[Value::Integer(1)]
+		]
+	);
+
+	// values have to fit the columns they are written to
+	let err = execute(
+		&mut db,
+		"People.insert({id: -1, name: \"a\", country: \"nz\", age: 1});",
+	)
+	.unwrap_err();
+	assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+	let err = execute(&mut db, "People.insert({id: 1, name: \"a\", age: 1});").unwrap_err();
+	assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+	let err = execute(&mut db, "People.where(true).update({country: \"nzl\"});").unwrap_err();
+	assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+	let err = execute(&mut db, "People.where(true).update({age: name});").unwrap_err();
+	assert!(matches!(err, Error::TypeMismatch(_)), "{err}");
+	assert_eq!(
+		execute(&mut db, "People.aggregate(count());").unwrap().rows,
+		[[Value::Integer(27)]]
+	);
+
+	for unsupported in [
+		"People.select(row_number() over(order_by(id)));",
+		"People.get(1);",
+		"People.delete();",
+	] {
+		let err = execute(&mut db, unsupported).unwrap_err();
+		assert!(
+			matches!(err, Error::InvalidArgument(_)),
+			"{unsupported}: {err}"
+		);
+	}
+	let err = execute(&mut db, "Nobody.select(id);").unwrap_err();
+	assert!(matches!(err, Error::NotFound(_)), "{err}");
+	let err = execute(&mut db, "People.select(id).update({id: 1});").unwrap_err();
+	assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+}
+
+#[test]
+fn executed_aggregation() {
+	use std::sync::Arc;
+
+	use lildb::{LilDbOpts, vfs::MemoryVfs};
+
+	let query = "People.group_by(country, age > 22 as older)
+		.having(count() > 6)
+		.aggregate(country, older, count() as people, avg(age), string_agg(name, \";\") as names);";
+	let sorted = |mut rows: Vec<Vec<Value>>| {
+		rows.sort_by(|a, b| format!("{a:?}").cmp(&format!("{b:?}")));
+		rows
+	};
+
+	let mut db = people_db(LilDbOpts::in_memory());
+	let hashed = execute(&mut db, query).unwrap();
+	assert_eq!(
+		hashed.columns,
+		["country", "older", "people", "avg", "names"]
+	);
+	assert_eq!(hashed.rows.len(), 3);
+	let nz = hashed
+		.rows
+		.iter()
+		.find(|row| row[..2] == [Value::String("nz".to_string()), Value::Bool(true)])
+		.unwrap();
+	assert_eq!(nz[2], Value::Integer(8));
+	let Value::String(names) = &nz[4] else {
+		panic!("Expected names, found {:?}", nz[4]);
+	};
+	assert_eq!(names.split(';').count(), 8);
+
+	// a few hundred bytes of query memory spill the groups to a sorted temporary file
+	let vfs = MemoryVfs::new();
+	let mut spilling = people_db(
+		LilDbOpts::new()
+			.vfs(Arc::new(vfs.clone()))
+			.query_memory(300),
+	);
+	let spilled = execute(&mut spilling, query).unwrap();
+	assert_eq!(spilled.columns, hashed.columns);
+	assert_eq!(sorted(spilled.rows), sorted(hashed.rows));
+
+	// only keys and aggregates can be read from groups
+	let err = execute(&mut db, "People.group_by(country).aggregate(name);").unwrap_err();
+	assert!(matches!(err, Error::NotFound(_)), "{err}");
+}
+
+#[test]
+fn executed_joins() {
+	use std::sync::Arc;
+
+	use lildb::{IndexDef, IndexKind, LilDbOpts, TableDef, ValueType, vfs::MemoryVfs};
+
+	let with_countries = |opts: LilDbOpts, indexed: bool| {
+		let mut db = people_db(opts);
+		let def = TableDef::new()
+			.column("code", ValueType::String(2))
+			.column("name", ValueType::String(16));
+		db.create_table("Countries", def).unwrap();
+		execute(
+			&mut db,
+			"Countries.insert([
+				{code: \"nz\", name: \"New Zealand\"},
+				{code: \"fr\", name: \"France\"},
+				{code: \"de\", name: \"Germany\"}
+			]);",
+		)
+		.unwrap();
+		if indexed {
+			let hash = |column: &str| IndexDef::new([column]).kind(IndexKind::Hash);
+			db.create_index("CountryCodes", "Countries", hash("code"))
+				.unwrap();
+			db.create_index("PersonIds", "People", hash("id")).unwrap();
+		}
+		db
+	};
+	let counted = [
+		(
+			"People.join(Countries, Countries.code = People.country)",
+			27,
+		),
+		(
+			"People.left_join(Countries, Countries.code = People.country)",
+			40,
+		),
+		(
+			"People.left_join(Countries, Countries.code = People.country).where(Countries.name is null)",
+			13,
+		),
+		(
+			"People.right_join(Countries, Countries.code = People.country)",
+			28,
+		),
+		(
+			"People.full_join(Countries, Countries.code = People.country)",
+			41,
+		),
+		(
+			"People.semi_join(Countries, Countries.code = People.country)",
+			27,
+		),
+		(
+			"People.anti_join(Countries, Countries.code = People.country)",
+			13,
+		),
+		("People.cross_join(Countries)", 120),
+		(
+			"People.join(Countries, People.country != Countries.code)",
+			93,
+		),
+		(
+			"People.join(People as next, next.id = People.id + 1 and next.age > People.age)",
+			34,
+		),
+		(
+			"let nz = People.where(country = \"nz\"); Countries.semi_join(nz, nz.country = Countries.code)",
+			1,
+		),
+	];
+	let count = |db: &mut lildb::LilDbConnection, query: &str| {
+		let result = execute(db, &format!("{query}.aggregate(count());")).unwrap();
+		match result.rows[..] {
+			[ref row] => row[0].clone(),
+			_ => panic!("Expected one row, found {:?}", result.rows),
+		}
+	};
+
+	// hash joins, sort-merge joins spilling to temporary files without any query memory, and index nested-loop joins
+	// where an index of the right side covers its keys
+	let vfs = MemoryVfs::new();
+	for mut db in [
+		with_countries(LilDbOpts::in_memory(), false),
+		with_countries(
+			LilDbOpts::new().vfs(Arc::new(vfs.clone())).query_memory(0),
+			false,
+		),
+		with_countries(LilDbOpts::in_memory(), true),
+	] {
+		for (query, expected) in counted {
+			assert_eq!(count(&mut db, query), Value::Integer(expected), "{query}");
+		}
+
+		let joined = execute(
+			&mut db,
+			"People.join(Countries, Countries.code = People.country)
+				.where(People.id = 3)
+				.select(People.name, Countries.name as country_name);",
+		)
+		.unwrap();
+		assert_eq!(joined.columns, ["name", "country_name"]);
+		assert_eq!(
+			joined.rows,
+			[[
+				Value::String("person 3".to_string()),
+				Value::String("New Zealand".to_string())
+			]]
+		);
+		let kept = execute(
+			&mut db,
+			"People.anti_join(Countries, Countries.code = People.country).order_by(id).limit(1);",
+		)
+		.unwrap();
+		assert_eq!(kept.columns, ["id", "name", "country", "age"]);
+		assert_eq!(kept.rows[0][0], Value::Integer(2));
+
+		let err = execute(
+			&mut db,
+			"People.join(Countries, Countries.code = People.country).select(name);",
+		)
+		.unwrap_err();
+		assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+		let err = execute(
+			&mut db,
+			"People.join(Nowhere, Nowhere.code = People.country);",
+		)
+		.unwrap_err();
+		assert!(matches!(err, Error::NotFound(_)), "{err}");
+	}
+}
+
+#[test]
+fn executed_scripts() {
+	use std::sync::Arc;
+
+	use lildb::{
+		LilDbOpts,
+		vfs::{FaultyVfs, MemoryVfs, OpenFlags, Vfs},
+	};
+
+	let run = |db: &mut lildb::LilDbConnection, script: &str| {
+		db.run_script(&parse_script(script.to_string())?)
+	};
+	let count = |db: &mut lildb::LilDbConnection, condition: &str| {
+		let query = format!("People.where({condition}).aggregate(count());");
+		execute(db, &query).unwrap().rows[0][0].clone()
+	};
+
+	let vfs = MemoryVfs::new();
+	let mut db = people_db(LilDbOpts::new().vfs(Arc::new(vfs.clone())));
+	let results = run(
+		&mut db,
+		"People.insert({id: 100, name: \"new\", country: \"de\", age: 50});
+		People.where(id = 0).update({age: 99});
+		-- reads what the queries before it wrote
+		People.where(age >= 50).order_by(id).select(id);",
+	)
+	.unwrap();
+	assert_eq!(results.len(), 3);
+	assert_eq!((results[0].changed, results[1].changed), (1, 1));
+	assert_eq!(
+		results[2].rows,
+		[[Value::Integer(0)], [Value::Integer(100)]]
+	);
+	let journal = vfs.open("people.ldb-journal".as_ref(), OpenFlags::new());
+	assert!(journal.is_err());
+
+	// a failing query rolls back the whole script
+	let err = run(
+		&mut db,
+		"People.insert({id: 101, name: \"newer\", country: \"de\", age: 1});
+		People.where(id < 10).delete_records();
+		People.insert({id: \"x\", name: \"x\", country: \"x\", age: 1});",
+	)
+	.unwrap_err();
+	assert!(matches!(err, Error::TypeMismatch(_)), "{err}");
+	assert_eq!(count(&mut db, "true"), Value::Integer(41));
+	assert_eq!(count(&mut db, "id < 10 or id = 101"), Value::Integer(10));
+	assert_eq!(count(&mut db, "age = 99"), Value::Integer(1));
+	let results = run(&mut db, "People.where(id = 100).delete_records();").unwrap();
+	assert_eq!(results[0].changed, 1);
+
+	// a script interrupted by a crash, with its rollback failing too, is rolled back when the database is reopened
+	let vfs = FaultyVfs::new(MemoryVfs::new());
+	let faults = vfs.faults();
+	let vfs: Arc<dyn Vfs> = Arc::new(vfs);
+	let mut db = people_db(LilDbOpts::new().vfs(vfs.clone()));
+	let inserts: Vec<String> = (0..200)
+		.map(|i| {
+			format!(
+				"People.insert({{id: {}, name: \"more\", country: \"de\", age: 1}});",
+				1000 + i
+			)
+		})
+		.collect();
+	faults.fail_writes_after(20);
+	assert!(run(&mut db, &inserts.join("\n")).is_err());
+	assert!(db.is_read_only());
+	faults.heal();
+	drop(db);
+	let journal = vfs
+		.open("people.ldb-journal".as_ref(), OpenFlags::new())
+		.unwrap();
+	assert!(!journal.is_empty().unwrap());
+	drop(journal);
+	let mut db = LilDbOpts::new()
+		.vfs(vfs.clone())
+		.open("people.ldb")
+		.unwrap();
+	assert!(
+		vfs.open("people.ldb-journal".as_ref(), OpenFlags::new())
+			.is_err()
+	);
+	assert_eq!(count(&mut db, "true"), Value::Integer(40));
+	assert_eq!(count(&mut db, "country = \"de\""), Value::Integer(0));
+}
+
+#[test]
+fn executed_search() {
+	use lildb::{IndexDef, IndexKind, LilDbOpts, TableDef, TextIndexDef, ValueType};
+
+	let mut db = LilDbOpts::in_memory().open("search.ldb").unwrap();
+	let def = TableDef::new()
+		.column("id", ValueType::U32)
+		.column("body", ValueType::String(64))
+		.column("location", ValueType::Point);
+	db.create_table("Posts", def).unwrap();
+	db.create_text_index("PostText", "Posts", TextIndexDef::new("body"))
+		.unwrap();
+	db.create_index(
+		"PostLocations",
+		"Posts",
+		IndexDef::new(["location"]).kind(IndexKind::Spatial),
+	)
+	.unwrap();
+	execute(
+		&mut db,
+		"Posts.insert([
+			{id: 1, body: \"the quick brown fox\", location: point(0, 0)},
+			{id: 2, body: \"a fox, a fox, another fox\", location: point(5, 5)},
+			{id: 3, body: \"lazy dogs\", location: point(1, 1)},
+			{id: 4, body: \"brown dogs chase a fox\", location: point(9, 9)}
+		]);",
+	)
+	.unwrap();
+	let ids = |result: lildb::QueryResult| -> Vec<i64> {
+		result
+			.rows
+			.iter()
+			.map(|row| match row[0] {
+				Value::Integer(id) => id,
+				_ => panic!("Expected an id, found {:?}", row[0]),
+			})
+			.collect()
+	};
+
+	let matched = execute(&mut db, "Posts.matches(\"fox\").rank().select(id);").unwrap();
+	assert_eq!(ids(matched)[0], 2);
+	let matched = execute(
+		&mut db,
+		"Posts.matches(\"brown fox\").order_by(id).select(id);",
+	)
+	.unwrap();
+	assert_eq!(ids(matched), [1, 4]);
+	let matched = execute(
+		&mut db,
+		"Posts.where(id > 1).matches(\"fox\").rank().select(id);",
+	)
+	.unwrap();
+	assert_eq!(ids(matched).len(), 2);
+
+	let within = execute(
+		&mut db,
+		"Posts.within(rect(-1, -1, 6, 6)).order_by(id).select(id);",
+	)
+	.unwrap();
+	assert_eq!(ids(within), [1, 2, 3]);
+	let nearest = execute(&mut db, "Posts.nearest(point(8, 8), 2).select(id);").unwrap();
+	assert_eq!(ids(nearest), [4, 2]);
+	let nearest = execute(
+		&mut db,
+		"Posts.where(id != 4).nearest(point(8, 8), 2).select(id);",
+	)
+	.unwrap();
+	assert_eq!(ids(nearest), [2, 3]);
+
+	let err = execute(&mut db, "Posts.select(id).matches(\"fox\");").unwrap_err();
+	assert!(matches!(err, Error::InvalidArgument(_)), "{err}");
+}
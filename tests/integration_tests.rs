@@ -9,3 +9,803 @@ fn create_db() {
 	let _ = open(db_path.clone()).unwrap();
 	assert!(db_path.exists())
 }
+
+#[test]
+fn open_on_custom_vfs() {
+	let mem = vfs::MemoryVfs::new();
+	let opts = LilDbOpts::default().vfs(std::sync::Arc::new(mem.clone()));
+	let _ = opts.open("mem.ldb").unwrap();
+	assert!(mem.exists("mem.ldb"));
+	assert!(!std::path::Path::new("mem.ldb").exists());
+}
+
+#[test]
+fn in_memory() {
+	let _ = memory_db();
+	let _ = LilDbOpts::in_memory().open("not_a_file.ldb").unwrap();
+	assert!(!std::path::Path::new(MEMORY_PATH).exists());
+	assert!(!std::path::Path::new("not_a_file.ldb").exists());
+}
+
+#[test]
+fn save_in_memory_db() {
+	let db_path = unique_db!();
+	let mut db = memory_db();
+	db.save_to(&db_path).unwrap();
+	assert!(std::fs::metadata(&db_path).unwrap().len() > 0);
+	let _ = open(db_path).unwrap();
+}
+
+#[test]
+fn save_to_configured_vfs() {
+	let mem = vfs::MemoryVfs::new();
+	let opts = LilDbOpts::new().vfs(std::sync::Arc::new(mem.clone()));
+	let mut db = opts.open(MEMORY_PATH).unwrap();
+	db.save_to("snapshot.ldb").unwrap();
+	assert!(!std::path::Path::new("snapshot.ldb").exists());
+	let _ = opts.create(false).open("snapshot.ldb").unwrap();
+}
+
+#[test]
+fn save_over_own_file() {
+	let db_path = unique_db!();
+	let mut db = open(db_path.clone()).unwrap();
+	db.create_table("Users", TableDef::new().column("id", ValueType::U32))
+		.unwrap();
+	let len = std::fs::metadata(&db_path).unwrap().len();
+	assert!(matches!(
+		db.save_to(&db_path),
+		Err(Error::InvalidArgument(_))
+	));
+	// another path to the same file is refused as well
+	#[cfg(unix)]
+	{
+		let link = db_path.with_extension("link.ldb");
+		std::os::unix::fs::symlink(db_path.canonicalize().unwrap(), &link).unwrap();
+		assert!(matches!(db.save_to(&link), Err(Error::InvalidArgument(_))));
+	}
+	assert_eq!(std::fs::metadata(&db_path).unwrap().len(), len);
+	drop(db);
+	let mut db = open(db_path).unwrap();
+	assert!(db.table("Users").is_ok());
+
+	let mem = vfs::MemoryVfs::new();
+	let mut db = LilDbOpts::new()
+		.vfs(std::sync::Arc::new(mem.clone()))
+		.open("mem.ldb")
+		.unwrap();
+	assert!(matches!(
+		db.save_to("mem.ldb"),
+		Err(Error::InvalidArgument(_))
+	));
+	db.save_to("copy.ldb").unwrap();
+	assert_eq!(mem.contents("mem.ldb"), mem.contents("copy.ldb"));
+}
+
+#[test]
+fn open_with_mmap() {
+	let db_path = unique_db!();
+	let _ = LilDbOpts::default()
+		.mmap(true)
+		.open(db_path.clone())
+		.unwrap();
+	let _ = LilDbOpts::default().mmap(true).open(db_path).unwrap();
+}
+
+#[test]
+fn page_size() {
+	let db_path = unique_db!();
+	let _ = LilDbOpts::default()
+		.page_size(32_768)
+		.open(db_path.clone())
+		.unwrap();
+	assert_eq!(std::fs::metadata(&db_path).unwrap().len(), 32_768);
+	// the recorded page size wins over the option
+	let _ = LilDbOpts::default().page_size(4_096).open(db_path).unwrap();
+
+	assert!(
+		LilDbOpts::in_memory()
+			.page_size(1_000)
+			.open(MEMORY_PATH)
+			.is_err()
+	);
+}
+
+#[test]
+fn compression() {
+	let db_path = unique_db!();
+	let mut db = LilDbOpts::new()
+		.compression(Compression::High)
+		.open(db_path.clone())
+		.unwrap();
+	let def = TableDef::new().column("id", ValueType::U32);
+	db.create_table("packed", def.clone()).unwrap();
+	let plain = def.compression(Compression::None);
+	db.create_table("plain", plain.clone()).unwrap();
+
+	let mut stats = db.compression_stats().unwrap();
+	for (name, compressed) in [("packed", true), ("plain", false)] {
+		let mut table = db.table(name).unwrap();
+		for i in 0..10_000 {
+			table
+				.insert(Record::new().item(Value::U32(i % 10)))
+				.unwrap();
+		}
+		let after = db.compression_stats().unwrap();
+		assert!(after.pages > stats.pages);
+		let page_bytes = after.page_bytes - stats.page_bytes;
+		let stored_bytes = after.stored_bytes - stats.stored_bytes;
+		assert_eq!(stored_bytes < page_bytes / 2, compressed, "{name}");
+		stats = after;
+	}
+	drop(db);
+
+	// compression is recorded in the header, like the page size, and the stats come from the stored pages
+	let mut db = LilDbOpts::new().open(db_path).unwrap();
+	assert_eq!(db.compression_stats().unwrap(), stats);
+	assert_eq!(*db.table("plain").unwrap().def(), plain);
+}
+
+#[test]
+#[cfg(feature = "encryption")]
+fn encryption() {
+	let db_path = unique_db!();
+	let key = [1u8; 32];
+	let mut db = LilDbOpts::new()
+		.encryption_key(key)
+		.open(db_path.clone())
+		.unwrap();
+	db.rekey([2u8; 32]).unwrap();
+	drop(db);
+
+	assert!(matches!(
+		LilDbOpts::new().encryption_key(key).open(db_path.clone()),
+		Err(Error::InvalidArgument(_))
+	));
+	assert!(matches!(
+		LilDbOpts::new().open(db_path.clone()),
+		Err(Error::InvalidArgument(_))
+	));
+	let _ = LilDbOpts::new()
+		.encryption_key([2u8; 32])
+		.open(db_path)
+		.unwrap();
+}
+
+#[test]
+#[cfg(not(feature = "encryption"))]
+fn encryption_disabled() {
+	assert!(matches!(
+		LilDbOpts::new()
+			.encryption_key([1u8; 32])
+			.open(unique_db!()),
+		Err(Error::InvalidArgument(_))
+	));
+}
+
+#[test]
+fn open_flags() {
+	let db_path = unique_db!();
+	assert!(matches!(
+		LilDbOpts::new().create(false).open(db_path.clone()),
+		Err(Error::NotFound(_))
+	));
+	assert!(
+		LilDbOpts::new()
+			.read_only(true)
+			.open(db_path.clone())
+			.is_err()
+	);
+	assert!(!db_path.exists());
+
+	let _ = LilDbOpts::new()
+		.create_new(true)
+		.open(db_path.clone())
+		.unwrap();
+	assert!(matches!(
+		LilDbOpts::new().create_new(true).open(db_path.clone()),
+		Err(Error::AlreadyExists(_))
+	));
+
+	let db = LilDbOpts::new()
+		.read_only(true)
+		.synchronous(Synchronous::Full)
+		.cache_size(64)
+		.busy_timeout(std::time::Duration::from_millis(100))
+		.open(db_path)
+		.unwrap();
+	assert!(db.is_read_only());
+}
+
+#[test]
+fn reset_busy_timeout() {
+	use vfs::{LockKind, OpenFlags, Vfs};
+
+	let mem = vfs::MemoryVfs::new();
+	let opts = LilDbOpts::new()
+		.vfs(std::sync::Arc::new(mem.clone()))
+		.busy_timeout(std::time::Duration::from_millis(20));
+	drop(opts.open("busy.ldb").unwrap());
+
+	let other = mem.open("busy.ldb".as_ref(), OpenFlags::new()).unwrap();
+	other.lock(LockKind::Exclusive).unwrap();
+	assert!(matches!(opts.open("busy.ldb"), Err(Error::Busy)));
+
+	// waits for the lock instead of giving up
+	let opts = opts.busy_timeout(None);
+	let waiting = std::thread::spawn(move || opts.open("busy.ldb").map(drop));
+	std::thread::sleep(std::time::Duration::from_millis(50));
+	assert!(!waiting.is_finished());
+	other.unlock().unwrap();
+	waiting.join().unwrap().unwrap();
+}
+
+#[test]
+fn tables_and_indexes() {
+	use query::{BinaryOp, Expr};
+
+	let db_path = unique_db!();
+	let user = |id: u32, group: u32, active: u32| {
+		Record::new()
+			.item(Value::U32(id))
+			.item(Value::U32(group))
+			.item(Value::U32(active))
+	};
+	let group = |group: u32| Record::new().item(Value::U32(group));
+	let ids = |found: Vec<(RecordId, Record)>| {
+		let mut ids: Vec<Value> = found
+			.into_iter()
+			.map(|(_, rec)| rec.get(0).unwrap().clone())
+			.collect();
+		ids.sort_by_key(|id| match id {
+			Value::U32(id) => *id,
+			_ => unreachable!(),
+		});
+		ids
+	};
+
+	let rids = {
+		let mut db = open(db_path.clone()).unwrap();
+		let def = TableDef::new()
+			.column("id", ValueType::U32)
+			.column("group", ValueType::U32)
+			.column("active", ValueType::U32);
+		db.create_table("Users", def.clone()).unwrap();
+		assert!(matches!(
+			db.create_table("Users", def),
+			Err(Error::AlreadyExists(_))
+		));
+
+		let mut users = db.table("Users").unwrap();
+		let rids: Vec<RecordId> = (0..10)
+			.map(|i| users.insert(user(i, i % 2, (i < 5) as u32)).unwrap())
+			.collect();
+
+		// built from the records already in the table, and only over active users
+		let active = Expr::binary(
+			BinaryOp::Eq,
+			Expr::ident("active"),
+			query::Value::Integer(1).into(),
+		);
+		db.create_index(
+			"ActiveByGroup",
+			"Users",
+			IndexDef::new(["group"]).filter(active),
+		)
+		.unwrap();
+		assert!(matches!(
+			db.create_index("ByName", "Users", IndexDef::new(["name"])),
+			Err(Error::NotFound(_))
+		));
+		assert!(matches!(
+			db.create_index("ByGroup", "Orders", IndexDef::new(["group"])),
+			Err(Error::NotFound(_))
+		));
+
+		let mut users = db.table("Users").unwrap();
+		assert_eq!(
+			ids(users.find("ActiveByGroup", &group(0)).unwrap()),
+			[0, 2, 4].map(Value::U32)
+		);
+
+		// every write keeps the index up to date
+		users.insert(user(10, 0, 1)).unwrap();
+		assert!(users.update(rids[1], user(1, 0, 1)).unwrap());
+		assert!(users.update(rids[2], user(2, 0, 0)).unwrap());
+		assert!(users.delete(rids[4]).unwrap());
+		assert!(!users.delete(rids[4]).unwrap());
+		assert_eq!(
+			ids(users.find("ActiveByGroup", &group(0)).unwrap()),
+			[0, 1, 10].map(Value::U32)
+		);
+		rids
+	};
+
+	let mut db = open(db_path).unwrap();
+	let mut users = db.table("Users").unwrap();
+	assert_eq!(users.get(rids[1]).unwrap(), Some(user(1, 0, 1)));
+	assert_eq!(users.scan().unwrap().len(), 10);
+	assert_eq!(
+		ids(users.find("ActiveByGroup", &group(0)).unwrap()),
+		[0, 1, 10].map(Value::U32)
+	);
+	assert!(matches!(
+		users.find("ByGroup", &group(0)),
+		Err(Error::NotFound(_))
+	));
+	assert!(matches!(db.table("Orders"), Err(Error::NotFound(_))));
+}
+
+#[test]
+fn failing_index_predicate() {
+	use query::{BinaryOp, Expr};
+
+	let mut db = memory_db();
+	let def = TableDef::new()
+		.column("id", ValueType::U32)
+		.column("score", ValueType::I32);
+	db.create_table("Users", def).unwrap();
+	// fails for a score of 0
+	let predicate = Expr::binary(
+		BinaryOp::Gt,
+		Expr::binary(
+			BinaryOp::Div,
+			query::Value::Integer(100).into(),
+			Expr::ident("score"),
+		),
+		query::Value::Integer(1).into(),
+	);
+	db.create_index("ById", "Users", IndexDef::new(["id"]).filter(predicate))
+		.unwrap();
+	let user = |id: u32, score: i32| Record::new().item(Value::U32(id)).item(Value::I32(score));
+	let id = |id: u32| Record::new().item(Value::U32(id));
+
+	let mut users = db.table("Users").unwrap();
+	let rid = users.insert(user(1, 10)).unwrap();
+	assert!(matches!(
+		users.insert(user(2, 0)),
+		Err(Error::InvalidArgument(_))
+	));
+	assert!(matches!(
+		users.update(rid, user(1, 0)),
+		Err(Error::InvalidArgument(_))
+	));
+
+	// neither write reached the table or the index
+	assert_eq!(users.scan().unwrap(), vec![(rid, user(1, 10))]);
+	assert_eq!(
+		users.find("ById", &id(1)).unwrap(),
+		vec![(rid, user(1, 10))]
+	);
+	assert!(users.find("ById", &id(2)).unwrap().is_empty());
+	assert!(users.delete(rid).unwrap());
+	assert!(users.find("ById", &id(1)).unwrap().is_empty());
+}
+
+#[test]
+fn failing_index_write() {
+	let vfs = vfs::FaultyVfs::new(vfs::MemoryVfs::new());
+	let faults = vfs.faults();
+	let mut db = LilDbOpts::new()
+		.vfs(std::sync::Arc::new(vfs))
+		.open("mem.ldb")
+		.unwrap();
+	let def = TableDef::new()
+		.column("id", ValueType::U32)
+		.column("score", ValueType::I32);
+	db.create_table("Users", def).unwrap();
+	db.create_index("ById", "Users", IndexDef::new(["id"]))
+		.unwrap();
+	db.create_index("ByScore", "Users", IndexDef::new(["score"]))
+		.unwrap();
+	let user = |id: u32, score: i32| Record::new().item(Value::U32(id)).item(Value::I32(score));
+	let id = |id: u32| Record::new().item(Value::U32(id));
+	let score = |score: i32| Record::new().item(Value::I32(score));
+
+	let mut users = db.table("Users").unwrap();
+	let rid = users.insert(user(1, 10)).unwrap();
+	// what the table and its indexes hold, to check that a failed write left them as they were
+	let state = |users: &mut Table| {
+		(
+			users.scan().unwrap(),
+			[1, 2].map(|i| users.find("ById", &id(i)).unwrap()),
+			[10, 20].map(|i| users.find("ByScore", &score(i)).unwrap()),
+		)
+	};
+
+	// fail each write in turn, whether to the storage or to either index, until the write gets through
+	let mut attempt = |write: &mut dyn FnMut(&mut Table) -> Result<()>| {
+		let before = state(&mut users);
+		for n in 0.. {
+			faults.fail_writes_after(n);
+			faults.fail_once();
+			let res = write(&mut users);
+			faults.heal();
+			match res {
+				Ok(()) => return n,
+				Err(Error::Io(_)) => assert_eq!(state(&mut users), before),
+				Err(e) => panic!("Unexpected error {e:?}"),
+			}
+		}
+		unreachable!()
+	};
+	assert!(attempt(&mut |users| users.update(rid, user(2, 20)).map(|_| ())) > 2);
+	assert!(attempt(&mut |users| users.update(rid, user(1, 10)).map(|_| ())) > 2);
+	let mut inserted = None;
+	assert!(attempt(&mut |users| users.insert(user(2, 20)).map(|rid| inserted = Some(rid))) > 2);
+	assert!(attempt(&mut |users| users.delete(inserted.unwrap()).map(|_| ())) > 2);
+	assert_eq!(
+		users.find("ByScore", &score(10)).unwrap(),
+		vec![(rid, user(1, 10))]
+	);
+}
+
+#[test]
+fn unstorable_index_predicate() {
+	use query::{BinaryOp, Expr};
+
+	let mut db = memory_db();
+	db.create_table("Users", TableDef::new().column("id", ValueType::U32))
+		.unwrap();
+	let pages = db.compression_stats().unwrap().pages;
+	let call = Expr::Call {
+		name: "is_even".to_string(),
+		args: vec![Expr::ident("id")],
+	};
+	let list = Expr::binary(
+		BinaryOp::Eq,
+		Expr::List(vec![Expr::ident("id")]),
+		Expr::List(vec![query::Value::Integer(1).into()]),
+	);
+	for predicate in [call, list] {
+		assert!(matches!(
+			db.create_index("ById", "Users", IndexDef::new(["id"]).filter(predicate)),
+			Err(Error::InvalidArgument(_))
+		));
+	}
+
+	// nothing was created, so the name is still free
+	assert_eq!(db.compression_stats().unwrap().pages, pages);
+	assert!(matches!(
+		db.table("Users").unwrap().find("ById", &Record::new()),
+		Err(Error::NotFound(_))
+	));
+	db.create_index("ById", "Users", IndexDef::new(["id"]))
+		.unwrap();
+}
+
+#[test]
+fn storage_per_table() {
+	let db_path = unique_db!();
+	let row = |i: u32| Record::new().item(Value::U32(i));
+	{
+		let mut db = open(db_path.clone()).unwrap();
+		for kind in [StorageKind::Heap, StorageKind::Lsm] {
+			let def = TableDef::new().column("id", ValueType::U32).storage(kind);
+			db.create_table(&format!("{kind:?}"), def).unwrap();
+			let mut table = db.table(&format!("{kind:?}")).unwrap();
+			let rid = table.insert(row(0)).unwrap();
+			for i in 1..100 {
+				table.insert(row(i)).unwrap();
+			}
+			assert!(table.update(rid, row(100)).unwrap());
+		}
+		// the LSM tree's memtable is written out when the connection is dropped
+	}
+
+	let mut db = open(db_path).unwrap();
+	for kind in [StorageKind::Heap, StorageKind::Lsm] {
+		let mut table = db.table(&format!("{kind:?}")).unwrap();
+		assert_eq!(table.def().columns().len(), 1);
+		let mut ids: Vec<Record> = table
+			.scan()
+			.unwrap()
+			.into_iter()
+			.map(|(_, rec)| rec)
+			.collect();
+		ids.sort_by_key(|rec| match rec.get(0) {
+			Some(Value::U32(i)) => *i,
+			_ => unreachable!(),
+		});
+		assert_eq!(ids, (1..=100).map(row).collect::<Vec<_>>(), "{kind:?}");
+	}
+}
+
+#[test]
+fn bloom_filters() {
+	let db_path = unique_db!();
+	let id = |i: u32| Record::new().item(Value::U32(i));
+	let rids: Vec<RecordId> = {
+		let mut db = open(db_path.clone()).unwrap();
+		db.create_table("Users", TableDef::new().column("id", ValueType::U32))
+			.unwrap();
+		db.create_index(
+			"ById",
+			"Users",
+			IndexDef::new(["id"]).bloom_filter(1_000, 0.01),
+		)
+		.unwrap();
+		let mut users = db.table("Users").unwrap();
+		(0..100).map(|i| users.insert(id(i)).unwrap()).collect()
+	};
+
+	// the filter is reopened with the index, and vacuuming rebuilds it
+	let mut db = open(db_path).unwrap();
+	let mut users = db.table("Users").unwrap();
+	assert_eq!(
+		users.find("ById", &id(42)).unwrap(),
+		vec![(rids[42], id(42))]
+	);
+	assert!(users.find("ById", &id(1_000)).unwrap().is_empty());
+	assert!(users.delete(rids[42]).unwrap());
+	db.vacuum().unwrap();
+	let mut users = db.table("Users").unwrap();
+	assert!(users.find("ById", &id(42)).unwrap().is_empty());
+	assert_eq!(
+		users.find("ById", &id(43)).unwrap(),
+		vec![(rids[43], id(43))]
+	);
+}
+
+#[test]
+fn spatial_indexes() {
+	let db_path = unique_db!();
+	let place = |id: u32, x: f64, y: f64| {
+		Record::new()
+			.item(Value::U32(id))
+			.item(Value::Point(Point::new(x, y)))
+	};
+	let ids = |found: Vec<(RecordId, Record)>| -> Vec<Value> {
+		found
+			.into_iter()
+			.map(|(_, rec)| rec.get(0).unwrap().clone())
+			.collect()
+	};
+
+	{
+		let mut db = open(db_path.clone()).unwrap();
+		let def = TableDef::new()
+			.column("id", ValueType::U32)
+			.column("location", ValueType::Point);
+		db.create_table("Places", def).unwrap();
+		let mut places = db.table("Places").unwrap();
+		for i in 0..100 {
+			places
+				.insert(place(i, (i % 10) as f64, (i / 10) as f64))
+				.unwrap();
+		}
+		db.create_index(
+			"ByLocation",
+			"Places",
+			IndexDef::new(["location"]).kind(IndexKind::Spatial),
+		)
+		.unwrap();
+		assert!(matches!(
+			db.create_index(
+				"ById",
+				"Places",
+				IndexDef::new(["id"]).kind(IndexKind::Spatial)
+			),
+			Err(Error::InvalidArgument(_))
+		));
+	}
+
+	let mut db = open(db_path).unwrap();
+	let mut places = db.table("Places").unwrap();
+	let window = Rect::new(Point::new(0.5, 0.5), Point::new(2.5, 1.5));
+	let mut found = ids(places.within("ByLocation", window).unwrap());
+	found.sort_by_key(|id| match id {
+		Value::U32(id) => *id,
+		_ => unreachable!(),
+	});
+	assert_eq!(found, [11, 12].map(Value::U32));
+	assert_eq!(
+		ids(places
+			.nearest("ByLocation", Point::new(3.1, 4.2), 2)
+			.unwrap()),
+		[43, 53].map(Value::U32)
+	);
+	assert!(matches!(
+		places.find("ByLocation", &Record::new().item(Value::U32(0))),
+		Err(Error::InvalidArgument(_))
+	));
+}
+
+#[test]
+fn string_columns() {
+	let db_path = unique_db!();
+	let user = |id: u32, name: &str| {
+		Record::new()
+			.item(Value::U32(id))
+			.item(Value::String(name.to_string()))
+	};
+	{
+		let mut db = open(db_path.clone()).unwrap();
+		assert!(matches!(
+			db.create_table(
+				"Huge",
+				TableDef::new().column("text", ValueType::String(u16::MAX))
+			),
+			Err(Error::InvalidArgument(_))
+		));
+		for (name, storage) in [("Users", StorageKind::Heap), ("LsmUsers", StorageKind::Lsm)] {
+			let def = TableDef::new()
+				.column("id", ValueType::U32)
+				.column("name", ValueType::String(16))
+				.storage(storage);
+			db.create_table(name, def).unwrap();
+			// name like 'a%' and name != 'ann'
+			let string = |s: &str| query::Value::String(s.to_string()).into();
+			let filter = query::Expr::binary(
+				query::BinaryOp::And,
+				query::Expr::binary(
+					query::BinaryOp::Like,
+					query::Expr::ident("name"),
+					string("a%"),
+				),
+				query::Expr::binary(
+					query::BinaryOp::NotEq,
+					query::Expr::ident("name"),
+					string("ann"),
+				),
+			);
+			db.create_index(
+				&format!("{name}ByName"),
+				name,
+				IndexDef::new(["name"]).include(["id"]).filter(filter),
+			)
+			.unwrap();
+			let mut users = db.table(name).unwrap();
+			for (id, name) in ["ann", "anna", "bob", "", "anna", "änne"]
+				.iter()
+				.enumerate()
+			{
+				users.insert(user(id as u32, name)).unwrap();
+			}
+			assert!(matches!(
+				users.insert(user(9, "a name that is far too long")),
+				Err(Error::TypeMismatch(_))
+			));
+		}
+	}
+
+	let mut db = open(db_path).unwrap();
+	for name in ["Users", "LsmUsers"] {
+		let mut users = db.table(name).unwrap();
+		let mut names: Vec<Value> = users
+			.scan()
+			.unwrap()
+			.into_iter()
+			.map(|(_, rec)| rec.get(1).unwrap().clone())
+			.collect();
+		names.sort_by_key(|name| format!("{name:?}"));
+		assert_eq!(
+			names,
+			["", "ann", "anna", "anna", "bob", "änne"].map(|s| Value::String(s.to_string()))
+		);
+
+		let index = format!("{name}ByName");
+		let key = |name: &str| Record::new().item(Value::String(name.to_string()));
+		let mut found: Vec<Record> = users
+			.find_covered(&index, &key("anna"))
+			.unwrap()
+			.into_iter()
+			.map(|(_, rec)| rec)
+			.collect();
+		found.sort_by_key(|rec| format!("{rec:?}"));
+		assert_eq!(found, [1, 4].map(|id| Record::new().item(Value::U32(id))));
+		// a shorter string isn't a prefix match, and "ann" is filtered out
+		assert!(users.find(&index, &key("an")).unwrap().is_empty());
+		assert!(users.find(&index, &key("ann")).unwrap().is_empty());
+	}
+}
+
+#[test]
+fn text_indexes() {
+	let db_path = unique_db!();
+	let post = |id: u32, body: &str| {
+		Record::new()
+			.item(Value::U32(id))
+			.item(Value::String(body.to_string()))
+	};
+	let ids = |found: Vec<(RecordId, Record)>| {
+		let mut ids: Vec<Value> = found
+			.into_iter()
+			.map(|(_, rec)| rec.get(0).unwrap().clone())
+			.collect();
+		ids.sort_by_key(|id| format!("{id:?}"));
+		ids
+	};
+	{
+		let mut db = open(db_path.clone()).unwrap();
+		let def = TableDef::new()
+			.column("id", ValueType::U32)
+			.column("body", ValueType::String(64));
+		db.create_table("Posts", def).unwrap();
+		let mut posts = db.table("Posts").unwrap();
+		posts.insert(post(0, "The quick brown fox")).unwrap();
+
+		// rows already in the table are indexed
+		db.create_text_index(
+			"ByBody",
+			"Posts",
+			TextIndexDef::new("body").stop_words(["the"]),
+		)
+		.unwrap();
+		db.create_text_index(
+			"Stemmed",
+			"Posts",
+			TextIndexDef::new("body").stemmer(StemmerKind::Porter),
+		)
+		.unwrap();
+		assert!(matches!(
+			db.create_text_index("ByBody", "Posts", TextIndexDef::new("body")),
+			Err(Error::AlreadyExists(_))
+		));
+		assert!(matches!(
+			db.create_text_index(
+				"Grams",
+				"Posts",
+				TextIndexDef::new("body").tokenizer(TokenizerKind::NGrams(0))
+			),
+			Err(Error::InvalidArgument(_))
+		));
+		assert!(matches!(
+			db.create_text_index("ById", "Posts", TextIndexDef::new("id")),
+			Err(Error::TypeMismatch(_))
+		));
+		assert!(matches!(
+			db.create_text_index("ByTitle", "Posts", TextIndexDef::new("title")),
+			Err(Error::NotFound(_))
+		));
+
+		let mut posts = db.table("Posts").unwrap();
+		let lazy = posts
+			.insert(post(1, "A lazy dog, and a quick one"))
+			.unwrap();
+		posts.insert(post(2, "Quick quick QUICK")).unwrap();
+		let connected = posts.insert(post(3, "Connected by running")).unwrap();
+		posts.insert(post(4, "Connections")).unwrap();
+		posts.delete(lazy).unwrap();
+		posts
+			.update(connected, post(3, "Connected by runners"))
+			.unwrap();
+	}
+
+	// the definition is reopened with the index, so queries are analyzed the same way
+	let mut db = open(db_path).unwrap();
+	let mut posts = db.table("Posts").unwrap();
+	assert!(matches!(
+		posts.matches("Comments", "quick"),
+		Err(Error::NotFound(_))
+	));
+	assert_eq!(
+		ids(posts.matches("ByBody", "the QUICK").unwrap()),
+		[0, 2].map(Value::U32)
+	);
+	assert!(posts.matches("ByBody", "dog").unwrap().is_empty());
+	let ranked: Vec<Value> = posts
+		.rank("ByBody", "quick fox")
+		.unwrap()
+		.into_iter()
+		.map(|(_, rec, _)| rec.get(0).unwrap().clone())
+		.collect();
+	assert_eq!(ranked, [0, 2].map(Value::U32));
+
+	assert_eq!(
+		ids(posts.matches("Stemmed", "connecting").unwrap()),
+		[3, 4].map(Value::U32)
+	);
+	assert!(posts.matches("Stemmed", "runs").unwrap().is_empty());
+	assert_eq!(
+		ids(posts.matches("Stemmed", "runner").unwrap()),
+		[3].map(Value::U32)
+	);
+	assert!(matches!(
+		posts.find(
+			"ByBody",
+			&Record::new().item(Value::String("quick".to_string()))
+		),
+		Err(Error::InvalidArgument(_))
+	));
+}
@@ -1,16 +1,26 @@
 use std::{fs, path::Path, sync::Once};
 
-pub const TEST_DIR: &str = "./test_artifacts/";
+/// Kept apart from `test_artifacts/unit`, which unit tests may be using at the same time
+pub const TEST_DIR: &str = "./test_artifacts/integration/";
 
 static TEST_DIR_CREATED: Once = Once::new();
 /// Makes sure that the test directory has been cleaned and created
 pub fn ensure_test_dir() {
 	let path = Path::new(TEST_DIR);
-	fs::remove_dir_all(path).unwrap();
-	TEST_DIR_CREATED.call_once(|| fs::create_dir_all(path).unwrap());
+	TEST_DIR_CREATED.call_once(|| {
+		let _ = fs::remove_dir_all(path);
+		fs::create_dir_all(path).unwrap();
+	});
 }
 
+/// Opens a throwaway in-memory database, for tests that don't need to touch the file system
+pub fn memory_db() -> lildb::LilDbConnection {
+	lildb::open(lildb::MEMORY_PATH).unwrap()
+}
+
+/// Path to a database file unique to the calling test, only needed when a test has to check what ends up on disk
 #[macro_export]
+#[allow(clippy::crate_in_macro_def)]
 macro_rules! unique_db {
 	() => {{
 		crate::utils::ensure_test_dir();